@@ -0,0 +1,129 @@
+//! Choosing between list and array tags.
+//!
+//! By default a sequence of `i8`, `i32` or `i64` is written as a
+//! `Tag_Byte_Array`, `Tag_Int_Array` or `Tag_Long_Array`, and any other
+//! sequence as a `Tag_List`. Some structures need the other choice, such as a
+//! `Tag_List` of ints. The wrapper types here force the tag a sequence is
+//! written as, and the modules of the same names do the same for a plain
+//! `Vec` field:
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Structure {
+//!     #[serde(with = "nbt_serde::arrays::list")]
+//!     size: Vec<i32>,
+//!     palette: nbt_serde::arrays::LongArray,
+//! }
+//! ```
+//!
+//! Either tag is accepted when decoding, so these only affect encoding. Other
+//! serializers see the wrapped sequence as a newtype struct.
+
+use std::ops::{Deref, DerefMut};
+
+use nbt::markers::{BYTE_ARRAY, INT_ARRAY, LIST, LONG_ARRAY};
+use serde::{de, ser};
+
+use kind::Kind;
+
+/// The tag forced by a wrapper, given the name it serializes its newtype
+/// struct with.
+pub(crate) fn forced_container(name: &str) -> Option<Kind> {
+    match name {
+        BYTE_ARRAY => Some(Kind::I8Array),
+        INT_ARRAY => Some(Kind::I32Array),
+        LONG_ARRAY => Some(Kind::I64Array),
+        LIST => Some(Kind::List),
+        _ => None,
+    }
+}
+
+macro_rules! forced_sequence {
+    ($(#[$attr:meta])* struct $name:ident<$($param:ident)*>($elem:ty) as $marker:ident; mod $module:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, Debug, Default, PartialEq)]
+        pub struct $name<$($param)*>(pub Vec<$elem>);
+
+        impl<$($param)*> Deref for $name<$($param)*> {
+            type Target = Vec<$elem>;
+
+            fn deref(&self) -> &Vec<$elem> {
+                &self.0
+            }
+        }
+
+        impl<$($param)*> DerefMut for $name<$($param)*> {
+            fn deref_mut(&mut self) -> &mut Vec<$elem> {
+                &mut self.0
+            }
+        }
+
+        impl<$($param)*> From<Vec<$elem>> for $name<$($param)*> {
+            fn from(values: Vec<$elem>) -> Self {
+                $name(values)
+            }
+        }
+
+        impl<$($param: ser::Serialize)*> ser::Serialize for $name<$($param)*> {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where S: ser::Serializer
+            {
+                $module::serialize(&self.0, serializer)
+            }
+        }
+
+        impl<$($param: de::Deserialize)*> de::Deserialize for $name<$($param)*> {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where D: de::Deserializer
+            {
+                $module::deserialize(deserializer).map($name)
+            }
+        }
+
+        /// Functions for `#[serde(with = "...")]` on a `Vec` field, writing it
+        /// as the wrapper of the same name does.
+        pub mod $module {
+            use serde::{de, ser};
+
+            #[allow(missing_docs)]
+            pub fn serialize<$($param: ser::Serialize,)* S>(values: &[$elem], serializer: S)
+                                                           -> Result<S::Ok, S::Error>
+                where S: ser::Serializer
+            {
+                serializer.serialize_newtype_struct(super::$marker, values)
+            }
+
+            #[allow(missing_docs)]
+            pub fn deserialize<$($param: de::Deserialize,)* D>(deserializer: D)
+                                                              -> Result<Vec<$elem>, D::Error>
+                where D: de::Deserializer
+            {
+                de::Deserialize::deserialize(deserializer)
+            }
+        }
+    };
+}
+
+forced_sequence! {
+    /// A sequence of bytes written as a `Tag_Byte_Array`, even where it would
+    /// otherwise be a `Tag_List`, such as inside a list of lists.
+    struct ByteArray<>(i8) as BYTE_ARRAY; mod byte_array
+}
+
+forced_sequence! {
+    /// A sequence of ints written as a `Tag_Int_Array`, even where it would
+    /// otherwise be a `Tag_List`, such as inside a list of lists.
+    struct IntArray<>(i32) as INT_ARRAY; mod int_array
+}
+
+forced_sequence! {
+    /// A sequence of longs written as a `Tag_Long_Array`, even where it would
+    /// otherwise be a `Tag_List`, such as inside a list of lists.
+    struct LongArray<>(i64) as LONG_ARRAY; mod long_array
+}
+
+forced_sequence! {
+    /// A sequence written as a `Tag_List`, even if it holds bytes, ints or
+    /// longs.
+    struct List<T>(T) as LIST; mod list
+}
@@ -1,25 +1,21 @@
-use std::io;
+use std::borrow::Cow;
+use std::io::{self, Read};
+use std::marker::PhantomData;
 
+use nbt::DecoderOptions;
+use nbt::markers::VALUE;
 use serde::de;
+use serde::de::value::{StringDeserializer, U8Deserializer, ValueDeserializer};
 use flate2::read;
 
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::ReadBytesExt;
 
-use error::{Error, Result};
+use encode::Unsigned;
+use error::{Error, Result, SizeLimit};
+use flavor::Flavor;
+use hooks::{Hooks, Scalar, Target};
 use kind::Kind;
-
-#[inline]
-fn read_bare_string<R>(src: &mut R) -> Result<String> where R: io::Read
-{
-    let len = src.read_u16::<BigEndian>()? as usize;
-
-    if len == 0 { return Ok("".to_string()); }
-
-    let mut bytes = vec![0; len];
-    src.read_exact(&mut bytes[0..]).map_err(Error::from)?;
-
-    String::from_utf8(bytes).map_err(From::from)
-}
+use strings::{StringCodec, Utf8};
 
 /// Decode an object from Named Binary Tag (NBT) format.
 ///
@@ -33,6 +29,30 @@ pub fn from_reader<R, T>(src: R) -> Result<T>
     de::Deserialize::deserialize(&mut decoder)
 }
 
+/// Decode an object from Named Binary Tag (NBT) format, along with the name
+/// of its root compound, such as the empty name of `level.dat`. Passing the
+/// name to `encode::to_writer` as the header writes the same root back.
+pub fn from_reader_named<R, T>(src: R) -> Result<(String, T)>
+    where R: io::Read,
+          T: de::Deserialize,
+{
+    let mut decoder = Decoder::new(src);
+    let value = de::Deserialize::deserialize(&mut decoder)?;
+    let name = decoder.root_name.take().unwrap_or_default();
+    Ok((name, value))
+}
+
+/// Decode an object from a buffer holding Named Binary Tag (NBT) data, such
+/// as a packet payload.
+///
+/// Note that only maps and structs can be decoded, because the NBT format does
+/// not support bare types. Other types will return `Error::NoRootCompound`.
+pub fn from_slice<T>(src: &[u8]) -> Result<T>
+    where T: de::Deserialize,
+{
+    from_reader(src)
+}
+
 /// Decode an object from Named Binary Tag (NBT) format.
 ///
 /// Note that only maps and structs can be decoded, because the NBT format does
@@ -42,7 +62,7 @@ pub fn from_gzip<R, T>(src: R) -> Result<T>
           T: de::Deserialize,
 {
     let gzip = read::GzDecoder::new(src)?;
-    let mut decoder = Decoder::new(gzip);
+    let mut decoder = Decoder::new(gzip).with_buffering(true);
     de::Deserialize::deserialize(&mut decoder)
 }
 
@@ -55,43 +75,439 @@ pub fn from_zlib<R, T>(src: R) -> Result<T>
           T: de::Deserialize,
 {
     let mut zlib = read::ZlibDecoder::new(src);
-    let mut decoder = Decoder::new(&mut zlib);
+    let mut decoder = Decoder::new(&mut zlib).with_buffering(true);
     de::Deserialize::deserialize(&mut decoder)
 }
 
+/// How compound keys are matched against the names of struct fields.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldMatching {
+    /// Keys must match field names exactly. This is the default.
+    Exact,
+    /// Keys match field names regardless of case, so `spawnX` fills a field
+    /// named `SpawnX`.
+    IgnoreCase,
+    /// Keys match field names regardless of case and underscores, so
+    /// `spawn_x` also fills a field named `SpawnX`.
+    IgnoreCaseAndUnderscores,
+}
+
+impl FieldMatching {
+    fn normalize(self, name: &str) -> String {
+        match self {
+            FieldMatching::Exact => name.to_string(),
+            FieldMatching::IgnoreCase => name.to_lowercase(),
+            FieldMatching::IgnoreCaseAndUnderscores => {
+                name.chars().filter(|&c| c != '_').collect::<String>().to_lowercase()
+            },
+        }
+    }
+
+    /// The field a key refers to, or the key itself if it matches none.
+    fn resolve(self, key: String, fields: &'static [&'static str]) -> String {
+        if self == FieldMatching::Exact || fields.contains(&&key[..]) {
+            return key;
+        }
+        let normalized = self.normalize(&key);
+        match fields.iter().find(|f| self.normalize(f) == normalized) {
+            Some(field) => field.to_string(),
+            None => key,
+        }
+    }
+}
+
 /// Decode objects from Named Binary Tag (NBT) format.
 ///
 /// Note that only maps and structs can be decoded, because the NBT format does
 /// not support bare types. Other types will return `Error::NoRootCompound`.
 pub struct Decoder<R> {
-    reader: R,
+    reader: Position<R>,
+    strings: Box<dyn StringCodec>,
+    flavor: Flavor,
+    fields: FieldMatching,
+    unsigned: Unsigned,
+    widening: bool,
+    strict: bool,
+    hooks: Hooks,
+    /// The path to the tag being decoded, which is only tracked when there
+    /// are hooks to match against it.
+    path: Vec<String>,
+    /// The bytes of the last string read by `visit_string`, kept to save
+    /// allocating a buffer for every compound key.
+    scratch: Vec<u8>,
+    root_name: Option<String>,
+    nameless_root: bool,
+    any_root: bool,
+    context: bool,
+    /// Whether the type ID of the root has been read, so that the end of the
+    /// source before it can be told from a truncated document.
+    started: bool,
+    /// The limits on what may be read.
+    options: DecoderOptions,
+    /// How many lists and compounds enclose the tag being decoded.
+    depth: usize,
 }
 
 impl<R> Decoder<R> where R: io::Read {
 
     /// Create an NBT Decoder from a given `io::Read` source.
     pub fn new(src: R) -> Self {
-        Decoder { reader: src }
+        Decoder {
+            reader: Position { inner: src, offset: 0, ahead: Vec::new(), pos: 0, buffered: false,
+                               limit: None },
+            strings: Box::new(Utf8),
+            flavor: Flavor::Java,
+            fields: FieldMatching::Exact,
+            unsigned: Unsigned::Reinterpret,
+            widening: false,
+            strict: false,
+            hooks: Hooks::default(),
+            path: Vec::new(),
+            scratch: Vec::new(),
+            root_name: None,
+            nameless_root: false,
+            any_root: false,
+            started: false,
+            context: false,
+            // VarInt string lengths in the Bedrock network flavor may exceed
+            // the 65535 the core limits strings to by default.
+            options: DecoderOptions {
+                max_string_len: usize::MAX,
+                ..DecoderOptions::default()
+            },
+            depth: 0,
+        }
     }
-}
 
-impl<'a, R: io::Read> de::Deserializer for &'a mut Decoder<R> {
-    type Error = Error;
+    /// Run `hook` on every number or string matching `target` as it is
+    /// decoded, replacing it with the value the hook returns.
+    pub fn with_hook<F>(mut self, target: Target, hook: F) -> Self
+        where F: FnMut(Scalar) -> Scalar + 'static
+    {
+        self.hooks.push(target, Box::new(hook));
+        self
+    }
+
+    /// Match compound keys to struct fields more loosely, for files whose
+    /// capitalization differs from the struct's. Keys that match a field
+    /// exactly are always used as they are.
+    pub fn with_field_matching(mut self, matching: FieldMatching) -> Self {
+        self.fields = matching;
+        self
+    }
+
+    /// Read unsigned integers according to `policy`. With the default,
+    /// `Unsigned::Reinterpret`, a tag of the same width as the field has its
+    /// bits reinterpreted, so that a `Tag_Byte` of -56 fills a `u8` with 200.
+    /// Any other integer tag, and every tag under the other policies, must
+    /// hold a value that fits in the field.
+    pub fn with_unsigned(mut self, policy: Unsigned) -> Self {
+        self.unsigned = policy;
+        self
+    }
+
+    /// Let number fields be filled from narrower tags that convert without
+    /// loss, such as an `i32` field from a `Tag_Byte` or an `f64` field from a
+    /// `Tag_Int`, for files written before a field was widened, even with
+    /// `with_strict_numbers`.
+    pub fn with_widening(mut self, widening: bool) -> Self {
+        self.widening = widening;
+        self
+    }
+
+    /// Require signed numbers to be stored as the tag matching their type,
+    /// failing with `Error::UnexpectedTag` otherwise. By default any number
+    /// tag is handed to the field's visitor, which converts it if the value
+    /// is in range, so that a `Tag_Double` of 2.0 may fill an `i32` field.
+    pub fn with_strict_numbers(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Read numbers and lengths as laid out in the given flavor, such as
+    /// `Flavor::BedrockNetwork` for Bedrock Edition packets, instead of
+    /// Java Edition's big-endian layout.
+    pub fn with_flavor(mut self, flavor: Flavor) -> Self {
+        self.flavor = flavor;
+        self
+    }
+
+    /// Read a root compound that has no name, whose type ID is followed
+    /// directly by its payload, as in the Java Edition network protocol since
+    /// 1.20.2. Its name is then reported as the empty string.
+    pub fn with_nameless_root(mut self, nameless: bool) -> Self {
+        self.nameless_root = nameless;
+        self
+    }
+
+    /// Allow the root to be any tag, such as the bare string or int network
+    /// NBT may carry, rather than only a compound. A type other than a map,
+    /// struct or enum is otherwise refused with `Error::NoRootCompound`.
+    pub fn with_any_root(mut self, any: bool) -> Self {
+        self.any_root = any;
+        self
+    }
 
-    fn deserialize<V>(self, _visitor: V) -> Result<V::Value>
+    /// Read the source in blocks rather than only as much as each value
+    /// needs, which makes decoding from an unbuffered source such as a
+    /// `File` much faster. The decoder may then read past the end of the
+    /// document, so leave this off if anything else follows it in the
+    /// source.
+    pub fn with_buffering(mut self, buffered: bool) -> Self {
+        self.reader.buffered = buffered;
+        self
+    }
+
+    /// Wrap errors met inside compounds and lists in `Error::At`, giving the
+    /// byte offset and path of the tag that could not be read, so that a
+    /// malformed file can be fixed. Keys are then read into a `String` before
+    /// being handed on, which costs an allocation for each one.
+    pub fn with_error_context(mut self, context: bool) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Decode within the given limits on nesting depth, string length, list
+    /// and array length and bytes read, as `nbt::Value::from_reader_with`
+    /// does, for data from untrusted sources. They fail with
+    /// `Error::DepthLimitExceeded`, `Error::LengthLimitExceeded` and
+    /// `Error::SizeLimitExceeded`. Lengths are checked before anything is
+    /// allocated for them. Without options, only the depth is limited.
+    pub fn with_options(mut self, options: DecoderOptions) -> Self {
+        self.reader.limit = options.max_bytes;
+        self.options = options;
+        self
+    }
+
+    /// Set only the limit on how deeply lists and compounds may nest, which
+    /// is 512 by default.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.options.max_depth = depth;
+        self
+    }
+
+    /// Use the given codec to decode strings and compound keys, instead of
+    /// strict UTF-8.
+    pub fn with_string_codec<S>(mut self, codec: S) -> Self
+        where S: StringCodec + 'static
+    {
+        self.strings = Box::new(codec);
+        self
+    }
+
+    /// The name of the root compound, once it has been read.
+    pub fn root_name(&self) -> Option<&str> {
+        self.root_name.as_ref().map(|name| &name[..])
+    }
+
+    /// The number of bytes read so far.
+    pub fn offset(&self) -> u64 {
+        self.reader.offset
+    }
+
+    /// Adds the location of a failure to `error` if errors are to carry it,
+    /// giving the name or index of the tag it happened in, which is empty
+    /// for the compound itself. The offset is that of the innermost failure.
+    fn locate(&self, error: Error, segment: String) -> Error {
+        if !self.context {
+            return error;
+        }
+        match error {
+            Error::At { offset, path, error } => {
+                let path = if path.is_empty() || path.starts_with('[') {
+                    segment + &path
+                } else {
+                    segment + "." + &path
+                };
+                Error::At { offset, path, error }
+            },
+            error => Error::At { offset: self.reader.offset, path: segment, error: Box::new(error) },
+        }
+    }
+
+    /// Decode documents stored back to back, as some tools and Bedrock
+    /// Edition's LevelDB values pack them, until the source ends between two
+    /// documents.
+    #[allow(clippy::should_implement_trait)]
+    pub fn into_iter<T>(self) -> StreamDecoder<R, T>
+        where T: de::Deserialize
+    {
+        StreamDecoder { decoder: self, done: false, marker: PhantomData }
+    }
+
+    /// Reads the type ID and name of the root, which must be a compound
+    /// unless any tag is allowed, and returns the type ID.
+    fn open_root(&mut self) -> Result<u8> {
+        let tag = self.reader.read_u8()?;
+        self.started = true;
+        self.depth = 0;
+        if tag != 0x0a && !self.any_root {
+            return Err(Error::NoRootCompound);
+        }
+        self.root_name = Some(if self.nameless_root { String::new() } else { self.read_string()? });
+        Ok(tag)
+    }
+
+    /// A decoder for the payload of the root, once its header has been read.
+    fn root(&mut self, tag: u8) -> InnerDecoder<'_, R> {
+        InnerDecoder { outer: self, tag, element: false }
+    }
+
+    /// Reads the length of a string, which must be within the limit.
+    fn read_string_len(&mut self) -> Result<usize> {
+        let len = self.flavor.read_string_len(&mut self.reader)?;
+        if len > self.options.max_string_len {
+            return Err(Error::LengthLimitExceeded(self.options.max_string_len));
+        }
+        Ok(len)
+    }
+
+    /// Reads the length of a list or array, which must be within the limit.
+    fn read_len(&mut self) -> Result<i32> {
+        match self.flavor.read_len(&mut self.reader)? {
+            len if len < 0 => Err(Error::NegativeLength(len)),
+            len if len as usize > self.options.max_len => {
+                Err(Error::LengthLimitExceeded(self.options.max_len))
+            },
+            len => Ok(len),
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String> {
+        let len = self.read_string_len()?;
+
+        if len == 0 { return Ok("".to_string()); }
+
+        // Read as much as there is rather than trusting the length with an
+        // allocation up front.
+        let mut bytes = Vec::new();
+        (&mut self.reader).take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(Error::IncompleteNbtValue);
+        }
+
+        self.strings.decode(bytes)
+    }
+
+    /// Reads a string and hands it to `visitor` without taking ownership, so
+    /// that visitors which only inspect it, such as those for struct field
+    /// names, do not allocate.
+    fn visit_string<V>(&mut self, visitor: V) -> Result<V::Value>
         where V: de::Visitor
     {
-        // The decoder cannot deserialize types by default. It can only handle
-        // maps and structs.
-        Err(Error::NoRootCompound)
+        let len = self.read_string_len()?;
+        self.scratch.clear();
+        (&mut self.reader).take(len as u64).read_to_end(&mut self.scratch)?;
+        if self.scratch.len() != len {
+            return Err(Error::IncompleteNbtValue);
+        }
+        match self.strings.decode_ref(&self.scratch)? {
+            Cow::Borrowed(value) => visitor.visit_str(value),
+            Cow::Owned(value) => visitor.visit_string(value),
+        }
+    }
+
+    /// Decodes the payload of a list or compound with `visit`, failing rather
+    /// than recursing further if it is nested more deeply than allowed.
+    fn nested<T, F>(&mut self, visit: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        if self.depth > self.options.max_depth {
+            return Err(Error::DepthLimitExceeded(self.options.max_depth));
+        }
+        self.depth += 1;
+        let result = visit(self);
+        self.depth -= 1;
+        result
     }
 
-    fn deserialize_struct<V>(self, _name: &'static str,
-                             _fields: &'static [&'static str], visitor: V)
+    /// Reads past the payload of a tag, nested `depth` deep in the tag being
+    /// skipped, without building anything from it.
+    fn skip(&mut self, tag: u8, depth: usize) -> Result<()> {
+        if (tag == 0x09 || tag == 0x0a) && depth > self.options.max_depth {
+            return Err(Error::DepthLimitExceeded(self.options.max_depth));
+        }
+        let flavor = self.flavor;
+        match tag {
+            0x01..=0x06 => match flavor.width(tag) {
+                Some(width) => self.skip_bytes(width),
+                None if tag == 0x03 => flavor.read_i32(&mut self.reader).map(|_| ()).map_err(From::from),
+                None => flavor.read_i64(&mut self.reader).map(|_| ()).map_err(From::from),
+            },
+            0x07 | 0x0b | 0x0c => {
+                let len = self.read_len()?;
+                let element = match tag { 0x07 => 0x01, 0x0b => 0x03, _ => 0x04 };
+                self.skip_elements(element, len, depth)
+            },
+            0x08 => {
+                let len = self.read_string_len()?;
+                self.skip_bytes(len as u64)
+            },
+            0x09 => {
+                let element = self.reader.read_u8()?;
+                let len = self.read_len()?;
+                self.skip_elements(element, len, depth + 1)
+            },
+            0x0a => loop {
+                match self.reader.read_u8()? {
+                    0x00 => return Ok(()),
+                    tag => {
+                        let len = self.read_string_len()?;
+                        self.skip_bytes(len as u64)?;
+                        self.skip(tag, depth + 1)?;
+                    },
+                }
+            },
+            t => Err(Error::UnknownTag(t)),
+        }
+    }
+
+    /// Reads past `len` payloads of the given tag, nested `depth` deep, all at
+    /// once if they are of a fixed size.
+    fn skip_elements(&mut self, tag: u8, len: i32, depth: usize) -> Result<()> {
+        if len < 0 {
+            return Err(Error::NegativeLength(len));
+        }
+        match self.flavor.width(tag) {
+            Some(width) => self.skip_bytes(width * len as u64),
+            None => (0..len).try_for_each(|_| self.skip(tag, depth)),
+        }
+    }
+
+    fn skip_bytes(&mut self, len: u64) -> Result<()> {
+        let skipped = io::copy(&mut (&mut self.reader).take(len), &mut io::sink())?;
+        if skipped != len {
+            return Err(Error::IncompleteNbtValue);
+        }
+        Ok(())
+    }
+
+    /// Reads a number or string, running any matching hooks on it.
+    fn read_scalar(&mut self, tag: u8) -> Result<Scalar> {
+        let value = match tag {
+            0x01 => Scalar::Byte(self.reader.read_i8()?),
+            0x02 => Scalar::Short(self.flavor.read_i16(&mut self.reader)?),
+            0x03 => Scalar::Int(self.flavor.read_i32(&mut self.reader)?),
+            0x04 => Scalar::Long(self.flavor.read_i64(&mut self.reader)?),
+            0x05 => Scalar::Float(self.flavor.read_f32(&mut self.reader)?),
+            0x06 => Scalar::Double(self.flavor.read_f64(&mut self.reader)?),
+            0x08 => Scalar::String(self.read_string()?),
+            t => return Err(Error::UnknownTag(t)),
+        };
+        Ok(self.hooks.apply(&self.path, value))
+    }
+}
+
+impl<'a, R: io::Read> de::Deserializer for &'a mut Decoder<R> {
+    type Error = Error;
+
+    fn deserialize_struct<V>(self, name: &'static str,
+                             fields: &'static [&'static str], visitor: V)
                              -> Result<V::Value>
         where V: de::Visitor
     {
-        self.deserialize_map(visitor)
+        let tag = self.open_root()?;
+        de::Deserializer::deserialize_struct(&mut self.root(tag), name, fields, visitor)
     }
 
     fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V)
@@ -101,32 +517,175 @@ impl<'a, R: io::Read> de::Deserializer for &'a mut Decoder<R> {
         visitor.visit_unit()
     }
 
-    /// Deserialize newtype structs by their underlying types.
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V)
+    /// Deserialize newtype structs by their underlying types. An
+    /// `nbt::Value` is read as the root tag.
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V)
                                      -> Result<V::Value>
         where V: de::Visitor
     {
+        if name == VALUE {
+            let tag = self.open_root()?;
+            return de::Deserializer::deserialize_newtype_struct(&mut self.root(tag), name, visitor);
+        }
         visitor.visit_newtype_struct(self)
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
         where V: de::Visitor
     {
-        match self.reader.read_i8()? {
-            0x0a => {
-            	// Ignore the name of the compound.
-            	read_bare_string(&mut self.reader)?;
-            	visitor.visit_map(MapDecoder::new(self))
+        let tag = self.open_root()?;
+        de::Deserializer::deserialize_map(&mut self.root(tag), visitor)
+    }
+
+    /// Deserialize enums from a compound with a single entry named after the
+    /// variant. Unit variants can only be the root with `with_any_root`.
+    fn deserialize_enum<V>(self, name: &'static str,
+                           variants: &'static [&'static str], visitor: V)
+                           -> Result<V::Value>
+        where V: de::Visitor
+    {
+        let tag = self.open_root()?;
+        de::Deserializer::deserialize_enum(&mut self.root(tag), name, variants, visitor)
+    }
+
+    // Other types can only be the root with `with_any_root`.
+    forward_to_root! {
+        deserialize() deserialize_bool() deserialize_option()
+        deserialize_u8() deserialize_u16() deserialize_u32() deserialize_u64()
+        deserialize_i8() deserialize_i16() deserialize_i32() deserialize_i64()
+        deserialize_f32() deserialize_f64() deserialize_bytes() deserialize_byte_buf()
+        deserialize_ignored_any() deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_seq_fixed_size(len: usize)
+    }
+
+    forward_to_deserialize! {
+        char str string unit seq struct_field
+    }
+}
+
+/// A reader that counts the bytes read through it.
+struct Position<R> {
+    inner: R,
+    offset: u64,
+    /// Bytes read ahead of time, served before any more are read from
+    /// `inner`.
+    ahead: Vec<u8>,
+    pos: usize,
+    /// Whether to read from `inner` in blocks rather than as needed.
+    buffered: bool,
+    /// The most bytes that may be read, if there is a budget.
+    limit: Option<u64>,
+}
+
+/// How many bytes a buffered `Position` reads from its source at a time.
+const BLOCK: usize = 8192;
+
+impl<R: io::Read> Position<R> {
+    /// Reads the next `len` bytes from the source in one go, so that the
+    /// elements of an array are not each read from it separately. Only as
+    /// much is allocated as the source actually holds.
+    fn prefetch(&mut self, len: u64) -> Result<()> {
+        if let Some(limit) = self.limit {
+            if self.offset.saturating_add(len) > limit {
+                return Err(Error::SizeLimitExceeded(limit as usize));
+            }
+        }
+        self.ahead.drain(..self.pos);
+        self.pos = 0;
+        let have = self.ahead.len() as u64;
+        if len > have {
+            (&mut self.inner).take(len - have).read_to_end(&mut self.ahead)?;
+        }
+        if (self.ahead.len() as u64) < len {
+            return Err(Error::IncompleteNbtValue);
+        }
+        Ok(())
+    }
+}
+
+impl<R: io::Read> io::Read for Position<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Never read past the budget, failing once it is used up.
+        let buf = match self.limit {
+            Some(limit) if !buf.is_empty() => {
+                let left = limit.saturating_sub(self.offset);
+                if left == 0 {
+                    return Err(io::Error::other(SizeLimit(limit as usize)));
+                }
+                let len = buf.len().min(left.min(usize::MAX as u64) as usize);
+                &mut buf[..len]
             },
-            _ => Err(Error::NoRootCompound)
+            _ => buf,
+        };
+        if self.buffered && self.pos == self.ahead.len() && buf.len() < BLOCK {
+            self.ahead.resize(BLOCK, 0);
+            let n = self.inner.read(&mut self.ahead)?;
+            self.ahead.truncate(n);
+            self.pos = 0;
         }
+        let n = if self.pos < self.ahead.len() {
+            let n = (&self.ahead[self.pos..]).read(buf)?;
+            self.pos += n;
+            n
+        } else {
+            self.inner.read(buf)?
+        };
+        self.offset += n as u64;
+        Ok(n)
     }
+}
 
-    forward_to_deserialize! {
-        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char
-        str string bytes byte_buf unit seq seq_fixed_size
-        tuple_struct struct_field tuple option enum
-        ignored_any
+/// Writes a compound key as a segment of a path, quoted if it would not
+/// read back as a name.
+fn key_segment(key: &str) -> String {
+    let plain = !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || "_-+".contains(c));
+    if plain { key.to_string() } else { format!("{:?}", key) }
+}
+
+/// An iterator over the documents in a source, returned by
+/// `Decoder::into_iter`. It ends after the first error.
+pub struct StreamDecoder<R, T> {
+    decoder: Decoder<R>,
+    done: bool,
+    marker: PhantomData<T>,
+}
+
+impl<R, T> StreamDecoder<R, T> where R: io::Read {
+    /// The name of the root compound of the last document decoded.
+    pub fn root_name(&self) -> Option<&str> {
+        self.decoder.root_name()
+    }
+
+    /// Consume this iterator and return the underlying decoder.
+    pub fn into_inner(self) -> Decoder<R> {
+        self.decoder
+    }
+}
+
+impl<R, T> Iterator for StreamDecoder<R, T>
+    where R: io::Read,
+          T: de::Deserialize,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        if self.done {
+            return None;
+        }
+        self.decoder.started = false;
+        self.decoder.root_name = None;
+        match T::deserialize(&mut self.decoder) {
+            Ok(value) => Some(Ok(value)),
+            Err(Error::IncompleteNbtValue) if !self.decoder.started => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            },
+        }
     }
 }
 
@@ -134,12 +693,19 @@ impl<'a, R: io::Read> de::Deserializer for &'a mut Decoder<R> {
 struct MapDecoder<'a, R: io::Read + 'a> {
     outer: &'a mut Decoder<R>,
     tag: Option<u8>,
+    fields: &'static [&'static str],
+    /// The key of the entry being read, which is only kept for errors.
+    key: String,
 }
 
 impl<'a, R> MapDecoder<'a, R> where R: io::Read {
 
     fn new(outer: &'a mut Decoder<R>) -> Self {
-        MapDecoder { outer: outer, tag: None }
+        MapDecoder { outer: outer, tag: None, fields: &[], key: String::new() }
+    }
+
+    fn with_fields(outer: &'a mut Decoder<R>, fields: &'static [&'static str]) -> Self {
+        MapDecoder { outer: outer, tag: None, fields: fields, key: String::new() }
     }
 }
 
@@ -148,6 +714,20 @@ impl<'a, R: io::Read + 'a> de::MapVisitor for MapDecoder<'a, R> {
 
     fn visit_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
         where K: de::DeserializeSeed
+    {
+        self.read_key(seed).map_err(|e| self.outer.locate(e, String::new()))
+    }
+
+    fn visit_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+        where V: de::DeserializeSeed
+    {
+        self.read_value(seed).map_err(|e| self.outer.locate(e, key_segment(&self.key)))
+    }
+}
+
+impl<'a, R> MapDecoder<'a, R> where R: io::Read {
+    fn read_key<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+        where K: de::DeserializeSeed
     {
         let tag = self.outer.reader.read_i8()?;
 
@@ -159,20 +739,38 @@ impl<'a, R: io::Read + 'a> de::MapVisitor for MapDecoder<'a, R> {
         // Keep track of the tag so that we can decode the field correctly.
         self.tag = Some(tag as u8);
 
+        let loose = !self.fields.is_empty() && self.outer.fields != FieldMatching::Exact;
+        if loose || !self.outer.hooks.is_empty() || self.outer.context {
+            let key = self.outer.read_string()?;
+            if !self.outer.hooks.is_empty() {
+                self.outer.path.push(key.clone());
+            }
+            if self.outer.context {
+                self.key.clone_from(&key);
+            }
+            let key = if loose { self.outer.fields.resolve(key, self.fields) } else { key };
+            let de: StringDeserializer<Error> = key.into_deserializer();
+            return Ok(Some(seed.deserialize(de)?));
+        }
+
         // TODO: Enforce that keys must be String. This is a bit of a hack.
-        let mut de = InnerDecoder { outer: self.outer, tag: 0x08 };
+        let mut de = InnerDecoder { outer: self.outer, tag: 0x08, element: false };
 
         Ok(Some(seed.deserialize(&mut de)?))
     }
 
-    fn visit_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    fn read_value<V>(&mut self, seed: V) -> Result<V::Value>
         where V: de::DeserializeSeed
     {
         let mut de = match self.tag {
-            Some(tag) => InnerDecoder { outer: self.outer, tag: tag },
-            None => unimplemented!(),
+            Some(tag) => InnerDecoder { outer: self.outer, tag: tag, element: false },
+            None => return Err(de::Error::custom("a map value was read without its key")),
         };
-        Ok(seed.deserialize(&mut de)?)
+        let value = seed.deserialize(&mut de)?;
+        if !self.outer.hooks.is_empty() {
+            self.outer.path.pop();
+        }
+        Ok(value)
     }
 }
 
@@ -182,33 +780,50 @@ struct SeqDecoder<'a, R: io::Read + 'a> {
     tag: u8,
     length: i32,
     current: i32,
+    /// Whether this is a byte, int or long array rather than a list.
+    array: bool,
 }
 
 impl<'a, R> SeqDecoder<'a, R> where R: io::Read {
 
     fn list(outer: &'a mut Decoder<R>) -> Result<Self> {
         let tag = outer.reader.read_i8()?;
-        let length = outer.reader.read_i32::<BigEndian>()?;
+        let length = outer.read_len()?;
         Ok(SeqDecoder { outer: outer, tag: tag as u8, length: length,
-                        current: 0 })
+                        current: 0, array: false })
     }
 
     fn byte_array(outer: &'a mut Decoder<R>) -> Result<Self> {
-        let length = outer.reader.read_i32::<BigEndian>()?;
-        Ok(SeqDecoder { outer: outer, tag: 0x01, length: length,
-                        current: 0 })
+        Self::array(outer, 0x01)
     }
 
     fn int_array(outer: &'a mut Decoder<R>) -> Result<Self> {
-        let length = outer.reader.read_i32::<BigEndian>()?;
-        Ok(SeqDecoder { outer: outer, tag: 0x03, length: length,
-                        current: 0 })
+        Self::array(outer, 0x03)
     }
     
     fn long_array(outer: &'a mut Decoder<R>) -> Result<Self> {
-        let length = outer.reader.read_i32::<BigEndian>()?;
-        Ok(SeqDecoder { outer: outer, tag: 0x04, length: length,
-                        current: 0 })
+        Self::array(outer, 0x04)
+    }
+
+    /// Reads the length of an array of numbers with the given tag, and
+    /// then its elements all at once where they have a fixed size.
+    fn array(outer: &'a mut Decoder<R>, tag: u8) -> Result<Self> {
+        let length = outer.read_len()?;
+        if let Some(width) = outer.flavor.width(tag) {
+            outer.reader.prefetch(length as u64 * width)?;
+        }
+        Ok(SeqDecoder { outer, tag, length,
+                        current: 0, array: true })
+    }
+
+
+    /// Fails unless there are exactly `len` elements, since a visitor for a
+    /// fixed-length type would leave the rest unread.
+    fn expect_len(&self, len: usize, exp: &de::Expected) -> Result<()> {
+        if self.length as usize != len {
+            return Err(de::Error::invalid_length(self.length as usize, exp));
+        }
+        Ok(())
     }
 }
 
@@ -222,8 +837,18 @@ impl<'a, R: io::Read + 'a> de::SeqVisitor for SeqDecoder<'a, R> {
             return Ok(None);
         }
 
-        let mut de = InnerDecoder { outer: self.outer, tag: self.tag };
-        let value = seed.deserialize(&mut de)?;
+        let tracked = !self.array && !self.outer.hooks.is_empty();
+        if tracked {
+            self.outer.path.push(self.current.to_string());
+        }
+        let mut de = InnerDecoder { outer: self.outer, tag: self.tag, element: self.array };
+        let value = match seed.deserialize(&mut de) {
+            Ok(value) => value,
+            Err(e) => return Err(self.outer.locate(e, format!("[{}]", self.current))),
+        };
+        if tracked {
+            self.outer.path.pop();
+        }
 
         self.current += 1;
 
@@ -240,6 +865,75 @@ impl<'a, R: io::Read + 'a> de::SeqVisitor for SeqDecoder<'a, R> {
 struct InnerDecoder<'a, R: io::Read + 'a> {
     outer: &'a mut Decoder<R>,
     tag: u8,
+    /// Whether this is an element of an array, which hooks are not run on.
+    element: bool,
+}
+
+impl<'a, R> InnerDecoder<'a, R> where R: io::Read {
+    /// Reads a number of any kind.
+    fn read_number(&mut self, expected: Kind) -> Result<Scalar> {
+        if !matches!(self.tag, 0x01..=0x06) {
+            return match Kind::from_id(self.tag as i8) {
+                Some(kind) => Err(Error::UnexpectedTag(kind, expected)),
+                None => Err(Error::UnknownTag(self.tag)),
+            };
+        }
+
+        let outer = &mut *self.outer;
+        if !self.element && !outer.hooks.is_empty() {
+            return outer.read_scalar(self.tag);
+        }
+        Ok(match self.tag {
+            0x01 => Scalar::Byte(outer.reader.read_i8()?),
+            0x02 => Scalar::Short(outer.flavor.read_i16(&mut outer.reader)?),
+            0x03 => Scalar::Int(outer.flavor.read_i32(&mut outer.reader)?),
+            0x04 => Scalar::Long(outer.flavor.read_i64(&mut outer.reader)?),
+            0x05 => Scalar::Float(outer.flavor.read_f32(&mut outer.reader)?),
+            _ => Scalar::Double(outer.flavor.read_f64(&mut outer.reader)?),
+        })
+    }
+
+    /// Reads a number for a field whose type matches `expected`. With strict
+    /// numbers, it must be stored as that kind, or, with widening, as a
+    /// narrower kind. Returns `None` without reading anything if the tag is
+    /// not a number, which is left to the visitor to reject.
+    fn read_typed(&mut self, expected: Kind) -> Result<Option<Scalar>> {
+        if !matches!(self.tag, 0x01..=0x06) {
+            return Ok(None);
+        }
+        let value = self.read_number(expected)?;
+        let found = value.kind();
+        let allowed = found == expected || (self.outer.widening && found.widens_to(expected));
+        if self.outer.strict && !allowed {
+            return Err(Error::UnexpectedTag(found, expected));
+        }
+        Ok(Some(value))
+    }
+
+    /// Reads an integer of any width, along with the kind of its tag.
+    fn read_integer(&mut self, expected: Kind) -> Result<(Kind, i64)> {
+        match self.read_number(expected)? {
+            Scalar::Byte(v) => Ok((Kind::I8, i64::from(v))),
+            Scalar::Short(v) => Ok((Kind::I16, i64::from(v))),
+            Scalar::Int(v) => Ok((Kind::I32, i64::from(v))),
+            Scalar::Long(v) => Ok((Kind::I64, v)),
+            other => Err(Error::UnexpectedTag(other.kind(), expected)),
+        }
+    }
+
+    /// Reads an unsigned integer no larger than `max`, whose signed
+    /// counterpart is `kind`.
+    fn read_unsigned(&mut self, kind: Kind, max: u64) -> Result<u64> {
+        let (found, value) = self.read_integer(kind)?;
+        if found == kind && self.outer.unsigned == Unsigned::Reinterpret {
+            return Ok(value as u64 & max);
+        }
+        if value < 0 || value as u64 > max {
+            return Err(de::Error::invalid_value(de::Unexpected::Signed(value),
+                                                &"an unsigned integer in range"));
+        }
+        Ok(value as u64)
+    }
 }
 
 impl<'a, 'b: 'a, R: io::Read> de::Deserializer for &'b mut InnerDecoder<'a, R> {
@@ -250,17 +944,21 @@ impl<'a, 'b: 'a, R: io::Read> de::Deserializer for &'b mut InnerDecoder<'a, R> {
     {
         let ref mut outer = self.outer;
 
+        if matches!(self.tag, 0x01..=0x06 | 0x08) && !self.element && !outer.hooks.is_empty() {
+            return outer.read_scalar(self.tag)?.visit(visitor);
+        }
+
         match self.tag {
             0x01 => visitor.visit_i8 (outer.reader.read_i8()?),
-            0x02 => visitor.visit_i16(outer.reader.read_i16::<BigEndian>()?),
-            0x03 => visitor.visit_i32(outer.reader.read_i32::<BigEndian>()?),
-            0x04 => visitor.visit_i64(outer.reader.read_i64::<BigEndian>()?),
-            0x05 => visitor.visit_f32(outer.reader.read_f32::<BigEndian>()?),
-            0x06 => visitor.visit_f64(outer.reader.read_f64::<BigEndian>()?),
+            0x02 => visitor.visit_i16(outer.flavor.read_i16(&mut outer.reader)?),
+            0x03 => visitor.visit_i32(outer.flavor.read_i32(&mut outer.reader)?),
+            0x04 => visitor.visit_i64(outer.flavor.read_i64(&mut outer.reader)?),
+            0x05 => visitor.visit_f32(outer.flavor.read_f32(&mut outer.reader)?),
+            0x06 => visitor.visit_f64(outer.flavor.read_f64(&mut outer.reader)?),
             0x07 => visitor.visit_seq(SeqDecoder::byte_array(outer)?),
-            0x08 => visitor.visit_string(read_bare_string(&mut outer.reader)?),
-            0x09 => visitor.visit_seq(SeqDecoder::list(outer)?),
-            0x0a => visitor.visit_map(MapDecoder::new(outer)),
+            0x08 => outer.visit_string(visitor),
+            0x09 => outer.nested(|outer| visitor.visit_seq(SeqDecoder::list(outer)?)),
+            0x0a => outer.nested(|outer| visitor.visit_map(MapDecoder::new(outer))),
             0x0b => visitor.visit_seq(SeqDecoder::int_array(outer)?),
             0x0c => visitor.visit_seq(SeqDecoder::long_array(outer)?),
             t => Err(Error::UnknownTag(t)),
@@ -273,12 +971,11 @@ impl<'a, 'b: 'a, R: io::Read> de::Deserializer for &'b mut InnerDecoder<'a, R> {
     {
         match self.tag {
             0x01 => {
-                let ref mut reader = self.outer.reader;
-                let value = reader.read_i8()?;
-                match value {
-                    0 => visitor.visit_bool(false),
-                    1 => visitor.visit_bool(true),
-                    b => Err(Error::NonBooleanByte(b)),
+                match self.outer.read_scalar(0x01)? {
+                    Scalar::Byte(0) => visitor.visit_bool(false),
+                    Scalar::Byte(1) => visitor.visit_bool(true),
+                    Scalar::Byte(b) => Err(Error::NonBooleanByte(b)),
+                    other => Err(Error::UnexpectedTag(other.kind(), Kind::I8)),
                 }
             },
             _ => match Kind::from_id(self.tag as i8) {
@@ -295,6 +992,106 @@ impl<'a, 'b: 'a, R: io::Read> de::Deserializer for &'b mut InnerDecoder<'a, R> {
         visitor.visit_some(self)
     }
 
+    /// Deserialize signed numbers from any number tag, or only the tag
+    /// matching their type and, with widening, narrower ones if the decoder
+    /// is strict.
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.read_typed(Kind::I8)? {
+            Some(Scalar::Byte(v)) => visitor.visit_i8(v),
+            Some(other) => other.visit(visitor),
+            None => self.deserialize(visitor),
+        }
+    }
+
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.read_typed(Kind::I16)? {
+            Some(Scalar::Byte(v)) => visitor.visit_i16(i16::from(v)),
+            Some(other) => other.visit(visitor),
+            None => self.deserialize(visitor),
+        }
+    }
+
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.read_typed(Kind::I32)? {
+            Some(Scalar::Byte(v)) => visitor.visit_i32(i32::from(v)),
+            Some(Scalar::Short(v)) => visitor.visit_i32(i32::from(v)),
+            Some(other) => other.visit(visitor),
+            None => self.deserialize(visitor),
+        }
+    }
+
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.read_typed(Kind::I64)? {
+            Some(Scalar::Byte(v)) => visitor.visit_i64(i64::from(v)),
+            Some(Scalar::Short(v)) => visitor.visit_i64(i64::from(v)),
+            Some(Scalar::Int(v)) => visitor.visit_i64(i64::from(v)),
+            Some(other) => other.visit(visitor),
+            None => self.deserialize(visitor),
+        }
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.read_typed(Kind::F32)? {
+            Some(Scalar::Byte(v)) => visitor.visit_f32(f32::from(v)),
+            Some(Scalar::Short(v)) => visitor.visit_f32(f32::from(v)),
+            Some(other) => other.visit(visitor),
+            None => self.deserialize(visitor),
+        }
+    }
+
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.read_typed(Kind::F64)? {
+            Some(Scalar::Byte(v)) => visitor.visit_f64(f64::from(v)),
+            Some(Scalar::Short(v)) => visitor.visit_f64(f64::from(v)),
+            Some(Scalar::Int(v)) => visitor.visit_f64(f64::from(v)),
+            Some(Scalar::Float(v)) => visitor.visit_f64(f64::from(v)),
+            Some(other) => other.visit(visitor),
+            None => self.deserialize(visitor),
+        }
+    }
+
+    /// Deserialize unsigned integers from a signed tag, according to the
+    /// decoder's `Unsigned` policy.
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        let value = self.read_unsigned(Kind::I8, u64::from(u8::MAX))?;
+        visitor.visit_u8(value as u8)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        let value = self.read_unsigned(Kind::I16, u64::from(u16::MAX))?;
+        visitor.visit_u16(value as u16)
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        let value = self.read_unsigned(Kind::I32, u64::from(u32::MAX))?;
+        visitor.visit_u32(value as u32)
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        let value = self.read_unsigned(Kind::I64, u64::MAX)?;
+        visitor.visit_u64(value)
+    }
+
     fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
         where V: de::Visitor
     {
@@ -308,18 +1105,278 @@ impl<'a, 'b: 'a, R: io::Read> de::Deserializer for &'b mut InnerDecoder<'a, R> {
         visitor.visit_unit()
     }
 
-    /// Deserialize newtype structs by their underlying types.
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V)
+    /// Deserialize newtype structs by their underlying types. An
+    /// `nbt::Value` is told the type of the tag, so that it can tell arrays
+    /// from lists.
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V)
                                      -> Result<V::Value>
         where V: de::Visitor
     {
+        if name == VALUE {
+            return visitor.visit_enum(TaggedValue { inner: self });
+        }
         visitor.visit_newtype_struct(self)
     }
 
+    fn deserialize_struct<V>(self, _name: &'static str,
+                             fields: &'static [&'static str], visitor: V)
+                             -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.tag {
+            0x0a => self.outer.nested(|outer| visitor.visit_map(MapDecoder::with_fields(outer, fields))),
+            _ => self.deserialize(visitor),
+        }
+    }
+
+    /// Deserialize byte arrays in one read, rather than byte by byte.
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        if self.tag != 0x07 {
+            return self.deserialize(visitor);
+        }
+        let outer = &mut *self.outer;
+        let len = outer.read_len()?;
+        // Read as much as there is rather than trusting the length with an
+        // allocation up front.
+        let mut bytes = Vec::new();
+        (&mut outer.reader).take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len as usize {
+            return Err(Error::IncompleteNbtValue);
+        }
+        visitor.visit_byte_buf(bytes)
+    }
+
+    /// Deserialize unit variants from a string naming them, and other
+    /// variants from a compound with a single entry named after the variant.
+    fn deserialize_enum<V>(self, _name: &'static str,
+                           _variants: &'static [&'static str], visitor: V)
+                           -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.tag {
+            0x08 => visitor.visit_enum(UnitVariant { name: self.outer.read_string()? }),
+            0x0a => self.outer.nested(|outer| visitor.visit_enum(VariantDecoder { outer, tag: 0 })),
+            t => match Kind::from_id(t as i8) {
+                Some(kind) => Err(Error::UnexpectedTag(kind, Kind::Compound)),
+                None => Err(Error::UnknownTag(t)),
+            }
+        }
+    }
+
+    /// Skip values that are not wanted, such as unknown fields, without
+    /// decoding them.
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        self.outer.skip(self.tag, self.outer.depth)?;
+        visitor.visit_unit()
+    }
+
+    /// Deserialize tuples and fixed-size arrays from lists or arrays of
+    /// exactly the right length.
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        let tag = self.tag;
+        let visit = |outer: &mut Decoder<R>| {
+            let seq = match tag {
+                0x07 => SeqDecoder::byte_array(outer)?,
+                0x09 => SeqDecoder::list(outer)?,
+                0x0b => SeqDecoder::int_array(outer)?,
+                0x0c => SeqDecoder::long_array(outer)?,
+                t => return match Kind::from_id(t as i8) {
+                    Some(kind) => Err(Error::UnexpectedTag(kind, Kind::List)),
+                    None => Err(Error::UnknownTag(t)),
+                },
+            };
+            seq.expect_len(len, &visitor)?;
+            visitor.visit_seq(seq)
+        };
+        match tag {
+            0x09 => self.outer.nested(visit),
+            _ => visit(self.outer),
+        }
+    }
+
+    fn deserialize_tuple_struct<V>(self, _name: &'static str, len: usize, visitor: V)
+                                   -> Result<V::Value>
+        where V: de::Visitor
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_seq_fixed_size<V>(self, len: usize, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
     forward_to_deserialize! {
-        u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char
-        str string bytes byte_buf seq seq_fixed_size map
-        tuple_struct struct struct_field tuple enum
-        ignored_any
+        char str string seq map struct_field
+    }
+}
+
+/// Decoder for a tag read into an `nbt::Value`, described as a variant named
+/// by the tag's type ID and holding its payload.
+struct TaggedValue<'a, R: io::Read + 'a> {
+    inner: &'a mut InnerDecoder<'a, R>,
+}
+
+impl<'a, R: io::Read + 'a> de::EnumVisitor for TaggedValue<'a, R> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn visit_variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+        where V: de::DeserializeSeed
+    {
+        let de: U8Deserializer<Error> = self.inner.tag.into_deserializer();
+        Ok((seed.deserialize(de)?, self))
+    }
+}
+
+impl<'a, R: io::Read + 'a> de::VariantVisitor for TaggedValue<'a, R> {
+    type Error = Error;
+
+    fn visit_unit(self) -> Result<()> {
+        Err(Error::UnrepresentableType("unit variant"))
+    }
+
+    fn visit_newtype_seed<T>(self, seed: T) -> Result<T::Value>
+        where T: de::DeserializeSeed
+    {
+        seed.deserialize(self.inner)
+    }
+
+    fn visit_tuple<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        Err(Error::UnrepresentableType("tuple variant"))
+    }
+
+    fn visit_struct<V>(self, _fields: &'static [&'static str], _visitor: V)
+                       -> Result<V::Value>
+        where V: de::Visitor
+    {
+        Err(Error::UnrepresentableType("struct variant"))
+    }
+}
+
+/// Decoder for a unit variant, stored as a string.
+struct UnitVariant {
+    name: String,
+}
+
+impl de::EnumVisitor for UnitVariant {
+    type Error = Error;
+    type Variant = Self;
+
+    fn visit_variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+        where V: de::DeserializeSeed
+    {
+        let de: StringDeserializer<Error> = self.name.clone().into_deserializer();
+        Ok((seed.deserialize(de)?, self))
+    }
+}
+
+impl de::VariantVisitor for UnitVariant {
+    type Error = Error;
+
+    fn visit_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn visit_newtype_seed<T>(self, _seed: T) -> Result<T::Value>
+        where T: de::DeserializeSeed
+    {
+        Err(Error::UnexpectedTag(Kind::String, Kind::Compound))
+    }
+
+    fn visit_tuple<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        Err(Error::UnexpectedTag(Kind::String, Kind::Compound))
+    }
+
+    fn visit_struct<V>(self, _fields: &'static [&'static str], _visitor: V)
+                       -> Result<V::Value>
+        where V: de::Visitor
+    {
+        Err(Error::UnexpectedTag(Kind::String, Kind::Compound))
+    }
+}
+
+/// Decoder for a newtype or struct variant, stored as a compound whose only
+/// entry is named after the variant.
+struct VariantDecoder<'a, R: io::Read + 'a> {
+    outer: &'a mut Decoder<R>,
+    /// The tag of the entry, once its name has been read.
+    tag: u8,
+}
+
+impl<'a, R> VariantDecoder<'a, R> where R: io::Read {
+    /// Reads the end of the compound, which must follow its one entry.
+    fn end(self) -> Result<()> {
+        match self.outer.reader.read_i8()? {
+            0x00 => Ok(()),
+            _ => Err(Error::InvalidVariant),
+        }
+    }
+}
+
+impl<'a, R: io::Read + 'a> de::EnumVisitor for VariantDecoder<'a, R> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn visit_variant_seed<V>(mut self, seed: V) -> Result<(V::Value, Self)>
+        where V: de::DeserializeSeed
+    {
+        self.tag = match self.outer.reader.read_i8()? {
+            0x00 => return Err(Error::InvalidVariant),
+            tag => tag as u8,
+        };
+        let value = seed.deserialize(&mut InnerDecoder { outer: self.outer, tag: 0x08, element: false })?;
+        Ok((value, self))
+    }
+}
+
+impl<'a, R: io::Read + 'a> de::VariantVisitor for VariantDecoder<'a, R> {
+    type Error = Error;
+
+    fn visit_unit(self) -> Result<()> {
+        Err(Error::UnexpectedTag(Kind::Compound, Kind::String))
+    }
+
+    fn visit_newtype_seed<T>(self, seed: T) -> Result<T::Value>
+        where T: de::DeserializeSeed
+    {
+        let value = seed.deserialize(&mut InnerDecoder { outer: self.outer, tag: self.tag, element: false })?;
+        self.end()?;
+        Ok(value)
+    }
+
+    fn visit_tuple<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        Err(Error::UnrepresentableType("tuple variant"))
+    }
+
+    fn visit_struct<V>(self, fields: &'static [&'static str], visitor: V)
+                       -> Result<V::Value>
+        where V: de::Visitor
+    {
+        let value = {
+            let mut de = InnerDecoder { outer: self.outer, tag: self.tag, element: false };
+            de::Deserializer::deserialize_struct(&mut de, "", fields, visitor)?
+        };
+        self.end()?;
+        Ok(value)
     }
 }
@@ -1,38 +1,49 @@
-use std::io;
+use std::borrow::Cow;
+use std::io::{self, Write};
 
 use serde;
 use serde::ser;
 
-use byteorder::{BigEndian, WriteBytesExt};
+use byteorder::WriteBytesExt;
+use flate2::write;
 
-use error::{Error, Result};
+pub use flate2::Compression;
+
+use arrays;
+use error::{Error, Result, SizeLimit};
+use flavor::Flavor;
 use kind::Kind;
+use strings::{StringCodec, Utf8};
 
 enum LevelState {
 	/// Writing a Compound at this level.
-	InNamed { name: Option<String> },
+	InNamed { name: Option<Cow<'static, str>> },
 	/// Writing a List/Array at this level.
 	InList  { kind: Kind },
 	/// A list is about to be written at this level.
 	/// Whether name is None or Some specifies whether it is in a Named or List.
-	List    { name: Option<String>, len: i32 }
+	/// `forced` is the container chosen by a wrapper from `arrays`, if any.
+	List    { name: Option<Cow<'static, str>>, len: i32, forced: Option<Kind> }
 }
 
 impl LevelState {
-	fn open_list(self, len: i32) -> (Self, Result<Option<LevelState>>) {
+	fn open_list(self, len: i32, forced: Option<Kind>) -> (Self, Result<Option<LevelState>>) {
 		match self {
-			LevelState::InNamed { name } => {
-				(LevelState::List { name: Some(name.expect("Key name not specified before value")), len }, Ok(None))
+			LevelState::InNamed { name: Some(name) } => {
+				(LevelState::List { name: Some(name), len, forced }, Ok(None))
+			},
+			LevelState::InNamed { name: None } => {
+				(self, Err(Error::InvalidWrite(MISSING_KEY)))
 			},
 			LevelState::InList { kind } => {
 				if kind.is_list() {
-					(LevelState::List { name: None, len }, Ok(None))
+					(self, Ok(Some(LevelState::List { name: None, len, forced })))
 				} else {
 					(self, Err(Error::HeterogenousList { original: kind, new: Kind::List }))
 				}
 			},
 			LevelState::List { .. } => {
-				(self, Ok(Some(LevelState::List { name: None, len })))
+				(self, Ok(Some(LevelState::List { name: None, len, forced })))
 			}
 		}
 	}
@@ -45,16 +56,99 @@ impl LevelState {
 	}
 }
 
-// TODO: Replace with a Trait on Write.
-#[inline]
-fn write_bare_string<W>(dst: &mut W, value: &str) -> Result<()> where W: io::Write
-{    
-    dst.write_u16::<BigEndian>(value.len() as u16)?;
-    dst.write_all(value.as_bytes()).map_err(From::from)
+// Descriptions of the mistakes a `Serialize` implementation can make.
+pub(crate) const MISSING_KEY: &str = "a map value was given without a key";
+const MISSING_VALUE: &str = "a map key was given without a value";
+const KEY_TWICE: &str = "a map key was given twice";
+const KEY_IN_LIST: &str = "a map key was given for a list element";
+const FINISHED: &str = "the root has already been written";
+
+/// Converts the length of a list or array to the `i32` it is written as,
+/// failing if it does not fit.
+fn list_len(len: usize) -> Result<i32> {
+    if len > i32::MAX as usize {
+        return Err(Error::ListTooLong(len));
+    }
+    Ok(len as i32)
+}
+
+/// How many bytes `Counter` collects before passing them on.
+const BLOCK: usize = 8192;
+
+/// A writer that counts the bytes passing through it, and fails without writing
+/// anything once they would exceed an optional limit.
+///
+/// Bytes are collected in blocks before being passed on, so that the many
+/// small writes of an encoding do not each reach the underlying writer.
+/// While a capture is open, bytes are kept in it instead, to be passed on
+/// later with `release`.
+struct Counter<W> {
+    inner: W,
+    written: usize,
+    limit: Option<usize>,
+    buf: Vec<u8>,
+    captures: Vec<Vec<u8>>,
+}
+
+impl<W: io::Write> Counter<W> {
+    /// Passes on the bytes collected so far.
+    fn write_buffered(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.inner.write_all(&self.buf)?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+
+    /// Keeps the bytes written from now on until `end_capture`.
+    fn begin_capture(&mut self) {
+        self.captures.push(Vec::new());
+    }
+
+    /// Returns the bytes kept since the last `begin_capture`.
+    fn end_capture(&mut self) -> Vec<u8> {
+        self.captures.pop().unwrap_or_default()
+    }
+
+    /// Passes on bytes that have already been counted, such as those of an
+    /// ended capture.
+    fn release(&mut self, buf: &[u8]) -> io::Result<()> {
+        if let Some(capture) = self.captures.last_mut() {
+            capture.extend_from_slice(buf);
+            return Ok(());
+        }
+        if self.buf.len() + buf.len() > BLOCK {
+            self.write_buffered()?;
+        }
+        if buf.len() >= BLOCK {
+            self.inner.write_all(buf)
+        } else {
+            self.buf.extend_from_slice(buf);
+            Ok(())
+        }
+    }
+}
+
+impl<W: io::Write> io::Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(limit) = self.limit {
+            if self.written + buf.len() > limit {
+                return Err(io::Error::other(SizeLimit(limit)));
+            }
+        }
+        self.release(buf)?;
+        self.written += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_buffered()?;
+        self.inner.flush()
+    }
 }
 
 /// Encode `value` in Named Binary Tag format to the given `io::Write`
-/// destination, with an optional header.
+/// destination, with an optional header naming the root compound.
 #[inline]
 pub fn to_writer<W, T>(dst: &mut W, value: &T, header: Option<String>)
                            -> Result<()>
@@ -65,15 +159,96 @@ pub fn to_writer<W, T>(dst: &mut W, value: &T, header: Option<String>)
     value.serialize(&mut encoder)
 }
 
+/// Encode `value` in Named Binary Tag format, with an optional header,
+/// returning the bytes.
+pub fn to_vec<T>(value: &T, header: Option<String>) -> Result<Vec<u8>>
+    where T: ?Sized + ser::Serialize,
+{
+    let mut dst = Vec::new();
+    to_writer(&mut dst, value, header)?;
+    Ok(dst)
+}
+
+/// Encode `value` in Named Binary Tag format to the given `io::Write`
+/// destination, with an optional header, compressed with gzip at the given
+/// level. Player and level `.dat` files are stored this way.
+pub fn to_gzip_writer<W, T>(dst: &mut W, value: &T, header: Option<String>,
+                            level: Compression) -> Result<()>
+    where W: ?Sized + io::Write,
+          T: ?Sized + ser::Serialize,
+{
+    let mut gzip = write::GzEncoder::new(dst, level);
+    to_writer(&mut gzip, value, header)?;
+    gzip.finish()?;
+    Ok(())
+}
+
+/// Encode `value` in Named Binary Tag format to the given `io::Write`
+/// destination, with an optional header, compressed with zlib at the given
+/// level. Chunks in region files are stored this way.
+pub fn to_zlib_writer<W, T>(dst: &mut W, value: &T, header: Option<String>,
+                            level: Compression) -> Result<()>
+    where W: ?Sized + io::Write,
+          T: ?Sized + ser::Serialize,
+{
+    let mut zlib = write::ZlibEncoder::new(dst, level);
+    to_writer(&mut zlib, value, header)?;
+    zlib.finish()?;
+    Ok(())
+}
+
+/// How unsigned integers are written, since NBT only has signed tags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unsigned {
+    /// Write the bits of the value as the signed tag of the same width, so
+    /// that a `u8` of 200 becomes a `Tag_Byte` of -56. This is the default,
+    /// and is how Bedrock Edition and many community formats store them.
+    Reinterpret,
+    /// Write the value as the signed tag of the next width up, so that a `u8`
+    /// becomes a `Tag_Short`. A `u64` is written as a `Tag_Long`, and fails
+    /// if it is too large for one.
+    Widen,
+    /// Fail with `Error::UnrepresentableType`. When decoding, this and
+    /// `Widen` both accept any integer tag whose value fits.
+    Reject,
+}
+
 /// Encode objects to Named Binary Tag format.
 ///
 /// This structure can be used to serialize objects which implement the
 /// `serde::Serialize` trait into NBT format. Note that not all types are
 /// representable in NBT format (notably unsigned integers), so this encoder may
 /// return errors.
+///
+/// Enums are written with the variant name outside the value: unit variants
+/// as a `Tag_String` naming the variant, and newtype and struct variants as a
+/// `Tag_Compound` with a single entry named after the variant. Tuple variants
+/// cannot be represented.
 pub struct Encoder<W> {
-    writer: W,
+    writer: Counter<W>,
     states: Vec<LevelState>,
+    strings: Box<dyn StringCodec>,
+    flavor: Flavor,
+    /// The encoded elements of the array or list of numbers being written,
+    /// which are written out in one block when it is closed.
+    pending: Vec<u8>,
+    unsigned: Unsigned,
+    /// The container forced on the next list by a wrapper from `arrays`.
+    forced: Option<Kind>,
+    /// Whether the name of the root, which has not been written yet, is to
+    /// be left out.
+    nameless_root: bool,
+    any_root: bool,
+    /// The entries of each open compound so far, when they are written
+    /// sorted by name, or `None` when they are written as they come.
+    sorting: Option<Vec<Sorting>>,
+}
+
+/// The entries of a compound whose entries are written sorted by name.
+struct Sorting {
+    entries: Vec<(Cow<'static, str>, Vec<u8>)>,
+    /// The name of the entry being captured, if any.
+    current: Option<Cow<'static, str>>,
 }
 
 impl<W> Encoder<W> where W: io::Write {
@@ -81,15 +256,106 @@ impl<W> Encoder<W> where W: io::Write {
     /// Create an encoder with optional `header` from a given Writer.
     pub fn new(writer: W, header: Option<String>) -> Self {
     	let mut states = Vec::with_capacity(32);
-    	states.push(LevelState::InNamed { name: Some(header.unwrap_or_else(|| "".to_string())) });
+    	states.push(LevelState::InNamed { name: Some(Cow::Owned(header.unwrap_or_default())) });
     	
-        Encoder { writer, states }
+        let writer = Counter { inner: writer, written: 0, limit: None, buf: Vec::new(),
+                               captures: Vec::new() };
+        Encoder { writer, states, strings: Box::new(Utf8), flavor: Flavor::Java, pending: Vec::new(),
+                  unsigned: Unsigned::Reinterpret, forced: None, nameless_root: false,
+                  any_root: false, sorting: None }
+    }
+
+    /// Use the given codec to encode strings and compound keys, instead of
+    /// UTF-8.
+    pub fn with_string_codec<S>(mut self, codec: S) -> Self
+        where S: StringCodec + 'static
+    {
+        self.strings = Box::new(codec);
+        self
+    }
+
+    /// Lay out numbers and lengths as in the given flavor, such as
+    /// `Flavor::BedrockNetwork` for Bedrock Edition packets, instead of
+    /// Java Edition's big-endian layout.
+    pub fn with_flavor(mut self, flavor: Flavor) -> Self {
+        self.flavor = flavor;
+        self
+    }
+
+    /// Write unsigned integers according to `policy` instead of
+    /// reinterpreting them as signed.
+    pub fn with_unsigned(mut self, policy: Unsigned) -> Self {
+        self.unsigned = policy;
+        self
+    }
+
+    /// Write the root compound without a name, so that its type ID is
+    /// followed directly by its payload, as in the Java Edition network
+    /// protocol since 1.20.2. The header is ignored.
+    pub fn with_nameless_root(mut self, nameless: bool) -> Self {
+        self.nameless_root = nameless;
+        self
+    }
+
+    /// Allow the root to be any tag, such as the bare string or int network
+    /// NBT may carry, rather than only a compound. Other types are otherwise
+    /// refused with `Error::NoRootCompound`.
+    pub fn with_any_root(mut self, any: bool) -> Self {
+        self.any_root = any;
+        self
+    }
+
+    /// Write the entries of every compound sorted by name, rather than in the
+    /// order they are serialized, so that maps such as `HashMap` give the same
+    /// bytes every time. Each compound is held in memory until it is closed.
+    ///
+    /// This does not restore the order a file was read in, since
+    /// `nbt::Value` compounds are `HashMap`s, so re-encoding a decoded file
+    /// is byte-identical only if its compounds were sorted to begin with.
+    pub fn with_sorted_keys(mut self) -> Self {
+        self.sorting = Some(Vec::new());
+        self
+    }
+
+    /// Abort encoding with `Error::SizeLimitExceeded` as soon as the output
+    /// would grow beyond `limit` bytes, such as the 2 MiB limit on NBT sent
+    /// over the network. Nothing past the limit is written.
+    pub fn with_size_limit(mut self, limit: usize) -> Self {
+        self.writer.limit = Some(limit);
+        self
+    }
+
+    /// The number of bytes written so far.
+    pub fn written(&self) -> usize {
+        self.writer.written
     }
 
     /// Consume this encoder and return the underlying writer.
+    ///
+    /// Output is collected in blocks and passed on to the writer once the
+    /// root is written, so nothing of an unfinished document may have
+    /// reached it yet.
     #[inline]
     pub fn into_inner(self) -> W {
-        self.writer
+        self.writer.inner
+    }
+
+    /// An encoder for a root that is not a compound, if any root is allowed.
+    fn bare(&mut self) -> Result<InnerEncoder<'_, W>> {
+        if !self.any_root {
+            return Err(Error::NoRootCompound);
+        }
+        Ok(InnerEncoder { outer: self })
+    }
+
+    /// Write a string, prefixed by its encoded length.
+    fn write_string(&mut self, value: &str) -> Result<()> {
+        let bytes = self.strings.encode(value)?;
+        if bytes.len() > self.flavor.max_string_len() {
+            return Err(Error::StringTooLong(bytes.len()));
+        }
+        self.flavor.write_string_len(&mut self.writer, bytes.len())?;
+        self.writer.write_all(&bytes).map_err(From::from)
     }
 
     /// Write the NBT tag and an optional header to the underlying writer.
@@ -97,64 +363,104 @@ impl<W> Encoder<W> where W: io::Write {
     fn write_header(&mut self, tag: i8, header: Option<&str>) -> Result<()> {
         self.writer.write_i8(tag)?;
         match header {
-            None    => self.writer.write_i16::<BigEndian>(0).map_err(From::from),
-            Some(h) => write_bare_string(&mut self.writer, h).map_err(From::from)
+            None    => self.flavor.write_string_len(&mut self.writer, 0).map_err(From::from),
+            Some(h) => self.write_string(h)
         }
     }
     
-    /// Specifies a kind at this level.
+    /// Whether a number is a further element of an array or list of numbers
+    /// of the same kind, whose header has already been written, so that it
+    /// should be buffered in `pending`.
+    #[inline]
+    fn is_buffered(&self, tag: Kind) -> bool {
+        match self.states.last() {
+            Some(&LevelState::InList { kind }) => kind == tag,
+            _ => false,
+        }
+    }
+
+    /// Takes the state of this level.
+    fn pop_state(&mut self) -> Result<LevelState> {
+        self.states.pop().ok_or(Error::InvalidWrite(FINISHED))
+    }
+
+    /// Specifies a kind at this level. Lists are opened with `open_list`
+    /// instead.
     fn specify_kind(&mut self, tag: Kind) -> Result<()> {
-    	if tag.is_list() {
-    		panic!("Encoder::specify_kind called with List, use Encoder::open_list instead.");
-    	}
-    	
-    	let replacement = match self.states.pop().unwrap() {
-    		LevelState::InNamed { name: None }           => panic!("value specified without key name"),
-    		LevelState::InNamed { name: Some(ref name) } => {
-    			self.writer.write_i8(tag.to_id())?;
-    			write_bare_string(&mut self.writer, name).map_err(Error::from)?;
-    			
-    			self.states.push(LevelState::InNamed { name: None });
-    		},
-    		LevelState::InList { kind } => {
-    			if kind != tag {
-    				return Err(Error::HeterogenousList { original: kind, new: tag } )
-    			} else {
-    				self.states.push(LevelState::InList { kind });
-    			}
-    		},
-    		LevelState::List { ref name, len } => {
-    			match *name {
-    				Some(ref name) => {
-    					let container = tag.list_container();
-    					
-    					self.writer.write_i8(container.to_id())?;
-		    			write_bare_string(&mut self.writer, name).map_err(Error::from)?;
-    					if container == Kind::List {
-    						self.writer.write_i8(tag.to_id())?;
-    					}
-    					self.writer.write_i32::<BigEndian>(len).map_err(Error::from)?;
-    					
-    					self.states.push(LevelState::InNamed { name: None });
-    					self.states.push(LevelState::InList { kind: tag });
-    				},
-    				None => {
-    					// name = None replaced with InList, have to propagate change up the stack, child is InList { tag }
-    					unimplemented!()
-    				}
-    			}
-    		}
-    	};
-    	
-    	if tag == Kind::Compound {
-    		self.states.push(LevelState::InNamed { name: None });
-    	}
-    	
-    	Ok(())
+        if tag.is_list() {
+            return Err(Error::InvalidWrite("a list was written as a single value"));
+        }
+
+        self.specify_element(tag)?;
+
+        if tag == Kind::Compound {
+            self.states.push(LevelState::InNamed { name: None });
+            if let Some(ref mut sorting) = self.sorting {
+                sorting.push(Sorting { entries: Vec::new(), current: None });
+            }
+        }
+
+        Ok(())
     }
-    
-    fn open_list(&mut self, len: i32) -> Result<()> {
-    	let (push1, push2) = self.states.pop().unwrap().open_list(len);
+
+    /// Specifies the kind of the next value at this level, writing the
+    /// headers of any lists that were waiting to learn their element kind.
+    /// Lists are given as the kind of their container.
+    fn specify_element(&mut self, tag: Kind) -> Result<()> {
+        match self.pop_state()? {
+            LevelState::InNamed { name: None } => return Err(Error::InvalidWrite(MISSING_KEY)),
+            LevelState::InNamed { name: Some(ref name) } => {
+                self.writer.write_i8(tag.to_id())?;
+                if self.nameless_root {
+                    self.nameless_root = false;
+                } else {
+                    self.write_string(name)?;
+                }
+
+                self.states.push(LevelState::InNamed { name: None });
+            },
+            LevelState::InList { kind } => {
+                if kind != tag {
+                    return Err(Error::HeterogenousList { original: kind, new: tag });
+                }
+                self.states.push(LevelState::InList { kind });
+            },
+            LevelState::List { name, len, forced } => {
+                let container = match (forced, &name, self.states.last()) {
+                    (Some(Kind::List), _, _) => Kind::List,
+                    // An empty array can be of any kind.
+                    (Some(array), _, _) if tag == Kind::End || tag.list_container() == array => array,
+                    (Some(array), _, _) => return Err(Error::UnexpectedTag(tag.list_container(), array)),
+                    // Inside a list of lists, any list will do, so numbers
+                    // are written as a list rather than an array.
+                    (None, &None, Some(&LevelState::InList { kind: Kind::List })) => Kind::List,
+                    // An empty list takes the kind of its siblings.
+                    (None, &None, Some(&LevelState::InList { kind })) if tag == Kind::End => kind,
+                    _ => tag.list_container(),
+                };
+
+                // Write the header of the enclosing entry or list first.
+                if let Some(name) = name {
+                    self.states.push(LevelState::InNamed { name: Some(name) });
+                }
+                self.specify_element(container)?;
+
+                if container == Kind::List {
+                    self.writer.write_i8(tag.to_id())?;
+                }
+                self.flavor.write_len(&mut self.writer, len).map_err(Error::from)?;
+
+                self.states.push(LevelState::InList { kind: tag });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn open_list(&mut self, len: usize) -> Result<()> {
+    	let len = list_len(len)?;
+    	let forced = self.forced.take();
+    	let (push1, push2) = self.pop_state()?.open_list(len, forced);
     	let push2 = push2?;
     	
     	self.states.push(push1);
@@ -165,47 +471,124 @@ impl<W> Encoder<W> where W: io::Write {
     	Ok(())
     }
     
-    /// Specifies the name at this level, only for InNamed.
-    fn specify_name(&mut self, name: String) -> Result<()> {
-    	match self.states.last_mut().unwrap() {
-    		&mut LevelState::InNamed { name: ref mut current_name } => {
+    /// Specifies the name at this level, only for InNamed. Struct fields and
+    /// variants are borrowed, so that only map keys cost an allocation.
+    fn specify_name(&mut self, name: Cow<'static, str>) -> Result<()> {
+    	match self.states.last_mut() {
+    		Some(&mut LevelState::InNamed { name: ref mut current_name }) => {
     			if current_name.is_none() {
     				*current_name = Some(name);
+    				if self.sorting.is_some() {
+    					let name = current_name.clone();
+    					self.capture_entry(name);
+    				}
     				Ok(())
     			} else {
-    				panic!("key name specified twice")
+    				Err(Error::InvalidWrite(KEY_TWICE))
     			}
     		},
-    		_ => panic!("key name specified while in a list")
+    		Some(_) => Err(Error::InvalidWrite(KEY_IN_LIST)),
+    		None => Err(Error::InvalidWrite(FINISHED)),
     	}
     }
     
     fn cancel_name(&mut self) -> Result<()> {
-    	match self.states.last_mut().unwrap() {
-    		&mut LevelState::InNamed { name: ref mut current_name } => *current_name = None,
-    		_ => ()
-    	};
+    	if let Some(&mut LevelState::InNamed { name: ref mut current_name }) = self.states.last_mut() {
+    		*current_name = None;
+    	}
     	
     	Ok(())
     }
     
+    /// Starts capturing the entry named `name` of the compound being
+    /// written, if its entries are sorted, keeping what was captured of the
+    /// previous one. `None` only ends the previous entry.
+    fn capture_entry(&mut self, name: Option<Cow<'static, str>>) {
+        let sorting = match self.sorting.as_mut().and_then(|s| s.last_mut()) {
+            Some(sorting) => sorting,
+            None => return,
+        };
+        if let Some(previous) = sorting.current.take() {
+            let bytes = self.writer.end_capture();
+            // Missing optional values write nothing.
+            if !bytes.is_empty() {
+                sorting.entries.push((previous, bytes));
+            }
+        }
+        if name.is_some() {
+            sorting.current = name;
+            self.writer.begin_capture();
+        }
+    }
+
+    /// Writes the captured entries of the compound being closed, sorted by
+    /// name, if they are sorted.
+    fn release_entries(&mut self) -> Result<()> {
+        self.capture_entry(None);
+        let mut entries = match self.sorting.as_mut().and_then(Vec::pop) {
+            Some(sorting) => sorting.entries,
+            None => return Ok(()),
+        };
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for (_, bytes) in entries {
+            self.writer.release(&bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a newtype variant as a compound with a single entry named after
+    /// the variant.
+    fn write_newtype_variant<T: ?Sized>(&mut self, variant: &'static str, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        self.specify_kind(Kind::Compound)?;
+        self.specify_name(Cow::Borrowed(variant))?;
+        value.serialize(&mut InnerEncoder { outer: self })?;
+        self.close_level()
+    }
+
+    /// Opens the compounds of a struct variant, which is written as a
+    /// compound with a single entry named after the variant.
+    fn open_struct_variant(&mut self, variant: &'static str) -> Result<()> {
+        self.specify_kind(Kind::Compound)?;
+        self.specify_name(Cow::Borrowed(variant))?;
+        self.specify_kind(Kind::Compound)
+    }
+
     /// Closes this level.
     fn close_level(&mut self) -> Result<()> {
-    	if self.states.last().unwrap().is_list() {
+    	if self.states.last().is_some_and(LevelState::is_list) {
     		self.specify_kind(Kind::End)?;
     	}
     	
-    	match self.states.pop().unwrap() {
+    	match self.pop_state()? {
     		LevelState::InNamed { name } => {
     			if name.is_some() {
-    				panic!("key name specified without value");
+    				return Err(Error::InvalidWrite(MISSING_VALUE));
     			}
     			
-    			self.writer.write_u8(0).map_err(From::from)
+    			self.release_entries()?;
+    			self.writer.write_u8(0)?;
+    		},
+    		LevelState::InList  { kind } => { // TODO: Check Length?
+    			self.writer.write_all(&self.pending)?;
+    			self.pending.clear();
     		},
-    		LevelState::InList  { kind } => Ok(()), // TODO: Check Length?
 	    	_ => unreachable!()
     	}
+    	
+    	// Only the state left by writing the root header remains once the
+    	// root is closed.
+    	if self.states.len() <= 1 {
+    	    return self.end_root();
+    	}
+    	Ok(())
+    }
+
+    /// Passes on what is still buffered of the document, once its root has
+    /// been written.
+    fn end_root(&mut self) -> Result<()> {
+        self.writer.write_buffered().map_err(From::from)
     }
 }
 
@@ -236,6 +619,40 @@ impl<'a, W> ser::SerializeSeq for Compound<'a, W>
     }
 }
 
+impl<'a, W> ser::SerializeTuple for Compound<'a, W>
+    where W: io::Write
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+        where T: serde::Serialize
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.outer.close_level()
+    }
+}
+
+impl<'a, W> ser::SerializeTupleStruct for Compound<'a, W>
+    where W: io::Write
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+        where T: serde::Serialize
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.outer.close_level()
+    }
+}
+
 impl<'a, W> ser::SerializeStruct for Compound<'a, W>
     where W: io::Write
 {
@@ -246,7 +663,52 @@ impl<'a, W> ser::SerializeStruct for Compound<'a, W>
                                   -> Result<()>
         where T: serde::Serialize
     {
-    	self.outer.specify_name(key.to_owned())?;
+    	self.outer.specify_name(Cow::Borrowed(key))?;
+        value.serialize(&mut InnerEncoder { outer: self.outer })
+    }
+
+    fn end(self) -> Result<()> {
+        self.outer.close_level()
+    }
+}
+
+/// Struct variants are written as a compound inside the compound holding the
+/// variant, so both are closed at the end.
+impl<'a, W> ser::SerializeStructVariant for Compound<'a, W>
+    where W: io::Write
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T)
+                                  -> Result<()>
+        where T: serde::Serialize
+    {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.outer.close_level()?;
+        self.outer.close_level()
+    }
+}
+
+impl<'a, W> ser::SerializeMap for Compound<'a, W>
+    where W: io::Write
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<()>
+        where T: serde::Serialize
+    {
+        let key = key.serialize(KeyEncoder)?;
+        self.outer.specify_name(Cow::Owned(key))
+    }
+
+    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
+        where T: serde::Serialize
+    {
         value.serialize(&mut InnerEncoder { outer: self.outer })
     }
 
@@ -255,23 +717,106 @@ impl<'a, W> ser::SerializeStruct for Compound<'a, W>
     }
 }
 
+/// Serializes the keys of maps, which must be strings to become the names of
+/// compound entries.
+pub(crate) struct KeyEncoder;
+
+impl serde::Serializer for KeyEncoder {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = ser::Impossible<String, Error>;
+    type SerializeTuple = ser::Impossible<String, Error>;
+    type SerializeTupleStruct = ser::Impossible<String, Error>;
+    type SerializeTupleVariant = ser::Impossible<String, Error>;
+    type SerializeMap = ser::Impossible<String, Error>;
+    type SerializeStruct = ser::Impossible<String, Error>;
+    type SerializeStructVariant = ser::Impossible<String, Error>;
+
+    return_expr_for_serialized_types!(
+        Err(Error::UnrepresentableType("non-string map key")); bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64
+            char bytes none some unit unit_struct unit_variant newtype_variant
+            seq seq_fixed_size tuple tuple_struct tuple_variant map struct struct_variant
+    );
+
+    #[inline]
+    fn serialize_str(self, value: &str) -> Result<String> {
+        Ok(value.to_string())
+    }
+
+    #[inline]
+    fn serialize_newtype_struct<T: ?Sized>(self, _name: &'static str, value: &T)
+                                           -> Result<String>
+        where T: ser::Serialize
+    {
+        value.serialize(self)
+    }
+}
+
 impl<'a, W> serde::Serializer for &'a mut Encoder<W> where W: io::Write {
     type Ok = ();
     type Error = Error;
-    type SerializeSeq = ser::Impossible<(), Error>;
-    type SerializeTuple = ser::Impossible<(), Error>;
-    type SerializeTupleStruct = ser::Impossible<(), Error>;
+    type SerializeSeq = Compound<'a, W>;
+    type SerializeTuple = Compound<'a, W>;
+    type SerializeTupleStruct = Compound<'a, W>;
     type SerializeTupleVariant = ser::Impossible<(), Error>;
-    type SerializeMap = ser::Impossible<(), Error>;
+    type SerializeMap = Compound<'a, W>;
     type SerializeStruct = Compound<'a, W>;
-    type SerializeStructVariant = ser::Impossible<(), Error>;
+    type SerializeStructVariant = Compound<'a, W>;
+
+    // Other types can only be the root with `with_any_root`.
+    forward_to_bare! {
+        serialize_bool(value: bool) serialize_i8(value: i8) serialize_i16(value: i16)
+        serialize_i32(value: i32) serialize_i64(value: i64) serialize_u8(value: u8)
+        serialize_u16(value: u16) serialize_u32(value: u32) serialize_u64(value: u64)
+        serialize_f32(value: f32) serialize_f64(value: f64) serialize_char(value: char)
+        serialize_str(value: &str) serialize_bytes(value: &[u8]) serialize_unit()
+        serialize_unit_variant(name: &'static str, index: usize, variant: &'static str)
+    }
 
     return_expr_for_serialized_types!(
-        Err(Error::NoRootCompound); bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64
-            char str bytes none some unit unit_variant newtype_variant
-            seq seq_fixed_size tuple tuple_struct tuple_variant struct_variant
+        Err(Error::NoRootCompound); none tuple_variant
     );
 
+    #[inline]
+    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        value.serialize(self)
+    }
+
+    #[inline]
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        self.bare()?;
+        match len {
+            Some(len) => self.serialize_seq_fixed_size(len),
+            None => Err(Error::UnrepresentableType("unsized list")),
+        }
+    }
+
+    #[inline]
+    fn serialize_seq_fixed_size(self, len: usize) -> Result<Self::SerializeSeq> {
+        self.bare()?;
+        self.open_list(len)?;
+        Ok(Compound { outer: self })
+    }
+
+    /// Serialize tuples as `Tag_List` data, so their elements must all have
+    /// the same type.
+    #[inline]
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.bare()?;
+        self.forced = Some(Kind::List);
+        self.open_list(len)?;
+        Ok(Compound { outer: self })
+    }
+
+    #[inline]
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize)
+                              -> Result<Self::SerializeTupleStruct>
+    {
+        self.serialize_tuple(len)
+    }
+
     /// Serialize unit structs as empty `Tag_Compound` data.
     #[inline]
     fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
@@ -280,20 +825,25 @@ impl<'a, W> serde::Serializer for &'a mut Encoder<W> where W: io::Write {
     }
 
     /// Serialize newtype structs by their underlying type. Note that this will
-    /// only be successful if the underyling type is a struct.
+    /// only be successful if the underyling type is a struct, unless any root
+    /// is allowed.
     #[inline]
-    fn serialize_newtype_struct<T: ?Sized>(self, _name: &'static str, value: &T)
+    fn serialize_newtype_struct<T: ?Sized>(self, name: &'static str, value: &T)
                                            -> Result<()>
         where T: ser::Serialize
     {
+        if self.any_root {
+            ser::Serializer::serialize_newtype_struct(&mut self.bare()?, name, value)?;
+            return self.end_root();
+        }
         value.serialize(self)
     }
 
-    /// Arbitrary maps cannot be serialized, so calling this method will always
-    /// return an error.
+    /// Serialize maps with string keys as `Tag_Compound` data.
     #[inline]
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        Err(Error::UnrepresentableType("map"))
+        self.specify_kind(Kind::Compound)?;
+        Ok(Compound { outer: self })
     }
 
     /// Serialize structs as `Tag_Compound` data.
@@ -304,18 +854,41 @@ impl<'a, W> serde::Serializer for &'a mut Encoder<W> where W: io::Write {
         self.specify_kind(Kind::Compound)?;
         Ok(Compound { outer: self })
     }
+
+    /// Serialize newtype variants as `Tag_Compound` data with a single entry
+    /// named after the variant.
+    #[inline]
+    fn serialize_newtype_variant<T: ?Sized>(self, _name: &'static str,
+                                            _index: usize,
+                                            variant: &'static str,
+                                            value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        self.write_newtype_variant(variant, value)
+    }
+
+    /// Serialize struct variants as `Tag_Compound` data with a single entry
+    /// named after the variant.
+    #[inline]
+    fn serialize_struct_variant(self, _name: &'static str, _index: usize,
+                                variant: &'static str, _len: usize)
+                                -> Result<Self::SerializeStructVariant>
+    {
+        self.open_struct_variant(variant)?;
+        Ok(Compound { outer: self })
+    }
 }
 
 impl<'a, W> serde::Serializer for &'a mut InnerEncoder<'a, W> where W: io::Write {
     type Ok = ();
     type Error = Error;
     type SerializeSeq = Compound<'a, W>;
-    type SerializeTuple = ser::Impossible<(), Error>;
-    type SerializeTupleStruct = ser::Impossible<(), Error>;
+    type SerializeTuple = Compound<'a, W>;
+    type SerializeTupleStruct = Compound<'a, W>;
     type SerializeTupleVariant = ser::Impossible<(), Error>;
-    type SerializeMap = ser::Impossible<(), Error>;
+    type SerializeMap = Compound<'a, W>;
     type SerializeStruct = Compound<'a, W>;
-    type SerializeStructVariant = ser::Impossible<(), Error>;
+    type SerializeStructVariant = Compound<'a, W>;
 
     #[inline]
     fn serialize_bool(self, value: bool) -> Result<()> {
@@ -324,62 +897,99 @@ impl<'a, W> serde::Serializer for &'a mut InnerEncoder<'a, W> where W: io::Write
 
     #[inline]
     fn serialize_i8(self, value: i8) -> Result<()> {
+        if self.outer.is_buffered(Kind::I8) {
+            self.outer.pending.push(value as u8);
+            return Ok(());
+        }
         self.outer.specify_kind(Kind::I8)?;
-        self.outer.writer.write_i8(value).map_err(From::from)
+        self.outer.writer.write_u8(value as u8).map_err(From::from)
     }
 
     #[inline]
     fn serialize_i16(self, value: i16) -> Result<()> {
+        let flavor = self.outer.flavor;
+        if self.outer.is_buffered(Kind::I16) {
+            return flavor.write_i16(&mut self.outer.pending, value).map_err(From::from);
+        }
         self.outer.specify_kind(Kind::I16)?;
-        self.outer.writer.write_i16::<BigEndian>(value).map_err(From::from)
+        flavor.write_i16(&mut self.outer.writer, value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_i32(self, value: i32) -> Result<()> {
+        let flavor = self.outer.flavor;
+        if self.outer.is_buffered(Kind::I32) {
+            return flavor.write_i32(&mut self.outer.pending, value).map_err(From::from);
+        }
         self.outer.specify_kind(Kind::I32)?;
-        self.outer.writer.write_i32::<BigEndian>(value).map_err(From::from)
+        flavor.write_i32(&mut self.outer.writer, value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_i64(self, value: i64) -> Result<()> {
+        let flavor = self.outer.flavor;
+        if self.outer.is_buffered(Kind::I64) {
+            return flavor.write_i64(&mut self.outer.pending, value).map_err(From::from);
+        }
         self.outer.specify_kind(Kind::I64)?;
-        self.outer.writer.write_i64::<BigEndian>(value).map_err(From::from)
+        flavor.write_i64(&mut self.outer.writer, value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_u8(self, value: u8) -> Result<()> {
-        self.outer.specify_kind(Kind::I8)?;
-        self.outer.writer.write_u8(value).map_err(From::from)
+        match self.outer.unsigned {
+            Unsigned::Reinterpret => self.serialize_i8(value as i8),
+            Unsigned::Widen => self.serialize_i16(i16::from(value)),
+            Unsigned::Reject => Err(Error::UnrepresentableType("u8")),
+        }
     }
 
     #[inline]
     fn serialize_u16(self, value: u16) -> Result<()> {
-        self.outer.specify_kind(Kind::I16)?;
-        self.outer.writer.write_u16::<BigEndian>(value).map_err(From::from)
+        match self.outer.unsigned {
+            Unsigned::Reinterpret => self.serialize_i16(value as i16),
+            Unsigned::Widen => self.serialize_i32(i32::from(value)),
+            Unsigned::Reject => Err(Error::UnrepresentableType("u16")),
+        }
     }
 
     #[inline]
     fn serialize_u32(self, value: u32) -> Result<()> {
-        self.outer.specify_kind(Kind::I32)?;
-        self.outer.writer.write_u32::<BigEndian>(value).map_err(From::from)
+        match self.outer.unsigned {
+            Unsigned::Reinterpret => self.serialize_i32(value as i32),
+            Unsigned::Widen => self.serialize_i64(i64::from(value)),
+            Unsigned::Reject => Err(Error::UnrepresentableType("u32")),
+        }
     }
 
     #[inline]
     fn serialize_u64(self, value: u64) -> Result<()> {
-        self.outer.specify_kind(Kind::I64)?;
-        self.outer.writer.write_u64::<BigEndian>(value).map_err(From::from)
+        match self.outer.unsigned {
+            Unsigned::Reinterpret => self.serialize_i64(value as i64),
+            Unsigned::Widen if value <= i64::MAX as u64 => self.serialize_i64(value as i64),
+            Unsigned::Widen => Err(Error::UnrepresentableType("u64 above i64::MAX")),
+            Unsigned::Reject => Err(Error::UnrepresentableType("u64")),
+        }
     }
 
     #[inline]
     fn serialize_f32(self, value: f32) -> Result<()> {
+        let flavor = self.outer.flavor;
+        if self.outer.is_buffered(Kind::F32) {
+            return flavor.write_f32(&mut self.outer.pending, value).map_err(From::from);
+        }
         self.outer.specify_kind(Kind::F32)?;
-        self.outer.writer.write_f32::<BigEndian>(value).map_err(From::from)
+        flavor.write_f32(&mut self.outer.writer, value).map_err(From::from)
     }
 
     #[inline]
     fn serialize_f64(self, value: f64) -> Result<()> {
+        let flavor = self.outer.flavor;
+        if self.outer.is_buffered(Kind::F64) {
+            return flavor.write_f64(&mut self.outer.pending, value).map_err(From::from);
+        }
         self.outer.specify_kind(Kind::F64)?;
-        self.outer.writer.write_f64::<BigEndian>(value).map_err(From::from)
+        flavor.write_f64(&mut self.outer.writer, value).map_err(From::from)
     }
 
     #[inline]
@@ -390,13 +1000,16 @@ impl<'a, W> serde::Serializer for &'a mut InnerEncoder<'a, W> where W: io::Write
     #[inline]
     fn serialize_str(self, value: &str) -> Result<()> {
         self.outer.specify_kind(Kind::String)?;
-        write_bare_string(&mut self.outer.writer, value).map_err(From::from)
+        self.outer.write_string(value)
     }
 
+    /// Serialize byte slices, such as `serde::bytes::Bytes`, as
+    /// `Tag_Byte_Array` data.
     #[inline]
     fn serialize_bytes(self, value: &[u8]) -> Result<()> {
-        self.outer.specify_kind(Kind::I8Array)?;
-        self.outer.writer.write_i32::<BigEndian>(value.len() as i32).map_err(Error::from)?;
+        self.outer.specify_element(Kind::I8Array)?;
+        let len = list_len(value.len())?;
+        self.outer.flavor.write_len(&mut self.outer.writer, len).map_err(Error::from)?;
         self.outer.writer.write_all(value).map_err(From::from)
     }
 
@@ -424,35 +1037,43 @@ impl<'a, W> serde::Serializer for &'a mut InnerEncoder<'a, W> where W: io::Write
         self.outer.close_level()
     }
 
+    /// Serialize unit variants as `Tag_String` data naming the variant.
     #[inline]
     fn serialize_unit_variant(self, _name: &'static str, _index: usize,
-                              _variant: &'static str) -> Result<()>
+                              variant: &'static str) -> Result<()>
     {
-        Err(Error::UnrepresentableType("unit variant"))
+        self.serialize_str(variant)
     }
 
+    /// Serialize newtype structs by their underlying type, except for the
+    /// wrappers in `arrays`, which choose the tag of the sequence they wrap.
     #[inline]
-    fn serialize_newtype_struct<T: ?Sized>(self, _name: &'static str, value: &T)
+    fn serialize_newtype_struct<T: ?Sized>(self, name: &'static str, value: &T)
                                            -> Result<()>
         where T: ser::Serialize
     {
-        value.serialize(self)
+        self.outer.forced = arrays::forced_container(name);
+        let result = value.serialize(&mut InnerEncoder { outer: &mut *self.outer });
+        self.outer.forced = None;
+        result
     }
 
+    /// Serialize newtype variants as `Tag_Compound` data with a single entry
+    /// named after the variant.
     #[inline]
     fn serialize_newtype_variant<T: ?Sized>(self, _name: &'static str,
                                             _index: usize,
-                                            _variant: &'static str,
-                                            _value: &T) -> Result<()>
+                                            variant: &'static str,
+                                            value: &T) -> Result<()>
         where T: ser::Serialize
     {
-        Err(Error::UnrepresentableType("newtype variant"))
+        self.outer.write_newtype_variant(variant, value)
     }
 
     #[inline]
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
         if let Some(l) = len {
-        	self.outer.open_list(l as i32)?;
+        	self.outer.open_list(l)?;
         	
             Ok(Compound { outer: self.outer })
         } else {
@@ -463,20 +1084,24 @@ impl<'a, W> serde::Serializer for &'a mut InnerEncoder<'a, W> where W: io::Write
     #[inline]
     fn serialize_seq_fixed_size(self, len: usize) -> Result<Self::SerializeSeq>
     {
-        self.outer.open_list(len as i32)?;
+        self.outer.open_list(len)?;
         Ok(Compound { outer: self.outer })
     }
 
+    /// Serialize tuples as `Tag_List` data, so their elements must all have
+    /// the same type.
     #[inline]
-    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
-        Err(Error::UnrepresentableType("tuple"))
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.outer.forced = Some(Kind::List);
+        self.outer.open_list(len)?;
+        Ok(Compound { outer: self.outer })
     }
 
     #[inline]
-    fn serialize_tuple_struct(self, _name: &'static str, _len: usize)
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize)
                               -> Result<Self::SerializeTupleStruct>
     {
-        Err(Error::UnrepresentableType("tuple struct"))
+        self.serialize_tuple(len)
     }
 
     #[inline]
@@ -489,7 +1114,8 @@ impl<'a, W> serde::Serializer for &'a mut InnerEncoder<'a, W> where W: io::Write
 
     #[inline]
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        Err(Error::UnrepresentableType("map"))
+        self.outer.specify_kind(Kind::Compound)?;
+        Ok(Compound { outer: self.outer })
     }
 
     #[inline]
@@ -500,11 +1126,14 @@ impl<'a, W> serde::Serializer for &'a mut InnerEncoder<'a, W> where W: io::Write
         Ok(Compound { outer: self.outer })
     }
 
+    /// Serialize struct variants as `Tag_Compound` data with a single entry
+    /// named after the variant.
     #[inline]
     fn serialize_struct_variant(self, _name: &'static str, _index: usize,
-                                _variant: &'static str, _len: usize)
+                                variant: &'static str, _len: usize)
                                 -> Result<Self::SerializeStructVariant>
     {
-        Err(Error::UnrepresentableType("struct variant"))
+        self.outer.open_struct_variant(variant)?;
+        Ok(Compound { outer: self.outer })
     }
 }
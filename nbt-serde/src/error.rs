@@ -2,6 +2,7 @@ use std::error;
 use std::fmt;
 use std::io;
 use std::result;
+use std::str;
 use std::string;
 
 use serde;
@@ -9,20 +10,74 @@ use kind::Kind;
 
 pub type Result<T> = result::Result<T, Error>;
 
+/// Errors that may be encountered when encoding or decoding.
+///
+/// More variants may be added, so matches on it need a catch-all arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
+    /// An error from the underlying reader or writer.
     Io(io::Error),
+    /// An error reported by a `Serialize` or `Deserialize` implementation.
     Serde(String),
+    /// The root is not a compound, and `with_any_root` was not set.
     NoRootCompound,
+    /// A type ID that does not name a tag.
     UnknownTag(u8),
+    /// A byte read as a `bool` that is not 0 or 1.
     NonBooleanByte(i8),
+    /// A tag of the first kind was found where the second was expected.
     UnexpectedTag(Kind, Kind),
+    /// A type that has no NBT representation, such as `char`.
     UnrepresentableType(&'static str),
+    /// A string that the string codec cannot encode or decode.
     InvalidUtf8,
+    /// The data ended in the middle of a tag.
     IncompleteNbtValue,
-    HeterogenousList { original: Kind, new: Kind }
+    /// An element of the second kind was added to a list of the first.
+    HeterogenousList { original: Kind, new: Kind },
+    /// The encoded size would exceed the limit given to the encoder, or the
+    /// data read the byte budget given to the decoder. Includes the limit.
+    SizeLimitExceeded(usize),
+    /// A list, array or string whose length is negative.
+    NegativeLength(i32),
+    /// An enum variant that is not a compound with exactly one entry.
+    InvalidVariant,
+    /// A string too long for its length to be written. Includes its length
+    /// in bytes, once encoded.
+    StringTooLong(usize),
+    /// A list or array with more elements than its length can hold.
+    ListTooLong(usize),
+    /// Lists and compounds nested more deeply than the decoder allows, which
+    /// is included.
+    DepthLimitExceeded(usize),
+    /// A string, list or array longer than the decoder allows, or a negative
+    /// length. Includes the limit.
+    LengthLimitExceeded(usize),
+    /// A `Serialize` implementation used the encoder in a way that does not
+    /// make a valid document, such as giving a map value without a key.
+    /// Includes a description of the mistake.
+    InvalidWrite(&'static str),
+    /// Where in the document another error happened, given by a decoder
+    /// made with `with_error_context`: the offset of the byte after the last
+    /// one read, and the path of the tag being read, such as
+    /// `Level.Sections[3].BlockStates`.
+    At { offset: u64, path: String, error: Box<Error> },
 }
 
+/// The error an encoder's writer or a decoder's reader fails with when the
+/// size limit is reached, so that it can be told apart from other I/O errors.
+#[derive(Debug)]
+pub(crate) struct SizeLimit(pub usize);
+
+impl fmt::Display for SizeLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "output exceeds {} bytes", self.0)
+    }
+}
+
+impl error::Error for SizeLimit {}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
         match *self {
@@ -46,6 +101,30 @@ impl fmt::Display for Error {
             Error::InvalidUtf8 => write!(f, "a string is not valid UTF-8"),
             Error::IncompleteNbtValue => write!(f, "data does not represent a complete NbtValue"),
             Error::HeterogenousList { original, new} => write!(f, "A list may only contain 1 type of tags: already contained {:?}, but tried to insert {:?}", original, new),
+            Error::SizeLimitExceeded(limit) => {
+                write!(f, "size exceeds the limit of {} bytes", limit)
+            },
+            Error::NegativeLength(len) => write!(f, "negative list or array length: {}", len),
+            Error::InvalidVariant => {
+                f.write_str("an enum variant must be a compound with exactly one entry")
+            },
+            Error::StringTooLong(len) => write!(f, "a string of {} bytes is too long to write", len),
+            Error::ListTooLong(len) => {
+                write!(f, "a list or array of {} elements is too long to write", len)
+            },
+            Error::DepthLimitExceeded(max) => {
+                write!(f, "lists and compounds are nested more than {} deep", max)
+            },
+            Error::LengthLimitExceeded(max) => {
+                write!(f, "a string, list or array is longer than {}", max)
+            },
+            Error::InvalidWrite(msg) => write!(f, "invalid write: {}", msg),
+            Error::At { offset, ref path, ref error } if path.is_empty() => {
+                write!(f, "at byte {}: {}", offset, error)
+            },
+            Error::At { offset, ref path, ref error } => {
+                write!(f, "at byte {}, path {}: {}", offset, path, error)
+            },
         }
     }
 }
@@ -54,12 +133,20 @@ impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
     	if err.kind() == io::ErrorKind::UnexpectedEof {
     		Error::IncompleteNbtValue
+    	} else if let Some(&SizeLimit(limit)) = err.get_ref().and_then(|e| e.downcast_ref()) {
+    		Error::SizeLimitExceeded(limit)
     	} else {
     		Error::Io(err)
     	}
     }
 }
 
+impl From<str::Utf8Error> for Error {
+    fn from(_: str::Utf8Error) -> Error {
+        Error::InvalidUtf8
+    }
+}
+
 impl From<string::FromUtf8Error> for Error {
     fn from(_: string::FromUtf8Error) -> Error {
         Error::InvalidUtf8
@@ -79,7 +166,24 @@ impl error::Error for Error {
             Error::UnrepresentableType(_) => "unrepresentable type",
             Error::InvalidUtf8 => "a string is not valid UTF-8",
             Error::IncompleteNbtValue => "data does not represent a complete NbtValue",
-            Error::HeterogenousList { .. } => "A list may only contain 1 type of tags"
+            Error::HeterogenousList { .. } => "A list may only contain 1 type of tags",
+            Error::SizeLimitExceeded(_) => "size exceeds the limit",
+            Error::NegativeLength(_) => "negative list or array length",
+            Error::InvalidVariant => "an enum variant must be a compound with exactly one entry",
+            Error::StringTooLong(_) => "a string is too long to write",
+            Error::ListTooLong(_) => "a list or array is too long to write",
+            Error::DepthLimitExceeded(_) => "lists and compounds are nested too deeply",
+            Error::LengthLimitExceeded(_) => "a string, list or array is too long",
+            Error::InvalidWrite(_) => "the write would not make a valid document",
+            Error::At { .. } => "an error at a known place in the document",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            Error::At { ref error, .. } => Some(&**error),
+            _ => None,
         }
     }
 }
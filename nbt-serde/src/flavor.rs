@@ -0,0 +1,183 @@
+//! The byte layouts used by different editions of the game.
+//!
+//! Tag ids, bytes and the layout of compounds are the same everywhere; the
+//! flavors differ only in how larger numbers and lengths are written.
+
+use std::io;
+
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+
+/// How numbers and lengths are laid out in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Flavor {
+    /// Big-endian, as in Java Edition files and network protocol. This is
+    /// the default.
+    #[default]
+    Java,
+    /// Little-endian, as in Bedrock Edition files such as `level.dat`.
+    BedrockDisk,
+    /// The Bedrock Edition network protocol. Shorts, floats and doubles are
+    /// little-endian; ints, longs and the lengths of lists and arrays are
+    /// ZigZag-encoded VarInts; and string lengths are unsigned VarInts.
+    BedrockNetwork,
+}
+
+impl Flavor {
+    pub(crate) fn write_i16<W: io::Write + ?Sized>(self, dst: &mut W, value: i16) -> io::Result<()> {
+        match self {
+            Flavor::Java => dst.write_i16::<BigEndian>(value),
+            Flavor::BedrockDisk | Flavor::BedrockNetwork => dst.write_i16::<LittleEndian>(value),
+        }
+    }
+
+    pub(crate) fn write_i32<W: io::Write + ?Sized>(self, dst: &mut W, value: i32) -> io::Result<()> {
+        match self {
+            Flavor::Java => dst.write_i32::<BigEndian>(value),
+            Flavor::BedrockDisk => dst.write_i32::<LittleEndian>(value),
+            Flavor::BedrockNetwork => write_var(dst, ((value << 1) ^ (value >> 31)) as u32 as u64),
+        }
+    }
+
+    pub(crate) fn write_i64<W: io::Write + ?Sized>(self, dst: &mut W, value: i64) -> io::Result<()> {
+        match self {
+            Flavor::Java => dst.write_i64::<BigEndian>(value),
+            Flavor::BedrockDisk => dst.write_i64::<LittleEndian>(value),
+            Flavor::BedrockNetwork => write_var(dst, ((value << 1) ^ (value >> 63)) as u64),
+        }
+    }
+
+    pub(crate) fn write_f32<W: io::Write + ?Sized>(self, dst: &mut W, value: f32) -> io::Result<()> {
+        match self {
+            Flavor::Java => dst.write_f32::<BigEndian>(value),
+            Flavor::BedrockDisk | Flavor::BedrockNetwork => dst.write_f32::<LittleEndian>(value),
+        }
+    }
+
+    pub(crate) fn write_f64<W: io::Write + ?Sized>(self, dst: &mut W, value: f64) -> io::Result<()> {
+        match self {
+            Flavor::Java => dst.write_f64::<BigEndian>(value),
+            Flavor::BedrockDisk | Flavor::BedrockNetwork => dst.write_f64::<LittleEndian>(value),
+        }
+    }
+
+    /// Writes the length of a list or array, which is an int in every flavor.
+    pub(crate) fn write_len<W: io::Write + ?Sized>(self, dst: &mut W, len: i32) -> io::Result<()> {
+        self.write_i32(dst, len)
+    }
+
+    /// The length in bytes of the longest string that can be written.
+    pub(crate) fn max_string_len(self) -> usize {
+        match self {
+            Flavor::Java | Flavor::BedrockDisk => u16::MAX as usize,
+            Flavor::BedrockNetwork => u32::MAX as usize,
+        }
+    }
+
+    pub(crate) fn write_string_len<W: io::Write + ?Sized>(self, dst: &mut W, len: usize) -> io::Result<()> {
+        match self {
+            Flavor::Java => dst.write_u16::<BigEndian>(len as u16),
+            Flavor::BedrockDisk => dst.write_u16::<LittleEndian>(len as u16),
+            Flavor::BedrockNetwork => write_var(dst, len as u32 as u64),
+        }
+    }
+
+    /// The number of bytes a number with the given tag takes up, or `None`
+    /// if it varies, as it does for VarInts.
+    pub(crate) fn width(self, tag: u8) -> Option<u64> {
+        match (self, tag) {
+            (_, 0x01) => Some(1),
+            (_, 0x02) => Some(2),
+            (Flavor::BedrockNetwork, 0x03) | (Flavor::BedrockNetwork, 0x04) => None,
+            (_, 0x03) | (_, 0x05) => Some(4),
+            (_, 0x04) | (_, 0x06) => Some(8),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn read_i16<R: io::Read + ?Sized>(self, src: &mut R) -> io::Result<i16> {
+        match self {
+            Flavor::Java => src.read_i16::<BigEndian>(),
+            Flavor::BedrockDisk | Flavor::BedrockNetwork => src.read_i16::<LittleEndian>(),
+        }
+    }
+
+    pub(crate) fn read_i32<R: io::Read + ?Sized>(self, src: &mut R) -> io::Result<i32> {
+        match self {
+            Flavor::Java => src.read_i32::<BigEndian>(),
+            Flavor::BedrockDisk => src.read_i32::<LittleEndian>(),
+            Flavor::BedrockNetwork => {
+                let raw = read_var(src, 5)? as u32;
+                Ok((raw >> 1) as i32 ^ -((raw & 1) as i32))
+            },
+        }
+    }
+
+    pub(crate) fn read_i64<R: io::Read + ?Sized>(self, src: &mut R) -> io::Result<i64> {
+        match self {
+            Flavor::Java => src.read_i64::<BigEndian>(),
+            Flavor::BedrockDisk => src.read_i64::<LittleEndian>(),
+            Flavor::BedrockNetwork => {
+                let raw = read_var(src, 10)?;
+                Ok((raw >> 1) as i64 ^ -((raw & 1) as i64))
+            },
+        }
+    }
+
+    pub(crate) fn read_f32<R: io::Read + ?Sized>(self, src: &mut R) -> io::Result<f32> {
+        match self {
+            Flavor::Java => src.read_f32::<BigEndian>(),
+            Flavor::BedrockDisk | Flavor::BedrockNetwork => src.read_f32::<LittleEndian>(),
+        }
+    }
+
+    pub(crate) fn read_f64<R: io::Read + ?Sized>(self, src: &mut R) -> io::Result<f64> {
+        match self {
+            Flavor::Java => src.read_f64::<BigEndian>(),
+            Flavor::BedrockDisk | Flavor::BedrockNetwork => src.read_f64::<LittleEndian>(),
+        }
+    }
+
+    pub(crate) fn read_len<R: io::Read + ?Sized>(self, src: &mut R) -> io::Result<i32> {
+        self.read_i32(src)
+    }
+
+    pub(crate) fn read_string_len<R: io::Read + ?Sized>(self, src: &mut R) -> io::Result<usize> {
+        match self {
+            Flavor::Java => src.read_u16::<BigEndian>().map(|len| len as usize),
+            Flavor::BedrockDisk => src.read_u16::<LittleEndian>().map(|len| len as usize),
+            Flavor::BedrockNetwork => read_var(src, 5).map(|len| len as u32 as usize),
+        }
+    }
+}
+
+/// Writes an unsigned VarInt: seven bits per byte, least significant first,
+/// with the top bit set on every byte but the last.
+fn write_var<W: io::Write + ?Sized>(dst: &mut W, mut value: u64) -> io::Result<()> {
+    let mut buf = [0u8; 10];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf[len] = byte;
+            len += 1;
+            break;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+    dst.write_all(&buf[..len])
+}
+
+/// Reads an unsigned VarInt of at most `max` bytes.
+fn read_var<R: io::Read + ?Sized>(src: &mut R, max: usize) -> io::Result<u64> {
+    let mut value = 0u64;
+    for i in 0..max {
+        let byte = src.read_u8()?;
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "VarInt is too long"))
+}
@@ -0,0 +1,105 @@
+//! Transforming values as they are decoded.
+//!
+//! Hooks are registered on a `Decoder` with `Decoder::with_hook`, and are run
+//! on every matching number or string before it reaches the type being
+//! deserialized. This makes it possible to sanitize data in the same pass that
+//! decodes it: clamping out-of-range values, rewriting legacy ids, or interning
+//! strings. Hooks are not run on the elements of byte, int and long arrays.
+//!
+//! Paths are dotted, starting from the fields of the root compound, and a list
+//! element is addressed by its index. A `*` matches any one field or element,
+//! so `Inventory.*.id` is the `id` of every item in the `Inventory` list.
+
+use serde::de;
+
+use error::Result;
+use kind::Kind;
+
+/// A number or string being decoded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Scalar {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+}
+
+impl Scalar {
+    /// The kind of tag this value is stored as.
+    pub fn kind(&self) -> Kind {
+        match *self {
+            Scalar::Byte(_) => Kind::I8,
+            Scalar::Short(_) => Kind::I16,
+            Scalar::Int(_) => Kind::I32,
+            Scalar::Long(_) => Kind::I64,
+            Scalar::Float(_) => Kind::F32,
+            Scalar::Double(_) => Kind::F64,
+            Scalar::String(_) => Kind::String,
+        }
+    }
+
+    pub(crate) fn visit<V: de::Visitor>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            Scalar::Byte(v) => visitor.visit_i8(v),
+            Scalar::Short(v) => visitor.visit_i16(v),
+            Scalar::Int(v) => visitor.visit_i32(v),
+            Scalar::Long(v) => visitor.visit_i64(v),
+            Scalar::Float(v) => visitor.visit_f32(v),
+            Scalar::Double(v) => visitor.visit_f64(v),
+            Scalar::String(v) => visitor.visit_string(v),
+        }
+    }
+}
+
+/// The values a hook is run on.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    /// Every value at a path, which may contain `*` wildcards.
+    Path(String),
+    /// Every value stored as the given kind of tag.
+    Kind(Kind),
+}
+
+impl Target {
+    fn matches(&self, path: &[String], kind: Kind) -> bool {
+        match *self {
+            Target::Path(ref pattern) => {
+                let mut segments = pattern.split('.');
+                path.iter().all(|p| segments.next().is_some_and(|s| s == "*" || s == p))
+                    && segments.next().is_none()
+            },
+            Target::Kind(k) => k == kind,
+        }
+    }
+}
+
+type Hook = Box<dyn FnMut(Scalar) -> Scalar>;
+
+/// The hooks registered on a decoder.
+#[derive(Default)]
+pub(crate) struct Hooks {
+    hooks: Vec<(Target, Hook)>,
+}
+
+impl Hooks {
+    pub(crate) fn push(&mut self, target: Target, hook: Hook) {
+        self.hooks.push((target, hook));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Runs every matching hook on a value, in the order they were
+    /// registered. Hooks are matched against the kind of tag the value was
+    /// stored as, even if an earlier hook changed it.
+    pub(crate) fn apply(&mut self, path: &[String], value: Scalar) -> Scalar {
+        let kind = value.kind();
+        self.hooks.iter_mut()
+            .filter(|&&mut (ref target, _)| target.matches(path, kind))
+            .fold(value, |value, &mut (_, ref mut hook)| hook(value))
+    }
+}
@@ -48,6 +48,20 @@ impl Kind {
 		}
 	}
 	
+	/// Whether every value of this kind converts to `other` without loss,
+	/// such as a `I16` to an `I32` or an `I32` to an `F64`.
+	pub fn widens_to(&self, other: Kind) -> bool {
+		match (*self, other) {
+			(Kind::I8, Kind::I16) => true,
+			(Kind::I8, Kind::I32) | (Kind::I16, Kind::I32) => true,
+			(Kind::I8, Kind::I64) | (Kind::I16, Kind::I64) | (Kind::I32, Kind::I64) => true,
+			(Kind::I8, Kind::F32) | (Kind::I16, Kind::F32) => true,
+			(Kind::I8, Kind::F64) | (Kind::I16, Kind::F64) | (Kind::I32, Kind::F64) => true,
+			(Kind::F32, Kind::F64) => true,
+			_ => false
+		}
+	}
+	
 	pub fn is_list(&self) -> bool {
 		match *self {
 			Kind::List => true,
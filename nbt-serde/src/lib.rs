@@ -1,14 +1,23 @@
 #[macro_use] extern crate serde;
 extern crate flate2;
 extern crate byteorder;
+extern crate nbt;
 
 #[macro_use] mod macros;
 
 pub use error::{Error, Result};
-pub use encode::Encoder;
-pub use decode::Decoder;
+pub use encode::{Encoder, Unsigned};
+pub use decode::{Decoder, FieldMatching, StreamDecoder};
+pub use flavor::Flavor;
+pub use value::{from_value, to_value, WithExtra};
 
+pub mod arrays;
 pub mod error;
 pub mod encode;
 pub mod decode;
+pub mod flavor;
 pub mod kind;
+pub mod hooks;
+pub mod snbt;
+pub mod strings;
+pub mod value;
@@ -172,3 +172,44 @@ macro_rules! return_expr_for_serialized_types {
         $(return_expr_for_serialized_types_helper!{$expr, $type})*
     };
 }
+
+/// Helper macro for implementing the `serde::se::Serializer` trait for the
+/// root `Encoder`.
+///
+/// Each method serializes the value as a bare root tag, as `InnerEncoder`
+/// would, if any root is allowed, and otherwise fails with
+/// `Error::NoRootCompound`.
+macro_rules! forward_to_bare {
+    ($($func:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            #[inline]
+            fn $func(self, $($arg: $ty),*) -> Result<()> {
+                ser::Serializer::$func(&mut self.bare()?, $($arg),*)?;
+                self.end_root()
+            }
+        )*
+    };
+}
+
+/// Helper macro for implementing the `serde::de::Deserializer` trait for the
+/// root `Decoder`.
+///
+/// Each method reads the header of the root and deserializes its payload as
+/// `InnerDecoder` would. These are the types that cannot be a root compound,
+/// so they fail with `Error::NoRootCompound` unless any root is allowed.
+macro_rules! forward_to_root {
+    ($($func:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            #[inline]
+            fn $func<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value>
+                where V: de::Visitor
+            {
+                if !self.any_root {
+                    return Err(Error::NoRootCompound);
+                }
+                let tag = self.open_root()?;
+                de::Deserializer::$func(&mut self.root(tag), $($arg,)* visitor)
+            }
+        )*
+    };
+}
@@ -0,0 +1,390 @@
+//! Serializing to stringified NBT (SNBT), the text form used in Minecraft
+//! commands.
+//!
+//! Values are written as the `Encoder` would write them in binary: sequences
+//! of `i8`, `i32` and `i64` become the typed arrays `[B;...]`, `[I;...]` and
+//! `[L;...]`, other sequences become lists, structs and string-keyed maps
+//! become compounds, leaving out fields that are `None`, and enums follow the
+//! `Encoder`'s convention. The wrappers in `arrays` choose between a list
+//! and an array as they do in binary. Numbers carry the suffixes the game
+//! expects, and keys and strings are quoted and escaped the
+//! way the game does. Unlike the binary format, any value may be the root,
+//! so a single number or list can be rendered too.
+
+use std::io;
+
+use nbt::snbt;
+use serde;
+use serde::ser;
+
+use arrays;
+use encode::{KeyEncoder, MISSING_KEY};
+use error::{Error, Result};
+use kind::Kind;
+
+/// Serializes `value` as compact SNBT.
+pub fn to_string<T>(value: &T) -> Result<String>
+    where T: ?Sized + ser::Serialize
+{
+    match value.serialize(Serializer(None))? {
+        Some(tag) => Ok(tag.text),
+        None => Err(Error::UnrepresentableType("none")),
+    }
+}
+
+/// Serializes `value` as compact SNBT to the given `io::Write` destination.
+pub fn to_writer<W, T>(dst: &mut W, value: &T) -> Result<()>
+    where W: ?Sized + io::Write,
+          T: ?Sized + ser::Serialize,
+{
+    dst.write_all(to_string(value)?.as_bytes()).map_err(From::from)
+}
+
+/// A value rendered as SNBT, with the kind of tag it stands for.
+struct Tag {
+    kind: Kind,
+    text: String,
+}
+
+impl Tag {
+    fn new(kind: Kind, text: String) -> Option<Tag> {
+        Some(Tag { kind, text })
+    }
+}
+
+// Numbers, strings and keys are written by the core crate's SNBT writer.
+fn float(v: f64, finite: String, suffix: char) -> String {
+    let mut out = String::new();
+    snbt::write_float(&mut out, v, finite, suffix);
+    out
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    snbt::write_string(&mut out, s);
+    out
+}
+
+fn key(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    snbt::write_key(&mut out, s);
+    out
+}
+
+/// Renders a single value. `None` stands for a missing optional value.
+/// Holds the container forced on a sequence by a wrapper from `arrays`.
+#[derive(Clone, Copy)]
+struct Serializer(Option<Kind>);
+
+impl serde::Serializer for Serializer {
+    type Ok = Option<Tag>;
+    type Error = Error;
+    type SerializeSeq = Seq;
+    type SerializeTuple = Seq;
+    type SerializeTupleStruct = Seq;
+    type SerializeTupleVariant = ser::Impossible<Option<Tag>, Error>;
+    type SerializeMap = Compound;
+    type SerializeStruct = Compound;
+    type SerializeStructVariant = Compound;
+
+    fn serialize_bool(self, value: bool) -> Result<Option<Tag>> {
+        self.serialize_i8(value as i8)
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<Option<Tag>> {
+        Ok(Tag::new(Kind::I8, format!("{}b", value)))
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<Option<Tag>> {
+        Ok(Tag::new(Kind::I16, format!("{}s", value)))
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<Option<Tag>> {
+        Ok(Tag::new(Kind::I32, value.to_string()))
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<Option<Tag>> {
+        Ok(Tag::new(Kind::I64, format!("{}L", value)))
+    }
+
+    // Unsigned numbers are stored with the bits of the signed tag of the
+    // same width, as the binary encoder does.
+    fn serialize_u8(self, value: u8) -> Result<Option<Tag>> {
+        self.serialize_i8(value as i8)
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<Option<Tag>> {
+        self.serialize_i16(value as i16)
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<Option<Tag>> {
+        self.serialize_i32(value as i32)
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<Option<Tag>> {
+        self.serialize_i64(value as i64)
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<Option<Tag>> {
+        Ok(Tag::new(Kind::F32, float(value as f64, format!("{:?}", value), 'f')))
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<Option<Tag>> {
+        Ok(Tag::new(Kind::F64, float(value, format!("{:?}", value), 'd')))
+    }
+
+    fn serialize_char(self, _value: char) -> Result<Option<Tag>> {
+        Err(Error::UnrepresentableType("char"))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<Option<Tag>> {
+        Ok(Tag::new(Kind::String, quote(value)))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<Option<Tag>> {
+        let elements: Vec<String> = value.iter().map(|&b| format!("{}b", b as i8)).collect();
+        Ok(Tag::new(Kind::I8Array, format!("[B;{}]", elements.join(","))))
+    }
+
+    fn serialize_none(self) -> Result<Option<Tag>> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Option<Tag>>
+        where T: ser::Serialize
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Option<Tag>> {
+        Err(Error::UnrepresentableType("unit"))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Option<Tag>> {
+        Ok(Tag::new(Kind::Compound, "{}".to_string()))
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: usize,
+                              variant: &'static str) -> Result<Option<Tag>>
+    {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized>(self, name: &'static str, value: &T)
+                                           -> Result<Option<Tag>>
+        where T: ser::Serialize
+    {
+        value.serialize(Serializer(arrays::forced_container(name)))
+    }
+
+    fn serialize_newtype_variant<T: ?Sized>(self, _name: &'static str, _index: usize,
+                                            variant: &'static str, value: &T)
+                                            -> Result<Option<Tag>>
+        where T: ser::Serialize
+    {
+        let mut compound = Compound::new(None);
+        compound.entry(variant, value)?;
+        Ok(compound.finish())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Seq> {
+        Ok(Seq { elements: Vec::new(), forced: self.0 })
+    }
+
+    fn serialize_seq_fixed_size(self, _len: usize) -> Result<Seq> {
+        Ok(Seq { elements: Vec::new(), forced: self.0 })
+    }
+
+    /// Tuples are always lists.
+    fn serialize_tuple(self, _len: usize) -> Result<Seq> {
+        Ok(Seq { elements: Vec::new(), forced: Some(Kind::List) })
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Seq> {
+        Ok(Seq { elements: Vec::new(), forced: Some(Kind::List) })
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: usize,
+                               _variant: &'static str, _len: usize)
+                               -> Result<Self::SerializeTupleVariant>
+    {
+        Err(Error::UnrepresentableType("tuple variant"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound> {
+        Ok(Compound::new(None))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Compound> {
+        Ok(Compound::new(None))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: usize,
+                                variant: &'static str, _len: usize)
+                                -> Result<Compound>
+    {
+        Ok(Compound::new(Some(variant)))
+    }
+}
+
+/// Collects the elements of a sequence, which becomes a typed array or a
+/// list once they are all known.
+struct Seq {
+    elements: Vec<Tag>,
+    forced: Option<Kind>,
+}
+
+impl ser::SerializeSeq for Seq {
+    type Ok = Option<Tag>;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        let tag = match value.serialize(Serializer(None))? {
+            Some(tag) => tag,
+            None => return Err(Error::UnrepresentableType("none in a list")),
+        };
+        if let Some(first) = self.elements.first() {
+            if first.kind != tag.kind {
+                return Err(Error::HeterogenousList { original: first.kind, new: tag.kind });
+            }
+        }
+        self.elements.push(tag);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Option<Tag>> {
+        let kind = self.elements.first().map_or(Kind::End, |tag| tag.kind);
+        let container = match self.forced {
+            Some(Kind::List) => Kind::List,
+            Some(array) if kind == Kind::End || kind.list_container() == array => array,
+            Some(array) => return Err(Error::UnexpectedTag(kind.list_container(), array)),
+            None => kind.list_container(),
+        };
+        let prefix = match container {
+            Kind::I8Array => "B;",
+            Kind::I32Array => "I;",
+            Kind::I64Array => "L;",
+            _ => "",
+        };
+        let elements: Vec<String> = self.elements.into_iter().map(|tag| tag.text).collect();
+        Ok(Tag::new(container, format!("[{}{}]", prefix, elements.join(","))))
+    }
+}
+
+impl ser::SerializeTuple for Seq {
+    type Ok = Option<Tag>;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Option<Tag>> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for Seq {
+    type Ok = Option<Tag>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Option<Tag>> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+/// Collects the entries of a compound.
+struct Compound {
+    entries: Vec<String>,
+    /// The key of a map entry whose value has not been serialized yet.
+    key: Option<String>,
+    /// The name of the variant, for a struct variant, which is wrapped in a
+    /// compound with a single entry named after it.
+    variant: Option<&'static str>,
+}
+
+impl Compound {
+    fn new(variant: Option<&'static str>) -> Compound {
+        Compound { entries: Vec::new(), key: None, variant }
+    }
+
+    fn entry<T: ?Sized>(&mut self, name: &str, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        if let Some(tag) = value.serialize(Serializer(None))? {
+            self.entries.push(format!("{}:{}", key(name), tag.text));
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Option<Tag> {
+        let text = format!("{{{}}}", self.entries.join(","));
+        match self.variant {
+            Some(variant) => Tag::new(Kind::Compound, format!("{{{}:{}}}", key(variant), text)),
+            None => Tag::new(Kind::Compound, text),
+        }
+    }
+}
+
+impl ser::SerializeStruct for Compound {
+    type Ok = Option<Tag>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        self.entry(key, value)
+    }
+
+    fn end(self) -> Result<Option<Tag>> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for Compound {
+    type Ok = Option<Tag>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        self.entry(key, value)
+    }
+
+    fn end(self) -> Result<Option<Tag>> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeMap for Compound {
+    type Ok = Option<Tag>;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        self.key = Some(key.serialize(KeyEncoder)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        let name = self.key.take().ok_or(Error::InvalidWrite(MISSING_KEY))?;
+        self.entry(&name, value)
+    }
+
+    fn end(self) -> Result<Option<Tag>> {
+        Ok(self.finish())
+    }
+}
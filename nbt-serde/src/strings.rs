@@ -0,0 +1,139 @@
+//! Encodings for the bytes of NBT strings.
+//!
+//! The NBT format stores strings in Java's "modified UTF-8", which differs from
+//! standard UTF-8 in how it encodes the NUL character and characters outside of
+//! the Basic Multilingual Plane. Most strings are plain ASCII, where the two
+//! agree, so the encoder and decoder use strict UTF-8 by default. A different
+//! `StringCodec` can be selected with `Encoder::with_string_codec` and
+//! `Decoder::with_string_codec`, for example to read files from very old
+//! versions or plugins that wrote strings in Latin-1.
+
+use std::borrow::Cow;
+use std::str;
+
+use error::{Error, Result};
+
+/// A scheme for converting between strings and the bytes stored in NBT.
+pub trait StringCodec {
+    /// Converts the stored bytes of a string to a `String`.
+    fn decode(&self, bytes: Vec<u8>) -> Result<String>;
+
+    /// Converts the stored bytes of a string, borrowing them if they need no
+    /// conversion. The decoder uses this for compound keys, which usually
+    /// only need to be compared with field names.
+    fn decode_ref<'a>(&self, bytes: &'a [u8]) -> Result<Cow<'a, str>> {
+        self.decode(bytes.to_vec()).map(Cow::Owned)
+    }
+
+    /// Converts a string to the bytes to be stored.
+    fn encode<'a>(&self, value: &'a str) -> Result<Cow<'a, [u8]>>;
+}
+
+/// Standard UTF-8, rejecting strings that are not valid UTF-8. This is the
+/// default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Utf8;
+
+impl StringCodec for Utf8 {
+    fn decode(&self, bytes: Vec<u8>) -> Result<String> {
+        String::from_utf8(bytes).map_err(From::from)
+    }
+
+    fn decode_ref<'a>(&self, bytes: &'a [u8]) -> Result<Cow<'a, str>> {
+        str::from_utf8(bytes).map(Cow::Borrowed).map_err(From::from)
+    }
+
+    fn encode<'a>(&self, value: &'a str) -> Result<Cow<'a, [u8]>> {
+        Ok(Cow::Borrowed(value.as_bytes()))
+    }
+}
+
+/// Java's modified UTF-8, exactly as the game reads and writes it.
+///
+/// NUL is encoded as the two bytes `0xc0 0x80`, and characters outside of the
+/// Basic Multilingual Plane are encoded as a surrogate pair of three-byte
+/// sequences. Four-byte sequences and unpaired surrogates are rejected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModifiedUtf8;
+
+impl StringCodec for ModifiedUtf8 {
+    fn decode(&self, bytes: Vec<u8>) -> Result<String> {
+        // Without NULs or surrogates, modified UTF-8 is just UTF-8.
+        if !bytes.iter().any(|&b| b == 0xc0 || b == 0xed) {
+            return String::from_utf8(bytes).map_err(From::from);
+        }
+        let mut units = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let b = bytes[i] as u16;
+            let cont = |j: usize| match bytes.get(i + j) {
+                Some(&c) if c & 0xc0 == 0x80 => Ok((c & 0x3f) as u16),
+                _ => Err(Error::InvalidUtf8),
+            };
+            if b < 0x80 {
+                units.push(b);
+                i += 1;
+            } else if b & 0xe0 == 0xc0 {
+                units.push(((b & 0x1f) << 6) | cont(1)?);
+                i += 2;
+            } else if b & 0xf0 == 0xe0 {
+                units.push(((b & 0x0f) << 12) | (cont(1)? << 6) | cont(2)?);
+                i += 3;
+            } else {
+                return Err(Error::InvalidUtf8);
+            }
+        }
+        String::from_utf16(&units).map_err(|_| Error::InvalidUtf8)
+    }
+
+    fn decode_ref<'a>(&self, bytes: &'a [u8]) -> Result<Cow<'a, str>> {
+        if !bytes.iter().any(|&b| b == 0xc0 || b == 0xed) {
+            return str::from_utf8(bytes).map(Cow::Borrowed).map_err(From::from);
+        }
+        self.decode(bytes.to_vec()).map(Cow::Owned)
+    }
+
+    fn encode<'a>(&self, value: &'a str) -> Result<Cow<'a, [u8]>> {
+        if !value.chars().any(|c| c == '\0' || c as u32 > 0xffff) {
+            return Ok(Cow::Borrowed(value.as_bytes()));
+        }
+        let mut bytes = Vec::with_capacity(value.len() + 8);
+        for unit in value.encode_utf16() {
+            match unit {
+                0x0001..=0x007f => bytes.push(unit as u8),
+                0x0000 | 0x0080..=0x07ff => {
+                    bytes.push(0xc0 | (unit >> 6) as u8);
+                    bytes.push(0x80 | (unit & 0x3f) as u8);
+                },
+                _ => {
+                    bytes.push(0xe0 | (unit >> 12) as u8);
+                    bytes.push(0x80 | ((unit >> 6) & 0x3f) as u8);
+                    bytes.push(0x80 | (unit & 0x3f) as u8);
+                },
+            }
+        }
+        Ok(Cow::Owned(bytes))
+    }
+}
+
+/// Latin-1 (ISO 8859-1), for recovering strings written by legacy software
+/// that did not use UTF-8.
+///
+/// Every byte decodes to the character with the same code point, so decoding
+/// never fails. Characters above `U+00FF` cannot be represented and are
+/// encoded as `?`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Latin1;
+
+impl StringCodec for Latin1 {
+    fn decode(&self, bytes: Vec<u8>) -> Result<String> {
+        Ok(bytes.into_iter().map(char::from).collect())
+    }
+
+    fn encode<'a>(&self, value: &'a str) -> Result<Cow<'a, [u8]>> {
+        if value.is_ascii() {
+            return Ok(Cow::Borrowed(value.as_bytes()));
+        }
+        Ok(Cow::Owned(value.chars().map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' }).collect()))
+    }
+}
@@ -0,0 +1,848 @@
+//! Converting between typed values and the dynamic `nbt::Value` tree.
+//!
+//! `to_value` builds the tree the `Encoder` would write, and `from_value`
+//! reads a type back out of a tree as the `Decoder` would read it from the
+//! same bytes, so data can be inspected or patched between the two without
+//! a round trip through binary. The `Encoder`'s defaults apply: unsigned
+//! numbers keep the bits of the signed tag of the same width. Numbers must be
+//! stored with the tag matching their type, as for a `Decoder` with
+//! `with_strict_numbers`. As with `snbt`, any value may be the root, not only
+//! a compound.
+//!
+//! `WithExtra` keeps the entries of a compound that a struct has no field
+//! for, such as tags added by mods, so that rewriting the data does not drop
+//! them.
+
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::vec;
+
+use nbt::Value;
+use nbt::markers::VALUE;
+use serde;
+use serde::{de, ser};
+use serde::de::value::{StringDeserializer, U8Deserializer, ValueDeserializer};
+
+use arrays;
+use encode::{KeyEncoder, MISSING_KEY};
+use error::{Error, Result};
+use kind::Kind;
+
+/// Converts `value` to the `Value` tree the `Encoder` would write for it.
+pub fn to_value<T>(value: &T) -> Result<Value>
+    where T: ?Sized + ser::Serialize
+{
+    match value.serialize(Serializer(None))? {
+        Some(value) => Ok(value),
+        None => Err(Error::UnrepresentableType("none")),
+    }
+}
+
+/// Reads a `T` out of a `Value` tree.
+pub fn from_value<T>(value: Value) -> Result<T>
+    where T: de::Deserialize
+{
+    T::deserialize(Deserializer(value))
+}
+
+/// A struct along with the entries of its compound that it has no field for.
+///
+/// When deserializing, the entries named by the fields of `T` go to `value`
+/// and the rest are kept in `extra`. When serializing, the extra entries are
+/// written alongside the fields, which win if both have the same name.
+///
+/// ```ignore
+/// let machine: WithExtra<Machine> = nbt_serde::decode::from_slice(&bytes)?;
+/// machine.value.energy += 10;
+/// let bytes = nbt_serde::encode::to_vec(&machine, None)?;
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WithExtra<T> {
+    /// The fields `T` declares.
+    pub value: T,
+    /// Every other entry of the compound.
+    pub extra: HashMap<String, Value>,
+}
+
+impl<T: ser::Serialize> ser::Serialize for WithExtra<T> {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+        where S: ser::Serializer
+    {
+        let mut value = to_value(&self.value).map_err(ser::Error::custom)?;
+        match value {
+            Value::Compound(ref mut map) => {
+                for (key, extra) in &self.extra {
+                    map.entry(key.clone()).or_insert_with(|| extra.clone());
+                }
+            },
+            _ => return Err(ser::Error::custom("only a struct or map can have extra entries")),
+        }
+        value.serialize(serializer)
+    }
+}
+
+impl<T: de::Deserialize> de::Deserialize for WithExtra<T> {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+        where D: de::Deserializer
+    {
+        let mut extra = match Value::deserialize(deserializer)? {
+            Value::Compound(map) => map,
+            _ => return Err(de::Error::custom("extra entries can only be kept from a compound")),
+        };
+        let value = T::deserialize(Capture { entries: &mut extra }).map_err(de::Error::custom)?;
+        Ok(WithExtra { value, extra })
+    }
+}
+
+fn kind_of(value: &Value) -> Kind {
+    Kind::from_id(value.id() as i8).expect("values always have a valid tag")
+}
+
+/// Builds a single value. `None` stands for a missing optional value.
+/// Holds the container forced on a sequence by a wrapper from `arrays`.
+#[derive(Clone, Copy)]
+struct Serializer(Option<Kind>);
+
+impl serde::Serializer for Serializer {
+    type Ok = Option<Value>;
+    type Error = Error;
+    type SerializeSeq = Seq;
+    type SerializeTuple = Seq;
+    type SerializeTupleStruct = Seq;
+    type SerializeTupleVariant = ser::Impossible<Option<Value>, Error>;
+    type SerializeMap = Compound;
+    type SerializeStruct = Compound;
+    type SerializeStructVariant = Compound;
+
+    fn serialize_bool(self, value: bool) -> Result<Option<Value>> {
+        self.serialize_i8(value as i8)
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<Option<Value>> {
+        Ok(Some(Value::Byte(value)))
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<Option<Value>> {
+        Ok(Some(Value::Short(value)))
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<Option<Value>> {
+        Ok(Some(Value::Int(value)))
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<Option<Value>> {
+        Ok(Some(Value::Long(value)))
+    }
+
+    // Unsigned numbers are stored with the bits of the signed tag of the
+    // same width, as the binary encoder does by default.
+    fn serialize_u8(self, value: u8) -> Result<Option<Value>> {
+        self.serialize_i8(value as i8)
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<Option<Value>> {
+        self.serialize_i16(value as i16)
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<Option<Value>> {
+        self.serialize_i32(value as i32)
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<Option<Value>> {
+        self.serialize_i64(value as i64)
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<Option<Value>> {
+        Ok(Some(Value::Float(value)))
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<Option<Value>> {
+        Ok(Some(Value::Double(value)))
+    }
+
+    fn serialize_char(self, _value: char) -> Result<Option<Value>> {
+        Err(Error::UnrepresentableType("char"))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<Option<Value>> {
+        Ok(Some(Value::String(value.to_string())))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<Option<Value>> {
+        Ok(Some(Value::ByteArray(value.iter().map(|&b| b as i8).collect())))
+    }
+
+    fn serialize_none(self) -> Result<Option<Value>> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Option<Value>>
+        where T: ser::Serialize
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Option<Value>> {
+        Err(Error::UnrepresentableType("unit"))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Option<Value>> {
+        Ok(Some(Value::Compound(HashMap::new())))
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: usize,
+                              variant: &'static str) -> Result<Option<Value>>
+    {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized>(self, name: &'static str, value: &T)
+                                           -> Result<Option<Value>>
+        where T: ser::Serialize
+    {
+        value.serialize(Serializer(arrays::forced_container(name)))
+    }
+
+    fn serialize_newtype_variant<T: ?Sized>(self, _name: &'static str, _index: usize,
+                                            variant: &'static str, value: &T)
+                                            -> Result<Option<Value>>
+        where T: ser::Serialize
+    {
+        let mut compound = Compound::new(None);
+        compound.entry(variant, value)?;
+        Ok(compound.finish())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Seq> {
+        Ok(Seq { elements: Vec::new(), forced: self.0 })
+    }
+
+    fn serialize_seq_fixed_size(self, _len: usize) -> Result<Seq> {
+        Ok(Seq { elements: Vec::new(), forced: self.0 })
+    }
+
+    /// Tuples are always lists.
+    fn serialize_tuple(self, _len: usize) -> Result<Seq> {
+        Ok(Seq { elements: Vec::new(), forced: Some(Kind::List) })
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Seq> {
+        Ok(Seq { elements: Vec::new(), forced: Some(Kind::List) })
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: usize,
+                               _variant: &'static str, _len: usize)
+                               -> Result<Self::SerializeTupleVariant>
+    {
+        Err(Error::UnrepresentableType("tuple variant"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound> {
+        Ok(Compound::new(None))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Compound> {
+        Ok(Compound::new(None))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: usize,
+                                variant: &'static str, _len: usize)
+                                -> Result<Compound>
+    {
+        Ok(Compound::new(Some(variant)))
+    }
+}
+
+/// Collects the elements of a sequence, which becomes a typed array or a
+/// list once they are all known.
+struct Seq {
+    elements: Vec<Value>,
+    forced: Option<Kind>,
+}
+
+impl ser::SerializeSeq for Seq {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        let value = match value.serialize(Serializer(None))? {
+            Some(value) => value,
+            None => return Err(Error::UnrepresentableType("none in a list")),
+        };
+        let value = match (self.elements.first(), value) {
+            // An empty list takes the kind of its siblings, as it does in the
+            // `Encoder`.
+            (Some(&Value::ByteArray(_)), Value::List(ref v)) if v.is_empty() => Value::ByteArray(Vec::new()),
+            (Some(&Value::IntArray(_)), Value::List(ref v)) if v.is_empty() => Value::IntArray(Vec::new()),
+            (Some(&Value::LongArray(_)), Value::List(ref v)) if v.is_empty() => Value::LongArray(Vec::new()),
+            // Inside a list of lists, numbers become a list rather than an
+            // array.
+            (Some(&Value::List(_)), value) => match value {
+                Value::ByteArray(v) => Value::List(v.into_iter().map(Value::Byte).collect()),
+                Value::IntArray(v) => Value::List(v.into_iter().map(Value::Int).collect()),
+                Value::LongArray(v) => Value::List(v.into_iter().map(Value::Long).collect()),
+                value => value,
+            },
+            (_, value) => value,
+        };
+        if let Some(first) = self.elements.first() {
+            if first.id() != value.id() {
+                return Err(Error::HeterogenousList { original: kind_of(first), new: kind_of(&value) });
+            }
+        }
+        self.elements.push(value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Option<Value>> {
+        let kind = self.elements.first().map_or(Kind::End, kind_of);
+        let container = match self.forced {
+            Some(Kind::List) => Kind::List,
+            Some(array) if kind == Kind::End || kind.list_container() == array => array,
+            Some(array) => return Err(Error::UnexpectedTag(kind.list_container(), array)),
+            None => kind.list_container(),
+        };
+        let elements = self.elements.into_iter();
+        Ok(Some(match container {
+            Kind::I8Array => Value::ByteArray(elements.map(|v| match v {
+                Value::Byte(b) => b,
+                _ => unreachable!(),
+            }).collect()),
+            Kind::I32Array => Value::IntArray(elements.map(|v| match v {
+                Value::Int(i) => i,
+                _ => unreachable!(),
+            }).collect()),
+            Kind::I64Array => Value::LongArray(elements.map(|v| match v {
+                Value::Long(l) => l,
+                _ => unreachable!(),
+            }).collect()),
+            _ => Value::List(elements.collect()),
+        }))
+    }
+}
+
+impl ser::SerializeTuple for Seq {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Option<Value>> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for Seq {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Option<Value>> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+/// Collects the entries of a compound.
+struct Compound {
+    entries: HashMap<String, Value>,
+    /// The key of a map entry whose value has not been serialized yet.
+    key: Option<String>,
+    /// The name of the variant, for a struct variant, which is wrapped in a
+    /// compound with a single entry named after it.
+    variant: Option<&'static str>,
+}
+
+impl Compound {
+    fn new(variant: Option<&'static str>) -> Compound {
+        Compound { entries: HashMap::new(), key: None, variant }
+    }
+
+    fn entry<T: ?Sized>(&mut self, name: &str, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        if let Some(value) = value.serialize(Serializer(None))? {
+            self.entries.insert(name.to_string(), value);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Option<Value> {
+        let compound = Value::Compound(self.entries);
+        match self.variant {
+            Some(variant) => {
+                let mut outer = HashMap::new();
+                outer.insert(variant.to_string(), compound);
+                Some(Value::Compound(outer))
+            },
+            None => Some(compound),
+        }
+    }
+}
+
+impl ser::SerializeStruct for Compound {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        self.entry(key, value)
+    }
+
+    fn end(self) -> Result<Option<Value>> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for Compound {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        self.entry(key, value)
+    }
+
+    fn end(self) -> Result<Option<Value>> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeMap for Compound {
+    type Ok = Option<Value>;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        self.key = Some(key.serialize(KeyEncoder)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
+        where T: ser::Serialize
+    {
+        let name = self.key.take().ok_or(Error::InvalidWrite(MISSING_KEY))?;
+        self.entry(&name, value)
+    }
+
+    fn end(self) -> Result<Option<Value>> {
+        Ok(self.finish())
+    }
+}
+
+/// Reads a single value out of the tree.
+struct Deserializer(Value);
+
+impl Deserializer {
+    /// Fails for a number stored with a tag other than `expected`. A value
+    /// that is not a number at all is left to the visitor to reject.
+    fn check_number(&self, expected: Kind) -> Result<()> {
+        let found = kind_of(&self.0);
+        let number = matches!(found, Kind::I8 | Kind::I16 | Kind::I32 | Kind::I64 | Kind::F32 | Kind::F64);
+        if number && found != expected {
+            return Err(Error::UnexpectedTag(found, expected));
+        }
+        Ok(())
+    }
+
+    /// Reads an unsigned integer no larger than `max`, whose signed
+    /// counterpart is `kind`.
+    fn unsigned(&self, kind: Kind, max: u64) -> Result<u64> {
+        let value = match self.0 {
+            Value::Byte(v) => i64::from(v),
+            Value::Short(v) => i64::from(v),
+            Value::Int(v) => i64::from(v),
+            Value::Long(v) => v,
+            ref other => return Err(Error::UnexpectedTag(kind_of(other), kind)),
+        };
+        if kind_of(&self.0) == kind {
+            return Ok(value as u64 & max);
+        }
+        if value < 0 || value as u64 > max {
+            return Err(de::Error::invalid_value(de::Unexpected::Signed(value),
+                                                &"an unsigned integer in range"));
+        }
+        Ok(value as u64)
+    }
+}
+
+macro_rules! deserialize_number {
+    ($($method:ident => $kind:ident,)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value>
+                where V: de::Visitor
+            {
+                self.check_number(Kind::$kind)?;
+                self.deserialize(visitor)
+            }
+        )*
+    };
+}
+
+impl de::Deserializer for Deserializer {
+    type Error = Error;
+
+    fn deserialize<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.0 {
+            Value::Byte(v) => visitor.visit_i8(v),
+            Value::Short(v) => visitor.visit_i16(v),
+            Value::Int(v) => visitor.visit_i32(v),
+            Value::Long(v) => visitor.visit_i64(v),
+            Value::Float(v) => visitor.visit_f32(v),
+            Value::Double(v) => visitor.visit_f64(v),
+            Value::ByteArray(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter().map(Value::Byte).collect())),
+            Value::String(v) => visitor.visit_string(v),
+            Value::List(v) => visitor.visit_seq(SeqDeserializer::new(v)),
+            Value::Compound(v) => visitor.visit_map(MapDeserializer::new(v)),
+            Value::IntArray(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter().map(Value::Int).collect())),
+            Value::LongArray(v) => visitor.visit_seq(SeqDeserializer::new(v.into_iter().map(Value::Long).collect())),
+        }
+    }
+
+    /// Deserialize bool values from a byte. Fail if that byte is not 0 or 1.
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.0 {
+            Value::Byte(0) => visitor.visit_bool(false),
+            Value::Byte(1) => visitor.visit_bool(true),
+            Value::Byte(b) => Err(Error::NonBooleanByte(b)),
+            ref other => Err(Error::UnexpectedTag(kind_of(other), Kind::I8)),
+        }
+    }
+
+    /// Values in the tree are never missing, so they are always `Some`.
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        visitor.visit_some(self)
+    }
+
+    // Signed numbers must be stored with the tag matching their type.
+    deserialize_number! {
+        deserialize_i8 => I8,
+        deserialize_i16 => I16,
+        deserialize_i32 => I32,
+        deserialize_i64 => I64,
+        deserialize_f32 => F32,
+        deserialize_f64 => F64,
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        visitor.visit_u8(self.unsigned(Kind::I8, u64::from(u8::MAX))? as u8)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        visitor.visit_u16(self.unsigned(Kind::I16, u64::from(u16::MAX))? as u16)
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        visitor.visit_u32(self.unsigned(Kind::I32, u64::from(u32::MAX))? as u32)
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        visitor.visit_u64(self.unsigned(Kind::I64, u64::MAX)?)
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V)
+                                  -> Result<V::Value>
+        where V: de::Visitor
+    {
+        visitor.visit_unit()
+    }
+
+    /// Deserialize newtype structs by their underlying types. An
+    /// `nbt::Value` is told the type of the tag, so that it can tell arrays
+    /// from lists.
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V)
+                                     -> Result<V::Value>
+        where V: de::Visitor
+    {
+        if name == VALUE {
+            return visitor.visit_enum(TaggedValue(self.0));
+        }
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.0 {
+            Value::ByteArray(v) => visitor.visit_byte_buf(v.into_iter().map(|b| b as u8).collect()),
+            other => Deserializer(other).deserialize(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    /// Deserialize unit variants from a string naming them, and other
+    /// variants from a compound with a single entry named after the variant.
+    fn deserialize_enum<V>(self, _name: &'static str,
+                           _variants: &'static [&'static str], visitor: V)
+                           -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.0 {
+            Value::String(name) => visitor.visit_enum(Variant { name, value: None }),
+            Value::Compound(map) => {
+                if map.len() != 1 {
+                    return Err(Error::InvalidVariant);
+                }
+                let (name, value) = map.into_iter().next().unwrap();
+                visitor.visit_enum(Variant { name, value: Some(value) })
+            },
+            ref other => Err(Error::UnexpectedTag(kind_of(other), Kind::Compound)),
+        }
+    }
+
+    forward_to_deserialize! {
+        char str string seq seq_fixed_size map struct
+        tuple_struct struct_field tuple
+        ignored_any
+    }
+}
+
+/// Reads the elements of a list or array.
+struct SeqDeserializer {
+    elements: vec::IntoIter<Value>,
+}
+
+impl SeqDeserializer {
+    fn new(elements: Vec<Value>) -> SeqDeserializer {
+        SeqDeserializer { elements: elements.into_iter() }
+    }
+}
+
+impl de::SeqVisitor for SeqDeserializer {
+    type Error = Error;
+
+    fn visit_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+        where K: de::DeserializeSeed
+    {
+        match self.elements.next() {
+            Some(value) => seed.deserialize(Deserializer(value)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.elements.size_hint()
+    }
+}
+
+/// Reads the entries of a compound.
+struct MapDeserializer {
+    entries: hash_map::IntoIter<String, Value>,
+    /// The value of the entry whose key has just been read.
+    value: Option<Value>,
+}
+
+impl MapDeserializer {
+    fn new(map: HashMap<String, Value>) -> MapDeserializer {
+        MapDeserializer { entries: map.into_iter(), value: None }
+    }
+}
+
+impl de::MapVisitor for MapDeserializer {
+    type Error = Error;
+
+    fn visit_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+        where K: de::DeserializeSeed
+    {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                let de: StringDeserializer<Error> = key.into_deserializer();
+                seed.deserialize(de).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn visit_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+        where V: de::DeserializeSeed
+    {
+        let value = match self.value.take() {
+            Some(value) => value,
+            None => return Err(de::Error::custom("a map value was read without its key")),
+        };
+        seed.deserialize(Deserializer(value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+/// Reads an enum variant: a unit variant from its name alone, and any other
+/// from the single entry of a compound.
+struct Variant {
+    name: String,
+    value: Option<Value>,
+}
+
+impl de::EnumVisitor for Variant {
+    type Error = Error;
+    type Variant = Self;
+
+    fn visit_variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+        where V: de::DeserializeSeed
+    {
+        let de: StringDeserializer<Error> = self.name.clone().into_deserializer();
+        Ok((seed.deserialize(de)?, self))
+    }
+}
+
+impl de::VariantVisitor for Variant {
+    type Error = Error;
+
+    fn visit_unit(self) -> Result<()> {
+        match self.value {
+            None => Ok(()),
+            Some(_) => Err(Error::UnexpectedTag(Kind::Compound, Kind::String)),
+        }
+    }
+
+    fn visit_newtype_seed<T>(self, seed: T) -> Result<T::Value>
+        where T: de::DeserializeSeed
+    {
+        match self.value {
+            Some(value) => seed.deserialize(Deserializer(value)),
+            None => Err(Error::UnexpectedTag(Kind::String, Kind::Compound)),
+        }
+    }
+
+    fn visit_tuple<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        Err(Error::UnrepresentableType("tuple variant"))
+    }
+
+    fn visit_struct<V>(self, _fields: &'static [&'static str], visitor: V)
+                       -> Result<V::Value>
+        where V: de::Visitor
+    {
+        match self.value {
+            Some(value) => de::Deserializer::deserialize(Deserializer(value), visitor),
+            None => Err(Error::UnexpectedTag(Kind::String, Kind::Compound)),
+        }
+    }
+}
+
+/// Reads a whole `Value` out of the tree, described as a variant named by
+/// the tag's type ID and holding its payload.
+struct TaggedValue(Value);
+
+impl de::EnumVisitor for TaggedValue {
+    type Error = Error;
+    type Variant = Self;
+
+    fn visit_variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+        where V: de::DeserializeSeed
+    {
+        let de: U8Deserializer<Error> = self.0.id().into_deserializer();
+        Ok((seed.deserialize(de)?, self))
+    }
+}
+
+impl de::VariantVisitor for TaggedValue {
+    type Error = Error;
+
+    fn visit_unit(self) -> Result<()> {
+        Err(Error::UnrepresentableType("unit variant"))
+    }
+
+    fn visit_newtype_seed<T>(self, seed: T) -> Result<T::Value>
+        where T: de::DeserializeSeed
+    {
+        seed.deserialize(Deserializer(self.0))
+    }
+
+    fn visit_tuple<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        Err(Error::UnrepresentableType("tuple variant"))
+    }
+
+    fn visit_struct<V>(self, _fields: &'static [&'static str], _visitor: V)
+                       -> Result<V::Value>
+        where V: de::Visitor
+    {
+        Err(Error::UnrepresentableType("struct variant"))
+    }
+}
+
+/// Gives a struct the entries of a compound it has fields for, leaving the
+/// rest behind. Any other type takes every entry.
+struct Capture<'a> {
+    entries: &'a mut HashMap<String, Value>,
+}
+
+impl<'a> de::Deserializer for Capture<'a> {
+    type Error = Error;
+
+    fn deserialize<V>(self, visitor: V) -> Result<V::Value>
+        where V: de::Visitor
+    {
+        let entries = self.entries.drain().collect();
+        Deserializer(Value::Compound(entries)).deserialize(visitor)
+    }
+
+    fn deserialize_struct<V>(self, name: &'static str,
+                             fields: &'static [&'static str], visitor: V)
+                             -> Result<V::Value>
+        where V: de::Visitor
+    {
+        let known = fields.iter()
+            .filter_map(|&f| self.entries.remove(f).map(|v| (f.to_string(), v)))
+            .collect();
+        Deserializer(Value::Compound(known)).deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V)
+                                     -> Result<V::Value>
+        where V: de::Visitor
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize! {
+        bool u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char
+        str string bytes byte_buf unit unit_struct seq seq_fixed_size map
+        tuple_struct struct_field tuple enum option
+        ignored_any
+    }
+}
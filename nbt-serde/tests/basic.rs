@@ -2,12 +2,23 @@
 extern crate serde_derive;
 extern crate serde;
 
+extern crate nbt;
 extern crate nbt_serde;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use nbt_serde::encode::to_writer;
-use nbt_serde::decode::from_reader;
+use serde::{Deserialize, Serialize};
+use serde::bytes::ByteBuf;
+
+use nbt_serde::arrays::{IntArray, LongArray};
+use nbt_serde::{Decoder, Encoder, FieldMatching, Flavor, Unsigned};
+use nbt_serde::{from_value, to_value, WithExtra};
+use nbt_serde::encode::{to_vec, to_writer, to_gzip_writer, to_zlib_writer, Compression};
+use nbt_serde::decode::{from_reader, from_reader_named, from_slice, from_gzip, from_zlib};
+use nbt_serde::hooks::{Scalar, Target};
+use nbt_serde::kind::Kind;
+use nbt_serde::snbt;
+use nbt_serde::strings::{Latin1, ModifiedUtf8};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct ByteNbt {
@@ -282,6 +293,53 @@ fn deserialize_int_array() {
     assert_eq!(read, nbt)
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Heightmaps {
+    #[serde(rename = "MOTION_BLOCKING")]
+    motion_blocking: Vec<i64>,
+    #[serde(rename = "WORLD_SURFACE")]
+    world_surface: Vec<i64>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct LongArrayNbt {
+    #[serde(rename = "Heightmaps")]
+    heightmaps: Heightmaps,
+}
+
+#[test]
+fn deserialize_long_array() {
+    let nbt = LongArrayNbt {
+        heightmaps: Heightmaps { motion_blocking: vec![-2, 1 << 33], world_surface: vec![] },
+    };
+
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x0a,
+                0x00, 0x0a,
+                0x48, 0x65, 0x69, 0x67, 0x68, 0x74, 0x6d, 0x61, 0x70, 0x73,
+                0x0c,
+                    0x00, 0x0f,
+                    0x4d, 0x4f, 0x54, 0x49, 0x4f, 0x4e, 0x5f, 0x42, 0x4c, 0x4f, 0x43, 0x4b,
+                    0x49, 0x4e, 0x47,
+                    0x00, 0x00, 0x00, 0x02, // Length.
+                    // Content.
+                    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+                    0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00,
+                0x0c,
+                    0x00, 0x0d,
+                    0x57, 0x4f, 0x52, 0x4c, 0x44, 0x5f, 0x53, 0x55, 0x52, 0x46, 0x41, 0x43,
+                    0x45,
+                    0x00, 0x00, 0x00, 0x00, // Length.
+            0x00,
+        0x00
+    ];
+
+    let read: LongArrayNbt = from_reader(&bytes[..]).unwrap();
+    assert_eq!(read, nbt)
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct BoolNbt {
     data: bool,
@@ -517,3 +575,1131 @@ fn deserialize_hashmap() {
     let read: HashMap<String,i8> = from_reader(&bytes[..]).unwrap();
     assert_eq!(read, nbt)
 }
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct NestedListNbt {
+    data: Vec<Vec<i32>>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct DeepListNbt {
+    names: Vec<Vec<Vec<String>>>,
+    items: Vec<Vec<ByteNbt>>,
+}
+
+#[test]
+fn serialize_nested_lists() {
+    let nbt = NestedListNbt { data: vec![vec![1, 2], vec![], vec![3]] };
+
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &nbt, None).unwrap();
+
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x09,
+                0x00, 0x04,
+                0x64, 0x61, 0x74, 0x61,
+                0x0b, // An empty list among int arrays is an empty int array.
+                0x00, 0x00, 0x00, 0x03,
+                0x00, 0x00, 0x00, 0x02,
+                    0x00, 0x00, 0x00, 0x01,
+                    0x00, 0x00, 0x00, 0x02,
+                0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x01,
+                    0x00, 0x00, 0x00, 0x03,
+        0x00
+    ];
+
+    assert_eq!(bytes, dst);
+
+    let read: NestedListNbt = from_reader(&bytes[..]).unwrap();
+    assert_eq!(read, nbt);
+
+    // When the first inner list is empty, the rest are written as lists.
+    let nbt = NestedListNbt { data: vec![vec![], vec![7]] };
+
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &nbt, None).unwrap();
+
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x09,
+                0x00, 0x04,
+                0x64, 0x61, 0x74, 0x61,
+                0x09,
+                0x00, 0x00, 0x00, 0x02,
+                0x00, // TAG_End.
+                0x00, 0x00, 0x00, 0x00,
+                0x03,
+                0x00, 0x00, 0x00, 0x01,
+                    0x00, 0x00, 0x00, 0x07,
+        0x00
+    ];
+
+    assert_eq!(bytes, dst);
+
+    let read: NestedListNbt = from_reader(&bytes[..]).unwrap();
+    assert_eq!(read, nbt);
+
+    let nbt = DeepListNbt {
+        names: vec![vec![], vec![vec!["a".to_string()], vec![]], vec![vec!["b".to_string(), "c".to_string()]]],
+        items: vec![vec![ByteNbt { data: 1 }], vec![], vec![ByteNbt { data: 2 }, ByteNbt { data: 3 }]],
+    };
+
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &nbt, None).unwrap();
+    let read: DeepListNbt = from_reader(&dst[..]).unwrap();
+    assert_eq!(read, nbt);
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct MapNbt {
+    data: BTreeMap<String, i8>,
+}
+
+#[test]
+fn serialize_map() {
+    let mut data = BTreeMap::new();
+    data.insert("a".to_string(), 1);
+    data.insert("b".to_string(), 2);
+    let nbt = MapNbt { data };
+
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &nbt, None).unwrap();
+
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x0a,
+                0x00, 0x04,
+                0x64, 0x61, 0x74, 0x61,
+                0x01,
+                    0x00, 0x01,
+                    0x61,
+                    0x01,
+                0x01,
+                    0x00, 0x01,
+                    0x62,
+                    0x02,
+            0x00,
+        0x00
+    ];
+
+    assert_eq!(bytes, dst);
+
+    let read: MapNbt = from_reader(&bytes[..]).unwrap();
+    assert_eq!(read, nbt);
+
+    // A map can also be the root compound.
+    let mut root = HashMap::new();
+    root.insert("data".to_string(), 100i8);
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &root, None).unwrap();
+    let read: HashMap<String, i8> = from_reader(&dst[..]).unwrap();
+    assert_eq!(read, root);
+}
+
+#[test]
+fn serialize_modified_utf8_string() {
+    let nbt = StringNbt { data: "a\0\u{1f600}".to_string() };
+
+    let mut encoder = Encoder::new(Vec::new(), None).with_string_codec(ModifiedUtf8);
+    nbt.serialize(&mut encoder).unwrap();
+    let dst = encoder.into_inner();
+
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x08,
+                0x00, 0x04,
+                0x64, 0x61, 0x74, 0x61,
+                0x00, 0x09,
+                0x61, 0xc0, 0x80,
+                0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80,
+        0x00
+    ];
+
+    assert_eq!(bytes, dst);
+
+    let mut decoder = Decoder::new(&bytes[..]).with_string_codec(ModifiedUtf8);
+    let read = StringNbt::deserialize(&mut decoder).unwrap();
+    assert_eq!(read, nbt);
+
+    // The surrogates are not valid in standard UTF-8.
+    assert!(from_reader::<_, StringNbt>(&bytes[..]).is_err());
+}
+
+#[test]
+fn deserialize_latin1_string() {
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x08,
+                0x00, 0x04,
+                0x64, 0x61, 0x74, 0x61,
+                0x00, 0x03,
+                0x63, 0x61, 0xe9,
+        0x00
+    ];
+
+    assert!(from_reader::<_, StringNbt>(&bytes[..]).is_err());
+
+    let mut decoder = Decoder::new(&bytes[..]).with_string_codec(Latin1);
+    let read = StringNbt::deserialize(&mut decoder).unwrap();
+    assert_eq!(read, StringNbt { data: "ca\u{e9}".to_string() });
+
+    let mut encoder = Encoder::new(Vec::new(), None).with_string_codec(Latin1);
+    read.serialize(&mut encoder).unwrap();
+    assert_eq!(encoder.into_inner(), bytes);
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+struct LegacyPlayer {
+    spawn_x: i32,
+    Id: String,
+    Inner: ByteNbt,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[allow(non_snake_case)]
+struct Player {
+    SpawnX: i32,
+    id: String,
+    inner: ByteNbt,
+}
+
+#[test]
+fn deserialize_fields_loosely() {
+    let legacy = LegacyPlayer { spawn_x: 5, Id: "steve".to_string(), Inner: ByteNbt { data: 1 } };
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &legacy, None).unwrap();
+
+    assert!(from_reader::<_, Player>(&dst[..]).is_err());
+
+    let mut decoder = Decoder::new(&dst[..]).with_field_matching(FieldMatching::IgnoreCase);
+    assert!(Player::deserialize(&mut decoder).is_err());
+
+    let mut decoder = Decoder::new(&dst[..])
+        .with_field_matching(FieldMatching::IgnoreCaseAndUnderscores);
+    let read = Player::deserialize(&mut decoder).unwrap();
+    assert_eq!(read, Player { SpawnX: 5, id: "steve".to_string(), inner: ByteNbt { data: 1 } });
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct HookedItem {
+    id: String,
+    count: i32,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct HookedNbt {
+    name: String,
+    flag: bool,
+    items: Vec<HookedItem>,
+    data: Vec<i32>,
+}
+
+#[test]
+fn deserialize_with_hooks() {
+    let nbt = HookedNbt {
+        name: "chest".to_string(),
+        flag: true,
+        items: vec![HookedItem { id: "1".to_string(), count: 500 },
+                    HookedItem { id: "minecraft:dirt".to_string(), count: 3 }],
+        data: vec![1000],
+    };
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &nbt, None).unwrap();
+
+    let mut decoder = Decoder::new(&dst[..])
+        .with_hook(Target::Path("items.*.id".to_string()), |value| match value {
+            Scalar::String(ref id) if id == "1" => Scalar::String("minecraft:stone".to_string()),
+            other => other,
+        })
+        .with_hook(Target::Kind(Kind::I32), |value| match value {
+            Scalar::Int(v) => Scalar::Int(v.min(64)),
+            other => other,
+        });
+    let read = HookedNbt::deserialize(&mut decoder).unwrap();
+
+    assert_eq!(read, HookedNbt {
+        name: "chest".to_string(),
+        flag: true,
+        items: vec![HookedItem { id: "minecraft:stone".to_string(), count: 64 },
+                    HookedItem { id: "minecraft:dirt".to_string(), count: 3 }],
+        // Hooks are not run on array elements.
+        data: vec![1000],
+    });
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct PackedNbt {
+    states: Vec<i64>,
+    motion: Vec<f32>,
+}
+
+#[test]
+fn serialize_packed_arrays() {
+    let nbt = PackedNbt { states: vec![1, -1, 1 << 40], motion: vec![0.5, -2.0] };
+
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &nbt, None).unwrap();
+
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x0c,
+                0x00, 0x06,
+                0x73, 0x74, 0x61, 0x74, 0x65, 0x73,
+                0x00, 0x00, 0x00, 0x03,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+                0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x09,
+                0x00, 0x06,
+                0x6d, 0x6f, 0x74, 0x69, 0x6f, 0x6e,
+                0x05,
+                0x00, 0x00, 0x00, 0x02,
+                0x3f, 0x00, 0x00, 0x00,
+                0xc0, 0x00, 0x00, 0x00,
+        0x00
+    ];
+
+    assert_eq!(bytes, dst);
+
+    let read: PackedNbt = from_reader(&bytes[..]).unwrap();
+    assert_eq!(read, nbt);
+}
+
+#[derive(Serialize)]
+struct SnbtNbt {
+    name: String,
+    count: u8,
+    glint: bool,
+    lore: Vec<String>,
+    heights: Vec<i64>,
+    damage: Option<i16>,
+    extra: BTreeMap<String, f64>,
+}
+
+#[test]
+fn serialize_snbt() {
+    let mut extra = BTreeMap::new();
+    extra.insert("odd key".to_string(), 0.5);
+    let nbt = SnbtNbt {
+        name: "it's".to_string(),
+        count: 200,
+        glint: true,
+        lore: vec!["a".to_string(), "b\\".to_string()],
+        heights: vec![1, -2],
+        damage: None,
+        extra: extra,
+    };
+
+    assert_eq!(snbt::to_string(&nbt).unwrap(),
+               "{name:\"it's\",count:-56b,glint:1b,lore:[\"a\",\"b\\\\\"],heights:[L;1L,-2L],extra:{\"odd key\":0.5d}}");
+    assert_eq!(snbt::to_string(&vec![1.5f32]).unwrap(), "[1.5f]");
+    assert_eq!(snbt::to_string(&Some(3)).unwrap(), "3");
+}
+
+#[test]
+fn compressed_round_trip() {
+    let nbt = PackedNbt { states: vec![7; 64], motion: vec![0.25, 1.0] };
+
+    let mut plain = Vec::new();
+    to_writer(&mut plain, &nbt, None).unwrap();
+
+    let mut gzip = Vec::new();
+    to_gzip_writer(&mut gzip, &nbt, None, Compression::Best).unwrap();
+    assert_eq!(&gzip[..2], &[0x1f, 0x8b]);
+    assert!(gzip.len() < plain.len());
+    let read: PackedNbt = from_gzip(&gzip[..]).unwrap();
+    assert_eq!(read, nbt);
+
+    let mut zlib = Vec::new();
+    to_zlib_writer(&mut zlib, &nbt, None, Compression::Default).unwrap();
+    assert_eq!(zlib[0], 0x78);
+    let read: PackedNbt = from_zlib(&zlib[..]).unwrap();
+    assert_eq!(read, nbt);
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct PacketNbt {
+    id: i32,
+    time: i64,
+    name: String,
+    slots: Vec<i32>,
+    speed: i16,
+}
+
+#[test]
+fn bedrock_flavors() {
+    let nbt = PacketNbt { id: -2, time: 300, name: "hi".to_string(), slots: vec![1, -1], speed: 2 };
+
+    let mut encoder = Encoder::new(Vec::new(), None).with_flavor(Flavor::BedrockNetwork);
+    nbt.serialize(&mut encoder).unwrap();
+    let dst = encoder.into_inner();
+
+    let bytes = vec![
+        0x0a,
+            0x00,
+            0x03,
+                0x02,
+                0x69, 0x64,
+                0x03,
+            0x04,
+                0x04,
+                0x74, 0x69, 0x6d, 0x65,
+                0xd8, 0x04,
+            0x08,
+                0x04,
+                0x6e, 0x61, 0x6d, 0x65,
+                0x02,
+                0x68, 0x69,
+            0x0b,
+                0x05,
+                0x73, 0x6c, 0x6f, 0x74, 0x73,
+                0x04,
+                0x02,
+                0x01,
+            0x02,
+                0x05,
+                0x73, 0x70, 0x65, 0x65, 0x64,
+                0x02, 0x00,
+        0x00
+    ];
+
+    assert_eq!(bytes, dst);
+
+    let mut decoder = Decoder::new(&bytes[..]).with_flavor(Flavor::BedrockNetwork);
+    let read = PacketNbt::deserialize(&mut decoder).unwrap();
+    assert_eq!(read, nbt);
+
+    let mut encoder = Encoder::new(Vec::new(), None).with_flavor(Flavor::BedrockDisk);
+    nbt.serialize(&mut encoder).unwrap();
+    let dst = encoder.into_inner();
+    assert_eq!(&dst[..9], &[0x0a, 0x00, 0x00, 0x03, 0x02, 0x00, 0x69, 0x64, 0xfe]);
+
+    let mut decoder = Decoder::new(&dst[..]).with_flavor(Flavor::BedrockDisk);
+    let read = PacketNbt::deserialize(&mut decoder).unwrap();
+    assert_eq!(read, nbt);
+}
+
+#[test]
+fn deserialize_from_slice() {
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x08,
+                0x00, 0x04,
+                0x64, 0x61, 0x74, 0x61,
+                0x00, 0x02,
+                0x68, 0x69,
+        0x00
+    ];
+
+    let read: StringNbt = from_slice(&bytes).unwrap();
+    assert_eq!(read, StringNbt { data: "hi".to_string() });
+    assert_eq!(to_vec(&read, None).unwrap(), bytes);
+
+    let read: HashMap<String, String> = from_slice(&bytes).unwrap();
+    assert_eq!(read.get("data").map(|s| &s[..]), Some("hi"));
+
+    // Keys are checked as strictly as values.
+    let mut invalid = bytes.clone();
+    invalid[6] = 0xff;
+    assert!(from_slice::<StringNbt>(&invalid).is_err());
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Rarity {
+    Common,
+    Epic,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Payload {
+    Count(i8),
+    Potion { duration: i16 },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct EnumNbt {
+    rarity: Rarity,
+    payload: Payload,
+}
+
+#[test]
+fn serialize_enums() {
+    let nbt = EnumNbt { rarity: Rarity::Epic, payload: Payload::Potion { duration: 3 } };
+
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &nbt, None).unwrap();
+
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x08,
+                0x00, 0x06,
+                0x72, 0x61, 0x72, 0x69, 0x74, 0x79,
+                0x00, 0x04,
+                0x45, 0x70, 0x69, 0x63,
+            0x0a,
+                0x00, 0x07,
+                0x70, 0x61, 0x79, 0x6c, 0x6f, 0x61, 0x64,
+                0x0a,
+                    0x00, 0x06,
+                    0x50, 0x6f, 0x74, 0x69, 0x6f, 0x6e,
+                    0x02,
+                        0x00, 0x08,
+                        0x64, 0x75, 0x72, 0x61, 0x74, 0x69, 0x6f, 0x6e,
+                        0x00, 0x03,
+                    0x00,
+                0x00,
+        0x00
+    ];
+
+    assert_eq!(bytes, dst);
+
+    let read: EnumNbt = from_reader(&bytes[..]).unwrap();
+    assert_eq!(read, nbt);
+
+    let nbt = EnumNbt { rarity: Rarity::Common, payload: Payload::Count(5) };
+    let read: EnumNbt = from_slice(&to_vec(&nbt, None).unwrap()).unwrap();
+    assert_eq!(read, nbt);
+    assert_eq!(snbt::to_string(&nbt).unwrap(), "{rarity:\"Common\",payload:{Count:5b}}");
+    assert_eq!(snbt::to_string(&Payload::Potion { duration: 3 }).unwrap(), "{Potion:{duration:3s}}");
+
+    // A root newtype variant is a compound in its own right.
+    let read: Payload = from_slice(&to_vec(&Payload::Count(-1), None).unwrap()).unwrap();
+    assert_eq!(read, Payload::Count(-1));
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct BlobNbt {
+    data: ByteBuf,
+    parts: Vec<ByteBuf>,
+}
+
+#[test]
+fn serialize_byte_buffers() {
+    let nbt = BlobNbt {
+        data: ByteBuf::from(vec![1, 0xff]),
+        parts: vec![ByteBuf::from(vec![7]), ByteBuf::new()],
+    };
+
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &nbt, None).unwrap();
+
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x07,
+                0x00, 0x04,
+                0x64, 0x61, 0x74, 0x61,
+                0x00, 0x00, 0x00, 0x02,
+                0x01, 0xff,
+            0x09,
+                0x00, 0x05,
+                0x70, 0x61, 0x72, 0x74, 0x73,
+                0x07,
+                0x00, 0x00, 0x00, 0x02,
+                0x00, 0x00, 0x00, 0x01,
+                0x07,
+                0x00, 0x00, 0x00, 0x00,
+        0x00
+    ];
+
+    assert_eq!(bytes, dst);
+
+    let read: BlobNbt = from_reader(&bytes[..]).unwrap();
+    assert_eq!(read, nbt);
+
+    // A length longer than the data is caught without allocating for it.
+    let mut truncated = bytes[..14].to_vec();
+    truncated[10] = 0x7f;
+    assert!(from_reader::<_, BlobNbt>(&truncated[..]).is_err());
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ForcedNbt {
+    #[serde(with = "nbt_serde::arrays::list")]
+    size: Vec<i32>,
+    palette: LongArray,
+    nested: Vec<IntArray>,
+}
+
+#[test]
+fn serialize_forced_containers() {
+    let nbt = ForcedNbt {
+        size: vec![1, 2],
+        palette: LongArray(vec![]),
+        nested: vec![IntArray(vec![3])],
+    };
+
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &nbt, None).unwrap();
+
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x09,
+                0x00, 0x04,
+                0x73, 0x69, 0x7a, 0x65,
+                0x03,
+                0x00, 0x00, 0x00, 0x02,
+                0x00, 0x00, 0x00, 0x01,
+                0x00, 0x00, 0x00, 0x02,
+            0x0c,
+                0x00, 0x07,
+                0x70, 0x61, 0x6c, 0x65, 0x74, 0x74, 0x65,
+                0x00, 0x00, 0x00, 0x00,
+            0x09,
+                0x00, 0x06,
+                0x6e, 0x65, 0x73, 0x74, 0x65, 0x64,
+                0x0b,
+                0x00, 0x00, 0x00, 0x01,
+                0x00, 0x00, 0x00, 0x01,
+                0x00, 0x00, 0x00, 0x03,
+        0x00
+    ];
+
+    assert_eq!(bytes, dst);
+
+    let read: ForcedNbt = from_reader(&bytes[..]).unwrap();
+    assert_eq!(read, nbt);
+
+    assert_eq!(snbt::to_string(&nbt).unwrap(), "{size:[1,2],palette:[L;],nested:[[I;3]]}");
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct UnsignedNbt {
+    count: u8,
+    ids: Vec<u16>,
+}
+
+#[test]
+fn unsigned_policies() {
+    let nbt = UnsignedNbt { count: 200, ids: vec![40000] };
+
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &nbt, None).unwrap();
+
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x01,
+                0x00, 0x05,
+                0x63, 0x6f, 0x75, 0x6e, 0x74,
+                0xc8,
+            0x09,
+                0x00, 0x03,
+                0x69, 0x64, 0x73,
+                0x02,
+                0x00, 0x00, 0x00, 0x01,
+                0x9c, 0x40,
+        0x00
+    ];
+
+    assert_eq!(bytes, dst);
+
+    let read: UnsignedNbt = from_reader(&bytes[..]).unwrap();
+    assert_eq!(read, nbt);
+
+    // Without reinterpreting, the negative byte does not fit.
+    let mut decoder = Decoder::new(&bytes[..]).with_unsigned(Unsigned::Widen);
+    assert!(UnsignedNbt::deserialize(&mut decoder).is_err());
+
+    let mut encoder = Encoder::new(Vec::new(), None).with_unsigned(Unsigned::Widen);
+    nbt.serialize(&mut encoder).unwrap();
+
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x02,
+                0x00, 0x05,
+                0x63, 0x6f, 0x75, 0x6e, 0x74,
+                0x00, 0xc8,
+            0x0b,
+                0x00, 0x03,
+                0x69, 0x64, 0x73,
+                0x00, 0x00, 0x00, 0x01,
+                0x00, 0x00, 0x9c, 0x40,
+        0x00
+    ];
+
+    assert_eq!(bytes, encoder.into_inner());
+
+    // Wider tags are read as long as their values fit.
+    let read: UnsignedNbt = from_reader(&bytes[..]).unwrap();
+    assert_eq!(read, nbt);
+
+    let mut encoder = Encoder::new(Vec::new(), None).with_unsigned(Unsigned::Reject);
+    assert!(nbt.serialize(&mut encoder).is_err());
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct WidenedNbt {
+    count: i32,
+    time: i64,
+    speed: f64,
+}
+
+#[test]
+fn deserialize_widened() {
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x01,
+                0x00, 0x05,
+                0x63, 0x6f, 0x75, 0x6e, 0x74,
+                0xff,
+            0x03,
+                0x00, 0x04,
+                0x74, 0x69, 0x6d, 0x65,
+                0x00, 0x00, 0x01, 0x00,
+            0x05,
+                0x00, 0x05,
+                0x73, 0x70, 0x65, 0x65, 0x64,
+                0x3f, 0x00, 0x00, 0x00,
+        0x00
+    ];
+
+    // By default any number tag is converted by the field's visitor.
+    let read: WidenedNbt = from_reader(&bytes[..]).unwrap();
+    assert_eq!(read, WidenedNbt { count: -1, time: 256, speed: 0.5 });
+
+    let mut decoder = Decoder::new(&bytes[..]).with_strict_numbers(true);
+    assert!(WidenedNbt::deserialize(&mut decoder).is_err());
+
+    let mut decoder = Decoder::new(&bytes[..]).with_strict_numbers(true).with_widening(true);
+    let read = WidenedNbt::deserialize(&mut decoder).unwrap();
+    assert_eq!(read, WidenedNbt { count: -1, time: 256, speed: 0.5 });
+
+    // A wider tag is never narrowed.
+    let mut narrowed = bytes.clone();
+    narrowed[3] = 0x04;
+    let mut decoder = Decoder::new(&narrowed[..]).with_strict_numbers(true).with_widening(true);
+    assert!(WidenedNbt::deserialize(&mut decoder).is_err());
+}
+
+#[test]
+fn root_name_round_trip() {
+    let nbt = ByteNbt { data: 100 };
+
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &nbt, Some("Data".to_string())).unwrap();
+
+    let (name, read): (String, ByteNbt) = from_reader_named(&dst[..]).unwrap();
+    assert_eq!(name, "Data");
+    assert_eq!(read, nbt);
+
+    let mut decoder = Decoder::new(&dst[..]);
+    assert_eq!(decoder.root_name(), None);
+    ByteNbt::deserialize(&mut decoder).unwrap();
+    assert_eq!(decoder.root_name(), Some("Data"));
+
+    let mut again = Vec::new();
+    to_writer(&mut again, &read, Some(name)).unwrap();
+    assert_eq!(again, dst);
+}
+
+#[test]
+fn nameless_root() {
+    let nbt = ByteNbt { data: 100 };
+
+    let mut encoder = Encoder::new(Vec::new(), Some("ignored".to_string())).with_nameless_root(true);
+    nbt.serialize(&mut encoder).unwrap();
+    let dst = encoder.into_inner();
+    assert_eq!(dst, [0x0a, 0x01, 0x00, 0x04, b'd', b'a', b't', b'a', 100, 0x00]);
+
+    let mut decoder = Decoder::new(&dst[..]).with_nameless_root(true);
+    assert_eq!(ByteNbt::deserialize(&mut decoder).unwrap(), nbt);
+    assert_eq!(decoder.root_name(), Some(""));
+
+    // Read as a named root, the first entry's type ID is taken for the
+    // length of the name.
+    assert!(from_slice::<ByteNbt>(&dst).is_err());
+}
+
+#[test]
+fn any_root() {
+    fn write<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut encoder = Encoder::new(Vec::new(), Some("v".to_string())).with_any_root(true);
+        value.serialize(&mut encoder).unwrap();
+        encoder.into_inner()
+    }
+
+    fn read<T: Deserialize>(src: &[u8]) -> T {
+        T::deserialize(&mut Decoder::new(src).with_any_root(true)).unwrap()
+    }
+
+    let dst = write(&7i32);
+    assert_eq!(dst, [0x03, 0x00, 0x01, b'v', 0, 0, 0, 7]);
+    assert_eq!(read::<i32>(&dst), 7);
+    assert_eq!(read::<nbt::Value>(&dst), nbt::Value::Int(7));
+
+    let dst = write(&"hi");
+    assert_eq!(dst, [0x08, 0x00, 0x01, b'v', 0x00, 0x02, b'h', b'i']);
+    assert_eq!(read::<String>(&dst), "hi");
+
+    let list = vec![1i8, 2, 3];
+    assert_eq!(read::<Vec<i8>>(&write(&list)), list);
+    assert_eq!(read::<nbt::Value>(&write(&(1i16, 2i16))),
+               nbt::Value::List(vec![nbt::Value::Short(1), nbt::Value::Short(2)]));
+    assert_eq!(read::<bool>(&write(&true)), true);
+
+    // Compounds are read as before.
+    let nbt = ByteNbt { data: 1 };
+    assert_eq!(read::<ByteNbt>(&write(&nbt)), nbt);
+
+    // Without the option, only compounds can be the root.
+    assert!(matches!(to_vec(&7i32, None), Err(nbt_serde::Error::NoRootCompound)));
+    assert!(matches!(from_slice::<i32>(&write(&7i32)), Err(nbt_serde::Error::NoRootCompound)));
+    assert!(matches!(from_slice::<ByteNbt>(&write(&7i32)), Err(nbt_serde::Error::NoRootCompound)));
+}
+
+#[test]
+fn concatenated_documents() {
+    let mut dst = Vec::new();
+    to_writer(&mut dst, &ByteNbt { data: 1 }, Some("one".to_string())).unwrap();
+    to_writer(&mut dst, &ByteNbt { data: 2 }, Some("two".to_string())).unwrap();
+
+    let mut iter = Decoder::new(&dst[..]).into_iter::<ByteNbt>();
+    assert_eq!(iter.next().unwrap().unwrap(), ByteNbt { data: 1 });
+    assert_eq!(iter.root_name(), Some("one"));
+    assert_eq!(iter.next().unwrap().unwrap(), ByteNbt { data: 2 });
+    assert_eq!(iter.root_name(), Some("two"));
+    assert!(iter.next().is_none());
+
+    assert_eq!(Decoder::new(&[][..]).into_iter::<ByteNbt>().count(), 0);
+
+    // A truncated document is an error, after which the iterator ends.
+    let mut iter = Decoder::new(&dst[..dst.len() - 1]).into_iter::<ByteNbt>();
+    assert!(iter.next().unwrap().is_ok());
+    assert!(matches!(iter.next(), Some(Err(nbt_serde::Error::IncompleteNbtValue))));
+    assert!(iter.next().is_none());
+}
+
+/// The tree `to_value` builds must be the one read back from the bytes the
+/// `Encoder` writes.
+fn assert_value_matches_encoding<T>(nbt: &T)
+    where T: Serialize + Deserialize + PartialEq + ::std::fmt::Debug
+{
+    let (_, decoded) = nbt::Value::from_reader_auto(&to_vec(nbt, None).unwrap()[..]).unwrap();
+    let value = to_value(nbt).unwrap();
+    assert_eq!(value, decoded);
+    assert_eq!(&from_value::<T>(value).unwrap(), nbt);
+}
+
+#[test]
+fn value_round_trip() {
+    assert_value_matches_encoding(&EnumNbt { rarity: Rarity::Epic, payload: Payload::Potion { duration: 3 } });
+    assert_value_matches_encoding(&EnumNbt { rarity: Rarity::Common, payload: Payload::Count(5) });
+    assert_value_matches_encoding(&ForcedNbt {
+        size: vec![1, 2],
+        palette: LongArray(vec![]),
+        nested: vec![IntArray(vec![3])],
+    });
+    assert_value_matches_encoding(&UnsignedNbt { count: 200, ids: vec![40000] });
+
+    // An empty list among arrays takes their kind, and numbers among lists
+    // become a list, just as the `Encoder` writes them.
+    assert_value_matches_encoding(&NestedListNbt { data: vec![vec![1], vec![]] });
+    assert_value_matches_encoding(&NestedListNbt { data: vec![vec![], vec![1]] });
+
+    // Any value may be the root.
+    assert_eq!(to_value(&vec![1i64, 2]).unwrap(), nbt::Value::LongArray(vec![1, 2]));
+    assert_eq!(to_value(&"text").unwrap(), nbt::Value::String("text".to_string()));
+    assert_eq!(from_value::<Vec<String>>(nbt::Value::List(vec!["a".into()])).unwrap(), vec!["a"]);
+
+    // Missing optional fields are left out of the tree.
+    let mut compound = HashMap::new();
+    compound.insert("data".to_string(), Some(1i8));
+    compound.insert("none".to_string(), None);
+    match to_value(&compound).unwrap() {
+        nbt::Value::Compound(map) => assert_eq!(map.keys().collect::<Vec<_>>(), vec!["data"]),
+        other => panic!("serialized as {:?}", other),
+    }
+
+    // Numbers must have the tag matching their type, as when decoding.
+    let mut map = HashMap::new();
+    map.insert("data".to_string(), nbt::Value::Int(100));
+    match from_value::<ByteNbt>(nbt::Value::Compound(map)) {
+        Err(nbt_serde::Error::UnexpectedTag(Kind::I32, Kind::I8)) => {},
+        other => panic!("decoded {:?}", other),
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ExtraNbt {
+    id: String,
+    extra: nbt::Value,
+}
+
+#[test]
+fn value_fields() {
+    let mut modded = HashMap::new();
+    modded.insert("pos".to_string(), nbt::Value::List(vec![nbt::Value::Int(1), nbt::Value::Int(-2)]));
+    modded.insert("light".to_string(), nbt::Value::ByteArray(vec![0, 15]));
+    modded.insert("palette".to_string(), nbt::Value::LongArray(vec![]));
+    modded.insert("tags".to_string(), nbt::Value::List(vec![]));
+    modded.insert("name".to_string(), nbt::Value::String("Mod".to_string()));
+    modded.insert("scale".to_string(), nbt::Value::Float(0.5));
+    let nbt = ExtraNbt { id: "mod:machine".to_string(), extra: nbt::Value::Compound(modded) };
+
+    let bytes = to_vec(&nbt, None).unwrap();
+    let read: ExtraNbt = from_slice(&bytes).unwrap();
+    assert_eq!(read, nbt);
+
+    // The field holds exactly the tags in the data, arrays and lists alike.
+    let (_, decoded) = nbt::Value::from_reader_auto(&bytes[..]).unwrap();
+    let mut fields = HashMap::new();
+    fields.insert("id".to_string(), nbt::Value::String(nbt.id.clone()));
+    fields.insert("extra".to_string(), nbt.extra.clone());
+    assert_eq!(decoded, nbt::Value::Compound(fields));
+    let root: nbt::Value = from_slice(&bytes).unwrap();
+    assert_eq!(root, decoded);
+
+    // Compounds are written in the canonical order when keys are sorted.
+    let mut canonical = Vec::new();
+    nbt::normalize::write_canonical(&mut canonical, "", &decoded).unwrap();
+    let mut sorted = Vec::new();
+    decoded.serialize(&mut Encoder::new(&mut sorted, None).with_sorted_keys()).unwrap();
+    assert_eq!(sorted, canonical);
+
+    assert_eq!(from_value::<ExtraNbt>(to_value(&nbt).unwrap()).unwrap(), nbt);
+    assert_eq!(snbt::to_string(&nbt::Value::List(vec![nbt::Value::Int(1)])).unwrap(), "[1]");
+}
+
+#[test]
+fn sorted_keys() {
+    // Fields are declared out of order, and the struct variant is a compound
+    // inside a compound.
+    let nbt = EnumNbt { rarity: Rarity::Epic, payload: Payload::Potion { duration: 3 } };
+    let mut dst = Vec::new();
+    let written = {
+        let mut encoder = Encoder::new(&mut dst, None).with_sorted_keys();
+        nbt.serialize(&mut encoder).unwrap();
+        encoder.written()
+    };
+    assert_eq!(written, dst.len());
+    let mut canonical = Vec::new();
+    nbt::normalize::write_canonical(&mut canonical, "", &to_value(&nbt).unwrap()).unwrap();
+    assert_eq!(dst, canonical);
+    assert_eq!(from_slice::<EnumNbt>(&dst).unwrap(), nbt);
+
+    // Missing optional values are still left out.
+    let mut dst = Vec::new();
+    OptionNbt { data: None }.serialize(&mut Encoder::new(&mut dst, None).with_sorted_keys()).unwrap();
+    assert_eq!(dst, to_vec(&OptionNbt { data: None }, None).unwrap());
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct MachineNbt {
+    id: String,
+    energy: i32,
+}
+
+#[test]
+fn extra_entries_survive_rewrite() {
+    let mut entries = HashMap::new();
+    entries.insert("id".to_string(), nbt::Value::String("mod:generator".to_string()));
+    entries.insert("energy".to_string(), nbt::Value::Int(5));
+    entries.insert("ModData".to_string(), nbt::Value::IntArray(vec![1, 2, 3]));
+    entries.insert("Owner".to_string(), nbt::Value::String("Alex".to_string()));
+    let bytes = to_vec(&nbt::Value::Compound(entries.clone()), None).unwrap();
+
+    let mut machine: WithExtra<MachineNbt> = from_slice(&bytes).unwrap();
+    assert_eq!(machine.value, MachineNbt { id: "mod:generator".to_string(), energy: 5 });
+    let mut keys: Vec<_> = machine.extra.keys().cloned().collect();
+    keys.sort();
+    assert_eq!(keys, vec!["ModData", "Owner"]);
+
+    machine.value.energy = 6;
+    entries.insert("energy".to_string(), nbt::Value::Int(6));
+    let rewritten: nbt::Value = from_slice(&to_vec(&machine, None).unwrap()).unwrap();
+    assert_eq!(rewritten, nbt::Value::Compound(entries));
+
+    // Fields win over extra entries of the same name.
+    machine.extra.insert("energy".to_string(), nbt::Value::Int(0));
+    let read: WithExtra<MachineNbt> = from_value(to_value(&machine).unwrap()).unwrap();
+    assert_eq!(read.value.energy, 6);
+    assert!(!read.extra.contains_key("energy"));
+}
+
+#[test]
+fn skip_unknown_fields() {
+    let mut inner = HashMap::new();
+    inner.insert("name".to_string(), nbt::Value::String("skipped".to_string()));
+    inner.insert("ids".to_string(), nbt::Value::IntArray(vec![1, 2, 3]));
+    let mut entries = HashMap::new();
+    entries.insert("data".to_string(), nbt::Value::Byte(7));
+    entries.insert("light".to_string(), nbt::Value::ByteArray(vec![0; 2048]));
+    entries.insert("time".to_string(), nbt::Value::Long(-1));
+    entries.insert("entities".to_string(), nbt::Value::List(vec![nbt::Value::Compound(inner); 3]));
+    entries.insert("motion".to_string(), nbt::Value::List(vec![nbt::Value::Double(0.5); 3]));
+    entries.insert("heights".to_string(), nbt::Value::LongArray(vec![i64::MIN, 300]));
+    entries.insert("empty".to_string(), nbt::Value::List(vec![]));
+    let value = nbt::Value::Compound(entries);
+
+    for &flavor in &[Flavor::Java, Flavor::BedrockDisk, Flavor::BedrockNetwork] {
+        let mut encoder = Encoder::new(Vec::new(), None).with_flavor(flavor);
+        value.serialize(&mut encoder).unwrap();
+        let bytes = encoder.into_inner();
+
+        let mut src = &bytes[..];
+        let read = ByteNbt::deserialize(&mut Decoder::new(&mut src).with_flavor(flavor)).unwrap();
+        assert_eq!(read, ByteNbt { data: 7 });
+        assert!(src.is_empty(), "{:?} left {} bytes unread", flavor, src.len());
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Rotation(f32, f32);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct EntityNbt {
+    #[serde(rename = "Pos")]
+    pos: [f64; 3],
+    #[serde(rename = "Motion")]
+    motion: (f64, f64, f64),
+    #[serde(rename = "Rotation")]
+    rotation: Rotation,
+}
+
+#[test]
+fn fixed_length_lists() {
+    let nbt = EntityNbt {
+        pos: [1.5, 64.0, -3.25],
+        motion: (0.0, -0.08, 0.0),
+        rotation: Rotation(90.0, 0.0),
+    };
+
+    let bytes = to_vec(&nbt, None).unwrap();
+    let read: EntityNbt = from_slice(&bytes).unwrap();
+    assert_eq!(read, nbt);
+
+    let value = match nbt::Value::from_reader_auto(&bytes[..]).unwrap().1 {
+        nbt::Value::Compound(map) => map,
+        other => panic!("expected a compound, got {:?}", other),
+    };
+    assert_eq!(value["Motion"], nbt::Value::List(vec![
+        nbt::Value::Double(0.0), nbt::Value::Double(-0.08), nbt::Value::Double(0.0),
+    ]));
+    assert_eq!(value["Rotation"], nbt::Value::List(vec![
+        nbt::Value::Float(90.0), nbt::Value::Float(0.0),
+    ]));
+
+    assert_value_matches_encoding(&nbt);
+    let text = snbt::to_string(&nbt).unwrap();
+    assert!(text.contains("Rotation:[90.0f,0.0f]"), "{}", text);
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct OwnedNbt {
+    #[serde(rename = "UUID", with = "nbt::uuid_int_array")]
+    uuid: u128,
+    #[serde(rename = "Owner", with = "nbt::uuid_int_array")]
+    owner: u128,
+}
+
+#[test]
+fn uuid_int_array_fields() {
+    let nbt = OwnedNbt {
+        uuid: 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210,
+        owner: 0xdead_beef_0000_0000_0000_0000_0000_0001,
+    };
+
+    let bytes = to_vec(&nbt, None).unwrap();
+    let read: OwnedNbt = from_slice(&bytes).unwrap();
+    assert_eq!(read, nbt);
+
+    let value = match nbt::Value::from_reader_auto(&bytes[..]).unwrap().1 {
+        nbt::Value::Compound(map) => map,
+        other => panic!("expected a compound, got {:?}", other),
+    };
+    assert_eq!(value["UUID"], nbt::Value::IntArray(vec![0x0123_4567, 0x89ab_cdef_u32 as i32,
+                                                         0xfedc_ba98_u32 as i32, 0x7654_3210]));
+    assert_value_matches_encoding(&nbt);
+
+    // Older files store some UUIDs as strings.
+    let mut entries = HashMap::new();
+    entries.insert("UUID".to_string(), value["UUID"].clone());
+    entries.insert("Owner".to_string(),
+                   nbt::Value::String("deadbeef-0000-0000-0000-000000000001".to_string()));
+    let read: OwnedNbt = from_value(nbt::Value::Compound(entries)).unwrap();
+    assert_eq!(read, nbt);
+}
+
+#[test]
+fn bulk_arrays() {
+    let mut entries = HashMap::new();
+    entries.insert("bytes".to_string(), nbt::Value::ByteArray((0..5000).map(|i| i as i8).collect()));
+    entries.insert("ints".to_string(), nbt::Value::IntArray((0..5000).map(|i| i * -65_537).collect()));
+    entries.insert("longs".to_string(), nbt::Value::LongArray((0..5000).map(|i| i << 40 | i).collect()));
+    entries.insert("after".to_string(), nbt::Value::Int(3));
+    let value = nbt::Value::Compound(entries);
+
+    for &flavor in &[Flavor::Java, Flavor::BedrockDisk, Flavor::BedrockNetwork] {
+        let mut encoder = Encoder::new(Vec::new(), None).with_flavor(flavor);
+        value.serialize(&mut encoder).unwrap();
+        let bytes = encoder.into_inner();
+
+        let mut decoder = Decoder::new(&bytes[..]).with_flavor(flavor);
+        let read = nbt::Value::deserialize(&mut decoder).unwrap();
+        assert_eq!(read, value, "{:?}", flavor);
+
+        let mut decoder = Decoder::new(&bytes[..bytes.len() - 3000]).with_flavor(flavor);
+        assert!(nbt::Value::deserialize(&mut decoder).is_err(), "{:?}", flavor);
+    }
+
+    let bytes = to_vec(&value, None).unwrap();
+    assert_eq!(nbt::Value::from_reader_auto(&bytes[..]).unwrap().1, value);
+}
+
+/// Counts the calls made to the reader or writer it wraps.
+struct Calls<T> {
+    inner: T,
+    calls: usize,
+}
+
+impl<T: std::io::Read> std::io::Read for Calls<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.calls += 1;
+        self.inner.read(buf)
+    }
+}
+
+impl<T: std::io::Write> std::io::Write for Calls<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.calls += 1;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn buffering() {
+    let mut entries = HashMap::new();
+    for i in 0..100 {
+        entries.insert(format!("entry{}", i), nbt::Value::List(vec![nbt::Value::Short(i); 3]));
+    }
+    let value = nbt::Value::Compound(entries);
+
+    let mut dst = Calls { inner: Vec::new(), calls: 0 };
+    to_writer(&mut dst, &value, None).unwrap();
+    assert_eq!(dst.calls, 1);
+    assert_eq!(dst.inner, to_vec(&value, None).unwrap());
+
+    let mut src = Calls { inner: &dst.inner[..], calls: 0 };
+    let read = nbt::Value::deserialize(&mut Decoder::new(&mut src).with_buffering(true)).unwrap();
+    assert_eq!(read, value);
+    assert!(src.calls <= 2, "{} reads", src.calls);
+}
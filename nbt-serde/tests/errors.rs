@@ -2,11 +2,20 @@
 extern crate serde_derive;
 extern crate serde;
 
+extern crate nbt;
 extern crate nbt_serde;
 
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use nbt_serde::{Decoder, Encoder, Flavor};
 use nbt_serde::error::{Error, Result};
 use nbt_serde::encode::to_writer;
-use nbt_serde::decode::from_reader;
+use nbt_serde::decode::{from_reader, from_slice};
+use nbt_serde::kind::Kind;
+
+use nbt::DecoderOptions;
 
 #[test]
 fn no_root_compound() {
@@ -115,3 +124,473 @@ fn non_boolean_byte() {
         _ => panic!("encountered an unexpected error"),
     }
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ListNbt {
+    data: Vec<i32>,
+}
+
+#[test]
+fn size_limit_exceeded() {
+    let nbt = ListNbt { data: vec![1, 2, 3, 4] };
+
+    // The full encoding is 31 bytes.
+    let mut encoder = Encoder::new(Vec::new(), None).with_size_limit(31);
+    nbt.serialize(&mut encoder).unwrap();
+    assert_eq!(encoder.written(), 31);
+
+    let mut encoder = Encoder::new(Vec::new(), None).with_size_limit(20);
+    match nbt.serialize(&mut encoder).unwrap_err() {
+        Error::SizeLimitExceeded(20) => (),
+        _ => panic!("encountered an unexpected error"),
+    }
+    assert!(encoder.written() <= 20);
+    assert!(encoder.into_inner().len() <= 20);
+}
+
+#[test]
+fn non_string_map_key() {
+    let mut nbt = BTreeMap::new();
+    nbt.insert(1i32, 100i8);
+
+    let mut dst = Vec::new();
+    match to_writer(&mut dst, &nbt, None) {
+        Err(Error::UnrepresentableType("non-string map key")) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Shape {
+    Dot,
+    Circle(i8),
+    Pair(i8, i8),
+}
+
+#[derive(Debug, Deserialize)]
+struct ShapeNbt {
+    shape: Shape,
+}
+
+#[test]
+fn enum_errors() {
+    let mut dst = Vec::new();
+    match to_writer(&mut dst, &Shape::Dot, None) {
+        Err(Error::NoRootCompound) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+    match to_writer(&mut Vec::new(), &Shape::Pair(1, 2), None) {
+        Err(Error::NoRootCompound) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+
+    // A variant compound may only hold the variant.
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x0a,
+                0x00, 0x05,
+                0x73, 0x68, 0x61, 0x70, 0x65,
+                0x01,
+                    0x00, 0x06,
+                    0x43, 0x69, 0x72, 0x63, 0x6c, 0x65,
+                    0x01,
+                0x01,
+                    0x00, 0x01,
+                    0x78,
+                    0x02,
+                0x00,
+        0x00
+    ];
+    match from_reader::<_, ShapeNbt>(&bytes[..]) {
+        Err(Error::InvalidVariant) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+
+    // A newtype variant cannot be read from a string.
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x08,
+                0x00, 0x05,
+                0x73, 0x68, 0x61, 0x70, 0x65,
+                0x00, 0x06,
+                0x43, 0x69, 0x72, 0x63, 0x6c, 0x65,
+        0x00
+    ];
+    match from_reader::<_, ShapeNbt>(&bytes[..]) {
+        Err(Error::UnexpectedTag(Kind::String, Kind::Compound)) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct IntNbt {
+    data: i32,
+}
+
+#[test]
+fn narrower_tag() {
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x01,
+                0x00, 0x04,
+                0x64, 0x61, 0x74, 0x61,
+                0x05,
+        0x00
+    ];
+
+    // Any number tag is converted by default.
+    assert_eq!(from_reader::<_, IntNbt>(&bytes[..]).unwrap().data, 5);
+
+    let mut decoder = Decoder::new(&bytes[..]).with_strict_numbers(true);
+    match IntNbt::deserialize(&mut decoder) {
+        Err(Error::UnexpectedTag(Kind::I8, Kind::I32)) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct StringNbt {
+    data: String,
+}
+
+#[test]
+fn truncated_string() {
+    // A VarInt length of nearly 4 GiB, with no data after it.
+    let bytes = vec![
+        0x0a,
+            0x00,
+            0x08,
+                0x04,
+                0x64, 0x61, 0x74, 0x61,
+                0xfe, 0xff, 0xff, 0xff, 0x0f,
+    ];
+
+    let mut decoder = Decoder::new(&bytes[..]).with_flavor(Flavor::BedrockNetwork);
+    match StringNbt::deserialize(&mut decoder) {
+        Err(Error::IncompleteNbtValue) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn truncated_unknown_field() {
+    // An unknown byte array claims more bytes than there are.
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x07,
+                0x00, 0x05,
+                0x6c, 0x69, 0x67, 0x68, 0x74,
+                0x00, 0x00, 0x08, 0x00,
+                0x00, 0x00,
+    ];
+
+    match from_reader::<_, ByteNbt>(&bytes[..]) {
+        Err(Error::IncompleteNbtValue) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct PosNbt {
+    pos: (f64, f64, f64),
+}
+
+#[test]
+fn tuple_errors() {
+    #[derive(Serialize)]
+    struct Mixed {
+        pair: (i32, String),
+    }
+
+    let mixed = Mixed { pair: (1, "one".to_string()) };
+    match to_writer(&mut Vec::new(), &mixed, None) {
+        Err(Error::HeterogenousList { original: Kind::I32, new: Kind::String }) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+
+    // A list of two doubles where three are expected.
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x09,
+                0x00, 0x03,
+                0x70, 0x6f, 0x73,
+                0x06,
+                0x00, 0x00, 0x00, 0x02,
+                0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x3f, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+
+    match from_reader::<_, PosNbt>(&bytes[..]) {
+        Err(Error::Serde(_)) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LevelNbt {
+    #[serde(rename = "Level")]
+    level: SectionsNbt,
+}
+
+#[derive(Debug, Deserialize)]
+struct SectionsNbt {
+    #[serde(rename = "Sections")]
+    sections: Vec<BoolNbt>,
+}
+
+#[test]
+fn error_context() {
+    // The second section holds a boolean byte of 2.
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x0a,
+                0x00, 0x05,
+                0x4c, 0x65, 0x76, 0x65, 0x6c,
+                0x09,
+                    0x00, 0x08,
+                    0x53, 0x65, 0x63, 0x74, 0x69, 0x6f, 0x6e, 0x73,
+                    0x0a,
+                    0x00, 0x00, 0x00, 0x02,
+                    0x01,
+                        0x00, 0x04,
+                        0x64, 0x61, 0x74, 0x61,
+                        0x01,
+                    0x00,
+                    0x01,
+                        0x00, 0x04,
+                        0x64, 0x61, 0x74, 0x61,
+                        0x02,
+                    0x00,
+            0x00,
+        0x00
+    ];
+
+    let mut decoder = Decoder::new(&bytes[..]).with_error_context(true);
+    let err = LevelNbt::deserialize(&mut decoder).unwrap_err();
+    assert_eq!(err.to_string(),
+               "at byte 44, path Level.Sections[1].data: boolean bytes must be 0 or 1, found 2");
+    match err {
+        Error::At { offset: 44, ref path, ref error } if path == "Level.Sections[1].data" => match **error {
+            Error::NonBooleanByte(2) => (),
+            ref other => panic!("encountered an unexpected error: {:?}", other),
+        },
+        other => panic!("encountered an unexpected error: {:?}", other),
+    }
+
+    // Without the option, the error is as it was.
+    match from_reader::<_, LevelNbt>(&bytes[..]) {
+        Err(Error::NonBooleanByte(2)) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn negative_length() {
+    let bytes = vec![
+        0x0a,
+            0x00, 0x00,
+            0x09,
+                0x00, 0x04,
+                0x64, 0x61, 0x74, 0x61,
+                0x01,
+                0xff, 0xff, 0xff, 0xfe,
+        0x00
+    ];
+
+    match from_reader::<_, ListNbt>(&bytes[..]) {
+        Err(Error::NegativeLength(-2)) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn error_source() {
+    use std::error::Error as StdError;
+    use std::io;
+
+    let err = Error::Io(io::Error::other("disk on fire"));
+    assert_eq!(err.source().unwrap().to_string(), "disk on fire");
+    assert!(Error::NoRootCompound.source().is_none());
+}
+
+#[test]
+fn string_too_long() {
+    #[derive(Serialize)]
+    struct Long {
+        text: String,
+    }
+
+    let long = Long { text: "a".repeat(70_000) };
+    match to_writer(&mut Vec::new(), &long, None) {
+        Err(Error::StringTooLong(70_000)) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+
+    // Bedrock Edition's network format stores lengths as VarInts.
+    let mut encoder = Encoder::new(Vec::new(), None).with_flavor(Flavor::BedrockNetwork);
+    long.serialize(&mut encoder).unwrap();
+}
+
+/// A map that gives its value without a key, or its key without a value.
+struct BadMap {
+    key: bool,
+    value: bool,
+}
+
+impl Serialize for BadMap {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        if self.key {
+            map.serialize_key("a")?;
+        }
+        if self.value {
+            map.serialize_value(&1i8)?;
+        }
+        map.end()
+    }
+}
+
+#[test]
+fn invalid_writes() {
+    for &(key, value) in &[(false, true), (true, false)] {
+        match to_writer(&mut Vec::new(), &BadMap { key, value }, None) {
+            Err(Error::InvalidWrite(_)) => (),
+            other => panic!("encountered an unexpected result: {:?}", other),
+        }
+    }
+
+    // An encoder writes a single document.
+    let map = BadMap { key: true, value: true };
+    let mut encoder = Encoder::new(Vec::new(), None);
+    map.serialize(&mut encoder).unwrap();
+    match map.serialize(&mut encoder) {
+        Err(Error::InvalidWrite(_)) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OptionalNbt {
+    b: Option<i32>,
+}
+
+#[test]
+fn deeply_nested_unknown_field() {
+    // An unknown field holding lists nested 200,000 deep, which would
+    // overflow the stack if skipping it were unbounded.
+    let mut bytes = vec![0x0a, 0x00, 0x00, 0x09, 0x00, 0x01, 0x61];
+    for _ in 0..200_000 {
+        bytes.extend_from_slice(&[0x09, 0x00, 0x00, 0x00, 0x01]);
+    }
+    bytes.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+    let read: Result<OptionalNbt> = from_reader(&bytes[..]);
+    match read {
+        Err(Error::DepthLimitExceeded(512)) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+
+    // A shallower unknown field is skipped up to the given limit, counting
+    // from the root compound as `nbt::Value::from_reader_with` does.
+    let nested = [
+        0x0a, 0x00, 0x00,
+            0x09, 0x00, 0x01, 0x61,
+                0x09, 0x00, 0x00, 0x00, 0x01,
+                    0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+    let mut decoder = Decoder::new(&nested[..]).with_max_depth(1);
+    match OptionalNbt::deserialize(&mut decoder) {
+        Err(Error::DepthLimitExceeded(1)) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+    let mut decoder = Decoder::new(&nested[..]).with_max_depth(2);
+    assert_eq!(OptionalNbt::deserialize(&mut decoder).unwrap().b, None);
+}
+
+#[test]
+fn deeply_nested_value() {
+    // Lists nested 200,000 deep, which would overflow the stack if reading
+    // them into a value were unbounded.
+    let mut bytes = vec![0x0a, 0x00, 0x00, 0x09, 0x00, 0x01, 0x61];
+    for _ in 0..200_000 {
+        bytes.extend_from_slice(&[0x09, 0x00, 0x00, 0x00, 0x01]);
+    }
+    bytes.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+    // Unoptimized builds need more than the 2 MiB test threads have to
+    // reach the default limit, though less than a main thread's 8 MiB.
+    let reader = std::thread::Builder::new().stack_size(8 << 20).spawn(move || {
+        match from_slice::<nbt::Value>(&bytes) {
+            Err(Error::DepthLimitExceeded(512)) => (),
+            other => panic!("encountered an unexpected result: {:?}", other),
+        }
+        match from_slice::<BTreeMap<String, nbt::Value>>(&bytes) {
+            Err(Error::DepthLimitExceeded(512)) => (),
+            other => panic!("encountered an unexpected result: {:?}", other),
+        }
+    });
+    reader.unwrap().join().unwrap();
+}
+
+#[test]
+fn decoder_limits() {
+    let strings = [
+        0x0a, 0x00, 0x00,
+            0x08, 0x00, 0x04, 0x64, 0x61, 0x74, 0x61,
+                0x00, 0x05, 0x68, 0x65, 0x6c, 0x6c, 0x6f,
+        0x00,
+    ];
+    let lists = [
+        0x0a, 0x00, 0x00,
+            0x09, 0x00, 0x04, 0x64, 0x61, 0x74, 0x61,
+                0x03, 0x00, 0x00, 0x00, 0x02,
+                    0x00, 0x00, 0x00, 0x01,
+                    0x00, 0x00, 0x00, 0x02,
+        0x00,
+    ];
+
+    let options = DecoderOptions { max_string_len: 4, ..DecoderOptions::default() };
+    let mut decoder = Decoder::new(&strings[..]).with_options(options);
+    match StringNbt::deserialize(&mut decoder) {
+        Err(Error::LengthLimitExceeded(4)) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+
+    let options = DecoderOptions { max_len: 1, ..DecoderOptions::default() };
+    let mut decoder = Decoder::new(&lists[..]).with_options(options);
+    match ListNbt::deserialize(&mut decoder) {
+        Err(Error::LengthLimitExceeded(1)) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+
+    let options = DecoderOptions { max_bytes: Some(20), ..DecoderOptions::default() };
+    let mut decoder = Decoder::new(&lists[..]).with_options(options);
+    match ListNbt::deserialize(&mut decoder) {
+        Err(Error::SizeLimitExceeded(20)) => (),
+        other => panic!("encountered an unexpected result: {:?}", other),
+    }
+
+    // Data within every limit decodes as before.
+    let options = DecoderOptions {
+        max_string_len: 5,
+        max_len: 2,
+        max_bytes: Some(lists.len() as u64),
+        ..DecoderOptions::default()
+    };
+    let mut decoder = Decoder::new(&strings[..]).with_options(options.clone());
+    assert_eq!(StringNbt::deserialize(&mut decoder).unwrap().data, "hello");
+    let mut decoder = Decoder::new(&lists[..]).with_options(options);
+    assert_eq!(ListNbt::deserialize(&mut decoder).unwrap().data, vec![1, 2]);
+}
@@ -38,6 +38,10 @@ mod bench {
 
     use test::Bencher;
 
+    use serde::Deserialize;
+
+    use nbt_serde::Decoder;
+    use nbt_serde::decode::from_reader;
     use nbt_serde::encode::to_writer;
 
     use super::*;
@@ -161,6 +165,43 @@ mod bench {
             nbt.write(&mut io::sink())
         });
     }
+
+    /// Writes the uncompressed contents of `level.dat` to a temporary file,
+    /// to read back without any buffering along the way.
+    fn uncompressed_level() -> std::path::PathBuf {
+        let mut file = File::open("../tests/level.dat").unwrap();
+        let nbt: data::Level = from_gzip(&mut file).unwrap();
+        let path = std::env::temp_dir().join("nbt-serde-bench-level.nbt");
+        to_writer(&mut File::create(&path).unwrap(), &nbt, None).unwrap();
+        path
+    }
+
+    #[bench]
+    fn deserialize_level_from_file(b: &mut Bencher) {
+        let path = uncompressed_level();
+        b.iter(|| {
+            let _: data::Level = from_reader(File::open(&path).unwrap()).unwrap();
+        });
+    }
+
+    #[bench]
+    fn deserialize_level_from_file_buffered(b: &mut Bencher) {
+        let path = uncompressed_level();
+        b.iter(|| {
+            let mut decoder = Decoder::new(File::open(&path).unwrap()).with_buffering(true);
+            let _ = data::Level::deserialize(&mut decoder).unwrap();
+        });
+    }
+
+    #[bench]
+    fn serialize_level_to_file(b: &mut Bencher) {
+        let mut file = File::open("../tests/level.dat").unwrap();
+        let nbt: data::Level = from_gzip(&mut file).unwrap();
+        let path = std::env::temp_dir().join("nbt-serde-bench-level-out.nbt");
+        b.iter(|| {
+            to_writer(&mut File::create(&path).unwrap(), &nbt, None).unwrap();
+        });
+    }
 }
 
 mod data {
@@ -0,0 +1,361 @@
+//! Decoding that reports every problem in a document, not just the first.
+//!
+//! `audit` decodes a document like `Value::from_reader`, but keeps going after
+//! problems it can recover from and returns them all together, each with the
+//! path of the tag and the byte offset where it was found. Invalid UTF-8 is
+//! replaced with U+FFFD, and a list with an unknown element type is read as
+//! empty as long as it has no elements. Problems that leave the position in
+//! the stream unknown, such as an unknown tag type in a compound or a
+//! truncated file, end decoding; the tags decoded up to that point are still
+//! returned. So do lists and compounds nested more deeply than
+//! `Options::max_depth`, which are reported as `Error::DepthLimitExceeded`.
+//!
+//! Separately from problems, the auditor reports `Warning`s about data that is
+//! valid but unusual, such as duplicate compound keys or UUIDs stored in the
+//! old pair-of-longs form. `read_with_warnings` decodes as strictly as
+//! `Value::from_reader` while passing these to a callback, so tools can tell
+//! users about them without refusing to load the file.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+use error::{Error, Result};
+use raw;
+use value::{DecoderOptions, Value};
+
+/// A problem found while auditing a document.
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    /// The path of the tag with the problem, such as `Level.Entities[3].id`,
+    /// or the empty string for the root.
+    pub path: String,
+    /// The offset of the problem in the document, in bytes.
+    pub offset: u64,
+    /// What went wrong.
+    pub error: Error,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = if self.path.is_empty() { "the root tag" } else { &self.path };
+        write!(f, "{} (at byte {}): {}", path, self.offset, self.error)
+    }
+}
+
+/// The kinds of `Warning`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WarningKind {
+    /// An empty list with an element type other than `TAG_End`, which the
+    /// game itself never writes. Includes the element type.
+    TypedEmptyList(u8),
+    /// A UUID stored as a `<prefix>Most`/`<prefix>Least` pair of longs, which
+    /// the game replaced with an int array in 1.16.
+    LegacyUuid,
+    /// An array longer than `Options::max_array_len`. Includes the length.
+    LargeArray(usize),
+    /// A compound entry with the same name as an earlier one, which replaced
+    /// it.
+    DuplicateKey,
+}
+
+/// Something valid but unusual found while decoding a document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    /// The path of the tag the warning is about.
+    pub path: String,
+    /// The offset in the document where the tag was found, in bytes.
+    pub offset: u64,
+    /// What was found.
+    pub kind: WarningKind,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = if self.path.is_empty() { "the root tag" } else { &self.path };
+        write!(f, "{} (at byte {}): ", path, self.offset)?;
+        match self.kind {
+            WarningKind::TypedEmptyList(id) => write!(f, "empty list has element type {}", id),
+            WarningKind::LegacyUuid => write!(f, "UUID is stored as a pair of longs"),
+            WarningKind::LargeArray(len) => write!(f, "array has {} elements", len),
+            WarningKind::DuplicateKey => write!(f, "duplicate key replaces an earlier entry"),
+        }
+    }
+}
+
+/// Settings for the checks that produce warnings, and for how deep decoding
+/// goes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    /// Arrays with more elements than this are reported as
+    /// `WarningKind::LargeArray`. Defaults to 2^20.
+    pub max_array_len: usize,
+    /// How deeply lists and compounds may nest, as for `DecoderOptions`.
+    /// Deeper ones are reported as `Error::DepthLimitExceeded` and end
+    /// decoding. Defaults to 512.
+    pub max_depth: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { max_array_len: 1 << 20, max_depth: DecoderOptions::default().max_depth }
+    }
+}
+
+/// The result of auditing a document.
+#[derive(Debug, PartialEq)]
+pub struct Audit {
+    /// The name of the root tag.
+    pub title: String,
+    /// As much of the document as could be decoded, or `None` if not even
+    /// the root tag's header could be read.
+    pub value: Option<Value>,
+    /// Every problem found, in the order they were found.
+    pub problems: Vec<Problem>,
+    /// Every warning, in the order they were found.
+    pub warnings: Vec<Warning>,
+}
+
+impl Audit {
+    /// Whether the document decoded without any problems.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// A reader that counts the bytes read through it.
+pub(crate) struct Counted<R> {
+    pub(crate) inner: R,
+    pub(crate) pos: u64,
+}
+
+impl<R: io::Read> io::Read for Counted<R> {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(dst)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+struct Auditor<'a, R> {
+    src: Counted<R>,
+    options: &'a Options,
+    problems: Vec<Problem>,
+    warn: &'a mut dyn FnMut(Warning),
+}
+
+/// Decodes a document, collecting every problem and warning found along the
+/// way.
+pub fn audit<R: io::Read>(src: R) -> Audit {
+    audit_with(src, &Options::default())
+}
+
+/// Decodes a document like `audit`, with the given settings for warnings.
+pub fn audit_with<R: io::Read>(src: R, options: &Options) -> Audit {
+    let mut warnings = Vec::new();
+    let (title, value, problems) = run(src, options, &mut |w| warnings.push(w));
+    Audit { title, value, problems, warnings }
+}
+
+/// Decodes a document, passing each warning to `callback`. Unlike `audit`,
+/// this fails on the first problem, as `Value::from_reader` would, so lists
+/// and compounds nested more deeply than `Options::max_depth` fail with
+/// `Error::DepthLimitExceeded`.
+pub fn read_with_warnings<R, F>(src: R, options: &Options, mut callback: F) -> Result<(String, Value)>
+    where R: io::Read, F: FnMut(Warning)
+{
+    let (title, value, problems) = run(src, options, &mut callback);
+    match (problems.into_iter().next(), value) {
+        (None, Some(value)) => Ok((title, value)),
+        (Some(problem), _) => Err(problem.error),
+        (None, None) => Err(Error::IncompleteNbtValue),
+    }
+}
+
+fn run<R: io::Read>(src: R, options: &Options, warn: &mut dyn FnMut(Warning))
+                    -> (String, Option<Value>, Vec<Problem>) {
+    let mut auditor = Auditor {
+        src: Counted { inner: src, pos: 0 },
+        options,
+        problems: Vec::new(),
+        warn,
+    };
+    let header = auditor.header("");
+    let (id, title) = match header {
+        Ok(header) => header,
+        Err(error) => {
+            auditor.report("", 0, error);
+            return (String::new(), None, auditor.problems);
+        },
+    };
+    if id != 0x0a {
+        auditor.report("", 0, Error::NoRootCompound);
+    }
+    let value = match auditor.read(id, "", 0) {
+        Ok(value) => Some(value),
+        Err(partial) => partial,
+    };
+    (title, value, auditor.problems)
+}
+
+impl<'a, R: io::Read> Auditor<'a, R> {
+    fn report(&mut self, path: &str, offset: u64, error: Error) {
+        self.problems.push(Problem { path: path.to_string(), offset, error });
+    }
+
+    fn warn(&mut self, path: &str, offset: u64, kind: WarningKind) {
+        (self.warn)(Warning { path: path.to_string(), offset, kind });
+    }
+
+    /// Reads a string, replacing invalid UTF-8 rather than failing on it.
+    fn string(&mut self, path: &str) -> Result<String> {
+        let offset = self.src.pos;
+        let len = raw::read_bare_short(&mut self.src)? as u16 as usize;
+        let mut bytes = vec![0; len];
+        io::Read::read_exact(&mut self.src, &mut bytes)?;
+        match String::from_utf8(bytes) {
+            Ok(s) => Ok(s),
+            Err(e) => {
+                self.report(path, offset, Error::InvalidUtf8);
+                Ok(String::from_utf8_lossy(e.as_bytes()).into_owned())
+            },
+        }
+    }
+
+    /// Reads a tag header, where `path` is that of the enclosing compound.
+    fn header(&mut self, path: &str) -> Result<(u8, String)> {
+        let id = raw::read_bare_byte(&mut self.src)? as u8;
+        if id == 0x00 {
+            return Ok((id, String::new()));
+        }
+        Ok((id, self.string(path)?))
+    }
+
+    /// Reads the payload of a tag nested `depth` lists and compounds deep.
+    /// If decoding cannot continue, the problem is reported and the part of
+    /// the tag decoded so far, if any, is returned as the error.
+    fn read(&mut self, id: u8, path: &str, depth: usize) -> ::std::result::Result<Value, Option<Value>> {
+        let offset = self.src.pos;
+        if (id == 0x09 || id == 0x0a) && depth > self.options.max_depth {
+            // Going any deeper could overflow the stack, and the tag cannot
+            // be skipped without going deeper, so stop here.
+            self.report(path, offset, Error::DepthLimitExceeded(self.options.max_depth));
+            return Err(None);
+        }
+        let result = match id {
+            0x08 => self.string(path).map(Value::String),
+            0x07 | 0x0b | 0x0c => self.array(id, path, offset),
+            0x09 => return self.list(path, offset, depth),
+            0x0a => return self.compound(path, depth),
+            _ => Value::from_reader(id, &mut self.src),
+        };
+        result.map_err(|error| {
+            self.report(path, offset, error);
+            None
+        })
+    }
+
+    fn array(&mut self, id: u8, path: &str, offset: u64) -> Result<Value> {
+        // A negative length is read as an empty array, rather than trusted.
+        let len = raw::read_bare_int(&mut self.src)?.max(0);
+        if len as usize > self.options.max_array_len {
+            self.warn(path, offset, WarningKind::LargeArray(len as usize));
+        }
+        match id {
+            0x07 => {
+                let vals = (0..len).map(|_| raw::read_bare_byte(&mut self.src)).collect::<Result<_>>()?;
+                Ok(Value::ByteArray(vals))
+            },
+            0x0b => {
+                let vals = (0..len).map(|_| raw::read_bare_int(&mut self.src)).collect::<Result<_>>()?;
+                Ok(Value::IntArray(vals))
+            },
+            _ => {
+                let vals = (0..len).map(|_| raw::read_bare_long(&mut self.src)).collect::<Result<_>>()?;
+                Ok(Value::LongArray(vals))
+            },
+        }
+    }
+
+    fn list(&mut self, path: &str, offset: u64, depth: usize) -> ::std::result::Result<Value, Option<Value>> {
+        let header = raw::read_bare_byte(&mut self.src)
+            .and_then(|elem| raw::read_bare_int(&mut self.src).map(|len| (elem as u8, len)));
+        let (elem, len) = match header {
+            Ok(header) => header,
+            Err(error) => {
+                self.report(path, offset, error);
+                return Err(None);
+            },
+        };
+        let known = elem <= 0x0c && (elem != 0x00 || len <= 0);
+        if !known {
+            self.report(path, offset, Error::InvalidTypeId(elem));
+            if len > 0 {
+                return Err(Some(Value::List(Vec::new())));
+            }
+        } else if elem != 0x00 && len <= 0 {
+            self.warn(path, offset, WarningKind::TypedEmptyList(elem));
+        }
+        let mut vals = Vec::new();
+        for i in 0..len.max(0) {
+            match self.read(elem, &format!("{}[{}]", path, i), depth + 1) {
+                Ok(value) => vals.push(value),
+                Err(partial) => {
+                    vals.extend(partial);
+                    return Err(Some(Value::List(vals)));
+                },
+            }
+        }
+        Ok(Value::List(vals))
+    }
+
+    fn compound(&mut self, path: &str, depth: usize) -> ::std::result::Result<Value, Option<Value>> {
+        let mut map = HashMap::new();
+        // The `...Most` longs, which may turn out to be half of a UUID.
+        let mut longs = Vec::new();
+        loop {
+            let offset = self.src.pos;
+            let (id, name) = match self.header(path) {
+                Ok(header) => header,
+                Err(error) => {
+                    self.report(path, offset, error);
+                    return Err(Some(Value::Compound(map)));
+                },
+            };
+            if id == 0x00 {
+                break;
+            }
+            let child = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+            if id > 0x0c {
+                // The size of an unknown tag is unknown too, so stop here.
+                self.report(&child, offset, Error::InvalidTypeId(id));
+                return Err(Some(Value::Compound(map)));
+            }
+            if id == 0x04 && name.ends_with("Most") {
+                longs.push((child.clone(), offset, name[..name.len() - 4].to_string()));
+            }
+            let value = self.read(id, &child, depth + 1);
+            if map.contains_key(&name) {
+                self.warn(&child, offset, WarningKind::DuplicateKey);
+            }
+            match value {
+                Ok(value) => {
+                    map.insert(name, value);
+                },
+                Err(partial) => {
+                    if let Some(value) = partial {
+                        map.insert(name, value);
+                    }
+                    return Err(Some(Value::Compound(map)));
+                },
+            }
+        }
+        for (child, offset, prefix) in longs {
+            let pair = (map.get(&format!("{}Most", prefix)), map.get(&format!("{}Least", prefix)));
+            if let (Some(&Value::Long(_)), Some(&Value::Long(_))) = pair {
+                self.warn(&child, offset, WarningKind::LegacyUuid);
+            }
+        }
+        Ok(Value::Compound(map))
+    }
+}
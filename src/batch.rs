@@ -0,0 +1,248 @@
+//! Processing every NBT file in a directory tree.
+//!
+//! `BatchProcessor` walks a directory, picks out files matching its glob
+//! patterns, and hands each decoded document to a callback on a pool of worker
+//! threads. The compression of each file is detected when it is read and kept
+//! when it is written back, unless the callback changes it. Modified files are
+//! replaced atomically, by writing a temporary file next to them and renaming
+//! it over the original, so an interrupted run never leaves a file half
+//! written.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+use compression::Registry;
+use error::{Error, Result};
+use normalize;
+use value::Value;
+
+/// A decoded file, as passed to the callback of `BatchProcessor::run`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+    /// The name of the root tag.
+    pub title: String,
+    /// The root tag.
+    pub value: Value,
+    /// The name of the compression scheme the file uses, such as
+    /// `minecraft:gzip`. Changing it converts the file to the new scheme when
+    /// it is written back.
+    pub compression: String,
+}
+
+impl Document {
+    /// Reads a file, detecting its compression with the given registry.
+    pub fn read(registry: &Registry, path: &Path) -> Result<Document> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        let codec = match registry.sniff(&data) {
+            Some(codec) => codec,
+            // A truncated file may be missing its magic bytes.
+            None if data.len() < 2 => return Err(Error::IncompleteNbtValue),
+            None => return Err(Error::UnknownCompression(path.display().to_string())),
+        };
+        let raw = codec.decompress(&data)?;
+        let mut src = &raw[..];
+        let (id, title) = Value::read_header(&mut src)?;
+        let value = Value::from_reader(id, &mut src)?;
+        Ok(Document { title, value, compression: codec.name().to_string() })
+    }
+
+    /// Encodes and compresses the document. Compound entries are written in
+    /// sorted order, so the output is the same every time.
+    pub fn to_bytes(&self, registry: &Registry) -> Result<Vec<u8>> {
+        let mut encoded = Vec::new();
+        normalize::write_canonical(&mut encoded, &self.title, &self.value)?;
+        match registry.by_name(&self.compression) {
+            Some(codec) => codec.compress(&encoded),
+            None => Err(Error::UnknownCompression(self.compression.clone())),
+        }
+    }
+
+    /// Writes the document to a file atomically, by writing a temporary file
+    /// next to it and renaming that over the original.
+    pub fn write(&self, registry: &Registry, path: &Path) -> Result<()> {
+        let output = self.to_bytes(registry)?;
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let written = File::create(&tmp)
+            .and_then(|mut file| file.write_all(&output).and_then(|_| file.sync_all()))
+            .and_then(|_| fs::rename(&tmp, path));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&tmp);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of processing a single file.
+#[derive(Debug)]
+pub struct FileReport {
+    /// The path to the file.
+    pub path: PathBuf,
+    /// Whether the file was rewritten, or the error encountered.
+    pub result: Result<bool>,
+}
+
+/// The outcomes of a batch run, in the order the files were found.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// The outcome for each file that matched.
+    pub files: Vec<FileReport>,
+}
+
+impl Report {
+    /// The files that were rewritten.
+    pub fn modified(&self) -> Vec<&Path> {
+        self.files.iter()
+            .filter(|f| f.result.as_ref().ok() == Some(&true))
+            .map(|f| f.path.as_path())
+            .collect()
+    }
+
+    /// The files that could not be processed, with their errors.
+    pub fn failed(&self) -> Vec<(&Path, &Error)> {
+        self.files.iter()
+            .filter_map(|f| f.result.as_ref().err().map(|e| (f.path.as_path(), e)))
+            .collect()
+    }
+}
+
+/// Applies a callback to every matching file in a directory tree.
+#[derive(Clone, Debug)]
+pub struct BatchProcessor {
+    root: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    threads: usize,
+}
+
+impl BatchProcessor {
+    /// Creates a processor for every file below `root`, using one worker
+    /// thread per available CPU.
+    pub fn new<P: Into<PathBuf>>(root: P) -> BatchProcessor {
+        BatchProcessor {
+            root: root.into(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    /// Only process files matching `pattern`. Patterns may use `*` to match
+    /// any run of characters and `?` to match any one character. Patterns
+    /// containing `/` are matched against the path relative to the root, and
+    /// others against the file name alone.
+    pub fn include<S: Into<String>>(mut self, pattern: S) -> BatchProcessor {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Skip files matching `pattern`, even if they are included.
+    pub fn exclude<S: Into<String>>(mut self, pattern: S) -> BatchProcessor {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Sets the number of worker threads.
+    pub fn threads(mut self, threads: usize) -> BatchProcessor {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// The matching files below the root, in sorted order.
+    pub fn files(&self) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        self.walk(&self.root, &mut files)?;
+        Ok(files)
+    }
+
+    fn walk(&self, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        let mut entries = fs::read_dir(dir)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        for path in entries {
+            if path.is_dir() {
+                self.walk(&path, files)?;
+            } else if self.matches(&path) {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path).to_string_lossy().replace('\\', "/");
+        let name = path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+        let test = |pattern: &String| {
+            let subject = if pattern.contains('/') { &relative } else { &name };
+            let pattern: Vec<char> = pattern.chars().collect();
+            glob(&pattern, &subject.chars().collect::<Vec<_>>())
+        };
+        (self.include.is_empty() || self.include.iter().any(test)) && !self.exclude.iter().any(test)
+    }
+
+    /// Runs `callback` on every matching file. The callback returns whether it
+    /// modified the document, and only modified documents are written back.
+    pub fn run<F>(&self, callback: F) -> Result<Report>
+        where F: Fn(&Path, &mut Document) -> Result<bool> + Sync
+    {
+        let files = self.files()?;
+        let next = Mutex::new(0);
+        let results = Mutex::new(Vec::with_capacity(files.len()));
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(files.len()) {
+                scope.spawn(|| {
+                    // Codecs are not thread-safe, so each worker has its own.
+                    let registry = Registry::new();
+                    loop {
+                        let i = {
+                            let mut next = next.lock().unwrap();
+                            *next += 1;
+                            *next - 1
+                        };
+                        let path = match files.get(i) {
+                            Some(path) => path,
+                            None => break,
+                        };
+                        let result = process(&registry, path, &callback);
+                        results.lock().unwrap().push((i, result));
+                    }
+                });
+            }
+        });
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|&(i, _)| i);
+        let files = results.into_iter()
+            .map(|(i, result)| FileReport { path: files[i].clone(), result })
+            .collect();
+        Ok(Report { files })
+    }
+}
+
+fn process<F>(registry: &Registry, path: &Path, callback: &F) -> Result<bool>
+    where F: Fn(&Path, &mut Document) -> Result<bool>
+{
+    let mut doc = Document::read(registry, path)?;
+    if !callback(path, &mut doc)? {
+        return Ok(false);
+    }
+
+    doc.write(registry, path)?;
+    Ok(true)
+}
+
+/// Matches `name` against a pattern of literal characters, `*` and `?`.
+pub(crate) fn glob(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((&'*', rest)) => (0..=name.len()).any(|i| glob(rest, &name[i..])),
+        Some((&'?', rest)) => !name.is_empty() && glob(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && glob(rest, &name[1..]),
+    }
+}
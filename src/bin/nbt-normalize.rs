@@ -0,0 +1,54 @@
+//! Rewrites NBT files, or every `.dat` file in world directories, in a
+//! deterministic encoding that keeps version-control diffs small.
+//!
+//! Usage: `nbt-normalize <file or directory>...`
+
+extern crate nbt;
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use nbt::normalize;
+
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: nbt-normalize <file or directory>...");
+        process::exit(2);
+    }
+
+    let mut failed = false;
+    for path in &paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            match normalize::normalize_world(path) {
+                Ok(report) => {
+                    for file in report.modified() {
+                        println!("normalized {}", file.display());
+                    }
+                    for (file, e) in report.failed() {
+                        eprintln!("{}: {}", file.display(), e);
+                        failed = true;
+                    }
+                },
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    failed = true;
+                },
+            }
+        } else {
+            match normalize::normalize_file(path) {
+                Ok(true) => println!("normalized {}", path.display()),
+                Ok(false) => {},
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    failed = true;
+                },
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,220 @@
+//! Inspects and converts NBT files. Built with the `cli` feature.
+//!
+//! Usage:
+//!
+//! * `nbt dump [--snbt] <file>` prints a file as a tree, or as SNBT;
+//! * `nbt convert [--compression=gzip|zlib|none] <input> <output>` converts
+//!   between binary NBT and YAML, or to SNBT, choosing formats by extension;
+//! * `nbt get <file> <path>` prints the tags an NBT path selects, as SNBT;
+//! * `nbt region ls <file.mca>` lists the chunks of a region file;
+//! * `nbt region extract [--snbt] <file.mca> <x> <z> [<output>]` prints a
+//!   chunk, or writes it to a file as `convert` would;
+//! * `nbt region stats <file.mca>` reports how much space each chunk takes
+//!   and how much of the file is wasted.
+//!
+//! Binary input may be in any variant `nbt::sniff` recognises, except region
+//! files.
+
+extern crate nbt;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::process;
+
+use nbt::batch::Document;
+use nbt::compression::Registry;
+use nbt::path::NbtPath;
+use nbt::region::RegionEditor;
+use nbt::{sniff, yaml, Blob, Result, Value};
+
+const USAGE: &str = "usage: nbt dump [--snbt] <file>
+       nbt convert [--compression=gzip|zlib|none] <input> <output>
+       nbt get <file> <path>
+       nbt region ls <file.mca>
+       nbt region extract [--snbt] [--compression=gzip|zlib|none] <file.mca> <x> <z> [<output>]
+       nbt region stats <file.mca>";
+
+const SECTOR: usize = 4096;
+
+/// The options given as flags.
+struct Flags<'a> {
+    snbt: bool,
+    compression: Option<&'a str>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut flags = Flags { snbt: false, compression: None };
+    let mut rest = Vec::new();
+    for arg in &args {
+        match &arg[..] {
+            "--snbt" => flags.snbt = true,
+            a if a.starts_with("--compression=") => flags.compression = Some(&a["--compression=".len()..]),
+            a if a.starts_with("--") => usage(),
+            a => rest.push(a),
+        }
+    }
+
+    let result = match rest[..] {
+        ["dump", file] => dump(file, &flags),
+        ["convert", input, output] => convert(input, output, &flags),
+        ["get", file, path] => get(file, path),
+        ["region", "ls", file] => region_ls(file),
+        ["region", "extract", file, x, z] => region_extract(file, x, z, None, &flags),
+        ["region", "extract", file, x, z, output] => region_extract(file, x, z, Some(output), &flags),
+        ["region", "stats", file] => region_stats(file),
+        _ => usage(),
+    };
+    if let Err(e) = result {
+        eprintln!("nbt: {}", e);
+        process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "yaml" || e == "yml")
+}
+
+/// Reads a binary or YAML file, returning the name and value of its root.
+fn read(path: &Path) -> Result<(String, Value)> {
+    if is_yaml(path) {
+        return Ok((String::new(), yaml::from_reader(&mut File::open(path)?)?));
+    }
+    let (_, title, value) = sniff::decode(&fs::read(path)?)?;
+    Ok((title, value))
+}
+
+fn dump(file: &str, flags: &Flags) -> Result<()> {
+    let (title, value) = read(Path::new(file))?;
+    print(title, value, flags)
+}
+
+fn convert(input: &str, output: &str, flags: &Flags) -> Result<()> {
+    let (title, value) = read(Path::new(input))?;
+    write(Path::new(output), title, value, flags)
+}
+
+/// Prints a root tag as a tree, or as SNBT.
+fn print(title: String, value: Value, flags: &Flags) -> Result<()> {
+    if flags.snbt {
+        println!("{}", value.to_snbt_pretty(4));
+        return Ok(());
+    }
+    // Only color the tree when it is going to a terminal.
+    let color = io::stdout().is_terminal();
+    match value {
+        Value::Compound(map) => {
+            let mut blob = Blob::new(title);
+            for (name, value) in map {
+                blob.insert(name, value)?;
+            }
+            if color { println!("{:#}", blob) } else { println!("{}", blob) }
+        },
+        other => if color { println!("{:#}", other) } else { println!("{}", other) },
+    }
+    Ok(())
+}
+
+/// Writes a root tag to a file, in the format its extension suggests.
+fn write(output: &Path, title: String, value: Value, flags: &Flags) -> Result<()> {
+    if is_yaml(output) {
+        return yaml::to_writer(&mut File::create(output)?, &value);
+    }
+    if output.extension().is_some_and(|e| e == "snbt") {
+        let mut file = File::create(output)?;
+        return writeln!(file, "{}", value.to_snbt_pretty(4)).map_err(From::from);
+    }
+
+    let compression = match flags.compression {
+        None | Some("gzip") => "minecraft:gzip",
+        Some("zlib") => "minecraft:deflate",
+        Some("none") => "minecraft:none",
+        Some(_) => usage(),
+    };
+    let doc = Document { title, value, compression: compression.to_string() };
+    doc.write(&Registry::new(), output)
+}
+
+fn get(file: &str, path: &str) -> Result<()> {
+    let path = NbtPath::parse(path)?;
+    let (_, value) = read(Path::new(file))?;
+    for found in path.get(&value) {
+        println!("{}", found.to_snbt());
+    }
+    Ok(())
+}
+
+fn coordinate(arg: &str) -> i32 {
+    match arg.parse() {
+        Ok(n) => n,
+        Err(_) => usage(),
+    }
+}
+
+fn region_ls(file: &str) -> Result<()> {
+    let mut region = RegionEditor::open_read_only(file)?;
+    for (x, z) in region.chunks() {
+        if let Some(info) = region.chunk_info(x, z)? {
+            let external = if info.external { " (external)" } else { "" };
+            println!("{:2} {:2}  {:8} bytes  {:3} sectors  {}{}  saved {}",
+                     x, z, info.length, info.sectors, info.compression, external, info.timestamp);
+        }
+    }
+    Ok(())
+}
+
+fn region_extract(file: &str, x: &str, z: &str, output: Option<&str>, flags: &Flags) -> Result<()> {
+    let (x, z) = (coordinate(x), coordinate(z));
+    let chunk = match RegionEditor::open_read_only(file)?.read_chunk(x, z)? {
+        Some(chunk) => chunk,
+        None => {
+            eprintln!("nbt: there is no chunk at {}, {}", x, z);
+            process::exit(1);
+        },
+    };
+    match output {
+        Some(output) => write(Path::new(output), chunk.title, chunk.value, flags),
+        None => print(chunk.title, chunk.value, flags),
+    }
+}
+
+fn region_stats(file: &str) -> Result<()> {
+    let mut region = RegionEditor::open_read_only(file)?;
+    let (mut chunks, mut stored, mut sectors, mut external) = (0, 0, 0, 0);
+    let mut compressions = BTreeMap::new();
+    for (x, z) in region.chunks() {
+        let info = match region.chunk_info(x, z)? {
+            Some(info) => info,
+            None => continue,
+        };
+        let slack = info.sectors * SECTOR - (info.length + 4).min(info.sectors * SECTOR);
+        println!("{:2} {:2}  {:8} bytes  {:3} sectors  {:5} bytes slack",
+                 x, z, info.length, info.sectors, slack);
+        chunks += 1;
+        stored += info.length;
+        sectors += info.sectors;
+        if info.external {
+            external += 1;
+        }
+        *compressions.entry(info.compression).or_insert(0) += 1;
+    }
+
+    let free = region.free_sectors()?;
+    println!();
+    println!("{} chunks, {} in .mcc files", chunks, external);
+    for (name, count) in &compressions {
+        println!("{:6} {}", count, name);
+    }
+    println!("{} bytes stored in {} sectors, {} bytes of slack",
+             stored, sectors, (sectors * SECTOR).saturating_sub(stored + 4 * chunks));
+    println!("{} sectors ({} bytes) unused, which compaction would free", free, free * SECTOR);
+    Ok(())
+}
@@ -8,7 +8,7 @@ use flate2::read::{GzDecoder, ZlibDecoder};
 use flate2::write::{GzEncoder, ZlibEncoder};
 
 use error::{Error, Result};
-use value::Value;
+use value::{self, Value};
 
 /// A generic, complete object in Named Binary Tag format.
 ///
@@ -130,6 +130,16 @@ impl Blob {
     }
 }
 
+/// Extracts an `Blob` object from an `io::Read` source that may be gzipped,
+/// zlib-compressed or uncompressed, detecting which from its first bytes.
+/// See `Value::from_reader_auto`.
+pub fn from_reader_auto<R: io::Read>(src: R) -> Result<Blob> {
+    match Value::from_reader_auto(src)? {
+        (title, Value::Compound(map)) => Ok(Blob { title, content: Value::Compound(map) }),
+        _ => Err(Error::NoRootCompound),
+    }
+}
+
 impl<'a> Index<&'a str> for Blob {
     type Output = Value;
 
@@ -143,6 +153,6 @@ impl<'a> Index<&'a str> for Blob {
 
 impl fmt::Display for Blob {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "TAG_Compound(\"{}\"): {}", self.title, self.content)
+        value::write_tree(f, &self.title, &self.content)
     }
 }
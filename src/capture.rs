@@ -0,0 +1,189 @@
+//! Decoding that keeps the raw bytes of every decoded tag.
+//!
+//! `read_captured` decodes a document from any `io::Read` source while copying
+//! everything it reads, and records where the payload of each tag starts and
+//! ends in that copy. This lets proxies and validators re-emit the parts of a
+//! document they did not touch byte-for-byte, and only re-encode what changed;
+//! `Captured::write_reusing` does exactly that. Decoding is bounded by
+//! `DecoderOptions` as `Value::from_reader_with` is.
+
+use std::collections::HashMap;
+use std::io;
+use std::ops::Range;
+
+use error::{Error, Result};
+use raw;
+use value::{DecoderOptions, Limited, Value};
+
+/// A reader that copies everything read through it.
+struct Tee<R> {
+    inner: R,
+    buf: Vec<u8>,
+}
+
+impl<R: io::Read> io::Read for Tee<R> {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(dst)?;
+        self.buf.extend_from_slice(&dst[..n]);
+        Ok(n)
+    }
+}
+
+/// The location of a tag's payload in the raw bytes of a document, along with
+/// the locations of its children.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    /// The range of the payload, excluding the tag's type ID and name.
+    pub range: Range<usize>,
+    children: Children,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Children {
+    None,
+    List(Vec<Span>),
+    Compound(HashMap<String, Span>),
+}
+
+impl Span {
+    /// The span of the compound entry with the given name, if this is the
+    /// span of a compound.
+    pub fn child(&self, name: &str) -> Option<&Span> {
+        match self.children {
+            Children::Compound(ref map) => map.get(name),
+            _ => None,
+        }
+    }
+
+    /// The span of the list element at the given index, if this is the span
+    /// of a list.
+    pub fn element(&self, index: usize) -> Option<&Span> {
+        match self.children {
+            Children::List(ref vals) => vals.get(index),
+            _ => None,
+        }
+    }
+}
+
+/// A decoded document, together with its raw bytes and the span of each tag.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Captured {
+    /// The name of the root tag.
+    pub title: String,
+    /// The decoded root tag.
+    pub value: Value,
+    /// Every byte that was read to decode the document.
+    pub raw: Vec<u8>,
+    /// The span of the root tag's payload.
+    pub span: Span,
+}
+
+/// Decodes a document, capturing its raw bytes, within the default
+/// `DecoderOptions`.
+pub fn read_captured<R: io::Read>(src: R) -> Result<Captured> {
+    read_captured_with(src, &DecoderOptions::default())
+}
+
+/// Decodes a document like `read_captured`, failing as soon as the data goes
+/// beyond one of the limits in `options`.
+pub fn read_captured_with<R: io::Read>(src: R, options: &DecoderOptions) -> Result<Captured> {
+    let mut tee = Tee { inner: src, buf: Vec::new() };
+    let (title, value, span) = {
+        let mut src = Limited::new(&mut tee, options);
+        let (id, title) = src.entry_header()?;
+        let (value, span) = read_spanned(id, &mut src, 0)?;
+        (title, value, span)
+    };
+    Ok(Captured { title, value, raw: tee.buf, span })
+}
+
+/// Reads a tag nested `depth` lists and compounds deep.
+fn read_spanned<R: io::Read>(id: u8, src: &mut Limited<Tee<R>>, depth: usize) -> Result<(Value, Span)> {
+    let start = src.src.buf.len();
+    let (value, children) = match id {
+        0x09 => {
+            src.nest(depth)?;
+            let (elem, len) = src.list_header()?;
+            let mut vals = Vec::with_capacity(raw::initial_capacity(len));
+            let mut spans = Vec::with_capacity(raw::initial_capacity(len));
+            for _ in 0..len {
+                let (value, span) = read_spanned(elem, src, depth + 1)?;
+                vals.push(value);
+                spans.push(span);
+            }
+            (Value::List(vals), Children::List(spans))
+        },
+        0x0a => {
+            src.nest(depth)?;
+            let mut map = HashMap::new();
+            let mut spans = HashMap::new();
+            loop {
+                let (id, name) = src.entry_header()?;
+                if id == 0x00 {
+                    break;
+                }
+                let (value, span) = read_spanned(id, src, depth + 1)?;
+                spans.insert(name.clone(), span);
+                map.insert(name, value);
+            }
+            (Value::Compound(map), Children::Compound(spans))
+        },
+        _ => (src.value(id, depth)?, Children::None),
+    };
+    Ok((value, Span { range: start..src.src.buf.len(), children }))
+}
+
+impl Captured {
+    /// The raw payload bytes of a tag, given its span.
+    pub fn bytes(&self, span: &Span) -> &[u8] {
+        &self.raw[span.range.clone()]
+    }
+
+    /// Writes `value` as the root of a document named `title`, copying the
+    /// raw bytes of every tag that is unchanged from the captured document
+    /// instead of encoding it again.
+    ///
+    /// Unchanged compounds are copied with their entries in the original
+    /// order, so a document that was not modified at all is written back
+    /// exactly as it was read.
+    pub fn write_reusing<W: io::Write>(&self, title: &str, value: &Value, dst: &mut W) -> Result<()> {
+        value.write_header(dst, title)?;
+        self.write_payload(value, Some((&self.value, &self.span)), dst)
+    }
+
+    fn write_payload<W: io::Write>(&self, value: &Value, original: Option<(&Value, &Span)>,
+                                   dst: &mut W) -> Result<()> {
+        let (old, span) = match original {
+            Some(original) => original,
+            None => return value.write(dst),
+        };
+        if old == value {
+            dst.write_all(self.bytes(span))?;
+            return Ok(());
+        }
+        match (value, old) {
+            (Value::Compound(map), Value::Compound(old_map)) => {
+                for (name, child) in map {
+                    let original = old_map.get(name).and_then(|old| span.child(name).map(|s| (old, s)));
+                    child.write_header(dst, name)?;
+                    self.write_payload(child, original, dst)?;
+                }
+                raw::close_nbt(dst)
+            },
+            (Value::List(vals), Value::List(old_vals)) if !vals.is_empty() => {
+                let id = vals[0].id();
+                if vals.iter().any(|v| v.id() != id) {
+                    return Err(Error::HeterogeneousList);
+                }
+                raw::write_bare_byte(dst, id as i8)?;
+                raw::write_bare_int(dst, raw::list_len(vals.len())?)?;
+                for (i, child) in vals.iter().enumerate() {
+                    let original = old_vals.get(i).and_then(|old| span.element(i).map(|s| (old, s)));
+                    self.write_payload(child, original, dst)?;
+                }
+                Ok(())
+            },
+            _ => value.write(dst),
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! Block access for the paletted block storage of chunk sections.
+//!
+//! Since Minecraft 1.16, each 16x16x16 section stores its blocks as a palette
+//! of block state compounds plus a `TAG_Long_Array` of palette indices. Each
+//! index takes `bits` bits, where `bits` is the smallest width (but at least
+//! four) that can address the whole palette, and indices are packed from the
+//! least significant end of each long without spanning two longs. Blocks are
+//! ordered by `y`, then `z`, then `x`.
+//!
+//! The older (1.13 to 1.15) layout, in which indices may span longs, is not
+//! supported.
+//!
+//! `Chunk::from_value` reads the sections of a chunk as stored since 1.18,
+//! from `sections[].block_states.palette` and `data`, and `Chunk::write_to`
+//! puts them back without disturbing the rest of the chunk.
+
+use std::collections::{btree_map, BTreeMap, HashMap};
+
+use error::{Error, Result};
+use value::Value;
+
+/// The number of blocks along each edge of a section.
+pub const SECTION_SIZE: usize = 16;
+
+const BLOCKS: usize = SECTION_SIZE * SECTION_SIZE * SECTION_SIZE;
+const MIN_BITS: usize = 4;
+
+/// The number of bits needed for each index into a palette of `len` entries.
+fn bits_for(len: usize) -> usize {
+    let mut bits = MIN_BITS;
+    while (1 << bits) < len {
+        bits += 1;
+    }
+    bits
+}
+
+/// The number of longs needed to store every index at the given width.
+fn longs_for(bits: usize) -> usize {
+    let per_long = 64 / bits;
+    BLOCKS.div_ceil(per_long)
+}
+
+fn index(x: usize, y: usize, z: usize) -> usize {
+    assert!(x < SECTION_SIZE && y < SECTION_SIZE && z < SECTION_SIZE,
+            "block coordinates ({}, {}, {}) are outside of the section", x, y, z);
+    (y * SECTION_SIZE + z) * SECTION_SIZE + x
+}
+
+/// The block states of a single 16x16x16 chunk section.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    palette: Vec<Value>,
+    data: Vec<i64>,
+}
+
+impl Section {
+    /// Creates a section filled entirely with the given block state.
+    pub fn new(fill: Value) -> Section {
+        Section { palette: vec![fill], data: Vec::new() }
+    }
+
+    /// Creates a section from its palette and packed index data, as found in
+    /// the `palette` and `data` fields of a section's block states.
+    ///
+    /// The data may be empty if the palette has a single entry. Otherwise,
+    /// this will return an error if the data is not the length expected for
+    /// the size of the palette, or `InvalidPaletteIndex` if any index is not
+    /// below the palette's length.
+    pub fn from_parts(palette: Vec<Value>, data: Vec<i64>) -> Result<Section> {
+        if palette.is_empty() {
+            return Err(Error::IncompleteNbtValue);
+        }
+        let single = palette.len() == 1 && data.is_empty();
+        if !single && data.len() != longs_for(bits_for(palette.len())) {
+            return Err(Error::IncompleteNbtValue);
+        }
+        let section = Section { palette, data };
+        if let Some(id) = (0..BLOCKS).map(|i| section.get_index(i)).find(|&id| id >= section.palette.len()) {
+            return Err(Error::InvalidPaletteIndex(id, section.palette.len()));
+        }
+        Ok(section)
+    }
+
+    /// Reads a section from its `block_states` compound.
+    pub fn from_value(value: &Value) -> Result<Section> {
+        let map = as_compound(value)?;
+        let palette = match map.get("palette") {
+            Some(Value::List(palette)) => palette.clone(),
+            Some(other) => return Err(Error::TagMismatch(other.id(), 0x09)),
+            None => return Err(Error::MissingField("palette".to_string())),
+        };
+        let data = match map.get("data") {
+            Some(Value::LongArray(data)) => data.clone(),
+            Some(other) => return Err(Error::TagMismatch(other.id(), 0x0c)),
+            None => Vec::new(),
+        };
+        Section::from_parts(palette, data)
+    }
+
+    /// Converts the section to a `block_states` compound, leaving out `data`
+    /// if the whole section is a single block state, as the game does.
+    pub fn to_value(&self) -> Value {
+        let mut map = HashMap::new();
+        map.insert("palette".to_string(), Value::List(self.palette.clone()));
+        if !self.data.is_empty() {
+            map.insert("data".to_string(), Value::LongArray(self.data.clone()));
+        }
+        Value::Compound(map)
+    }
+
+    /// Consumes the section, returning its palette and packed index data.
+    pub fn into_parts(self) -> (Vec<Value>, Vec<i64>) {
+        (self.palette, self.data)
+    }
+
+    /// The block states referenced by this section.
+    pub fn palette(&self) -> &[Value] {
+        &self.palette
+    }
+
+    /// The packed palette indices of this section, which is empty if the
+    /// whole section is a single block state.
+    pub fn data(&self) -> &[i64] {
+        &self.data
+    }
+
+    fn bits(&self) -> usize {
+        bits_for(self.palette.len())
+    }
+
+    fn get_index(&self, i: usize) -> usize {
+        if self.data.is_empty() {
+            return 0;
+        }
+        let bits = self.bits();
+        let per_long = 64 / bits;
+        let long = self.data[i / per_long] as u64;
+        let shift = (i % per_long) * bits;
+        ((long >> shift) & ((1 << bits) - 1)) as usize
+    }
+
+    fn set_index(&mut self, i: usize, value: usize) {
+        let bits = self.bits();
+        let per_long = 64 / bits;
+        let shift = (i % per_long) * bits;
+        let mask = ((1u64 << bits) - 1) << shift;
+        let long = &mut self.data[i / per_long];
+        *long = ((*long as u64 & !mask) | ((value as u64) << shift)) as i64;
+    }
+
+    /// Returns the block state at the given coordinates within the section.
+    ///
+    /// Panics if any coordinate is 16 or more.
+    pub fn block_at(&self, x: usize, y: usize, z: usize) -> &Value {
+        &self.palette[self.get_index(index(x, y, z))]
+    }
+
+    /// Sets the block state at the given coordinates within the section,
+    /// adding it to the palette and widening the packed data if needed.
+    ///
+    /// Panics if any coordinate is 16 or more.
+    pub fn set_block_at(&mut self, x: usize, y: usize, z: usize, state: Value) {
+        let i = index(x, y, z);
+        let id = match self.palette.iter().position(|s| *s == state) {
+            Some(id) => id,
+            None => {
+                self.grow(state);
+                self.palette.len() - 1
+            },
+        };
+        if self.data.is_empty() {
+            if id == 0 {
+                return;
+            }
+            self.data = vec![0; longs_for(self.bits())];
+        }
+        self.set_index(i, id);
+    }
+
+    /// Adds a state to the palette, repacking the data if the wider palette
+    /// needs more bits per index.
+    fn grow(&mut self, state: Value) {
+        let old_bits = self.bits();
+        let indices: Vec<usize> = if self.data.is_empty() {
+            Vec::new()
+        } else {
+            (0..BLOCKS).map(|i| self.get_index(i)).collect()
+        };
+        self.palette.push(state);
+        if self.bits() != old_bits && !indices.is_empty() {
+            self.data = vec![0; longs_for(self.bits())];
+            for (i, id) in indices.into_iter().enumerate() {
+                self.set_index(i, id);
+            }
+        }
+    }
+}
+
+/// The sections of a chunk column, keyed by their section `y` coordinate.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Chunk {
+    sections: BTreeMap<i32, Section>,
+}
+
+impl Chunk {
+    /// Creates a chunk with no sections.
+    pub fn new() -> Chunk {
+        Chunk { sections: BTreeMap::new() }
+    }
+
+    /// Reads the sections of a chunk compound. Sections without
+    /// `block_states`, such as those holding only light, are skipped.
+    pub fn from_value(value: &Value) -> Result<Chunk> {
+        let sections = match as_compound(value)?.get("sections") {
+            Some(Value::List(sections)) => sections,
+            Some(other) => return Err(Error::TagMismatch(other.id(), 0x09)),
+            None => return Err(Error::MissingField("sections".to_string())),
+        };
+        let mut chunk = Chunk::new();
+        for section in sections {
+            let map = as_compound(section)?;
+            let states = match map.get("block_states") {
+                Some(states) => states,
+                None => continue,
+            };
+            chunk.sections.insert(section_y(map)?, Section::from_value(states)?);
+        }
+        Ok(chunk)
+    }
+
+    /// Converts the chunk to a compound holding only its `sections`, each
+    /// with its `Y` and `block_states`.
+    pub fn to_value(&self) -> Value {
+        let mut chunk = Value::Compound(HashMap::new());
+        // A fresh compound with no sections cannot fail to be written to.
+        let _ = self.write_to(&mut chunk);
+        chunk
+    }
+
+    /// Replaces the `block_states` of the sections of a chunk compound with
+    /// those of this chunk, keeping the other fields of each section, such as
+    /// biomes and light. Sections missing from the compound are added.
+    pub fn write_to(&self, chunk: &mut Value) -> Result<()> {
+        let map = match *chunk {
+            Value::Compound(ref mut map) => map,
+            ref other => return Err(Error::TagMismatch(other.id(), 0x0a)),
+        };
+        let sections = match *map.entry("sections".to_string()).or_insert_with(|| Value::List(Vec::new())) {
+            Value::List(ref mut sections) => sections,
+            ref other => return Err(Error::TagMismatch(other.id(), 0x09)),
+        };
+        let mut missing: BTreeMap<i32, &Section> = self.sections.iter().map(|(&y, s)| (y, s)).collect();
+        for section in sections.iter_mut() {
+            let map = match *section {
+                Value::Compound(ref mut map) => map,
+                ref other => return Err(Error::TagMismatch(other.id(), 0x0a)),
+            };
+            if let Some(stored) = missing.remove(&section_y(map)?) {
+                map.insert("block_states".to_string(), stored.to_value());
+            }
+        }
+        for (y, stored) in missing {
+            let mut map = HashMap::new();
+            map.insert("Y".to_string(), Value::Byte(y as i8));
+            map.insert("block_states".to_string(), stored.to_value());
+            sections.push(Value::Compound(map));
+        }
+        Ok(())
+    }
+
+    /// Returns the section at the given section `y` coordinate.
+    pub fn section(&self, y: i32) -> Option<&Section> {
+        self.sections.get(&y)
+    }
+
+    /// Returns the section at the given section `y` coordinate, mutably.
+    pub fn section_mut(&mut self, y: i32) -> Option<&mut Section> {
+        self.sections.get_mut(&y)
+    }
+
+    /// Inserts a section, returning the section it replaced.
+    pub fn insert_section(&mut self, y: i32, section: Section) -> Option<Section> {
+        self.sections.insert(y, section)
+    }
+
+    /// Iterates over the sections in ascending `y` order.
+    pub fn sections(&self) -> btree_map::Iter<'_, i32, Section> {
+        self.sections.iter()
+    }
+
+    /// Returns the block state at the given column-local `x` and `z` and
+    /// world `y` coordinate, or `None` if there is no section there.
+    pub fn block_at(&self, x: usize, y: i32, z: usize) -> Option<&Value> {
+        let local = (y & 15) as usize;
+        self.sections.get(&(y >> 4)).map(|s| s.block_at(x, local, z))
+    }
+
+    /// Sets the block state at the given column-local `x` and `z` and world
+    /// `y` coordinate. A missing section is created and filled with air.
+    pub fn set_block_at(&mut self, x: usize, y: i32, z: usize, state: Value) {
+        let local = (y & 15) as usize;
+        self.sections.entry(y >> 4)
+            .or_insert_with(|| Section::new(air()))
+            .set_block_at(x, local, z, state);
+    }
+}
+
+fn as_compound(value: &Value) -> Result<&HashMap<String, Value>> {
+    match *value {
+        Value::Compound(ref map) => Ok(map),
+        ref other => Err(Error::TagMismatch(other.id(), 0x0a)),
+    }
+}
+
+/// Reads the `Y` of a section, which is a byte but may be wider in data
+/// written by other tools.
+fn section_y(section: &HashMap<String, Value>) -> Result<i32> {
+    match section.get("Y") {
+        Some(&Value::Byte(y)) => Ok(y as i32),
+        Some(&Value::Short(y)) => Ok(y as i32),
+        Some(&Value::Int(y)) => Ok(y),
+        Some(other) => Err(Error::TagMismatch(other.id(), 0x01)),
+        None => Err(Error::MissingField("Y".to_string())),
+    }
+}
+
+fn air() -> Value {
+    let mut map = HashMap::new();
+    map.insert("Name".to_string(), Value::String("minecraft:air".to_string()));
+    Value::Compound(map)
+}
@@ -0,0 +1,240 @@
+//! A registry of compression schemes, for reading and writing compressed NBT
+//! data.
+//!
+//! Files and chunks may be compressed in several ways: whole files are usually
+//! gzipped, while each chunk in a region file is prefixed with a byte naming
+//! its scheme (1 for gzip, 2 for zlib, 3 for none and 4 for LZ4). Since
+//! Minecraft 1.20.5, the byte 127 marks a custom scheme whose namespaced name
+//! is stored as a string before the data.
+//!
+//! A `Registry` maps these identifiers, and the magic bytes at the start of
+//! compressed data, to `Codec`s. Gzip, zlib and uncompressed data are
+//! supported out of the box, and other schemes can be added with
+//! `Registry::register` without changes to this crate.
+
+use std::fmt;
+use std::io::{Read, Write};
+
+use flate2::Compression;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+
+use error::{Error, Result};
+use raw;
+
+/// The region compression byte marking a custom, named scheme.
+pub const CUSTOM_REGION_ID: u8 = 127;
+
+/// A compression scheme. Codecs are shared by the threads decompressing
+/// chunks in parallel, so they must be `Send` and `Sync`.
+pub trait Codec: Send + Sync {
+    /// The name of the scheme. Custom schemes in region files are identified
+    /// by this name, so it should be namespaced, such as `mymod:zstd`.
+    fn name(&self) -> &str;
+
+    /// The region compression byte identifying this scheme, if it has one.
+    fn region_id(&self) -> Option<u8> {
+        None
+    }
+
+    /// Whether `data` looks like it was compressed with this scheme, usually
+    /// by checking its magic bytes.
+    fn sniff(&self, _data: &[u8]) -> bool {
+        false
+    }
+
+    /// Decompresses `data`.
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>>;
+
+    /// Compresses `data`.
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Gzip compression, which is used for most standalone files.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Gzip;
+
+impl Codec for Gzip {
+    fn name(&self) -> &str { "minecraft:gzip" }
+
+    fn region_id(&self) -> Option<u8> { Some(1) }
+
+    fn sniff(&self, data: &[u8]) -> bool {
+        data.starts_with(&[0x1f, 0x8b])
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        GzDecoder::new(data)?.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::Default);
+        encoder.write_all(data)?;
+        Ok(encoder.finish()?)
+    }
+}
+
+/// Zlib compression, which is used for most chunks in region files.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Zlib;
+
+impl Codec for Zlib {
+    fn name(&self) -> &str { "minecraft:deflate" }
+
+    fn region_id(&self) -> Option<u8> { Some(2) }
+
+    fn sniff(&self, data: &[u8]) -> bool {
+        // The compression method must be deflate, and the header a multiple
+        // of 31.
+        data.len() >= 2 && data[0] & 0x0f == 8 && (data[0] as u16 * 256 + data[1] as u16).is_multiple_of(31)
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        ZlibDecoder::new(data).read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::Default);
+        encoder.write_all(data)?;
+        Ok(encoder.finish()?)
+    }
+}
+
+/// No compression at all.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Uncompressed;
+
+impl Codec for Uncompressed {
+    fn name(&self) -> &str { "minecraft:none" }
+
+    fn region_id(&self) -> Option<u8> { Some(3) }
+
+    fn sniff(&self, data: &[u8]) -> bool {
+        // Uncompressed documents begin with the root compound's tag.
+        data.first() == Some(&0x0a)
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+}
+
+/// A set of compression schemes, looked up by region compression byte, by
+/// name or by sniffing their magic bytes.
+///
+/// When several codecs claim the same identifier, the one registered last
+/// wins, so built-in schemes can be replaced.
+pub struct Registry {
+    codecs: Vec<Box<dyn Codec>>,
+}
+
+impl Registry {
+    /// Creates a registry with the built-in gzip, zlib and uncompressed
+    /// schemes.
+    pub fn new() -> Registry {
+        let mut registry = Registry::empty();
+        registry.register(Gzip);
+        registry.register(Zlib);
+        registry.register(Uncompressed);
+        registry
+    }
+
+    /// Creates a registry with no schemes at all.
+    pub fn empty() -> Registry {
+        Registry { codecs: Vec::new() }
+    }
+
+    /// Adds a scheme, taking precedence over any registered earlier.
+    pub fn register<C: Codec + 'static>(&mut self, codec: C) {
+        self.codecs.push(Box::new(codec));
+    }
+
+    fn find<F: Fn(&dyn Codec) -> bool>(&self, pred: F) -> Option<&dyn Codec> {
+        self.codecs.iter().rev().map(|c| &**c).find(|c| pred(*c))
+    }
+
+    /// Looks up a scheme by its region compression byte.
+    pub fn by_region_id(&self, id: u8) -> Option<&dyn Codec> {
+        self.find(|c| c.region_id() == Some(id))
+    }
+
+    /// Looks up a scheme by its name.
+    pub fn by_name(&self, name: &str) -> Option<&dyn Codec> {
+        self.find(|c| c.name() == name)
+    }
+
+    /// Finds a scheme that recognises the start of `data`.
+    pub fn sniff(&self, data: &[u8]) -> Option<&dyn Codec> {
+        self.find(|c| c.sniff(data))
+    }
+
+    /// Decompresses `data` with whichever scheme recognises it.
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.sniff(data) {
+            Some(codec) => codec.decompress(data),
+            None => Err(Error::UnknownCompression("unrecognised data".to_string())),
+        }
+    }
+
+    /// Decompresses the payload of a chunk in a region file, given the
+    /// compression byte stored before it. For custom schemes, the payload
+    /// starts with the scheme's name.
+    ///
+    /// The flag marking chunks stored in separate `.mcc` files is ignored;
+    /// the caller must supply the contents of that file as the payload.
+    pub fn decompress_region(&self, id: u8, payload: &[u8]) -> Result<Vec<u8>> {
+        let id = id & 0x7f;
+        if id == CUSTOM_REGION_ID {
+            let mut src = payload;
+            let name = raw::read_bare_string(&mut src)?;
+            return match self.by_name(&name) {
+                Some(codec) => codec.decompress(src),
+                None => Err(Error::UnknownCompression(name)),
+            };
+        }
+        match self.by_region_id(id) {
+            Some(codec) => codec.decompress(payload),
+            None => Err(Error::UnknownCompression(format!("region compression {}", id))),
+        }
+    }
+
+    /// Compresses a chunk for a region file with the named scheme, returning
+    /// the compression byte and the payload. Schemes without a region
+    /// compression byte are written as custom schemes.
+    pub fn compress_region(&self, name: &str, data: &[u8]) -> Result<(u8, Vec<u8>)> {
+        let codec = match self.by_name(name) {
+            Some(codec) => codec,
+            None => return Err(Error::UnknownCompression(name.to_string())),
+        };
+        let compressed = codec.compress(data)?;
+        match codec.region_id() {
+            Some(id) => Ok((id, compressed)),
+            None => {
+                let mut payload = Vec::with_capacity(compressed.len() + name.len() + 2);
+                raw::write_bare_string(&mut payload, name)?;
+                payload.extend_from_slice(&compressed);
+                Ok((CUSTOM_REGION_ID, payload))
+            },
+        }
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.codecs.iter().map(|c| c.name())).finish()
+    }
+}
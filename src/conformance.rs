@@ -0,0 +1,236 @@
+//! A corpus of reference documents for checking decoder conformance.
+//!
+//! The corpus embeds the well-known `bigtest.nbt` and `hello_world.nbt`
+//! files, a few small documents exercising nested compounds and lists of
+//! compounds, and a `servers.dat`-style file with an empty root name, each
+//! paired with the tree it is expected to decode to. `run` checks any decoder
+//! against all of them, which makes it usable as a conformance gate for
+//! alternative backends.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+use error::Result;
+use testing;
+use value::Value;
+
+/// How a reference document is compressed on disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// The document is stored as-is.
+    None,
+    /// The document is gzip-compressed.
+    Gzip,
+}
+
+/// A reference document and the tree it must decode to.
+#[derive(Clone, Copy)]
+pub struct Case {
+    /// A short name identifying the document.
+    pub name: &'static str,
+    /// The document as stored on disk.
+    pub bytes: &'static [u8],
+    /// How `bytes` is compressed.
+    pub compression: Compression,
+    /// The name of the root tag.
+    pub title: &'static str,
+    expected: fn() -> Value,
+}
+
+impl Case {
+    /// The tree the root tag must decode to.
+    pub fn expected(&self) -> Value {
+        (self.expected)()
+    }
+
+    /// The uncompressed document.
+    pub fn decompressed(&self) -> Result<Vec<u8>> {
+        match self.compression {
+            Compression::None => Ok(self.bytes.to_vec()),
+            Compression::Gzip => {
+                let mut buf = Vec::new();
+                GzDecoder::new(self.bytes)?.read_to_end(&mut buf)?;
+                Ok(buf)
+            },
+        }
+    }
+}
+
+impl fmt::Debug for Case {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Case")
+            .field("name", &self.name)
+            .field("compression", &self.compression)
+            .field("title", &self.title)
+            .finish()
+    }
+}
+
+/// Returns every document in the corpus.
+pub fn cases() -> Vec<Case> {
+    vec![
+        Case {
+            name: "hello_world",
+            bytes: include_bytes!("../tests/small1.nbt"),
+            compression: Compression::None,
+            title: "hello world",
+            expected: hello_world,
+        },
+        Case {
+            name: "bigtest",
+            bytes: include_bytes!("../tests/big1.nbt"),
+            compression: Compression::Gzip,
+            title: "Level",
+            expected: bigtest,
+        },
+        Case {
+            name: "nested_compounds",
+            bytes: include_bytes!("../tests/small2.nbt"),
+            compression: Compression::None,
+            title: "hello world",
+            expected: nested_compounds,
+        },
+        Case {
+            name: "compound_list",
+            bytes: include_bytes!("../tests/small3.nbt"),
+            compression: Compression::None,
+            title: "aaa",
+            expected: compound_list,
+        },
+        Case {
+            name: "byte_compounds",
+            bytes: include_bytes!("../tests/small4.nbt"),
+            compression: Compression::None,
+            title: "Level",
+            expected: byte_compounds,
+        },
+        Case {
+            name: "servers",
+            bytes: include_bytes!("../tests/servers.dat"),
+            compression: Compression::None,
+            title: "",
+            expected: servers,
+        },
+    ]
+}
+
+/// A reference document that a decoder got wrong.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Failure {
+    /// The name of the case that failed.
+    pub case: &'static str,
+    /// What went wrong.
+    pub reason: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.case, self.reason)
+    }
+}
+
+/// Runs a decoder over every document in the corpus and returns the cases it
+/// got wrong, which is empty if it conforms.
+///
+/// The decoder is given each uncompressed document and must return the name
+/// and value of its root tag.
+pub fn run<F>(mut decode: F) -> Vec<Failure>
+    where F: FnMut(&[u8]) -> Result<(String, Value)>
+{
+    let mut failures = Vec::new();
+    for case in cases() {
+        let reason = match case.decompressed().and_then(|bytes| decode(&bytes)) {
+            Err(e) => Some(format!("failed to decode: {}", e)),
+            Ok((ref title, _)) if title != case.title => {
+                Some(format!("expected root name {:?}, found {:?}", case.title, title))
+            },
+            Ok((_, value)) => testing::compare(&case.expected(), &value).map(|d| d.to_string()),
+        };
+        if let Some(reason) = reason {
+            failures.push(Failure { case: case.name, reason });
+        }
+    }
+    failures
+}
+
+/// Decodes a document with this crate's own decoder, for use with `run`.
+pub fn decode(mut bytes: &[u8]) -> Result<(String, Value)> {
+    let (id, title) = Value::read_header(&mut bytes)?;
+    Ok((title, Value::from_reader(id, &mut bytes)?))
+}
+
+fn compound(entries: Vec<(&str, Value)>) -> Value {
+    Value::Compound(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect::<HashMap<_, _>>())
+}
+
+fn string(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+fn hello_world() -> Value {
+    compound(vec![("name", string("Bananrama"))])
+}
+
+fn bigtest() -> Value {
+    let food = |name: &str, value: f32| compound(vec![("name", string(name)), ("value", Value::Float(value))]);
+    let tag = |n: i32| compound(vec![
+        ("name", string(&format!("Compound tag #{}", n))),
+        ("created-on", Value::Long(1264099775885)),
+    ]);
+    let bytes = (0..1000).map(|n: i32| ((n * n * 255 + n * 7) % 100) as i8).collect();
+    compound(vec![
+        ("longTest", Value::Long(i64::MAX)),
+        ("shortTest", Value::Short(i16::MAX)),
+        ("stringTest", string("HELLO WORLD THIS IS A TEST STRING \u{c5}\u{c4}\u{d6}!")),
+        ("floatTest", Value::Float(0.498_231_47)),
+        ("intTest", Value::Int(i32::MAX)),
+        ("nested compound test", compound(vec![
+            ("ham", food("Hampus", 0.75)),
+            ("egg", food("Eggbert", 0.5)),
+        ])),
+        ("listTest (long)", Value::List((11..16).map(Value::Long).collect())),
+        ("listTest (compound)", Value::List(vec![tag(0), tag(1)])),
+        ("byteTest", Value::Byte(127)),
+        ("byteArrayTest (the first 1000 values of (n*n*255+n*7)%100, starting with n=0 (0, 62, 34, 16, 8, ...))",
+         Value::ByteArray(bytes)),
+        ("doubleTest", Value::Double(0.493_128_713_218_231_5)),
+    ])
+}
+
+fn nested_compounds() -> Value {
+    let inner = || compound(vec![
+        ("1", Value::Byte(0x11)),
+        ("2", Value::Short(0x1122)),
+        ("3", Value::Int(0x1122_3344)),
+    ]);
+    compound(vec![("aaa", inner()), ("bbb", inner())])
+}
+
+fn compound_list() -> Value {
+    let entry = || compound(vec![("name", string("wololo")), ("ccc", Value::Int(0x1122_3344))]);
+    compound(vec![("bbb", Value::List(vec![entry(), entry()]))])
+}
+
+fn byte_compounds() -> Value {
+    let inner = || compound(vec![
+        ("aaa", Value::Byte(0x11)),
+        ("bbb", Value::Byte(0x22)),
+        ("ccc", Value::Byte(0x33)),
+        ("ddd", Value::Byte(0x44)),
+    ]);
+    compound(vec![("c1", inner()), ("c2", inner())])
+}
+
+fn servers() -> Value {
+    compound(vec![("servers", Value::List(vec![
+        compound(vec![("ip", string("localhost:25565")), ("name", string("Minecraft Server"))]),
+        compound(vec![
+            ("ip", string("mc.example.com")),
+            ("name", string("Survival")),
+            ("acceptTextures", Value::Byte(1)),
+        ]),
+    ]))])
+}
@@ -0,0 +1,174 @@
+//! Structural comparison of `Value` trees.
+//!
+//! `diff` walks two trees side by side and reports each place where they
+//! differ, with its path in the form `NbtPath::parse` reads, such as
+//! `Inventory[3].Count`. Compound entries are compared by name, so the order
+//! they happen to be stored in never matters, and are reported in sorted
+//! order. Arrays are compared whole.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use path;
+use value::Value;
+
+/// How a tag differs between the two trees.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// The tag is only in the second tree.
+    Added(Value),
+    /// The tag is only in the first tree.
+    Removed(Value),
+    /// The tag has a different value or type in each tree. Holds the old and
+    /// new values.
+    Changed(Value, Value),
+}
+
+/// A difference found by `diff`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiffEntry {
+    /// The path of the tag that differs, or the empty string for the root.
+    /// For lists compared without regard to order, removed elements are
+    /// indexed as in the first tree and added ones as in the second.
+    pub path: String,
+    pub change: Change,
+}
+
+impl fmt::Display for DiffEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = if self.path.is_empty() { "the root tag" } else { &self.path };
+        match self.change {
+            Change::Added(ref v) => write!(f, "+ {}: {}", path, v.to_snbt()),
+            Change::Removed(ref v) => write!(f, "- {}: {}", path, v.to_snbt()),
+            Change::Changed(ref a, ref b) =>
+                write!(f, "~ {}: {} -> {}", path, a.to_snbt(), b.to_snbt()),
+        }
+    }
+}
+
+/// Options for `diff_with`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    /// Floats and doubles closer than this are treated as equal. Defaults to
+    /// zero, so only identical values are.
+    pub epsilon: f64,
+    /// Compare lists as unordered collections, reporting only the elements
+    /// that have no equal counterpart in the other list. Defaults to false.
+    pub ignore_list_order: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { epsilon: 0.0, ignore_list_order: false }
+    }
+}
+
+/// Lists the differences between `a` and `b`.
+pub fn diff(a: &Value, b: &Value) -> Vec<DiffEntry> {
+    diff_with(a, b, &Options::default())
+}
+
+/// Lists the differences between `a` and `b`, compared as `options` says.
+pub fn diff_with(a: &Value, b: &Value, options: &Options) -> Vec<DiffEntry> {
+    let mut differ = Differ { options, path: String::new(), entries: Vec::new() };
+    differ.value(a, b);
+    differ.entries
+}
+
+struct Differ<'a> {
+    options: &'a Options,
+    path: String,
+    entries: Vec<DiffEntry>,
+}
+
+impl<'a> Differ<'a> {
+    fn push(&mut self, change: Change) {
+        self.entries.push(DiffEntry { path: self.path.clone(), change });
+    }
+
+    fn value(&mut self, a: &Value, b: &Value) {
+        match (a, b) {
+            (Value::Compound(a), Value::Compound(b)) => self.compound(a, b),
+            (Value::List(a), Value::List(b)) if self.options.ignore_list_order =>
+                self.unordered_list(a, b),
+            (Value::List(a), Value::List(b)) => self.list(a, b),
+            _ if !self.same(a, b) => self.push(Change::Changed(a.clone(), b.clone())),
+            _ => (),
+        }
+    }
+
+    fn compound(&mut self, a: &HashMap<String, Value>, b: &HashMap<String, Value>) {
+        let mut keys: Vec<&String> = a.keys().chain(b.keys().filter(|k| !a.contains_key(*k))).collect();
+        keys.sort();
+
+        let restore = self.path.len();
+        for key in keys {
+            path::push_name(&mut self.path, key);
+            match (a.get(key), b.get(key)) {
+                (Some(a), Some(b)) => self.value(a, b),
+                (Some(a), None) => self.push(Change::Removed(a.clone())),
+                (None, Some(b)) => self.push(Change::Added(b.clone())),
+                (None, None) => unreachable!(),
+            }
+            self.path.truncate(restore);
+        }
+    }
+
+    fn list(&mut self, a: &[Value], b: &[Value]) {
+        let restore = self.path.len();
+        for i in 0..a.len().max(b.len()) {
+            self.path.push_str(&format!("[{}]", i));
+            match (a.get(i), b.get(i)) {
+                (Some(a), Some(b)) => self.value(a, b),
+                (Some(a), None) => self.push(Change::Removed(a.clone())),
+                (None, Some(b)) => self.push(Change::Added(b.clone())),
+                (None, None) => unreachable!(),
+            }
+            self.path.truncate(restore);
+        }
+    }
+
+    /// Pairs each element of `a` with the first unpaired equal element of
+    /// `b`, and reports the rest.
+    fn unordered_list(&mut self, a: &[Value], b: &[Value]) {
+        let mut paired = vec![false; b.len()];
+        let mut removed = Vec::new();
+        for (i, x) in a.iter().enumerate() {
+            match (0..b.len()).find(|&j| !paired[j] && self.same(x, &b[j])) {
+                Some(j) => paired[j] = true,
+                None => removed.push(i),
+            }
+        }
+
+        let restore = self.path.len();
+        for i in removed {
+            self.path.push_str(&format!("[{}]", i));
+            self.push(Change::Removed(a[i].clone()));
+            self.path.truncate(restore);
+        }
+        for (j, _) in paired.iter().enumerate().filter(|&(_, &p)| !p) {
+            self.path.push_str(&format!("[{}]", j));
+            self.push(Change::Added(b[j].clone()));
+            self.path.truncate(restore);
+        }
+    }
+
+    /// Whether two values are equal under the options.
+    fn same(&self, a: &Value, b: &Value) -> bool {
+        let epsilon = self.options.epsilon;
+        match (a, b) {
+            (&Value::Float(x), &Value::Float(y)) => x == y || ((x - y) as f64).abs() <= epsilon,
+            (&Value::Double(x), &Value::Double(y)) => x == y || (x - y).abs() <= epsilon,
+            (Value::List(x), Value::List(y)) if self.options.ignore_list_order => {
+                let mut differ = Differ { options: self.options, path: String::new(), entries: Vec::new() };
+                differ.unordered_list(x, y);
+                differ.entries.is_empty()
+            },
+            (Value::List(x), Value::List(y)) =>
+                x.len() == y.len() && x.iter().zip(y).all(|(x, y)| self.same(x, y)),
+            (Value::Compound(x), Value::Compound(y)) =>
+                x.len() == y.len() && x.iter().all(|(k, x)| y.get(k).is_some_and(|y| self.same(x, y))),
+            _ => a == b,
+        }
+    }
+}
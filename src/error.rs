@@ -13,8 +13,10 @@ pub type Result<T> = StdResult<T, Error>;
 /// `NbtValue` and `NbtBlob` objects.
 ///
 /// `Error`s can be seamlessly converted to more general `io::Error` objects
-/// using `std::convert::From::from()`.
+/// using `std::convert::From::from()`. More variants may be added, so matches
+/// on it need a catch-all arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// Wraps errors emitted by methods during I/O operations.
     IoError(io::Error),
@@ -38,12 +40,70 @@ pub enum Error {
     /// An error encountered when parsing NBT binary representations, where
     /// deserialization encounters a field name it is not expecting.
     UnexpectedField(String),
+    /// An error encountered when parsing YAML text into an `NbtValue`, with a
+    /// description of what went wrong and where.
+    InvalidYaml(String),
+    /// An error for when a typed model is built from an `NbtValue` that is
+    /// missing a required field. Includes the name of the field.
+    MissingField(String),
+    /// An error for when data is compressed with a scheme that has not been
+    /// registered. Includes the name or identifier of the scheme.
+    UnknownCompression(String),
+    /// An error for when data was written by a newer version of the game than
+    /// is supported. Includes the data version found and the newest supported.
+    DataVersionTooNew(i32, i32),
+    /// An error for when data was written by an older version of the game than
+    /// is supported. Includes the data version found and the oldest supported.
+    DataVersionTooOld(i32, i32),
+    /// An error for when lists and compounds are nested more deeply than
+    /// allowed while decoding. Includes the limit.
+    DepthLimitExceeded(usize),
+    /// An error for when a string, list or array is longer than allowed while
+    /// decoding. Includes the limit.
+    LengthLimitExceeded(usize),
+    /// An error for when decoding would read more bytes than allowed.
+    /// Includes the limit.
+    SizeLimitExceeded(u64),
+    /// An error for when a `stream::Writer` is asked to write something that
+    /// would not make a valid document. Includes a description of the mistake.
+    InvalidWrite(&'static str),
+    /// An error encountered when parsing an NBT path, with a description of
+    /// what went wrong and where.
+    InvalidPath(String),
+    /// An error for when a string is too long to be written, since its
+    /// length is stored in 16 bits. Includes its length in bytes.
+    StringTooLong(usize),
+    /// An error for when a list or array has more elements than its 32-bit
+    /// length can hold. Includes its length.
+    ListTooLong(usize),
+    /// An error for when the packed data of a chunk section refers past the
+    /// end of its palette. Includes the index found and the palette's length.
+    InvalidPaletteIndex(usize, usize),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             &Error::IoError(ref e) => e.fmt(f),
+            &Error::InvalidYaml(ref msg) => write!(f, "invalid YAML: {}", msg),
+            &Error::UnknownCompression(ref name) => write!(f, "unknown compression scheme: {}", name),
+            &Error::DataVersionTooNew(found, newest) =>
+                write!(f, "data version {} is newer than the newest supported, {}", found, newest),
+            &Error::DataVersionTooOld(found, oldest) =>
+                write!(f, "data version {} is older than the oldest supported, {}", found, oldest),
+            &Error::DepthLimitExceeded(max) =>
+                write!(f, "lists and compounds are nested more than {} deep", max),
+            &Error::LengthLimitExceeded(max) =>
+                write!(f, "a string, list or array is longer than {}", max),
+            &Error::SizeLimitExceeded(max) => write!(f, "data is larger than {} bytes", max),
+            &Error::InvalidWrite(msg) => write!(f, "invalid write: {}", msg),
+            &Error::InvalidPath(ref msg) => write!(f, "invalid NBT path: {}", msg),
+            &Error::StringTooLong(len) =>
+                write!(f, "a string of {} bytes is longer than 65535", len),
+            &Error::ListTooLong(len) =>
+                write!(f, "a list or array of {} elements is longer than {}", len, i32::MAX),
+            &Error::InvalidPaletteIndex(index, len) =>
+                write!(f, "palette index {} is out of range for a palette of {}", index, len),
             other                 => write!(f, "{}", other.description()),
         }
     }
@@ -60,12 +120,25 @@ impl StdError for Error {
             Error::IncompleteNbtValue => "data does not represent a complete NbtValue",
             Error::TagMismatch(_, _)  => "encountered one NBT tag but expected another",
             Error::UnexpectedField(_) => "encountered an unexpected field",
+            Error::InvalidYaml(_)     => "invalid YAML representation of an NbtValue",
+            Error::MissingField(_)    => "a required field is missing",
+            Error::UnknownCompression(_) => "unknown compression scheme",
+            Error::DataVersionTooNew(_, _) => "data was written by a newer version of the game",
+            Error::DataVersionTooOld(_, _) => "data was written by an older version of the game",
+            Error::DepthLimitExceeded(_) => "lists and compounds are nested too deeply",
+            Error::LengthLimitExceeded(_) => "a string, list or array is too long",
+            Error::SizeLimitExceeded(_) => "data is too large",
+            Error::InvalidWrite(_) => "the write would not make a valid document",
+            Error::InvalidPath(_) => "invalid NBT path",
+            Error::StringTooLong(_) => "a string is too long to be written",
+            Error::ListTooLong(_) => "a list or array is too long to be written",
+            Error::InvalidPaletteIndex(_, _) => "a palette index is out of range",
         }
     }
 
-    fn cause(&self) -> Option<&StdError> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match *self {
-            Error::IoError(ref e) => e.cause(),
+            Error::IoError(ref e) => Some(e),
             _ => None
         }
     }
@@ -75,7 +148,11 @@ impl StdError for Error {
 impl PartialEq<Error> for Error {
     fn eq(&self, other: &Error) -> bool {
         use Error::{IoError, InvalidTypeId, HeterogeneousList, NoRootCompound,
-                    InvalidUtf8, IncompleteNbtValue, TagMismatch, UnexpectedField};
+                    InvalidUtf8, IncompleteNbtValue, TagMismatch, UnexpectedField,
+                    InvalidYaml, MissingField, UnknownCompression, DataVersionTooNew,
+                    DataVersionTooOld, DepthLimitExceeded, LengthLimitExceeded,
+                    SizeLimitExceeded, InvalidWrite, InvalidPath, StringTooLong, ListTooLong,
+                    InvalidPaletteIndex};
 
         match (self, other) {
             (&IoError(_), &IoError(_))                 => true,
@@ -86,6 +163,19 @@ impl PartialEq<Error> for Error {
             (&IncompleteNbtValue, &IncompleteNbtValue) => true,
             (&TagMismatch(a, b), &TagMismatch(c, d))   => a == c && b == d,
             (&UnexpectedField(ref a), &UnexpectedField(ref b)) => a == b,
+            (&InvalidYaml(ref a), &InvalidYaml(ref b)) => a == b,
+            (&MissingField(ref a), &MissingField(ref b)) => a == b,
+            (&UnknownCompression(ref a), &UnknownCompression(ref b)) => a == b,
+            (&DataVersionTooNew(a, b), &DataVersionTooNew(c, d)) => a == c && b == d,
+            (&DataVersionTooOld(a, b), &DataVersionTooOld(c, d)) => a == c && b == d,
+            (&DepthLimitExceeded(a), &DepthLimitExceeded(b)) => a == b,
+            (&LengthLimitExceeded(a), &LengthLimitExceeded(b)) => a == b,
+            (&SizeLimitExceeded(a), &SizeLimitExceeded(b)) => a == b,
+            (&InvalidWrite(a), &InvalidWrite(b)) => a == b,
+            (&InvalidPath(ref a), &InvalidPath(ref b)) => a == b,
+            (&StringTooLong(a), &StringTooLong(b)) => a == b,
+            (&ListTooLong(a), &ListTooLong(b)) => a == b,
+            (&InvalidPaletteIndex(a, b), &InvalidPaletteIndex(c, d)) => a == c && b == d,
             _ => false
         }
     }
@@ -120,6 +210,42 @@ impl From<Error> for io::Error {
             Error::UnexpectedField(f) =>
                 io::Error::new(InvalidInput, &format!("encountered unexpected field \
                                                        with name {}", f)[..]),
+            Error::InvalidYaml(msg) =>
+                io::Error::new(InvalidInput, &format!("invalid YAML: {}", msg)[..]),
+            Error::MissingField(f) =>
+                io::Error::new(InvalidInput, &format!("missing required field \
+                                                       with name {}", f)[..]),
+            Error::UnknownCompression(name) =>
+                io::Error::new(InvalidInput, &format!("unknown compression \
+                                                       scheme {}", name)[..]),
+            Error::DataVersionTooNew(found, newest) =>
+                io::Error::new(InvalidInput, &format!("data version {} is newer than \
+                                                       {}", found, newest)[..]),
+            Error::DataVersionTooOld(found, oldest) =>
+                io::Error::new(InvalidInput, &format!("data version {} is older than \
+                                                       {}", found, oldest)[..]),
+            Error::DepthLimitExceeded(max) =>
+                io::Error::new(InvalidInput, &format!("lists and compounds are nested \
+                                                      more than {} deep", max)[..]),
+            Error::LengthLimitExceeded(max) =>
+                io::Error::new(InvalidInput, &format!("a string, list or array is longer \
+                                                      than {}", max)[..]),
+            Error::SizeLimitExceeded(max) =>
+                io::Error::new(InvalidInput, &format!("data is larger than {} \
+                                                      bytes", max)[..]),
+            Error::InvalidWrite(msg) =>
+                io::Error::new(InvalidInput, &format!("invalid write: {}", msg)[..]),
+            Error::InvalidPath(msg) =>
+                io::Error::new(InvalidInput, &format!("invalid NBT path: {}", msg)[..]),
+            Error::StringTooLong(len) =>
+                io::Error::new(InvalidInput, &format!("a string of {} bytes is longer \
+                                                      than 65535", len)[..]),
+            Error::ListTooLong(len) =>
+                io::Error::new(InvalidInput, &format!("a list or array of {} elements \
+                                                      is longer than {}", len, i32::MAX)[..]),
+            Error::InvalidPaletteIndex(index, len) =>
+                io::Error::new(InvalidInput, &format!("palette index {} is out of range \
+                                                      for a palette of {}", index, len)[..]),
             other => io::Error::new(InvalidInput, other.description()),
         }
     }
@@ -0,0 +1,201 @@
+//! Sharing identical subtrees between documents.
+//!
+//! Whole-world analysis keeps many documents in memory at once, and these are
+//! highly repetitive: the same block states appear in the palettes of
+//! thousands of chunks, and the same item stacks in many inventories. A `Pool`
+//! converts `Value` trees to `Node` trees in which every distinct subtree is
+//! stored once and shared through an `Arc`, no matter how many documents it
+//! appears in.
+
+use std::collections::{BTreeMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::sync::Arc;
+
+use error::Result;
+use value::Value;
+
+/// An immutable tag whose children may be shared with other trees.
+///
+/// Floats are compared and hashed by their bits, so that every node is equal
+/// to itself, including NaNs.
+#[derive(Clone, Debug)]
+pub enum Node {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Arc<Node>>),
+    Compound(BTreeMap<String, Arc<Node>>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        match (self, other) {
+            (&Node::Byte(a), &Node::Byte(b)) => a == b,
+            (&Node::Short(a), &Node::Short(b)) => a == b,
+            (&Node::Int(a), &Node::Int(b)) => a == b,
+            (&Node::Long(a), &Node::Long(b)) => a == b,
+            (&Node::Float(a), &Node::Float(b)) => a.to_bits() == b.to_bits(),
+            (&Node::Double(a), &Node::Double(b)) => a.to_bits() == b.to_bits(),
+            (Node::ByteArray(a), Node::ByteArray(b)) => a == b,
+            (Node::String(a), Node::String(b)) => a == b,
+            // Children are interned, so identical children are the same node.
+            (Node::List(a), Node::List(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| Arc::ptr_eq(a, b) || a == b)
+            },
+            (Node::Compound(a), Node::Compound(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|((ka, a), (kb, b))| {
+                    ka == kb && (Arc::ptr_eq(a, b) || a == b)
+                })
+            },
+            (Node::IntArray(a), Node::IntArray(b)) => a == b,
+            (Node::LongArray(a), Node::LongArray(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Node {}
+
+impl Hash for Node {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+        match *self {
+            Node::Byte(v) => v.hash(state),
+            Node::Short(v) => v.hash(state),
+            Node::Int(v) => v.hash(state),
+            Node::Long(v) => v.hash(state),
+            Node::Float(v) => v.to_bits().hash(state),
+            Node::Double(v) => v.to_bits().hash(state),
+            Node::ByteArray(ref v) => v.hash(state),
+            Node::String(ref v) => v.hash(state),
+            Node::List(ref vals) => vals.hash(state),
+            Node::Compound(ref map) => map.hash(state),
+            Node::IntArray(ref v) => v.hash(state),
+            Node::LongArray(ref v) => v.hash(state),
+        }
+    }
+}
+
+impl Node {
+    /// The type ID of this node, as for `Value::id`.
+    pub fn id(&self) -> u8 {
+        match *self {
+            Node::Byte(_) => 0x01,
+            Node::Short(_) => 0x02,
+            Node::Int(_) => 0x03,
+            Node::Long(_) => 0x04,
+            Node::Float(_) => 0x05,
+            Node::Double(_) => 0x06,
+            Node::ByteArray(_) => 0x07,
+            Node::String(_) => 0x08,
+            Node::List(_) => 0x09,
+            Node::Compound(_) => 0x0a,
+            Node::IntArray(_) => 0x0b,
+            Node::LongArray(_) => 0x0c,
+        }
+    }
+
+    /// Copies this node into an unshared `Value` tree.
+    pub fn to_value(&self) -> Value {
+        match *self {
+            Node::Byte(v) => Value::Byte(v),
+            Node::Short(v) => Value::Short(v),
+            Node::Int(v) => Value::Int(v),
+            Node::Long(v) => Value::Long(v),
+            Node::Float(v) => Value::Float(v),
+            Node::Double(v) => Value::Double(v),
+            Node::ByteArray(ref v) => Value::ByteArray(v.clone()),
+            Node::String(ref v) => Value::String(v.clone()),
+            Node::List(ref vals) => Value::List(vals.iter().map(|v| v.to_value()).collect()),
+            Node::Compound(ref map) => {
+                Value::Compound(map.iter().map(|(k, v)| (k.clone(), v.to_value())).collect())
+            },
+            Node::IntArray(ref v) => Value::IntArray(v.clone()),
+            Node::LongArray(ref v) => Value::LongArray(v.clone()),
+        }
+    }
+}
+
+/// A set of interned nodes, shared by every tree converted through it.
+#[derive(Debug, Default)]
+pub struct Pool {
+    nodes: HashSet<Arc<Node>>,
+    hits: usize,
+}
+
+impl Pool {
+    /// Creates an empty pool.
+    pub fn new() -> Pool {
+        Pool { nodes: HashSet::new(), hits: 0 }
+    }
+
+    /// The number of distinct nodes in the pool.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the pool has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The number of times an existing node was reused.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Converts a `Value` tree to a tree of shared nodes.
+    pub fn intern(&mut self, value: &Value) -> Arc<Node> {
+        let node = match *value {
+            Value::Byte(v) => Node::Byte(v),
+            Value::Short(v) => Node::Short(v),
+            Value::Int(v) => Node::Int(v),
+            Value::Long(v) => Node::Long(v),
+            Value::Float(v) => Node::Float(v),
+            Value::Double(v) => Node::Double(v),
+            Value::ByteArray(ref v) => Node::ByteArray(v.clone()),
+            Value::String(ref v) => Node::String(v.clone()),
+            Value::List(ref vals) => Node::List(vals.iter().map(|v| self.intern(v)).collect()),
+            Value::Compound(ref map) => {
+                Node::Compound(map.iter().map(|(k, v)| (k.clone(), self.intern(v))).collect())
+            },
+            Value::IntArray(ref v) => Node::IntArray(v.clone()),
+            Value::LongArray(ref v) => Node::LongArray(v.clone()),
+        };
+        if let Some(existing) = self.nodes.get(&node) {
+            self.hits += 1;
+            return existing.clone();
+        }
+        let node = Arc::new(node);
+        self.nodes.insert(node.clone());
+        node
+    }
+
+    /// Decodes a document and interns its root tag, returning the name of
+    /// the root along with it.
+    pub fn read<R: io::Read>(&mut self, src: &mut R) -> Result<(String, Arc<Node>)> {
+        let (id, title) = Value::read_header(src)?;
+        let value = Value::from_reader(id, src)?;
+        Ok((title, self.intern(&value)))
+    }
+
+    /// Drops every node that is no longer used outside of the pool.
+    pub fn shrink(&mut self) {
+        // Removing a parent can free its children, so repeat until stable.
+        loop {
+            let before = self.nodes.len();
+            self.nodes.retain(|node| Arc::strong_count(node) > 1);
+            if self.nodes.len() == before {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Reading single tags out of a document without parsing the rest of it.
+//!
+//! A `Document` reads only the header of the root when it is opened. Asking
+//! for a compound entry scans that compound once, recording where each of its
+//! entries starts and seeking over their payloads, and later lookups in it
+//! use the recorded offsets. Nothing is decoded until `LazyValue::read`, so
+//! pulling `Level.Sections` out of a chunk parses only that list:
+//!
+//! ```no_run
+//! # use nbt::lazy::Document;
+//! # fn main() -> nbt::Result<()> {
+//! let doc = Document::open(std::fs::File::open("chunk.nbt")?)?;
+//! let sections = doc.get("Level")?.get("Sections")?.read()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The source must be uncompressed, since it has to be seekable. Scanning and
+//! reading are bounded by `DecoderOptions`, counting depth from the root, so
+//! that reading a deeply nested tag is limited just as reading the whole
+//! document with `Value::from_reader_with` would be.
+
+use std::cell::RefCell;
+use std::collections::{hash_map, HashMap};
+use std::io::{Read, Seek, SeekFrom};
+
+use byteorder::{BigEndian, ReadBytesExt};
+
+use error::{Error, Result};
+use raw;
+use value::{DecoderOptions, Limited, Value};
+
+/// An uncompressed document over a seekable source, whose tags are read on
+/// demand.
+pub struct Document<R> {
+    src: RefCell<R>,
+    name: String,
+    id: u8,
+    offset: u64,
+    /// The limits on scanning and reading tags.
+    options: DecoderOptions,
+    /// The entries of each compound scanned so far, by the offset of its
+    /// payload.
+    compounds: RefCell<HashMap<u64, Vec<Entry>>>,
+    /// The offsets of the elements of each list of variable-sized tags
+    /// scanned so far, by the offset of its payload.
+    lists: RefCell<HashMap<u64, Vec<u64>>>,
+}
+
+impl<R: Read + Seek> Document<R> {
+    /// Reads the header of the root tag, leaving its payload unread, within
+    /// the default `DecoderOptions`.
+    pub fn open(src: R) -> Result<Document<R>> {
+        Document::open_with(src, DecoderOptions::default())
+    }
+
+    /// Reads the header of the root tag like `open`, with the given limits
+    /// on what may be read from the document later.
+    pub fn open_with(mut src: R, options: DecoderOptions) -> Result<Document<R>> {
+        let (id, name) = Value::read_header(&mut src)?;
+        let offset = src.stream_position()?;
+        Ok(Document {
+            src: RefCell::new(src),
+            name,
+            id,
+            offset,
+            options,
+            compounds: RefCell::new(HashMap::new()),
+            lists: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// The name of the root tag, which is usually empty.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The root tag.
+    pub fn root(&self) -> LazyValue<'_, R> {
+        LazyValue { doc: self, id: self.id, offset: self.offset, depth: 0 }
+    }
+
+    /// The entry of the root compound with the given name, as for
+    /// `LazyValue::get`.
+    pub fn get(&self, name: &str) -> Result<LazyValue<'_, R>> {
+        self.root().get(name)
+    }
+
+    /// Consume this document and return the underlying source.
+    pub fn into_inner(self) -> R {
+        self.src.into_inner()
+    }
+
+    /// Scans the compound at `offset`, nested `depth` deep, if it has not
+    /// been already.
+    fn scan_compound(&self, offset: u64, depth: usize) -> Result<()> {
+        if self.compounds.borrow().contains_key(&offset) {
+            return Ok(());
+        }
+        let mut src = self.src.borrow_mut();
+        src.seek(SeekFrom::Start(offset))?;
+        let mut entries = Vec::new();
+        loop {
+            let (id, name) = raw::emit_next_header(&mut *src)?;
+            if id == 0x00 {
+                break;
+            }
+            entries.push(Entry { name, id, offset: src.stream_position()? });
+            skip(id, &mut *src, depth + 1, self.options.max_depth)?;
+        }
+        self.compounds.borrow_mut().insert(offset, entries);
+        Ok(())
+    }
+
+    /// Finds the `index`th element of the list at `offset`, nested `depth`
+    /// deep, as its type ID and the offset of its payload.
+    fn list_element(&self, offset: u64, depth: usize, index: usize) -> Result<Option<(u8, u64)>> {
+        let mut src = self.src.borrow_mut();
+        src.seek(SeekFrom::Start(offset))?;
+        let id = src.read_u8()?;
+        let len = src.read_i32::<BigEndian>()?.max(0) as usize;
+        if index >= len {
+            return Ok(None);
+        }
+        if let Some(size) = size(id) {
+            return Ok(Some((id, offset + 5 + index as u64 * size)));
+        }
+
+        let mut lists = self.lists.borrow_mut();
+        let elements = match lists.entry(offset) {
+            hash_map::Entry::Occupied(e) => e.into_mut(),
+            hash_map::Entry::Vacant(e) => {
+                let mut elements = Vec::with_capacity(raw::initial_capacity(len));
+                for _ in 0..len {
+                    elements.push(src.stream_position()?);
+                    skip(id, &mut *src, depth + 1, self.options.max_depth)?;
+                }
+                e.insert(elements)
+            },
+        };
+        Ok(Some((id, elements[index])))
+    }
+}
+
+/// A compound entry found by scanning its compound.
+struct Entry {
+    name: String,
+    id: u8,
+    /// Where the payload of the entry starts.
+    offset: u64,
+}
+
+/// A tag in a `Document`, which has not been read yet.
+pub struct LazyValue<'a, R: 'a> {
+    doc: &'a Document<R>,
+    id: u8,
+    offset: u64,
+    /// How many lists and compounds the tag is nested in.
+    depth: usize,
+}
+
+impl<'a, R> Clone for LazyValue<'a, R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, R> Copy for LazyValue<'a, R> {}
+
+impl<'a, R: Read + Seek> LazyValue<'a, R> {
+    /// The type ID of the tag.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Where the payload of the tag starts in the source.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The entry of this compound with the given name. Fails with
+    /// `Error::MissingField` if there is none, and `Error::TagMismatch` if
+    /// this is not a compound.
+    pub fn get(&self, name: &str) -> Result<LazyValue<'a, R>> {
+        if self.id != 0x0a {
+            return Err(Error::TagMismatch(self.id, 0x0a));
+        }
+        self.doc.scan_compound(self.offset, self.depth)?;
+        let compounds = self.doc.compounds.borrow();
+        match compounds[&self.offset].iter().find(|e| e.name == name) {
+            Some(e) => Ok(LazyValue { doc: self.doc, id: e.id, offset: e.offset, depth: self.depth + 1 }),
+            None => Err(Error::MissingField(name.to_string())),
+        }
+    }
+
+    /// The names of the entries of this compound, in the order they are
+    /// stored.
+    pub fn keys(&self) -> Result<Vec<String>> {
+        if self.id != 0x0a {
+            return Err(Error::TagMismatch(self.id, 0x0a));
+        }
+        self.doc.scan_compound(self.offset, self.depth)?;
+        let compounds = self.doc.compounds.borrow();
+        Ok(compounds[&self.offset].iter().map(|e| e.name.clone()).collect())
+    }
+
+    /// The element of this list at `index`. Fails with `Error::MissingField`
+    /// naming the index if it is out of range, and `Error::TagMismatch` if
+    /// this is not a list.
+    pub fn index(&self, index: usize) -> Result<LazyValue<'a, R>> {
+        if self.id != 0x09 {
+            return Err(Error::TagMismatch(self.id, 0x09));
+        }
+        match self.doc.list_element(self.offset, self.depth, index)? {
+            Some((id, offset)) => Ok(LazyValue { doc: self.doc, id, offset, depth: self.depth + 1 }),
+            None => Err(Error::MissingField(format!("[{}]", index))),
+        }
+    }
+
+    /// Reads and decodes the whole tag, within the limits the document was
+    /// opened with.
+    pub fn read(&self) -> Result<Value> {
+        let mut src = self.doc.src.borrow_mut();
+        src.seek(SeekFrom::Start(self.offset))?;
+        Limited::new(&mut *src, &self.doc.options).value(self.id, self.depth)
+    }
+}
+
+/// The size of the payload of a tag with the given type ID, if it is always
+/// the same.
+fn size(id: u8) -> Option<u64> {
+    match id {
+        0x01 => Some(1),
+        0x02 => Some(2),
+        0x03 | 0x05 => Some(4),
+        0x04 | 0x06 => Some(8),
+        _ => None,
+    }
+}
+
+/// Moves past the payload of a tag with the given type ID, nested `depth`
+/// deep, seeking over whatever can be skipped without looking at it, unlike
+/// `raw::skip_payload`. Lists and compounds nested deeper than `max_depth`
+/// fail with `Error::DepthLimitExceeded`.
+fn skip<R: Read + Seek + ?Sized>(id: u8, src: &mut R, depth: usize, max_depth: usize) -> Result<()> {
+    if (id == 0x09 || id == 0x0a) && depth > max_depth {
+        return Err(Error::DepthLimitExceeded(max_depth));
+    }
+    let len = match id {
+        0x07 => src.read_i32::<BigEndian>()?.max(0) as u64,
+        0x0b => src.read_i32::<BigEndian>()?.max(0) as u64 * 4,
+        0x0c => src.read_i32::<BigEndian>()?.max(0) as u64 * 8,
+        0x08 => src.read_u16::<BigEndian>()? as u64,
+        0x09 => {
+            let elem = src.read_u8()?;
+            let len = src.read_i32::<BigEndian>()?.max(0) as u64;
+            match size(elem) {
+                Some(size) => len * size,
+                None => {
+                    for _ in 0..len {
+                        skip(elem, src, depth + 1, max_depth)?;
+                    }
+                    0
+                },
+            }
+        },
+        0x0a => {
+            loop {
+                let id = src.read_u8()?;
+                if id == 0x00 {
+                    break;
+                }
+                let name_len = src.read_u16::<BigEndian>()?;
+                src.seek(SeekFrom::Current(i64::from(name_len)))?;
+                skip(id, src, depth + 1, max_depth)?;
+            }
+            0
+        },
+        id => size(id).ok_or(Error::InvalidTypeId(id))?,
+    };
+    src.seek(SeekFrom::Current(len as i64))?;
+    Ok(())
+}
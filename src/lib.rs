@@ -1,17 +1,60 @@
 //! MC Named Binary Tag type.
+//!
+//! This crate reads and writes tags as `Value`s and does not need serde. The
+//! `serde` feature implements serde's traits for `Value`; the `Encoder` and
+//! `Decoder` for other types are in the `hematite-nbt-serde` crate.
 
 extern crate byteorder;
 extern crate flate2;
+#[cfg(feature = "serde")] extern crate serde;
+#[cfg(all(feature = "mmap", unix))] extern crate libc;
 
 /* Re-export the core API from submodules. */
-pub use blob::Blob;
+pub use blob::{from_reader_auto, Blob};
+pub use diff::diff;
 pub use error::{Error, Result};
-pub use value::Value;
+pub use stream::transcode;
+pub use value::{DecoderOptions, ReadAll, Value};
+#[cfg(all(feature = "mmap", unix))] pub use mmap::open_mmap;
 
+#[macro_use] mod macros;
+
+pub mod audit;
+pub mod batch;
+pub mod capture;
+pub mod chunk;
+pub mod compression;
+pub mod conformance;
+pub mod diff;
+pub mod intern;
+pub mod lazy;
+pub mod map;
+#[doc(hidden)] pub mod markers;
+pub mod mc;
+#[cfg(all(feature = "mmap", unix))] pub mod mmap;
+pub mod normalize;
+pub mod parallel;
+pub mod path;
+pub mod project;
+pub mod push;
 pub mod raw;
+pub mod region;
+pub mod remap;
+pub mod rewrite;
+pub mod shared;
+pub mod snbt;
+pub mod sniff;
+pub mod stream;
+pub mod testing;
+pub mod uuid;
+#[cfg(feature = "serde")] pub mod uuid_int_array;
+pub mod watch;
+pub mod world;
+pub mod yaml;
 
 mod blob;
 mod error;
 mod value;
+#[cfg(feature = "serde")] mod value_serde;
 
 #[cfg(test)] mod tests;
@@ -0,0 +1,87 @@
+//! The `nbt!` macro.
+
+/// Builds a `Value` tree inline, with a syntax close to SNBT.
+///
+/// Compounds are written as `{"key": value, ...}`, lists as `[value, ...]`
+/// and typed arrays as `[B; ...]`, `[I; ...]` and `[L; ...]`. Anything else is
+/// an expression converted with `Value::from`, so numbers take the tag their
+/// literal suffix gives, with unsuffixed ones becoming ints and doubles. Keys
+/// may also be parenthesized expressions.
+///
+/// ```rust
+/// #[macro_use] extern crate nbt;
+///
+/// # fn main() {
+/// let count = 1i8;
+/// let chest = nbt!({
+///     "id": "minecraft:chest",
+///     "Items": [
+///         {"Slot": 0i8, "id": "minecraft:stone", "Count": count},
+///     ],
+///     "Lock": [B; 1, -1],
+/// });
+/// # let _ = chest;
+/// # }
+/// ```
+#[macro_export]
+macro_rules! nbt {
+    // Lists are split at each comma, since a value may span several tokens.
+    (@list [$($out:expr,)*]) => {
+        vec![$($out,)*]
+    };
+    (@list [$($out:expr,)*] $($rest:tt)+) => {
+        nbt!(@elem [$($out,)*] () $($rest)+)
+    };
+    (@elem [$($out:expr,)*] ($($cur:tt)+) , $($rest:tt)*) => {
+        nbt!(@list [$($out,)* nbt!($($cur)+),] $($rest)*)
+    };
+    (@elem [$($out:expr,)*] ($($cur:tt)*) $next:tt $($rest:tt)*) => {
+        nbt!(@elem [$($out,)*] ($($cur)* $next) $($rest)*)
+    };
+    (@elem [$($out:expr,)*] ($($cur:tt)+)) => {
+        nbt!(@list [$($out,)* nbt!($($cur)+),])
+    };
+
+    // Compound entries are inserted one at a time.
+    (@compound $map:ident) => {};
+    (@compound $map:ident $key:tt : $($rest:tt)+) => {
+        nbt!(@entry $map $key () $($rest)+)
+    };
+    (@entry $map:ident $key:tt ($($cur:tt)+) , $($rest:tt)*) => {
+        $map.insert(::std::string::String::from($key), nbt!($($cur)+));
+        nbt!(@compound $map $($rest)*);
+    };
+    (@entry $map:ident $key:tt ($($cur:tt)*) $next:tt $($rest:tt)*) => {
+        nbt!(@entry $map $key ($($cur)* $next) $($rest)*)
+    };
+    (@entry $map:ident $key:tt ($($cur:tt)+)) => {
+        $map.insert(::std::string::String::from($key), nbt!($($cur)+));
+    };
+
+    ([B; $($e:expr),* $(,)*]) => {
+        $crate::Value::ByteArray(vec![$($e),*])
+    };
+    ([I; $($e:expr),* $(,)*]) => {
+        $crate::Value::IntArray(vec![$($e),*])
+    };
+    ([L; $($e:expr),* $(,)*]) => {
+        $crate::Value::LongArray(vec![$($e),*])
+    };
+    ([]) => {
+        $crate::Value::List(vec![])
+    };
+    ([$($tt:tt)+]) => {
+        $crate::Value::List(nbt!(@list [] $($tt)+))
+    };
+    ({}) => {
+        $crate::Value::Compound(::std::collections::HashMap::new())
+    };
+    ({$($tt:tt)+}) => {{
+        let mut map = ::std::collections::HashMap::new();
+        nbt!(@compound map $($tt)+);
+        $crate::Value::Compound(map)
+    }};
+    ($e:expr) => {
+        $crate::Value::from($e)
+    };
+}
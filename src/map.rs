@@ -0,0 +1,132 @@
+//! Conversion between map item color data and RGBA pixels.
+//!
+//! The `data.colors` field of a `map_<id>.dat` file is a 128x128
+//! `TAG_Byte_Array`, stored row by row. Each byte is `base * 4 + shade`, where
+//! `base` indexes the table of map base colors and `shade` selects one of four
+//! brightness multipliers. Base color 0 is transparent.
+
+/// The RGB values of each map base color, as of Minecraft 1.17.
+pub const BASE_COLORS: [[u8; 3]; 62] = [
+    [0x00, 0x00, 0x00], // none (transparent)
+    [0x7f, 0xb2, 0x38], // grass
+    [0xf7, 0xe9, 0xa3], // sand
+    [0xc7, 0xc7, 0xc7], // wool
+    [0xff, 0x00, 0x00], // fire
+    [0xa0, 0xa0, 0xff], // ice
+    [0xa7, 0xa7, 0xa7], // metal
+    [0x00, 0x7c, 0x00], // plant
+    [0xff, 0xff, 0xff], // snow
+    [0xa4, 0xa8, 0xb8], // clay
+    [0x97, 0x6d, 0x4d], // dirt
+    [0x70, 0x70, 0x70], // stone
+    [0x40, 0x40, 0xff], // water
+    [0x8f, 0x77, 0x48], // wood
+    [0xff, 0xfc, 0xf5], // quartz
+    [0xd8, 0x7f, 0x33], // orange
+    [0xb2, 0x4c, 0xd8], // magenta
+    [0x66, 0x99, 0xd8], // light blue
+    [0xe5, 0xe5, 0x33], // yellow
+    [0x7f, 0xcc, 0x19], // lime
+    [0xf2, 0x7f, 0xa5], // pink
+    [0x4c, 0x4c, 0x4c], // gray
+    [0x99, 0x99, 0x99], // light gray
+    [0x4c, 0x7f, 0x99], // cyan
+    [0x7f, 0x3f, 0xb2], // purple
+    [0x33, 0x4c, 0xb2], // blue
+    [0x66, 0x4c, 0x33], // brown
+    [0x66, 0x7f, 0x33], // green
+    [0x99, 0x33, 0x33], // red
+    [0x19, 0x19, 0x19], // black
+    [0xfa, 0xee, 0x4d], // gold
+    [0x5c, 0xdb, 0xd5], // diamond
+    [0x4a, 0x80, 0xff], // lapis
+    [0x00, 0xd9, 0x3a], // emerald
+    [0x81, 0x56, 0x31], // podzol
+    [0x70, 0x02, 0x00], // nether
+    [0xd1, 0xb1, 0xa1], // white terracotta
+    [0x9f, 0x52, 0x24], // orange terracotta
+    [0x95, 0x57, 0x6c], // magenta terracotta
+    [0x70, 0x6c, 0x8a], // light blue terracotta
+    [0xba, 0x85, 0x24], // yellow terracotta
+    [0x67, 0x75, 0x35], // lime terracotta
+    [0xa0, 0x4d, 0x4e], // pink terracotta
+    [0x39, 0x29, 0x23], // gray terracotta
+    [0x87, 0x6b, 0x62], // light gray terracotta
+    [0x57, 0x5c, 0x5c], // cyan terracotta
+    [0x7a, 0x49, 0x58], // purple terracotta
+    [0x4c, 0x3e, 0x5c], // blue terracotta
+    [0x4c, 0x32, 0x23], // brown terracotta
+    [0x4c, 0x52, 0x2a], // green terracotta
+    [0x8e, 0x3c, 0x2e], // red terracotta
+    [0x25, 0x16, 0x10], // black terracotta
+    [0xbd, 0x30, 0x31], // crimson nylium
+    [0x94, 0x3f, 0x61], // crimson stem
+    [0x5c, 0x19, 0x1d], // crimson hyphae
+    [0x16, 0x7e, 0x86], // warped nylium
+    [0x3a, 0x8e, 0x8c], // warped stem
+    [0x56, 0x2c, 0x3e], // warped hyphae
+    [0x14, 0xb4, 0x85], // warped wart block
+    [0x64, 0x64, 0x64], // deepslate
+    [0xd8, 0xaf, 0x93], // raw iron
+    [0x7f, 0xa7, 0x96], // glow lichen
+];
+
+/// The brightness multipliers (out of 255) for each of the four shades.
+pub const SHADES: [u32; 4] = [180, 220, 255, 135];
+
+/// The width and height of a map, in pixels.
+pub const MAP_SIZE: usize = 128;
+
+/// Looks up the RGBA color of a single map color byte. Transparent colors and
+/// colors outside the known palette are returned as `[0, 0, 0, 0]`.
+pub fn color(id: i8) -> [u8; 4] {
+    let id = id as u8 as usize;
+    let (base, shade) = (id / 4, id % 4);
+    if base == 0 || base >= BASE_COLORS.len() {
+        return [0, 0, 0, 0];
+    }
+    let rgb = BASE_COLORS[base];
+    let scale = |c: u8| (c as u32 * SHADES[shade] / 255) as u8;
+    [scale(rgb[0]), scale(rgb[1]), scale(rgb[2]), 0xff]
+}
+
+/// Converts map color bytes into a buffer of RGBA pixels, four bytes per
+/// pixel, in the same row-major order.
+pub fn to_rgba(colors: &[i8]) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(colors.len() * 4);
+    for &id in colors {
+        pixels.extend_from_slice(&color(id));
+    }
+    pixels
+}
+
+/// Finds the map color byte closest to an RGBA pixel. Pixels that are less
+/// than half opaque map to transparency.
+pub fn nearest(pixel: [u8; 4]) -> i8 {
+    if pixel[3] < 0x80 {
+        return 0;
+    }
+    let mut best = (u32::MAX, 0);
+    for base in 1..BASE_COLORS.len() {
+        for shade in 0..SHADES.len() {
+            let id = (base * 4 + shade) as u8 as i8;
+            let c = color(id);
+            let dist = (0..3).map(|i| {
+                let d = c[i] as i32 - pixel[i] as i32;
+                (d * d) as u32
+            }).sum();
+            if dist < best.0 {
+                best = (dist, id);
+            }
+        }
+    }
+    best.1
+}
+
+/// Quantizes a buffer of RGBA pixels, four bytes per pixel, to the nearest
+/// map colors. Any trailing partial pixel is ignored.
+pub fn from_rgba(pixels: &[u8]) -> Vec<i8> {
+    pixels.chunks_exact(4)
+          .map(|p| nearest([p[0], p[1], p[2], p[3]]))
+          .collect()
+}
@@ -0,0 +1,16 @@
+//! The names of the newtype structs through which `Value` and the wrappers
+//! in `nbt_serde::arrays` tell the `nbt_serde` encoder and decoder which tag
+//! to use. Not part of the public API.
+
+/// Marks a sequence to be written as a `TAG_Byte_Array`.
+pub const BYTE_ARRAY: &str = "__nbt_byte_array";
+/// Marks a sequence to be written as a `TAG_Int_Array`.
+pub const INT_ARRAY: &str = "__nbt_int_array";
+/// Marks a sequence to be written as a `TAG_Long_Array`.
+pub const LONG_ARRAY: &str = "__nbt_long_array";
+/// Marks a sequence to be written as a `TAG_List`.
+pub const LIST: &str = "__nbt_list";
+
+/// The name of the newtype struct asked for when deserializing a `Value`,
+/// so that the decoder describes the type of each tag.
+pub const VALUE: &str = "__nbt_value";
@@ -0,0 +1,194 @@
+//! Entity data, currently the trade offers of villagers and wandering
+//! traders.
+//!
+//! A merchant's trades are stored under its `Offers` compound, which holds a
+//! `Recipes` list. Each recipe takes a `buy` stack and an optional `buyB`
+//! stack in exchange for a `sell` stack.
+
+use std::collections::HashMap;
+
+use error::{Error, Result};
+use value::Value;
+
+use super::{as_compound, as_float, as_integer, required};
+use super::item::ItemStack;
+
+/// A single trade offered by a merchant.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Offer {
+    /// The first item the player pays with.
+    pub buy: ItemStack,
+    /// The optional second item the player pays with.
+    pub buy_b: Option<ItemStack>,
+    /// The item the player receives.
+    pub sell: ItemStack,
+    /// How many times the trade has been used since the last restock.
+    pub uses: i32,
+    /// How many times the trade can be used before it locks.
+    pub max_uses: i32,
+    /// Whether the trade gives the player experience orbs.
+    pub reward_exp: bool,
+    /// The experience the merchant gains from the trade.
+    pub xp: i32,
+    /// How strongly demand and reputation change the price of `buy`.
+    pub price_multiplier: f32,
+    /// A price adjustment applied on top of `buy`, from reputation and
+    /// effects such as Hero of the Village.
+    pub special_price: i32,
+    /// The demand for the trade, which raises the price when positive.
+    pub demand: i32,
+}
+
+impl Offer {
+    /// Creates a trade of `buy` for `sell` with the defaults the game uses
+    /// for missing fields.
+    pub fn new(buy: ItemStack, sell: ItemStack) -> Offer {
+        Offer {
+            buy,
+            buy_b: None,
+            sell,
+            uses: 0,
+            max_uses: 4,
+            reward_exp: true,
+            xp: 1,
+            price_multiplier: 0.0,
+            special_price: 0,
+            demand: 0,
+        }
+    }
+
+    /// Sets the second item the player pays with.
+    pub fn with_buy_b(mut self, buy_b: ItemStack) -> Offer {
+        self.buy_b = Some(buy_b);
+        self
+    }
+
+    /// Sets how many times the trade can be used before it locks.
+    pub fn with_max_uses(mut self, max_uses: i32) -> Offer {
+        self.max_uses = max_uses;
+        self
+    }
+
+    /// Whether the trade is locked until the merchant restocks.
+    pub fn is_out_of_stock(&self) -> bool {
+        self.uses >= self.max_uses
+    }
+
+    /// Reads a trade from an element of the `Recipes` list.
+    pub fn from_value(value: &Value) -> Result<Offer> {
+        let map = as_compound(value)?;
+        let int = |key: &str, default: i32| -> Result<i32> {
+            map.get(key).map_or(Ok(default), |v| as_integer(v).map(|v| v as i32))
+        };
+        // Older versions wrote an air stack rather than omitting `buyB`.
+        let buy_b = match map.get("buyB") {
+            Some(v) => Some(ItemStack::from_value(v)?).filter(|s| !is_empty_stack(s)),
+            None => None,
+        };
+        Ok(Offer {
+            buy: ItemStack::from_value(required(map, "buy")?)?,
+            buy_b,
+            sell: ItemStack::from_value(required(map, "sell")?)?,
+            uses: int("uses", 0)?,
+            max_uses: int("maxUses", 4)?,
+            reward_exp: int("rewardExp", 1)? != 0,
+            xp: int("xp", 1)?,
+            price_multiplier: map.get("priceMultiplier").map_or(Ok(0.0), as_float)?,
+            special_price: int("specialPrice", 0)?,
+            demand: int("demand", 0)?,
+        })
+    }
+
+    /// Converts this trade to an element of the `Recipes` list.
+    pub fn to_value(&self) -> Value {
+        let mut map = HashMap::new();
+        map.insert("buy".to_string(), self.buy.to_value());
+        if let Some(ref buy_b) = self.buy_b {
+            map.insert("buyB".to_string(), buy_b.to_value());
+        }
+        map.insert("sell".to_string(), self.sell.to_value());
+        map.insert("uses".to_string(), Value::Int(self.uses));
+        map.insert("maxUses".to_string(), Value::Int(self.max_uses));
+        map.insert("rewardExp".to_string(), Value::Byte(self.reward_exp as i8));
+        map.insert("xp".to_string(), Value::Int(self.xp));
+        map.insert("priceMultiplier".to_string(), Value::Float(self.price_multiplier));
+        map.insert("specialPrice".to_string(), Value::Int(self.special_price));
+        map.insert("demand".to_string(), Value::Int(self.demand));
+        Value::Compound(map)
+    }
+}
+
+fn is_empty_stack(stack: &ItemStack) -> bool {
+    stack.id == "minecraft:air" || stack.count <= 0
+}
+
+/// The trades offered by a merchant.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Offers {
+    /// The trades, in the order they are shown to the player.
+    pub recipes: Vec<Offer>,
+}
+
+impl Offers {
+    /// Creates an empty set of trades.
+    pub fn new() -> Offers {
+        Offers { recipes: Vec::new() }
+    }
+
+    /// Reads trades from an `Offers` compound.
+    pub fn from_value(value: &Value) -> Result<Offers> {
+        let map = as_compound(value)?;
+        let recipes = match map.get("Recipes") {
+            Some(Value::List(list)) => {
+                let mut recipes = Vec::with_capacity(list.len());
+                for recipe in list {
+                    recipes.push(Offer::from_value(recipe)?);
+                }
+                recipes
+            },
+            Some(other) => return Err(Error::TagMismatch(other.id(), 0x09)),
+            None => Vec::new(),
+        };
+        Ok(Offers { recipes })
+    }
+
+    /// Converts these trades to an `Offers` compound.
+    pub fn to_value(&self) -> Value {
+        let mut map = HashMap::new();
+        let recipes = self.recipes.iter().map(Offer::to_value).collect();
+        map.insert("Recipes".to_string(), Value::List(recipes));
+        Value::Compound(map)
+    }
+
+    /// Reads the trades of a merchant entity. An entity without an `Offers`
+    /// compound has no trades.
+    pub fn from_entity(entity: &Value) -> Result<Offers> {
+        match as_compound(entity)?.get("Offers") {
+            Some(offers) => Offers::from_value(offers),
+            None => Ok(Offers::new()),
+        }
+    }
+
+    /// Replaces the trades of a merchant entity with these trades.
+    pub fn write_to_entity(&self, entity: &mut Value) -> Result<()> {
+        match *entity {
+            Value::Compound(ref mut map) => {
+                map.insert("Offers".to_string(), self.to_value());
+                Ok(())
+            },
+            ref other => Err(Error::TagMismatch(other.id(), 0x0a)),
+        }
+    }
+
+    /// Adds a trade to the end of the list.
+    pub fn push(&mut self, offer: Offer) {
+        self.recipes.push(offer);
+    }
+
+    /// Resets the uses of every trade, unlocking any that were out of stock.
+    pub fn restock(&mut self) {
+        for offer in &mut self.recipes {
+            offer.uses = 0;
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! Item stacks, in both the legacy `tag` form and the 1.20.5+ data component
+//! form.
+//!
+//! Before 1.20.5, an item stack is stored as `{id, Count: byte, tag: {...}}`,
+//! where `tag` holds arbitrary extra data. Since 1.20.5 it is stored as
+//! `{id, count: int, components: {...}}`, where each component is keyed by
+//! its namespaced id. Either form may carry a `Slot` byte when stored in an
+//! inventory.
+
+use std::collections::{BTreeMap, HashMap};
+
+use error::Result;
+use value::Value;
+
+use super::{as_compound, as_integer, as_string, required};
+
+/// The known data components, plus any others passed through untouched.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Components {
+    /// `minecraft:damage`, the durability used up.
+    pub damage: Option<i32>,
+    /// `minecraft:repair_cost`, the extra anvil cost.
+    pub repair_cost: Option<i32>,
+    /// `minecraft:custom_name`, a JSON text component.
+    pub custom_name: Option<String>,
+    /// `minecraft:lore`, a list of JSON text components.
+    pub lore: Vec<String>,
+    /// `minecraft:enchantments`, a map of enchantment ids to levels.
+    pub enchantments: BTreeMap<String, i32>,
+    /// `minecraft:unbreakable`.
+    pub unbreakable: bool,
+    /// All other components, keyed by their namespaced id.
+    pub other: HashMap<String, Value>,
+}
+
+/// The extra data attached to an item stack.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ItemData {
+    /// The pre-1.20.5 `tag` compound, which is empty if the stack had none.
+    Tag(HashMap<String, Value>),
+    /// The 1.20.5+ data components.
+    Components(Components),
+}
+
+/// A stack of items.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemStack {
+    /// The namespaced item id, such as `minecraft:diamond_sword`.
+    pub id: String,
+    /// The number of items in the stack.
+    pub count: i32,
+    /// The inventory slot, if the stack is stored in one.
+    pub slot: Option<i8>,
+    /// The extra data attached to the stack, which also determines the format
+    /// it is written in.
+    pub data: ItemData,
+}
+
+const DAMAGE: &str = "minecraft:damage";
+const REPAIR_COST: &str = "minecraft:repair_cost";
+const CUSTOM_NAME: &str = "minecraft:custom_name";
+const LORE: &str = "minecraft:lore";
+const ENCHANTMENTS: &str = "minecraft:enchantments";
+const UNBREAKABLE: &str = "minecraft:unbreakable";
+const CUSTOM_DATA: &str = "minecraft:custom_data";
+
+impl ItemStack {
+    /// Creates a stack of `count` items in the 1.20.5+ format, with no
+    /// components.
+    pub fn new<S: Into<String>>(id: S, count: i32) -> ItemStack {
+        ItemStack {
+            id: id.into(),
+            count,
+            slot: None,
+            data: ItemData::Components(Components::default()),
+        }
+    }
+
+    /// Reads an item stack in either format from a `Value::Compound`.
+    pub fn from_value(value: &Value) -> Result<ItemStack> {
+        let map = as_compound(value)?;
+        let id = as_string(required(map, "id")?)?;
+        let slot = match map.get("Slot") {
+            Some(v) => Some(as_integer(v)? as i8),
+            None => None,
+        };
+        if let Some(count) = map.get("Count") {
+            let tag = match map.get("tag") {
+                Some(tag) => as_compound(tag)?.clone(),
+                None => HashMap::new(),
+            };
+            return Ok(ItemStack { id, count: as_integer(count)? as i32, slot, data: ItemData::Tag(tag) });
+        }
+        // Stacks of one may omit their count in the component format.
+        let count = match map.get("count") {
+            Some(count) => as_integer(count)? as i32,
+            None => 1,
+        };
+        let components = match map.get("components") {
+            Some(components) => Components::from_map(as_compound(components)?)?,
+            None => Components::default(),
+        };
+        Ok(ItemStack { id, count, slot, data: ItemData::Components(components) })
+    }
+
+    /// Converts this stack to a `Value::Compound` in the format of its data.
+    pub fn to_value(&self) -> Value {
+        let mut map = HashMap::new();
+        map.insert("id".to_string(), Value::String(self.id.clone()));
+        if let Some(slot) = self.slot {
+            map.insert("Slot".to_string(), Value::Byte(slot));
+        }
+        match self.data {
+            ItemData::Tag(ref tag) => {
+                map.insert("Count".to_string(), Value::Byte(self.count as i8));
+                if !tag.is_empty() {
+                    map.insert("tag".to_string(), Value::Compound(tag.clone()));
+                }
+            },
+            ItemData::Components(ref components) => {
+                map.insert("count".to_string(), Value::Int(self.count));
+                let components = components.to_map();
+                if !components.is_empty() {
+                    map.insert("components".to_string(), Value::Compound(components));
+                }
+            },
+        }
+        Value::Compound(map)
+    }
+
+    /// Converts this stack to the 1.20.5+ component format, the way the game
+    /// upgrades it: known `tag` fields become components, and everything else
+    /// in the tag is kept in `minecraft:custom_data`.
+    pub fn to_components(&self) -> ItemStack {
+        let tag = match self.data {
+            ItemData::Components(_) => return self.clone(),
+            ItemData::Tag(ref tag) => tag,
+        };
+        let mut rest = tag.clone();
+        let mut components = Components::default();
+        if let Some(v) = rest.remove("Damage").and_then(|v| as_integer(&v).ok()) {
+            if v != 0 {
+                components.damage = Some(v as i32);
+            }
+        }
+        if let Some(v) = rest.remove("RepairCost").and_then(|v| as_integer(&v).ok()) {
+            components.repair_cost = Some(v as i32);
+        }
+        if let Some(v) = rest.remove("Unbreakable").and_then(|v| as_integer(&v).ok()) {
+            components.unbreakable = v != 0;
+        }
+        if let Some(Value::List(enchantments)) = rest.remove("Enchantments") {
+            for ench in enchantments {
+                if let Value::Compound(ench) = ench {
+                    let id = ench.get("id").and_then(|v| as_string(v).ok());
+                    let lvl = ench.get("lvl").and_then(|v| as_integer(v).ok());
+                    if let (Some(id), Some(lvl)) = (id, lvl) {
+                        components.enchantments.insert(id, lvl as i32);
+                    }
+                }
+            }
+        }
+        if let Some(Value::Compound(mut display)) = rest.remove("display") {
+            if let Some(Value::String(name)) = display.remove("Name") {
+                components.custom_name = Some(name);
+            }
+            if let Some(Value::List(lore)) = display.remove("Lore") {
+                components.lore = lore.iter().filter_map(|v| as_string(v).ok()).collect();
+            }
+            if !display.is_empty() {
+                rest.insert("display".to_string(), Value::Compound(display));
+            }
+        }
+        if !rest.is_empty() {
+            components.other.insert(CUSTOM_DATA.to_string(), Value::Compound(rest));
+        }
+        ItemStack { data: ItemData::Components(components), ..self.clone() }
+    }
+
+    /// Converts this stack to the pre-1.20.5 `tag` format. Known components
+    /// become their `tag` fields and `minecraft:custom_data` is merged into
+    /// the tag. Other components have no legacy equivalent and are dropped.
+    pub fn to_legacy(&self) -> ItemStack {
+        let components = match self.data {
+            ItemData::Tag(_) => return self.clone(),
+            ItemData::Components(ref components) => components,
+        };
+        let mut tag = match components.other.get(CUSTOM_DATA) {
+            Some(Value::Compound(data)) => data.clone(),
+            _ => HashMap::new(),
+        };
+        if let Some(damage) = components.damage {
+            tag.insert("Damage".to_string(), Value::Int(damage));
+        }
+        if let Some(cost) = components.repair_cost {
+            tag.insert("RepairCost".to_string(), Value::Int(cost));
+        }
+        if components.unbreakable {
+            tag.insert("Unbreakable".to_string(), Value::Byte(1));
+        }
+        if !components.enchantments.is_empty() {
+            let list = components.enchantments.iter().map(|(id, &lvl)| {
+                let mut ench = HashMap::new();
+                ench.insert("id".to_string(), Value::String(id.clone()));
+                ench.insert("lvl".to_string(), Value::Short(lvl as i16));
+                Value::Compound(ench)
+            }).collect();
+            tag.insert("Enchantments".to_string(), Value::List(list));
+        }
+        if components.custom_name.is_some() || !components.lore.is_empty() {
+            let mut display = match tag.remove("display") {
+                Some(Value::Compound(display)) => display,
+                _ => HashMap::new(),
+            };
+            if let Some(ref name) = components.custom_name {
+                display.insert("Name".to_string(), Value::String(name.clone()));
+            }
+            if !components.lore.is_empty() {
+                let lore = components.lore.iter().map(|l| Value::String(l.clone())).collect();
+                display.insert("Lore".to_string(), Value::List(lore));
+            }
+            tag.insert("display".to_string(), Value::Compound(display));
+        }
+        ItemStack { data: ItemData::Tag(tag), ..self.clone() }
+    }
+}
+
+impl Components {
+    fn from_map(map: &HashMap<String, Value>) -> Result<Components> {
+        let mut components = Components::default();
+        for (key, value) in map {
+            match &key[..] {
+                DAMAGE => components.damage = Some(as_integer(value)? as i32),
+                REPAIR_COST => components.repair_cost = Some(as_integer(value)? as i32),
+                CUSTOM_NAME => components.custom_name = Some(as_string(value)?),
+                UNBREAKABLE => components.unbreakable = true,
+                LORE => match *value {
+                    Value::List(ref lore) => {
+                        for line in lore {
+                            components.lore.push(as_string(line)?);
+                        }
+                    },
+                    ref other => { components.other.insert(key.clone(), other.clone()); },
+                },
+                ENCHANTMENTS => {
+                    let ench = as_compound(value)?;
+                    // 1.20.5 wraps the levels in a `levels` compound, which was
+                    // dropped in 1.21.5.
+                    let levels = match ench.get("levels") {
+                        Some(levels) => as_compound(levels)?,
+                        None => ench,
+                    };
+                    for (id, lvl) in levels {
+                        components.enchantments.insert(id.clone(), as_integer(lvl)? as i32);
+                    }
+                },
+                _ => { components.other.insert(key.clone(), value.clone()); },
+            }
+        }
+        Ok(components)
+    }
+
+    pub(crate) fn to_map(&self) -> HashMap<String, Value> {
+        let mut map = self.other.clone();
+        if let Some(damage) = self.damage {
+            map.insert(DAMAGE.to_string(), Value::Int(damage));
+        }
+        if let Some(cost) = self.repair_cost {
+            map.insert(REPAIR_COST.to_string(), Value::Int(cost));
+        }
+        if let Some(ref name) = self.custom_name {
+            map.insert(CUSTOM_NAME.to_string(), Value::String(name.clone()));
+        }
+        if !self.lore.is_empty() {
+            let lore = self.lore.iter().map(|l| Value::String(l.clone())).collect();
+            map.insert(LORE.to_string(), Value::List(lore));
+        }
+        if !self.enchantments.is_empty() {
+            let levels = self.enchantments.iter()
+                .map(|(id, &lvl)| (id.clone(), Value::Int(lvl)))
+                .collect();
+            let mut ench = HashMap::new();
+            ench.insert("levels".to_string(), Value::Compound(levels));
+            map.insert(ENCHANTMENTS.to_string(), Value::Compound(ench));
+        }
+        if self.unbreakable {
+            map.insert(UNBREAKABLE.to_string(), Value::Compound(HashMap::new()));
+        }
+        map
+    }
+}
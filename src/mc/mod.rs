@@ -0,0 +1,51 @@
+//! Typed models of common Minecraft data structures.
+//!
+//! These types convert to and from `Value` trees, so they can be used with
+//! any of the ways this crate reads and writes NBT data.
+
+pub mod entity;
+pub mod item;
+pub mod version;
+
+use std::collections::HashMap;
+
+use error::{Error, Result};
+use value::Value;
+
+fn as_compound(value: &Value) -> Result<&HashMap<String, Value>> {
+    match *value {
+        Value::Compound(ref map) => Ok(map),
+        ref other => Err(Error::TagMismatch(other.id(), 0x0a)),
+    }
+}
+
+fn required<'a>(map: &'a HashMap<String, Value>, key: &str) -> Result<&'a Value> {
+    map.get(key).ok_or_else(|| Error::MissingField(key.to_string()))
+}
+
+fn as_string(value: &Value) -> Result<String> {
+    match *value {
+        Value::String(ref s) => Ok(s.clone()),
+        ref other => Err(Error::TagMismatch(other.id(), 0x08)),
+    }
+}
+
+/// Reads an integer of any width, since Minecraft has changed the width of
+/// many fields over time.
+fn as_integer(value: &Value) -> Result<i64> {
+    match *value {
+        Value::Byte(v)  => Ok(v as i64),
+        Value::Short(v) => Ok(v as i64),
+        Value::Int(v)   => Ok(v as i64),
+        Value::Long(v)  => Ok(v),
+        ref other => Err(Error::TagMismatch(other.id(), 0x03)),
+    }
+}
+
+fn as_float(value: &Value) -> Result<f32> {
+    match *value {
+        Value::Float(v)  => Ok(v),
+        Value::Double(v) => Ok(v as f32),
+        ref other => Err(Error::TagMismatch(other.id(), 0x05)),
+    }
+}
@@ -0,0 +1,105 @@
+//! Stamping and checking the `DataVersion` of saved data.
+//!
+//! Since 1.9, the game records the version that wrote each chunk, player and
+//! `level.dat` in an int named `DataVersion`, and uses it to decide which
+//! upgrades to apply when loading. A `DataVersion` holds the range of versions
+//! a world supports; it writes the newest one into data being saved, and
+//! checks data being loaded against the range, so that data needing migration
+//! is caught with a typed error instead of being misread.
+//!
+//! Data written before 1.9 has no `DataVersion` and is treated as version 0.
+
+use std::collections::HashMap;
+
+use error::{Error, Result};
+use value::Value;
+
+use super::{as_compound, as_integer};
+
+const TAG: &str = "DataVersion";
+
+/// The kinds of saved data that carry a `DataVersion`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Structure {
+    /// A chunk, which stores it at the root.
+    Chunk,
+    /// A player, which stores it at the root.
+    Player,
+    /// A `level.dat`, which stores it in the `Data` compound.
+    Level,
+}
+
+fn holder(structure: Structure, value: &Value) -> Result<&HashMap<String, Value>> {
+    let map = as_compound(value)?;
+    match structure {
+        Structure::Level => as_compound(map.get("Data").ok_or_else(|| Error::MissingField("Data".to_string()))?),
+        _ => Ok(map),
+    }
+}
+
+/// Reads the `DataVersion` of some saved data, or 0 if it has none.
+pub fn read(structure: Structure, value: &Value) -> Result<i32> {
+    match holder(structure, value)?.get(TAG) {
+        Some(v) => Ok(as_integer(v)? as i32),
+        None => Ok(0),
+    }
+}
+
+/// The range of data versions supported by a world.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DataVersion {
+    current: i32,
+    oldest: i32,
+}
+
+impl DataVersion {
+    /// Creates a range that accepts any data up to and including `current`,
+    /// which is the version written when stamping.
+    pub fn new(current: i32) -> DataVersion {
+        DataVersion { current, oldest: 0 }
+    }
+
+    /// Sets the oldest version accepted when checking data.
+    pub fn with_oldest(mut self, oldest: i32) -> DataVersion {
+        self.oldest = oldest;
+        self
+    }
+
+    /// The version written when stamping.
+    pub fn current(&self) -> i32 {
+        self.current
+    }
+
+    /// Writes the current version into some saved data, replacing any
+    /// version it had.
+    pub fn stamp(&self, structure: Structure, value: &mut Value) -> Result<()> {
+        let map = match *value {
+            Value::Compound(ref mut map) => map,
+            ref other => return Err(Error::TagMismatch(other.id(), 0x0a)),
+        };
+        let map = match structure {
+            Structure::Level => match map.get_mut("Data") {
+                Some(&mut Value::Compound(ref mut data)) => data,
+                Some(other) => return Err(Error::TagMismatch(other.id(), 0x0a)),
+                None => return Err(Error::MissingField("Data".to_string())),
+            },
+            _ => map,
+        };
+        map.insert(TAG.to_string(), Value::Int(self.current));
+        Ok(())
+    }
+
+    /// Checks that some saved data is within the supported range, returning
+    /// its version. Data outside the range is an
+    /// `Error::DataVersionTooNew` or `Error::DataVersionTooOld`.
+    pub fn check(&self, structure: Structure, value: &Value) -> Result<i32> {
+        let version = read(structure, value)?;
+        if version > self.current {
+            return Err(Error::DataVersionTooNew(version, self.current));
+        }
+        if version < self.oldest {
+            return Err(Error::DataVersionTooOld(version, self.oldest));
+        }
+        Ok(version)
+    }
+}
@@ -0,0 +1,90 @@
+//! Reading uncompressed files through a memory map, enabled by the `mmap`
+//! feature on Unix.
+//!
+//! `open_mmap` maps a file and opens it as a `lazy::Document`, so a large
+//! structure file can be queried without reading it into memory first: only
+//! the pages holding the tags asked for are ever loaded. `Mmap` gives the
+//! mapped bytes themselves, to decode with `Value::from_reader` over a slice
+//! or any other reader.
+//!
+//! The file must not be changed while it is mapped, or what is read from it
+//! is undefined.
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+
+use libc;
+
+use error::Result;
+use lazy::Document;
+
+/// A read-only memory map of a whole file.
+pub struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// The mapping is read-only and owned by this value alone.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Maps the file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Mmap> {
+        Mmap::map(&File::open(path)?)
+    }
+
+    /// Maps an open file.
+    pub fn map(file: &File) -> Result<Mmap> {
+        let len = file.metadata()?.len() as usize;
+        // Empty mappings are not allowed, and there is nothing to map.
+        if len == 0 {
+            return Ok(Mmap { ptr: ptr::null_mut(), len: 0 });
+        }
+        let ptr = unsafe {
+            libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(Mmap { ptr, len })
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl AsRef<[u8]> for Mmap {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+/// Maps the uncompressed file at `path` and opens it as a lazily read
+/// document.
+pub fn open_mmap<P: AsRef<Path>>(path: P) -> Result<Document<io::Cursor<Mmap>>> {
+    Document::open(io::Cursor::new(Mmap::open(path)?))
+}
@@ -0,0 +1,125 @@
+//! Deterministic encoding, for keeping worlds under version control.
+//!
+//! The game writes compound entries in hash order and tags empty lists
+//! inconsistently, so saving an unchanged world can still change every file.
+//! The canonical encoding written here sorts compound entries by name and
+//! tags every empty list as a list of `TAG_End`, as the game does for lists
+//! it creates empty. Compressed files are written at a fixed compression level
+//! with no timestamp in the gzip header, so the same document always produces
+//! the same bytes.
+//!
+//! Only standalone files such as `level.dat` and player data are handled;
+//! chunks inside region files are not rewritten.
+//!
+//! The canonical encoding also makes a good basis for hashing, since it does
+//! not depend on the order entries were stored in or on compression. See
+//! `Value::canonical_hash`, and `HashingWriter` for hashing what any encoder
+//! writes without buffering it.
+
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::path::Path;
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+use batch::{BatchProcessor, Document, Report};
+use compression::Registry;
+use error::{Error, Result};
+use raw;
+use value::Value;
+
+/// Writes a named root tag in the canonical encoding.
+pub fn write_canonical<W: io::Write>(dst: &mut W, title: &str, value: &Value) -> Result<()> {
+    dst.write_u8(value.id())?;
+    raw::write_bare_string(dst, title)?;
+    write_payload(dst, value)
+}
+
+pub(crate) fn write_payload<W: io::Write>(dst: &mut W, value: &Value) -> Result<()> {
+    match *value {
+        Value::List(ref vals) => {
+            let id = vals.first().map_or(0x00, Value::id);
+            if vals.iter().any(|v| v.id() != id) {
+                return Err(Error::HeterogeneousList);
+            }
+            dst.write_u8(id)?;
+            dst.write_i32::<BigEndian>(raw::list_len(vals.len())?)?;
+            for val in vals {
+                write_payload(dst, val)?;
+            }
+            Ok(())
+        },
+        Value::Compound(ref map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, val) in entries {
+                dst.write_u8(val.id())?;
+                raw::write_bare_string(dst, name)?;
+                write_payload(dst, val)?;
+            }
+            raw::close_nbt(dst)
+        },
+        ref other => other.write(dst),
+    }
+}
+
+/// A writer that feeds everything written to it into a `Hasher`.
+pub struct HashingWriter<H> {
+    hasher: H,
+    written: u64,
+}
+
+impl<H: Hasher> HashingWriter<H> {
+    pub fn new(hasher: H) -> HashingWriter<H> {
+        HashingWriter { hasher, written: 0 }
+    }
+
+    /// The hash of everything written so far.
+    pub fn finish(&self) -> u64 {
+        self.hasher.finish()
+    }
+
+    /// The number of bytes written so far.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn into_inner(self) -> H {
+        self.hasher
+    }
+}
+
+impl<H: Hasher> io::Write for HashingWriter<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.hasher.write(buf);
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Rewrites a file in the canonical encoding, keeping its compression, and
+/// returns whether its contents changed.
+pub fn normalize_file<P: AsRef<Path>>(path: P) -> Result<bool> {
+    let registry = Registry::new();
+    let path = path.as_ref();
+    let doc = Document::read(&registry, path)?;
+    if fs::read(path)? == doc.to_bytes(&registry)? {
+        return Ok(false);
+    }
+    doc.write(&registry, path)?;
+    Ok(true)
+}
+
+/// Rewrites every `.dat` file below a world directory in the canonical
+/// encoding, returning which files changed and which could not be read.
+pub fn normalize_world<P: AsRef<Path>>(dir: P) -> Result<Report> {
+    BatchProcessor::new(dir.as_ref()).include("*.dat").run(|path, doc| {
+        // The processor writes the document back if it reports a change.
+        Ok(fs::read(path)? != doc.to_bytes(&Registry::new())?)
+    })
+}
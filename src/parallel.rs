@@ -0,0 +1,169 @@
+//! Walking, searching and transforming large trees on several threads.
+//!
+//! The tree is split at compound and list boundaries into enough subtrees to
+//! keep every CPU busy, and the subtrees are then processed on a pool of
+//! worker threads, like `batch::BatchProcessor` does with files. Tags above
+//! the split are visited on the calling thread first, so every tag is still
+//! visited before its children.
+//!
+//! Paths are given as in `testing::Divergence`: compound entries are joined
+//! with dots and list elements are indexed, as in `Level.Entities[3].id`.
+
+use std::sync::Mutex;
+use std::thread;
+
+use value::Value;
+
+/// How many subtrees to aim for per thread, so that uneven subtrees still
+/// balance out.
+const SPLIT_PER_THREAD: usize = 4;
+
+fn threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+fn has_children(value: &Value) -> bool {
+    match *value {
+        Value::List(ref vals) => !vals.is_empty(),
+        Value::Compound(ref map) => !map.is_empty(),
+        _ => false,
+    }
+}
+
+/// Calls `f` on every tag in the tree, with its path. Tags are visited before
+/// their children, but otherwise in no particular order.
+pub fn par_walk<F>(root: &Value, f: F)
+    where F: Fn(&str, &Value) + Sync
+{
+    let want = threads() * SPLIT_PER_THREAD;
+    let mut frontier = vec![(String::new(), root)];
+    while frontier.len() < want && frontier.iter().any(|&(_, v)| has_children(v)) {
+        let mut next = Vec::new();
+        for (path, value) in frontier {
+            if !has_children(value) {
+                next.push((path, value));
+                continue;
+            }
+            f(&path, value);
+            match *value {
+                Value::List(ref vals) => {
+                    next.extend(vals.iter().enumerate().map(|(i, v)| (format!("{}[{}]", path, i), v)));
+                },
+                Value::Compound(ref map) => {
+                    next.extend(map.iter().map(|(k, v)| (child_path(&path, k), v)));
+                },
+                _ => unreachable!(),
+            }
+        }
+        frontier = next;
+    }
+    run(frontier, |(path, value)| walk(&path, value, &f));
+}
+
+pub(crate) fn walk<F>(path: &str, value: &Value, f: &F)
+    where F: Fn(&str, &Value)
+{
+    f(path, value);
+    match *value {
+        Value::List(ref vals) => {
+            for (i, v) in vals.iter().enumerate() {
+                walk(&format!("{}[{}]", path, i), v, f);
+            }
+        },
+        Value::Compound(ref map) => {
+            for (k, v) in map {
+                walk(&child_path(path, k), v, f);
+            }
+        },
+        _ => (),
+    }
+}
+
+/// The paths of every tag in the tree for which `predicate` returns true, in
+/// sorted order.
+pub fn par_find<P>(root: &Value, predicate: P) -> Vec<String>
+    where P: Fn(&str, &Value) -> bool + Sync
+{
+    let found = Mutex::new(Vec::new());
+    par_walk(root, |path, value| {
+        if predicate(path, value) {
+            found.lock().unwrap().push(path.to_string());
+        }
+    });
+    let mut found = found.into_inner().unwrap();
+    found.sort();
+    found
+}
+
+/// Calls `f` on every tag in the tree, with its path, allowing it to change
+/// the tag. A tag is changed before its children are visited, so the
+/// children visited are those the tag has after the change.
+pub fn par_transform<F>(root: &mut Value, f: F)
+    where F: Fn(&str, &mut Value) + Sync
+{
+    let want = threads() * SPLIT_PER_THREAD;
+    let mut frontier = vec![(String::new(), root)];
+    while frontier.len() < want && frontier.iter().any(|(_, v)| has_children(v)) {
+        let mut next = Vec::new();
+        for (path, value) in frontier {
+            if !has_children(value) {
+                next.push((path, value));
+                continue;
+            }
+            f(&path, value);
+            match *value {
+                Value::List(ref mut vals) => {
+                    next.extend(vals.iter_mut().enumerate().map(|(i, v)| (format!("{}[{}]", path, i), v)));
+                },
+                Value::Compound(ref mut map) => {
+                    next.extend(map.iter_mut().map(|(k, v)| (child_path(&path, k), v)));
+                },
+                _ => (),
+            }
+        }
+        frontier = next;
+    }
+    run(frontier, |(path, value)| transform(&path, value, &f));
+}
+
+fn transform<F>(path: &str, value: &mut Value, f: &F)
+    where F: Fn(&str, &mut Value)
+{
+    f(path, value);
+    match *value {
+        Value::List(ref mut vals) => {
+            for (i, v) in vals.iter_mut().enumerate() {
+                transform(&format!("{}[{}]", path, i), v, f);
+            }
+        },
+        Value::Compound(ref mut map) => {
+            for (k, v) in map.iter_mut() {
+                transform(&child_path(path, k), v, f);
+            }
+        },
+        _ => (),
+    }
+}
+
+/// Hands each item to `work` on a pool of worker threads.
+pub(crate) fn run<T, F>(items: Vec<T>, work: F)
+    where T: Send, F: Fn(T) + Sync
+{
+    let workers = threads().min(items.len());
+    let items = Mutex::new(items.into_iter());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let item = items.lock().unwrap().next();
+                match item {
+                    Some(item) => work(item),
+                    None => break,
+                }
+            });
+        }
+    });
+}
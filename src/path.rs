@@ -0,0 +1,528 @@
+//! NBT paths, in the syntax of the game's `/data` command.
+//!
+//! An `NbtPath` selects tags inside a `Value` tree. It is made of nodes
+//! separated by dots:
+//!
+//! * `name` or `"quoted name"` selects an entry of a compound, and
+//!   `name{Count:1b}` selects it only if it matches the compound given;
+//! * `[2]` selects an element of a list, counting back from the end if the
+//!   index is negative, `[]` selects every element, and `[{Slot:0b}]` every
+//!   element matching the compound given;
+//! * `{id:"minecraft:pig"}` at the very start selects the root if it matches.
+//!
+//! A compound matches a pattern if it has every entry of the pattern, with
+//! compounds matching recursively and each element of a list in the pattern
+//! matching some element of the list in the compound. So
+//! `Inventory[{Slot:0b}].tag.Enchantments[0].id` is the first enchantment of
+//! the item in the first hotbar slot.
+//!
+//! As with the command, a path may select several tags at once. Elements of
+//! typed arrays are not values of their own and cannot be selected.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use error::{Error, Result};
+use value::Value;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Root(HashMap<String, Value>),
+    Named(String),
+    NamedMatch(String, HashMap<String, Value>),
+    Index(i32),
+    All,
+    Matching(HashMap<String, Value>),
+}
+
+/// A parsed path selecting tags inside a `Value` tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NbtPath {
+    nodes: Vec<Node>,
+    source: String,
+}
+
+/// Whether `value` has everything in `pattern`, as the game compares them.
+fn matches(pattern: &Value, value: &Value) -> bool {
+    match (pattern, value) {
+        (Value::Compound(p), Value::Compound(v)) => matches_compound(p, v),
+        (Value::List(p), Value::List(v)) if !p.is_empty() =>
+            p.iter().all(|p| v.iter().any(|v| matches(p, v))),
+        _ => pattern == value,
+    }
+}
+
+fn matches_compound(pattern: &HashMap<String, Value>, map: &HashMap<String, Value>) -> bool {
+    pattern.iter().all(|(k, p)| map.get(k).is_some_and(|v| matches(p, v)))
+}
+
+fn is_match(pattern: &HashMap<String, Value>, value: &Value) -> bool {
+    match *value {
+        Value::Compound(ref map) => matches_compound(pattern, map),
+        _ => false,
+    }
+}
+
+/// Resolves a list index, which counts back from the end if negative.
+fn position(index: i32, len: usize) -> Option<usize> {
+    let i = if index < 0 { len as i64 + index as i64 } else { index as i64 };
+    if i >= 0 && i < len as i64 { Some(i as usize) } else { None }
+}
+
+impl Node {
+    fn get<'a>(&self, value: &'a Value, out: &mut Vec<&'a Value>) {
+        match (self, value) {
+            (Node::Root(p), v) if is_match(p, v) => out.push(v),
+            (Node::Named(name), Value::Compound(map)) => out.extend(map.get(name)),
+            (Node::NamedMatch(name, p), Value::Compound(map)) =>
+                out.extend(map.get(name).filter(|v| is_match(p, v))),
+            (Node::Index(i), Value::List(list)) =>
+                out.extend(position(*i, list.len()).map(|i| &list[i])),
+            (Node::All, Value::List(list)) => out.extend(list.iter()),
+            (Node::Matching(p), Value::List(list)) =>
+                out.extend(list.iter().filter(|v| is_match(p, v))),
+            _ => {},
+        }
+    }
+
+    fn get_mut<'a>(&self, value: &'a mut Value, out: &mut Vec<&'a mut Value>) {
+        match (self, value) {
+            (Node::Root(p), v) if is_match(p, v) => out.push(v),
+            (Node::Named(name), Value::Compound(map)) =>
+                out.extend(map.get_mut(name)),
+            (Node::NamedMatch(name, p), Value::Compound(map)) =>
+                out.extend(map.get_mut(name).filter(|v| is_match(p, v))),
+            (Node::Index(i), Value::List(list)) => {
+                let len = list.len();
+                out.extend(position(*i, len).map(move |i| &mut list[i]));
+            },
+            (Node::All, Value::List(list)) => out.extend(list.iter_mut()),
+            (Node::Matching(p), Value::List(list)) =>
+                out.extend(list.iter_mut().filter(|v| is_match(p, v))),
+            _ => {},
+        }
+    }
+
+    /// Like `get_mut`, but adds a missing compound entry, holding an empty
+    /// tag of the kind `next` expects to select from.
+    fn get_or_create<'a>(&self, value: &'a mut Value, next: &Node, out: &mut Vec<&'a mut Value>) {
+        let created = match *self {
+            Node::Named(_) if next.selects_elements() => Value::List(Vec::new()),
+            Node::Named(_) => Value::Compound(HashMap::new()),
+            Node::NamedMatch(_, ref p) => Value::Compound(p.clone()),
+            _ => return self.get_mut(value, out),
+        };
+        match (self, value) {
+            (Node::Named(name), Value::Compound(map)) |
+            (Node::NamedMatch(name, _), Value::Compound(map)) => {
+                let entry = map.entry(name.clone()).or_insert(created);
+                if let Node::NamedMatch(_, ref p) = *self {
+                    if !is_match(p, entry) {
+                        return;
+                    }
+                }
+                out.push(entry);
+            },
+            _ => {},
+        }
+    }
+
+    fn selects_elements(&self) -> bool {
+        matches!(*self, Node::Index(_) | Node::All | Node::Matching(_))
+    }
+
+    /// Sets the tags this node selects in `value`, returning how many changed.
+    fn set(&self, value: &mut Value, new: &Value) -> usize {
+        let replace = |old: &mut Value| if *old != *new { *old = new.clone(); 1 } else { 0 };
+        let fits = |list: &Vec<Value>| list.first().is_none_or(|e| e.id() == new.id());
+        match (self, value) {
+            (Node::Named(name), Value::Compound(map)) => {
+                if map.get(name) == Some(new) {
+                    return 0;
+                }
+                map.insert(name.clone(), new.clone());
+                1
+            },
+            (Node::NamedMatch(name, p), Value::Compound(map)) =>
+                map.get_mut(name).filter(|v| is_match(p, v)).map_or(0, replace),
+            (Node::Index(i), Value::List(list)) if fits(list) =>
+                position(*i, list.len()).map_or(0, |i| replace(&mut list[i])),
+            (Node::All, Value::List(list)) if list.is_empty() => {
+                list.push(new.clone());
+                1
+            },
+            (Node::All, Value::List(list)) if fits(list) =>
+                list.iter_mut().map(replace).sum(),
+            (Node::Matching(p), Value::List(list)) if fits(list) =>
+                list.iter_mut().filter(|v| is_match(p, v)).map(replace).sum(),
+            _ => 0,
+        }
+    }
+
+    /// Removes the tags this node selects from `value`, returning how many
+    /// were removed.
+    fn remove(&self, value: &mut Value) -> usize {
+        match (self, value) {
+            (Node::Named(name), Value::Compound(map)) =>
+                map.remove(name).map_or(0, |_| 1),
+            (Node::NamedMatch(name, p), Value::Compound(map)) => {
+                if map.get(name).is_some_and(|v| is_match(p, v)) {
+                    map.remove(name);
+                    return 1;
+                }
+                0
+            },
+            (Node::Index(i), Value::List(list)) =>
+                position(*i, list.len()).map_or(0, |i| { list.remove(i); 1 }),
+            (Node::All, Value::List(list)) => list.drain(..).count(),
+            (Node::Matching(p), Value::List(list)) => {
+                let len = list.len();
+                list.retain(|v| !is_match(p, v));
+                len - list.len()
+            },
+            _ => 0,
+        }
+    }
+}
+
+impl NbtPath {
+    /// Parses a path.
+    pub fn parse(path: &str) -> Result<NbtPath> {
+        let mut parser = Parser { src: path, pos: 0 };
+        let mut nodes = Vec::new();
+        if parser.peek() == Some('{') {
+            nodes.push(Node::Root(parser.compound()?));
+            if parser.peek().is_none() {
+                return Ok(NbtPath { nodes, source: path.to_string() });
+            }
+            parser.expect('.')?;
+        }
+        loop {
+            nodes.push(parser.node()?);
+            match parser.peek() {
+                None => break,
+                Some('[') => {},
+                Some(_) => parser.expect('.')?,
+            }
+        }
+        Ok(NbtPath { nodes, source: path.to_string() })
+    }
+
+    /// The tags the path selects in `root`.
+    pub fn get<'a>(&self, root: &'a Value) -> Vec<&'a Value> {
+        let mut current = vec![root];
+        for node in &self.nodes {
+            let mut next = Vec::new();
+            for value in current {
+                node.get(value, &mut next);
+            }
+            current = next;
+        }
+        current
+    }
+
+    /// The tags the path selects in `root`, for modification.
+    pub fn get_mut<'a>(&self, root: &'a mut Value) -> Vec<&'a mut Value> {
+        let mut current = vec![root];
+        for node in &self.nodes {
+            let mut next = Vec::new();
+            for value in current {
+                node.get_mut(value, &mut next);
+            }
+            current = next;
+        }
+        current
+    }
+
+    /// Sets every tag the path selects to `value`, returning how many tags
+    /// changed.
+    ///
+    /// Missing compound entries along the way are added, as the command does.
+    /// List elements are only replaced by a value of the same type as the
+    /// list, and `[]` on an empty list adds `value` to it. A path that only
+    /// matches the root selects nothing that can be set.
+    pub fn set<V: Into<Value>>(&self, root: &mut Value, value: V) -> usize {
+        let value = value.into();
+        let (last, parents) = self.nodes.split_last().expect("paths are never empty");
+        let mut current = vec![root];
+        for (i, node) in parents.iter().enumerate() {
+            let mut next = Vec::new();
+            for v in current {
+                node.get_or_create(v, &self.nodes[i + 1], &mut next);
+            }
+            current = next;
+        }
+        current.into_iter().map(|v| last.set(v, &value)).sum()
+    }
+
+    /// Removes every tag the path selects, returning how many were removed.
+    /// A path that only matches the root removes nothing.
+    pub fn remove(&self, root: &mut Value) -> usize {
+        let (last, parents) = self.nodes.split_last().expect("paths are never empty");
+        let mut current = vec![root];
+        for node in parents {
+            let mut next = Vec::new();
+            for v in current {
+                node.get_mut(v, &mut next);
+            }
+            current = next;
+        }
+        current.into_iter().map(|v| last.remove(v)).sum()
+    }
+}
+
+impl FromStr for NbtPath {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<NbtPath> {
+        NbtPath::parse(s)
+    }
+}
+
+impl fmt::Display for NbtPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Appends a compound entry name to a path being built, quoting it if it
+/// cannot be written bare.
+pub(crate) fn push_name(path: &mut String, name: &str) {
+    if !path.is_empty() {
+        path.push('.');
+    }
+    if !name.is_empty() && name.chars().all(is_name_char) {
+        path.push_str(name);
+        return;
+    }
+    path.push('"');
+    for c in name.chars() {
+        if c == '"' || c == '\\' {
+            path.push('\\');
+        }
+        path.push(c);
+    }
+    path.push('"');
+}
+
+fn is_name_char(c: char) -> bool {
+    !matches!(c, ' ' | '"' | '\'' | '[' | ']' | '.' | '{' | '}')
+}
+
+fn is_bare_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')
+}
+
+/// Reads a path, and the SNBT compounds used as patterns in it.
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> Error {
+        Error::InvalidPath(format!("{} at position {} of `{}`", msg, self.pos, self.src))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += c.map_or(0, |c| c.len_utf8());
+        c
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected `{}`", c)));
+        }
+        self.bump();
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, f: F) -> &'a str {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.bump();
+        }
+        &self.src[start..self.pos]
+    }
+
+    fn node(&mut self) -> Result<Node> {
+        match self.peek() {
+            Some('[') => {
+                self.bump();
+                let node = match self.peek() {
+                    Some(']') => Node::All,
+                    Some('{') => Node::Matching(self.compound()?),
+                    _ => {
+                        let digits = self.take_while(|c| c == '-' || c.is_ascii_digit());
+                        Node::Index(digits.parse().map_err(|_| self.error("expected an index"))?)
+                    },
+                };
+                self.expect(']')?;
+                Ok(node)
+            },
+            Some('"') | Some('\'') => {
+                let name = self.quoted()?;
+                self.named(name)
+            },
+            _ => {
+                let name = self.take_while(is_name_char);
+                if name.is_empty() {
+                    return Err(self.error("expected a name"));
+                }
+                self.named(name.to_string())
+            },
+        }
+    }
+
+    fn named(&mut self, name: String) -> Result<Node> {
+        if self.peek() == Some('{') {
+            return Ok(Node::NamedMatch(name, self.compound()?));
+        }
+        Ok(Node::Named(name))
+    }
+
+    fn quoted(&mut self) -> Result<String> {
+        let quote = self.bump();
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some('\\') => match self.bump() {
+                    Some(c) if c == '\\' || Some(c) == quote => out.push(c),
+                    _ => return Err(self.error("invalid escape")),
+                },
+                c if c == quote => return Ok(out),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn compound(&mut self) -> Result<HashMap<String, Value>> {
+        self.expect('{')?;
+        let mut map = HashMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(map);
+        }
+        loop {
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some('"') | Some('\'') => self.quoted()?,
+                _ => self.take_while(is_bare_char).to_string(),
+            };
+            if key.is_empty() {
+                return Err(self.error("expected a key"));
+            }
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.value()?;
+            map.insert(key, value);
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => {},
+                Some('}') => return Ok(map),
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => Ok(Value::Compound(self.compound()?)),
+            Some('[') => self.list(),
+            Some('"') | Some('\'') => Ok(Value::String(self.quoted()?)),
+            _ => {
+                let token = self.take_while(is_bare_char);
+                if token.is_empty() {
+                    return Err(self.error("expected a value"));
+                }
+                Ok(scalar(token))
+            },
+        }
+    }
+
+    fn list(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        let rest = &self.src[self.pos..];
+        let array = ["B;", "I;", "L;"].iter().position(|p| rest.starts_with(p));
+        if array.is_some() {
+            self.pos += 2;
+        }
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.bump();
+        } else {
+            loop {
+                elements.push(self.value()?);
+                self.skip_whitespace();
+                match self.bump() {
+                    Some(',') => {},
+                    Some(']') => break,
+                    _ => return Err(self.error("expected `,` or `]`")),
+                }
+            }
+        }
+        let elements = elements.into_iter();
+        let value = match array {
+            Some(0) => elements.map(|e| match e {
+                Value::Byte(b) => Some(b),
+                _ => None,
+            }).collect::<Option<Vec<_>>>().map(Value::ByteArray),
+            Some(1) => elements.map(|e| match e {
+                Value::Int(i) => Some(i),
+                _ => None,
+            }).collect::<Option<Vec<_>>>().map(Value::IntArray),
+            Some(_) => elements.map(|e| match e {
+                Value::Long(l) => Some(l),
+                _ => None,
+            }).collect::<Option<Vec<_>>>().map(Value::LongArray),
+            None => {
+                let list: Vec<Value> = elements.collect();
+                if list.iter().any(|e| e.id() != list[0].id()) {
+                    return Err(Error::HeterogeneousList);
+                }
+                Some(Value::List(list))
+            },
+        };
+        value.ok_or_else(|| self.error("mismatched array element"))
+    }
+}
+
+/// Reads an unquoted SNBT token as a number, if it is one with a valid
+/// suffix, or as a string.
+fn scalar(token: &str) -> Value {
+    let (body, suffix) = match token.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&token[..i], Some(c.to_ascii_lowercase())),
+        _ => (token, None),
+    };
+    let number = match suffix {
+        Some('b') => body.parse().ok().map(Value::Byte),
+        Some('s') => body.parse().ok().map(Value::Short),
+        Some('l') => body.parse().ok().map(Value::Long),
+        Some('f') => body.parse().ok().map(Value::Float),
+        Some('d') => body.parse().ok().map(Value::Double),
+        None if body.contains('.') => body.parse().ok().map(Value::Double),
+        None => body.parse().ok().map(Value::Int),
+        _ => None,
+    };
+    match token {
+        "true" => Value::Byte(1),
+        "false" => Value::Byte(0),
+        _ => number.unwrap_or_else(|| Value::String(token.to_string())),
+    }
+}
@@ -0,0 +1,201 @@
+//! Parsing documents from bytes as they arrive, without doing any IO.
+//!
+//! `stream::Parser` pulls bytes from an `io::Read` source, which blocks when
+//! the source has nothing to give. The `Parser` here is pushed bytes instead:
+//! the caller hands it each chunk with `feed` as it arrives, from a socket,
+//! an io_uring completion or a JavaScript callback, and takes events out with
+//! `next_event` until it says it needs more data. The events and offsets are
+//! exactly those `stream::Parser` gives for the same document.
+//!
+//! ```ignore
+//! let mut parser = Parser::new();
+//! loop {
+//!     match parser.next_event()? {
+//!         Status::Event(_, event) => handle(event),
+//!         Status::NeedMoreData => parser.feed(&socket.recv()?),
+//!         Status::Done => break,
+//!     }
+//! }
+//! ```
+//!
+//! A tag that arrives in pieces is parsed again from its start each time more
+//! data is fed, so feeding very large arrays a few bytes at a time is slow.
+//!
+//! `Parser::with_options` bounds what a document may hold by
+//! `DecoderOptions`, as `Value::from_reader_with` does, for data from
+//! untrusted peers.
+
+use error::{Error, Result};
+use raw;
+use stream::{Event, Frame};
+use value::{DecoderOptions, Limited};
+
+/// What `Parser::next_event` found.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    /// The next event, with the offset in the document where its tag starts.
+    Event(u64, Event),
+    /// The next event is not complete in the data fed so far.
+    NeedMoreData,
+    /// The root tag has ended. Any bytes fed after it are in `remaining`.
+    Done,
+}
+
+/// A parser for a single document that is fed its bytes in chunks.
+#[derive(Default)]
+pub struct Parser {
+    /// Bytes fed but not yet parsed start at `buf[pos]`.
+    buf: Vec<u8>,
+    pos: usize,
+    /// The offset in the document of `buf[0]`.
+    base: u64,
+    stack: Vec<Frame>,
+    done: bool,
+    /// The length of `buf` when the last attempt ran out of data, so the
+    /// next event is not parsed again until more arrives.
+    starved: Option<usize>,
+    options: DecoderOptions,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    /// A parser that fails as soon as the document goes beyond one of the
+    /// limits in `options`, with depth counted from the root and bytes from
+    /// the start of the document.
+    pub fn with_options(options: DecoderOptions) -> Parser {
+        Parser { options, ..Parser::default() }
+    }
+
+    /// Adds the next chunk of the document.
+    pub fn feed(&mut self, data: &[u8]) {
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.base += self.pos as u64;
+            self.pos = 0;
+            self.starved = None;
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Parses the next event from the data fed so far.
+    pub fn next_event(&mut self) -> Result<Status> {
+        if self.done {
+            return Ok(Status::Done);
+        }
+        if self.starved == Some(self.buf.len()) {
+            return Ok(Status::NeedMoreData);
+        }
+
+        let mut src = &self.buf[self.pos..];
+        let read = match self.read(&mut src) {
+            // An event that runs past the data fed so far is over the budget
+            // if that data already is.
+            Err(Error::IncompleteNbtValue) => {
+                self.check_size(self.buf.len()).and(Err(Error::IncompleteNbtValue))
+            },
+            read => read.and_then(|read| self.check_size(self.buf.len() - src.len()).map(|_| read)),
+        };
+        let (event, frame) = match read {
+            Ok(read) => read,
+            Err(Error::IncompleteNbtValue) => {
+                self.starved = Some(self.buf.len());
+                return Ok(Status::NeedMoreData);
+            },
+            Err(e) => {
+                self.done = true;
+                return Err(e);
+            },
+        };
+        let offset = self.position();
+        self.pos = self.buf.len() - src.len();
+
+        match event {
+            Event::End => { self.stack.pop(); },
+            _ => if let Some(&mut Frame::List { ref mut remaining, .. }) = self.stack.last_mut() {
+                *remaining -= 1;
+            },
+        }
+        self.stack.extend(frame);
+        if self.stack.is_empty() {
+            self.done = true;
+        }
+        Ok(Status::Event(offset, event))
+    }
+
+    /// Checks that the whole document was fed, once there is no more data.
+    pub fn finish(&self) -> Result<()> {
+        if self.done { Ok(()) } else { Err(Error::IncompleteNbtValue) }
+    }
+
+    /// The offset in the document of the next byte to be parsed.
+    pub fn position(&self) -> u64 {
+        self.base + self.pos as u64
+    }
+
+    /// How many compounds and lists are open.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// The bytes fed that have not been parsed, which once the parser is
+    /// done are whatever followed the document.
+    pub fn remaining(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    /// Fails if the document would run up to `buf[end]`, beyond the byte
+    /// budget.
+    fn check_size(&self, end: usize) -> Result<()> {
+        match self.options.max_bytes {
+            Some(max) if self.base + end as u64 > max => Err(Error::SizeLimitExceeded(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the next event, returning the frame it opens, without changing
+    /// the parser, so that nothing is lost if the data runs out.
+    fn read(&self, src: &mut &[u8]) -> Result<(Event, Option<Frame>)> {
+        // The byte budget is checked against the whole document instead.
+        let options = DecoderOptions { max_bytes: None, ..self.options.clone() };
+        let mut src = Limited::new(src, &options);
+        let depth = self.stack.len();
+        match self.stack.last() {
+            None => {
+                let (id, name) = src.entry_header()?;
+                if id == 0x00 {
+                    return Err(Error::NoRootCompound);
+                }
+                open(&mut src, id, Some(name), depth)
+            },
+            Some(&Frame::List { remaining: 0, .. }) => Ok((Event::End, None)),
+            Some(&Frame::List { kind, .. }) => open(&mut src, kind, None, depth),
+            Some(&Frame::Compound) => match src.entry_header()? {
+                (0x00, _) => Ok((Event::End, None)),
+                (id, name) => open(&mut src, id, Some(name), depth),
+            },
+        }
+    }
+}
+
+/// Reads the tag with the given type ID and name whose payload is next,
+/// nested `depth` lists and compounds deep.
+fn open(src: &mut Limited<&[u8]>, id: u8, name: Option<String>, depth: usize)
+        -> Result<(Event, Option<Frame>)> {
+    match id {
+        0x09 => {
+            src.nest(depth)?;
+            let kind = raw::read_bare_byte(src.src)? as u8;
+            let len = raw::read_bare_int(src.src)?;
+            src.check_len(len.max(0) as usize)?;
+            Ok((Event::ListStart { name, kind, len }, Some(Frame::List { kind, remaining: len.max(0) })))
+        },
+        0x0a => {
+            src.nest(depth)?;
+            Ok((Event::CompoundStart(name), Some(Frame::Compound)))
+        },
+        _ => Ok((Event::Tag(name, src.value(id, depth)?), None)),
+    }
+}
@@ -7,11 +7,60 @@
 //! A high-level API for reading and writing generic NBT data is available in
 //! the [`Blob`](../struct.Blob.html) struct.
 
-use std::io;
+use std::cmp;
+use std::io::{self, Read};
 
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
 
 use error::{Error, Result};
+use value::DecoderOptions;
+
+/// The most elements to make room for before any have been read, so that a
+/// corrupt length cannot make a reader allocate far more than the data holds.
+const MAX_PREALLOC: usize = 4096;
+
+/// The capacity to start a buffer for `len` elements with. Buffers grow as
+/// elements are actually read.
+pub(crate) fn initial_capacity(len: usize) -> usize {
+    cmp::min(len, MAX_PREALLOC)
+}
+
+/// Reads exactly `len` bytes, allocating only as much as is actually read.
+pub(crate) fn read_bytes<R>(src: &mut R, len: usize) -> Result<Vec<u8>>
+    where R: io::Read + ?Sized
+{
+    let mut bytes = Vec::new();
+    src.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(Error::IncompleteNbtValue);
+    }
+    Ok(bytes)
+}
+
+/// Reads the elements of a byte array of `len` elements in one go.
+pub(crate) fn read_bytes_i8<R>(src: &mut R, len: usize) -> Result<Vec<i8>>
+    where R: io::Read + ?Sized
+{
+    Ok(read_bytes(src, len)?.into_iter().map(|b| b as i8).collect())
+}
+
+/// Reads the elements of an int array of `len` elements in one go, and
+/// then swaps them from the byte order `B` into native order.
+pub(crate) fn read_bytes_i32<B, R>(src: &mut R, len: usize) -> Result<Vec<i32>>
+    where B: ByteOrder, R: io::Read + ?Sized
+{
+    let bytes = read_bytes(src, len.saturating_mul(4))?;
+    Ok(bytes.chunks_exact(4).map(B::read_i32).collect())
+}
+
+/// Reads the elements of a long array of `len` elements in one go, and
+/// then swaps them from the byte order `B` into native order.
+pub(crate) fn read_bytes_i64<B, R>(src: &mut R, len: usize) -> Result<Vec<i64>>
+    where B: ByteOrder, R: io::Read + ?Sized
+{
+    let bytes = read_bytes(src, len.saturating_mul(8))?;
+    Ok(bytes.chunks_exact(8).map(B::read_i64).collect())
+}
 
 /// A convenience function for closing NBT format objects.
 ///
@@ -69,32 +118,55 @@ pub fn write_bare_double<W>(dst: &mut W, value: f64) -> Result<()>
 pub fn write_bare_byte_array<W>(dst: &mut W, value: &[i8]) -> Result<()>
    where W: io::Write
 {
-    try!(dst.write_i32::<BigEndian>(value.len() as i32));
+    dst.write_i32::<BigEndian>(list_len(value.len())?)?;
+    let bytes: Vec<u8> = value.iter().map(|&v| v as u8).collect();
+    dst.write_all(&bytes).map_err(From::from)
+}
+
+#[inline]
+pub fn write_bare_int_array<W>(dst: &mut W, value: &[i32]) -> Result<()>
+   where W: io::Write
+{
+    dst.write_i32::<BigEndian>(list_len(value.len())?)?;
+    let mut bytes = Vec::with_capacity(value.len() * 4);
     for &v in value {
-        try!(dst.write_i8(v));
+        bytes.extend_from_slice(&v.to_be_bytes());
     }
-    Ok(())
+    dst.write_all(&bytes).map_err(From::from)
 }
 
 #[inline]
-pub fn write_bare_int_array<W>(dst: &mut W, value: &[i32]) -> Result<()>
+pub fn write_bare_long_array<W>(dst: &mut W, value: &[i64]) -> Result<()>
    where W: io::Write
 {
-    try!(dst.write_i32::<BigEndian>(value.len() as i32));
+    dst.write_i32::<BigEndian>(list_len(value.len())?)?;
+    let mut bytes = Vec::with_capacity(value.len() * 8);
     for &v in value {
-        try!(dst.write_i32::<BigEndian>(v));
+        bytes.extend_from_slice(&v.to_be_bytes());
     }
-    Ok(())
+    dst.write_all(&bytes).map_err(From::from)
 }
 
 #[inline]
 pub fn write_bare_string<W>(dst: &mut W, value: &str) -> Result<()>
    where W: io::Write
-{    
+{
+    if value.len() > u16::MAX as usize {
+        return Err(Error::StringTooLong(value.len()));
+    }
     try!(dst.write_u16::<BigEndian>(value.len() as u16));
     dst.write_all(value.as_bytes()).map_err(From::from)
 }
 
+/// Converts the length of a list or array to the `i32` it is written as,
+/// failing if it does not fit.
+pub(crate) fn list_len(len: usize) -> Result<i32> {
+    if len > i32::MAX as usize {
+        return Err(Error::ListTooLong(len));
+    }
+    Ok(len as i32)
+}
+
 /// Extracts the next header (tag and name) from an NBT format source.
 ///
 /// This function will also return the `TAG_End` byte and an empty name if it
@@ -159,28 +231,24 @@ pub fn read_bare_double<R>(src: &mut R) -> Result<f64>
 pub fn read_bare_byte_array<R>(src: &mut R) -> Result<Vec<i8>>
     where R: io::Read
 {
-    // FIXME: Is there a way to return [u8; len]?
-    let len = try!(src.read_i32::<BigEndian>()) as usize;
-    let mut buf = Vec::with_capacity(len);
-    // FIXME: Test performance vs transmute.
-    for _ in 0..len {
-        buf.push(try!(src.read_i8()));
-    }
-    Ok(buf)
+    let len = src.read_i32::<BigEndian>()? as usize;
+    read_bytes_i8(src, len)
 }
 
 #[inline]
 pub fn read_bare_int_array<R>(src: &mut R) -> Result<Vec<i32>>
     where R: io::Read
 {
-    // FIXME: Is there a way to return [i32; len]?
-    let len = try!(src.read_i32::<BigEndian>()) as usize;
-    let mut buf = Vec::with_capacity(len);
-    // FIXME: Test performance vs transmute.
-    for _ in 0..len {
-        buf.push(try!(src.read_i32::<BigEndian>()));
-    }
-    Ok(buf)
+    let len = src.read_i32::<BigEndian>()? as usize;
+    read_bytes_i32::<BigEndian, _>(src, len)
+}
+
+#[inline]
+pub fn read_bare_long_array<R>(src: &mut R) -> Result<Vec<i64>>
+    where R: io::Read
+{
+    let len = src.read_i32::<BigEndian>()? as usize;
+    read_bytes_i64::<BigEndian, _>(src, len)
 }
 
 #[inline]
@@ -191,14 +259,55 @@ pub fn read_bare_string<R>(src: &mut R) -> Result<String>
 
     if len == 0 { return Ok("".to_string()); }
 
-    let mut bytes = vec![0; len];
-    let mut n_read = 0usize;
-    while n_read < bytes.len() {
-        match try!(src.read(&mut bytes[n_read..])) {
-            0 => return Err(Error::IncompleteNbtValue),
-            n => n_read += n
-        }
-    }
-
+    let bytes = read_bytes(src, len)?;
     String::from_utf8(bytes).map_err(From::from)
 }
+
+/// Reads past the payload of a tag with the given type ID without decoding
+/// it. Like `Value::from_reader`, this fails with `Error::DepthLimitExceeded`
+/// on lists and compounds nested deeper than the default `max_depth`.
+pub fn skip_payload<R>(id: u8, src: &mut R) -> Result<()>
+    where R: io::Read
+{
+    skip_nested(id, src, 0, DecoderOptions::default().max_depth)
+}
+
+fn skip_nested<R>(id: u8, src: &mut R, depth: usize, max_depth: usize) -> Result<()>
+    where R: io::Read
+{
+    if (id == 0x09 || id == 0x0a) && depth > max_depth {
+        return Err(Error::DepthLimitExceeded(max_depth));
+    }
+    let len = match id {
+        0x01 => 1,
+        0x02 => 2,
+        0x03 | 0x05 => 4,
+        0x04 | 0x06 => 8,
+        0x07 => src.read_i32::<BigEndian>()?.max(0) as u64,
+        0x0b => src.read_i32::<BigEndian>()?.max(0) as u64 * 4,
+        0x0c => src.read_i32::<BigEndian>()?.max(0) as u64 * 8,
+        0x08 => src.read_u16::<BigEndian>()? as u64,
+        0x09 => {
+            let elem = src.read_u8()?;
+            for _ in 0..src.read_i32::<BigEndian>()? {
+                skip_nested(elem, src, depth + 1, max_depth)?;
+            }
+            0
+        },
+        0x0a => {
+            loop {
+                let (id, _) = emit_next_header(src)?;
+                if id == 0x00 {
+                    break;
+                }
+                skip_nested(id, src, depth + 1, max_depth)?;
+            }
+            0
+        },
+        e => return Err(Error::InvalidTypeId(e)),
+    };
+    if io::copy(&mut io::Read::take(src.by_ref(), len), &mut io::sink())? != len {
+        return Err(Error::IncompleteNbtValue);
+    }
+    Ok(())
+}
@@ -0,0 +1,537 @@
+//! Editing chunks in region (`.mca`) files in place.
+//!
+//! A region file holds up to 32×32 chunks. It starts with two 4 KiB tables,
+//! giving the sector offset and sector count of each chunk and the time it
+//! was last saved, followed by the chunks themselves in 4 KiB sectors. Each
+//! chunk is stored as its length, a compression byte, and the compressed
+//! document.
+//!
+//! A `RegionEditor` keeps the chunks that were changed in memory, and `save`
+//! writes only those back. A changed chunk reuses its old sectors when it
+//! still fits in them and moves to the first free run of sectors otherwise.
+//! Every other byte of the file, including the other chunks, is left exactly
+//! as it was.
+//!
+//! `compact` goes further, moving every chunk down to close the gaps and
+//! shortening the file.
+//!
+//! `iter_chunks` reads every chunk in turn, `par_iter_chunks` decodes them on
+//! several threads, and `chunk` gives the decompressed bytes of one for
+//! decoding some other way.
+//!
+//! A chunk too large for the 255 sectors the offset table allows, about
+//! 1 MiB, is stored in a separate `c.<x>.<z>.mcc` file next to the region
+//! file, named by its absolute chunk coordinates, and only its compression
+//! byte with the top bit set is kept in the region. Such chunks are read and
+//! written transparently, which needs the region file to have its usual
+//! `r.<x>.<z>.mca` name.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use batch::Document;
+use compression::{Registry, CUSTOM_REGION_ID};
+use error::{Error, Result};
+use normalize;
+use parallel;
+use raw;
+use value::Value;
+
+const SECTOR: usize = 4096;
+const CHUNKS: usize = 1024;
+/// The largest number of sectors a chunk can be given in the offset table.
+const MAX_SECTORS: usize = 255;
+/// The flag set on the compression byte of a chunk stored in a `.mcc` file.
+const EXTERNAL: u8 = 0x80;
+
+fn index(x: i32, z: i32) -> usize {
+    ((x & 31) + (z & 31) * 32) as usize
+}
+
+/// Reads and edits the chunks of one region file.
+#[derive(Debug)]
+pub struct RegionEditor {
+    file: File,
+    path: PathBuf,
+    /// The region coordinates given by the file name, if it has the usual
+    /// form.
+    origin: Option<(i32, i32)>,
+    /// The sector offset and sector count of each chunk.
+    locations: Vec<(usize, usize)>,
+    timestamps: Vec<u32>,
+    /// The compression byte and payload of each changed chunk, or `None` for
+    /// a removed one. Whether it goes in a `.mcc` file is decided on saving.
+    dirty: BTreeMap<usize, Option<Vec<u8>>>,
+    registry: Registry,
+}
+
+impl RegionEditor {
+    /// Opens a region file for editing, creating it if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<RegionEditor> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(&[0; 2 * SECTOR])?;
+        }
+        RegionEditor::from_file(file, path)
+    }
+
+    /// Opens an existing region file for reading only. An empty file, as the
+    /// game sometimes leaves behind, has no chunks. Saving any changes fails.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<RegionEditor> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        if file.metadata()?.len() == 0 {
+            return Ok(RegionEditor {
+                file,
+                path: path.to_path_buf(),
+                origin: origin(path),
+                locations: vec![(0, 0); CHUNKS],
+                timestamps: vec![0; CHUNKS],
+                dirty: BTreeMap::new(),
+                registry: Registry::new(),
+            });
+        }
+        RegionEditor::from_file(file, path)
+    }
+
+    fn from_file(mut file: File, path: &Path) -> Result<RegionEditor> {
+        file.seek(SeekFrom::Start(0))?;
+        let mut locations = Vec::with_capacity(CHUNKS);
+        for _ in 0..CHUNKS {
+            let entry = file.read_u32::<BigEndian>()?;
+            locations.push(((entry >> 8) as usize, (entry & 0xff) as usize));
+        }
+        let mut timestamps = Vec::with_capacity(CHUNKS);
+        for _ in 0..CHUNKS {
+            timestamps.push(file.read_u32::<BigEndian>()?);
+        }
+        Ok(RegionEditor {
+            file,
+            path: path.to_path_buf(),
+            origin: origin(path),
+            locations,
+            timestamps,
+            dirty: BTreeMap::new(),
+            registry: Registry::new(),
+        })
+    }
+
+    /// Registers an extra compression scheme for reading and writing chunks.
+    pub fn registry(&mut self) -> &mut Registry {
+        &mut self.registry
+    }
+
+    /// The time the chunk at the given chunk coordinates was last saved, in
+    /// seconds since the Unix epoch, or 0 if it has never been.
+    pub fn timestamp(&self, x: i32, z: i32) -> u32 {
+        self.timestamps[index(x, z)]
+    }
+
+    /// The coordinates, relative to the region, of the chunks present,
+    /// including any unsaved changes.
+    pub fn chunks(&self) -> Vec<(i32, i32)> {
+        (0..CHUNKS)
+            .filter(|i| match self.dirty.get(i) {
+                Some(stored) => stored.is_some(),
+                None => self.locations[*i].0 != 0,
+            })
+            .map(|i| ((i % 32) as i32, (i / 32) as i32))
+            .collect()
+    }
+
+    /// The coordinates, relative to the region, of the chunks changed since
+    /// the last save.
+    pub fn dirty_chunks(&self) -> Vec<(i32, i32)> {
+        self.dirty.keys().map(|&i| ((i % 32) as i32, (i / 32) as i32)).collect()
+    }
+
+    /// Reads the chunk at the given chunk coordinates, including any unsaved
+    /// changes. Only the low five bits of each coordinate are used.
+    pub fn read_chunk(&mut self, x: i32, z: i32) -> Result<Option<Document>> {
+        match self.read_data(index(x, z))? {
+            Some((compression, data)) => parse(compression, &data).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The decompressed bytes of the chunk at the given chunk coordinates,
+    /// for decoding with something other than `Value`, such as nbt-serde.
+    pub fn chunk(&mut self, x: i32, z: i32) -> Result<Option<impl Read>> {
+        Ok(self.read_data(index(x, z))?.map(|(_, data)| io::Cursor::new(data)))
+    }
+
+    /// Reads every chunk present, including any unsaved changes, in the order
+    /// of `chunks`. A chunk that cannot be read does not stop the others.
+    pub fn iter_chunks(&mut self) -> Chunks<'_> {
+        let coords = self.chunks().into_iter();
+        Chunks { editor: self, coords }
+    }
+
+    /// Reads every chunk present like `iter_chunks`, but decompresses and
+    /// decodes them on a pool of worker threads, one per available CPU. The
+    /// file itself is still read on the calling thread.
+    pub fn par_iter_chunks(&mut self) -> Vec<((i32, i32), Result<Document>)> {
+        let mut stored = Vec::new();
+        for (x, z) in self.chunks() {
+            let i = index(x, z);
+            match self.read_payload(i) {
+                Ok(Some(payload)) => stored.push((i, Ok(payload))),
+                Ok(None) => (),
+                Err(e) => stored.push((i, Err(e))),
+            }
+        }
+
+        let results = Mutex::new(Vec::with_capacity(stored.len()));
+        parallel::run(stored, |(i, payload): (usize, Result<Vec<u8>>)| {
+            let chunk = payload
+                .and_then(|payload| self.decompress(i, payload))
+                .and_then(|(compression, data)| parse(compression, &data));
+            results.lock().unwrap().push((i, chunk));
+        });
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|r| r.0);
+        results.into_iter().map(|(i, chunk)| (((i % 32) as i32, (i / 32) as i32), chunk)).collect()
+    }
+
+    /// Reads and decompresses a chunk, returning the name of its compression
+    /// scheme and its data.
+    fn read_data(&mut self, i: usize) -> Result<Option<(String, Vec<u8>)>> {
+        match self.read_payload(i)? {
+            Some(payload) => self.decompress(i, payload).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the compression byte and payload of a chunk, including any
+    /// unsaved changes.
+    fn read_payload(&mut self, i: usize) -> Result<Option<Vec<u8>>> {
+        match self.dirty.get(&i) {
+            Some(stored) => Ok(stored.clone()),
+            None => self.read_stored(i),
+        }
+    }
+
+    /// Decompresses a chunk read by `read_payload`, returning the name of
+    /// its compression scheme and its data.
+    fn decompress(&self, i: usize, mut payload: Vec<u8>) -> Result<(String, Vec<u8>)> {
+        if payload.is_empty() {
+            return Err(Error::IncompleteNbtValue);
+        }
+        let id = payload.remove(0);
+        if id & EXTERNAL != 0 {
+            payload = fs::read(self.external_path(i)?)?;
+        }
+        let compression = if id & 0x7f == CUSTOM_REGION_ID {
+            raw::read_bare_string(&mut &payload[..])?
+        } else {
+            match self.registry.by_region_id(id & 0x7f) {
+                Some(codec) => codec.name().to_string(),
+                None => return Err(Error::UnknownCompression(format!("region compression {}", id))),
+            }
+        };
+        let data = self.registry.decompress_region(id, &payload)?;
+        Ok((compression, data))
+    }
+
+    /// Reads the compression byte and payload of a chunk as stored in the
+    /// file.
+    fn read_stored(&mut self, i: usize) -> Result<Option<Vec<u8>>> {
+        let (offset, count) = self.locations[i];
+        if offset == 0 || count == 0 {
+            return Ok(None);
+        }
+        self.file.seek(SeekFrom::Start((offset * SECTOR) as u64))?;
+        let len = self.file.read_u32::<BigEndian>()? as usize;
+        if len == 0 || len + 4 > count * SECTOR {
+            return Err(Error::IncompleteNbtValue);
+        }
+        let mut stored = vec![0; len];
+        self.file.read_exact(&mut stored)?;
+        Ok(Some(stored))
+    }
+
+    /// How the chunk at the given chunk coordinates is stored in the file,
+    /// ignoring any unsaved changes.
+    pub fn chunk_info(&mut self, x: i32, z: i32) -> Result<Option<ChunkInfo>> {
+        let i = index(x, z);
+        let stored = match self.read_stored(i)? {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+        let id = stored[0];
+        let compression = if id & 0x7f == CUSTOM_REGION_ID {
+            if id & EXTERNAL != 0 {
+                raw::read_bare_string(&mut &fs::read(self.external_path(i)?)?[..])?
+            } else {
+                raw::read_bare_string(&mut &stored[1..])?
+            }
+        } else {
+            match self.registry.by_region_id(id & 0x7f) {
+                Some(codec) => codec.name().to_string(),
+                None => format!("region compression {}", id & 0x7f),
+            }
+        };
+        Ok(Some(ChunkInfo {
+            length: stored.len(),
+            sectors: self.locations[i].1,
+            compression,
+            external: id & EXTERNAL != 0,
+            timestamp: self.timestamps[i],
+        }))
+    }
+
+    /// The number of sectors after the tables that no chunk uses, ignoring
+    /// any unsaved changes. `compact` would free these.
+    pub fn free_sectors(&self) -> Result<usize> {
+        let end = (self.file.metadata()?.len() as usize).div_ceil(SECTOR).max(2);
+        let mut used = vec![false; end];
+        for &(offset, count) in &self.locations {
+            if offset != 0 {
+                mark(&mut used, offset, count, true);
+            }
+        }
+        Ok(used[2..].iter().filter(|&&u| !u).count())
+    }
+
+    /// Replaces the chunk at the given chunk coordinates. The chunk is
+    /// compressed with the scheme named by the document, and written out by
+    /// the next `save`.
+    pub fn write_chunk(&mut self, x: i32, z: i32, chunk: &Document) -> Result<()> {
+        let mut encoded = Vec::new();
+        normalize::write_canonical(&mut encoded, &chunk.title, &chunk.value)?;
+        self.write_chunk_data(x, z, &chunk.compression, &encoded)
+    }
+
+    /// Like `write_chunk`, but taking the chunk already encoded, as returned
+    /// by `chunk`, and the name of the scheme to compress it with.
+    pub fn write_chunk_data(&mut self, x: i32, z: i32, compression: &str, data: &[u8]) -> Result<()> {
+        let (id, payload) = self.registry.compress_region(compression, data)?;
+        let mut stored = Vec::with_capacity(payload.len() + 1);
+        stored.push(id);
+        stored.extend_from_slice(&payload);
+        if (stored.len() + 4).div_ceil(SECTOR) > MAX_SECTORS {
+            // Fail now rather than on saving.
+            self.external_path(index(x, z))?;
+        }
+        self.dirty.insert(index(x, z), Some(stored));
+        Ok(())
+    }
+
+    /// Removes the chunk at the given chunk coordinates at the next `save`.
+    pub fn remove_chunk(&mut self, x: i32, z: i32) {
+        self.dirty.insert(index(x, z), None);
+    }
+
+    /// Writes every changed chunk to the file, returning how many there
+    /// were.
+    pub fn save(&mut self) -> Result<usize> {
+        if self.dirty.is_empty() {
+            return Ok(0);
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as u32);
+        let end = self.file.metadata()?.len() as usize;
+        let mut used = vec![false; end.div_ceil(SECTOR).max(2)];
+        used[0] = true;
+        used[1] = true;
+        for &(offset, count) in &self.locations {
+            if offset != 0 {
+                mark(&mut used, offset, count, true);
+            }
+        }
+
+        let dirty = ::std::mem::take(&mut self.dirty);
+        let saved = dirty.len();
+        for (i, stored) in dirty {
+            let (old_offset, old_count) = self.locations[i];
+            let mut stored = match stored {
+                Some(stored) => stored,
+                None => {
+                    self.remove_external(i)?;
+                    mark(&mut used, old_offset, old_count, false);
+                    self.locations[i] = (0, 0);
+                    self.timestamps[i] = 0;
+                    self.write_entry(i)?;
+                    continue;
+                },
+            };
+            if (stored.len() + 4).div_ceil(SECTOR) > MAX_SECTORS {
+                fs::write(self.external_path(i)?, &stored[1..])?;
+                stored = vec![stored[0] | EXTERNAL];
+            } else {
+                self.remove_external(i)?;
+            }
+            let count = (stored.len() + 4).div_ceil(SECTOR);
+            let offset = if old_offset != 0 && count <= old_count {
+                mark(&mut used, old_offset + count, old_count - count, false);
+                old_offset
+            } else {
+                // The old sectors may be part of a larger free run.
+                if old_offset != 0 {
+                    mark(&mut used, old_offset, old_count, false);
+                }
+                find_free(&used, count)
+            };
+            mark(&mut used, offset, count, true);
+
+            let mut sectors = Vec::with_capacity(count * SECTOR);
+            sectors.write_u32::<BigEndian>(stored.len() as u32)?;
+            sectors.extend_from_slice(&stored);
+            sectors.resize(count * SECTOR, 0);
+            self.file.seek(SeekFrom::Start((offset * SECTOR) as u64))?;
+            self.file.write_all(&sectors)?;
+
+            self.locations[i] = (offset, count);
+            self.timestamps[i] = now;
+            self.write_entry(i)?;
+        }
+        self.file.sync_all()?;
+        Ok(saved)
+    }
+
+    /// Saves any changes, then moves every chunk down to close the gaps
+    /// left by chunks that were removed or moved, and shortens the file.
+    /// Returns how many sectors were freed.
+    ///
+    /// Unlike `save`, this rewrites most of the file.
+    pub fn compact(&mut self) -> Result<usize> {
+        self.save()?;
+        let end = (self.file.metadata()?.len() as usize).div_ceil(SECTOR);
+        let mut order: Vec<usize> = (0..CHUNKS).filter(|&i| self.locations[i].0 != 0).collect();
+        order.sort_by_key(|&i| self.locations[i].0);
+        let mut next = 2;
+        for i in order {
+            let (offset, count) = self.locations[i];
+            if offset != next {
+                let mut sectors = vec![0; count * SECTOR];
+                self.file.seek(SeekFrom::Start((offset * SECTOR) as u64))?;
+                self.file.read_exact(&mut sectors)?;
+                self.file.seek(SeekFrom::Start((next * SECTOR) as u64))?;
+                self.file.write_all(&sectors)?;
+                self.locations[i] = (next, count);
+                self.write_entry(i)?;
+            }
+            next += count;
+        }
+        self.file.set_len((next * SECTOR) as u64)?;
+        self.file.sync_all()?;
+        Ok(end.saturating_sub(next))
+    }
+
+    /// The `.mcc` file for the chunk at index `i`.
+    fn external_path(&self, i: usize) -> Result<PathBuf> {
+        let (rx, rz) = match self.origin {
+            Some(origin) => origin,
+            None => return Err(Error::IoError(io::Error::new(io::ErrorKind::InvalidInput,
+                "chunk is stored in a .mcc file, but the region file name does not give its coordinates"))),
+        };
+        let (x, z) = (rx * 32 + (i % 32) as i32, rz * 32 + (i / 32) as i32);
+        Ok(self.path.with_file_name(format!("c.{}.{}.mcc", x, z)))
+    }
+
+    /// Deletes the `.mcc` file of a chunk that is no longer stored in one.
+    fn remove_external(&self, i: usize) -> Result<()> {
+        if self.origin.is_none() {
+            return Ok(());
+        }
+        match fs::remove_file(self.external_path(i)?) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    /// Writes the offset table and timestamp entries of one chunk.
+    fn write_entry(&mut self, i: usize) -> Result<()> {
+        let (offset, count) = self.locations[i];
+        self.file.seek(SeekFrom::Start((i * 4) as u64))?;
+        self.file.write_u32::<BigEndian>(((offset as u32) << 8) | count as u32)?;
+        self.file.seek(SeekFrom::Start((SECTOR + i * 4) as u64))?;
+        self.file.write_u32::<BigEndian>(self.timestamps[i])?;
+        Ok(())
+    }
+}
+
+/// How a chunk is stored, as returned by `RegionEditor::chunk_info`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkInfo {
+    /// The length of the stored chunk in bytes, including its compression
+    /// byte but not its four byte length.
+    pub length: usize,
+    /// The number of sectors given to the chunk.
+    pub sectors: usize,
+    /// The name of the compression scheme.
+    pub compression: String,
+    /// Whether the chunk is stored in a separate `.mcc` file, leaving only
+    /// its compression byte in the region.
+    pub external: bool,
+    /// The time the chunk was last saved, as given by `timestamp`.
+    pub timestamp: u32,
+}
+
+/// An iterator over the chunks of a region, returned by
+/// `RegionEditor::iter_chunks`.
+pub struct Chunks<'a> {
+    editor: &'a mut RegionEditor,
+    coords: ::std::vec::IntoIter<(i32, i32)>,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = ((i32, i32), Result<Document>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (x, z) in &mut self.coords {
+            match self.editor.read_chunk(x, z) {
+                Ok(Some(chunk)) => return Some(((x, z), Ok(chunk))),
+                Ok(None) => continue,
+                Err(e) => return Some(((x, z), Err(e))),
+            }
+        }
+        None
+    }
+}
+
+/// Decodes the decompressed data of a chunk.
+fn parse(compression: String, data: &[u8]) -> Result<Document> {
+    let mut src = data;
+    let (tag, title) = Value::read_header(&mut src)?;
+    let value = Value::from_reader(tag, &mut src)?;
+    Ok(Document { title, value, compression })
+}
+
+/// The region coordinates given by a file name of the form `r.<x>.<z>.mca`.
+fn origin(path: &Path) -> Option<(i32, i32)> {
+    let name = path.file_name()?.to_str()?;
+    let mut parts = name.split('.');
+    match (parts.next(), parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("r"), Some(x), Some(z), Some("mca"), None) => Some((x.parse().ok()?, z.parse().ok()?)),
+        _ => None,
+    }
+}
+
+fn mark(used: &mut Vec<bool>, offset: usize, count: usize, value: bool) {
+    if used.len() < offset + count {
+        used.resize(offset + count, false);
+    }
+    for sector in &mut used[offset..offset + count] {
+        *sector = value;
+    }
+}
+
+/// The offset of the first run of `count` free sectors, which may extend
+/// past the end of the file.
+fn find_free(used: &[bool], count: usize) -> usize {
+    let mut run = 0;
+    for (sector, &taken) in used.iter().enumerate() {
+        run = if taken { 0 } else { run + 1 };
+        if run == count {
+            return sector + 1 - count;
+        }
+    }
+    used.len() - run
+}
@@ -0,0 +1,137 @@
+//! Rewriting of block palette entries.
+//!
+//! Chunk sections (`Palette` before 1.18, `block_states.palette` since) and
+//! structure templates (`palette` and `palettes`) store block states as
+//! compounds with a `Name` string and an optional `Properties` compound. A
+//! `Remapper` walks any `Value` tree, finds these palette entries and rewrites
+//! them according to a table of `BlockMapping`s, which makes it suitable for
+//! migrating both chunks and structures across mod or game versions.
+
+use std::collections::{BTreeMap, HashMap};
+
+use value::Value;
+
+/// The replacement for a block id: a new name plus changes to its block state
+/// properties.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockMapping {
+    name: String,
+    set: Vec<(String, String)>,
+    remove: Vec<String>,
+}
+
+impl BlockMapping {
+    /// Maps a block to the block with the given name, keeping its properties.
+    pub fn new<S: Into<String>>(name: S) -> BlockMapping {
+        BlockMapping { name: name.into(), set: Vec::new(), remove: Vec::new() }
+    }
+
+    /// Sets a property on the remapped block, adding it if it is missing.
+    pub fn with_property<K, V>(mut self, key: K, value: V) -> BlockMapping
+        where K: Into<String>, V: Into<String>
+    {
+        self.set.push((key.into(), value.into()));
+        self
+    }
+
+    /// Removes a property from the remapped block, if present.
+    pub fn without_property<K: Into<String>>(mut self, key: K) -> BlockMapping {
+        self.remove.push(key.into());
+        self
+    }
+
+    fn apply(&self, entry: &mut HashMap<String, Value>) {
+        entry.insert("Name".to_string(), Value::String(self.name.clone()));
+        if self.set.is_empty() && self.remove.is_empty() {
+            return;
+        }
+        let mut props = match entry.remove("Properties") {
+            Some(Value::Compound(props)) => props,
+            _ => HashMap::new(),
+        };
+        for key in &self.remove {
+            props.remove(key);
+        }
+        for (key, value) in &self.set {
+            props.insert(key.clone(), Value::String(value.clone()));
+        }
+        if !props.is_empty() {
+            entry.insert("Properties".to_string(), Value::Compound(props));
+        }
+    }
+}
+
+/// A summary of the palette entries visited by `Remapper::apply`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RemapReport {
+    /// The number of palette entries that were rewritten.
+    pub remapped: usize,
+    /// Block names that had no mapping, with the number of palette entries
+    /// they appeared in.
+    pub unmapped: BTreeMap<String, usize>,
+}
+
+/// Rewrites block palette entries according to a mapping table keyed by the
+/// old block name.
+#[derive(Clone, Debug, Default)]
+pub struct Remapper {
+    table: HashMap<String, BlockMapping>,
+}
+
+impl Remapper {
+    /// Creates a remapper with an empty mapping table.
+    pub fn new() -> Remapper {
+        Remapper { table: HashMap::new() }
+    }
+
+    /// Adds a mapping for the block with the given name, replacing any
+    /// previous mapping for it.
+    pub fn insert<S: Into<String>>(&mut self, old: S, mapping: BlockMapping) {
+        self.table.insert(old.into(), mapping);
+    }
+
+    /// Rewrites every palette entry found in `value`, returning a report of
+    /// what was changed and which blocks had no mapping.
+    pub fn apply(&self, value: &mut Value) -> RemapReport {
+        let mut report = RemapReport::default();
+        self.visit(value, false, &mut report);
+        report
+    }
+
+    fn visit(&self, value: &mut Value, in_palette: bool, report: &mut RemapReport) {
+        match *value {
+            Value::Compound(ref mut map) => {
+                if in_palette && self.remap_entry(map, report) {
+                    return;
+                }
+                for (key, child) in map.iter_mut() {
+                    let palette = key == "Palette" || key == "palette" || key == "palettes";
+                    self.visit(child, palette, report);
+                }
+            },
+            Value::List(ref mut vals) => {
+                for child in vals {
+                    self.visit(child, in_palette, report);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Remaps a single palette entry, returning `false` if the compound does
+    /// not look like a block state.
+    fn remap_entry(&self, entry: &mut HashMap<String, Value>, report: &mut RemapReport) -> bool {
+        let name = match entry.get("Name") {
+            Some(Value::String(name)) => name.clone(),
+            _ => return false,
+        };
+        match self.table.get(&name) {
+            Some(mapping) => {
+                mapping.apply(entry);
+                report.remapped += 1;
+            },
+            None => *report.unmapped.entry(name).or_insert(0) += 1,
+        }
+        true
+    }
+}
@@ -0,0 +1,194 @@
+//! Streaming edits of documents, without decoding them into a `Value` tree.
+//!
+//! `rewrite` copies a document from a reader to a writer tag by tag, applying a
+//! set of `Rules` as it goes. Only replacement values are ever held in memory,
+//! so even very large documents can be sanitized cheaply.
+//!
+//! Rules are addressed by dotted paths from the root compound, such as
+//! `abilities.mayfly`. A list element is addressed by its index, and `*`
+//! matches any compound entry or list element, so `Inventory.*.tag` refers to
+//! the `tag` of every item in the `Inventory` list.
+
+use std::collections::HashSet;
+use std::io::{self, Read};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use error::{Error, Result};
+use raw;
+use value::{DecoderOptions, Value};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Edit {
+    Set(Value),
+    Delete,
+    Rename(String),
+}
+
+/// A set of path-based edits to apply with `rewrite`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rules {
+    rules: Vec<(Vec<String>, Edit)>,
+}
+
+fn split(path: &str) -> Vec<String> {
+    path.split('.').map(|s| s.to_string()).collect()
+}
+
+fn matches(pattern: &[String], path: &[String]) -> bool {
+    pattern.len() == path.len() && pattern.iter().zip(path).all(|(p, s)| p == "*" || p == s)
+}
+
+impl Rules {
+    /// Creates an empty set of rules.
+    pub fn new() -> Rules {
+        Rules { rules: Vec::new() }
+    }
+
+    /// Sets the tag at `path` to `value`. Compound entries are added if they
+    /// are missing, as long as the path has no wildcard in its last segment
+    /// and the compound containing them exists. List elements must keep the
+    /// type of the list.
+    pub fn set<V: Into<Value>>(mut self, path: &str, value: V) -> Rules {
+        self.rules.push((split(path), Edit::Set(value.into())));
+        self
+    }
+
+    /// Removes the compound entry at `path`.
+    pub fn delete(mut self, path: &str) -> Rules {
+        self.rules.push((split(path), Edit::Delete));
+        self
+    }
+
+    /// Renames the compound entry at `path`, keeping its value.
+    pub fn rename<S: Into<String>>(mut self, path: &str, name: S) -> Rules {
+        self.rules.push((split(path), Edit::Rename(name.into())));
+        self
+    }
+
+    /// The first edit for the tag at `path`, if any.
+    fn find(&self, path: &[String]) -> Option<&Edit> {
+        self.rules.iter().find(|&(pattern, _)| matches(pattern, path)).map(|(_, edit)| edit)
+    }
+}
+
+/// Copies a document from `src` to `dst`, applying `rules` on the way, and
+/// returns the number of edits made.
+pub fn rewrite<R, W>(src: &mut R, dst: &mut W, rules: &Rules) -> Result<usize>
+    where R: io::Read, W: io::Write
+{
+    let (id, title) = raw::emit_next_header(src)?;
+    dst.write_u8(id)?;
+    raw::write_bare_string(dst, &title)?;
+    let mut edits = 0;
+    copy(id, src, dst, &mut Vec::new(), rules, &mut edits)?;
+    Ok(edits)
+}
+
+fn copy<R, W>(id: u8, src: &mut R, dst: &mut W, path: &mut Vec<String>,
+              rules: &Rules, edits: &mut usize) -> Result<()>
+    where R: io::Read, W: io::Write
+{
+    // The path holds one segment for each list or compound the tag is in.
+    let max_depth = DecoderOptions::default().max_depth;
+    if (id == 0x09 || id == 0x0a) && path.len() > max_depth {
+        return Err(Error::DepthLimitExceeded(max_depth));
+    }
+    match id {
+        0x01 => copy_bytes(src, dst, 1),
+        0x02 => copy_bytes(src, dst, 2),
+        0x03 | 0x05 => copy_bytes(src, dst, 4),
+        0x04 | 0x06 => copy_bytes(src, dst, 8),
+        0x07 | 0x0b | 0x0c => {
+            let len = src.read_i32::<BigEndian>()?;
+            dst.write_i32::<BigEndian>(len)?;
+            let size = match id { 0x07 => 1, 0x0b => 4, _ => 8 };
+            copy_bytes(src, dst, len.max(0) as u64 * size)
+        },
+        0x08 => {
+            let len = src.read_u16::<BigEndian>()?;
+            dst.write_u16::<BigEndian>(len)?;
+            copy_bytes(src, dst, len as u64)
+        },
+        0x09 => {
+            let elem = src.read_u8()?;
+            let len = src.read_i32::<BigEndian>()?;
+            dst.write_u8(elem)?;
+            dst.write_i32::<BigEndian>(len)?;
+            for i in 0..len.max(0) {
+                path.push(i.to_string());
+                match rules.find(path) {
+                    Some(Edit::Set(value)) => {
+                        if value.id() != elem {
+                            return Err(Error::HeterogeneousList);
+                        }
+                        raw::skip_payload(elem, src)?;
+                        value.write(dst)?;
+                        *edits += 1;
+                    },
+                    _ => copy(elem, src, dst, path, rules, edits)?,
+                }
+                path.pop();
+            }
+            Ok(())
+        },
+        0x0a => {
+            let mut seen = HashSet::new();
+            loop {
+                let (id, name) = raw::emit_next_header(src)?;
+                if id == 0x00 {
+                    break;
+                }
+                path.push(name);
+                match rules.find(path) {
+                    Some(Edit::Set(value)) => {
+                        raw::skip_payload(id, src)?;
+                        value.write_header(dst, &path[path.len() - 1])?;
+                        value.write(dst)?;
+                        *edits += 1;
+                    },
+                    Some(Edit::Delete) => {
+                        raw::skip_payload(id, src)?;
+                        *edits += 1;
+                    },
+                    Some(Edit::Rename(new_name)) => {
+                        dst.write_u8(id)?;
+                        raw::write_bare_string(dst, new_name)?;
+                        copy(id, src, dst, path, rules, edits)?;
+                        *edits += 1;
+                    },
+                    None => {
+                        dst.write_u8(id)?;
+                        raw::write_bare_string(dst, &path[path.len() - 1])?;
+                        copy(id, src, dst, path, rules, edits)?;
+                    },
+                }
+                seen.insert(path.pop().unwrap());
+            }
+            // Add any entries that were set but not already present.
+            for (pattern, edit) in &rules.rules {
+                let value = match *edit {
+                    Edit::Set(ref value) => value,
+                    _ => continue,
+                };
+                let (name, parent) = pattern.split_last().unwrap();
+                if name != "*" && matches(parent, path) && seen.insert(name.clone()) {
+                    value.write_header(dst, name)?;
+                    value.write(dst)?;
+                    *edits += 1;
+                }
+            }
+            raw::close_nbt(dst)
+        },
+        e => Err(Error::InvalidTypeId(e)),
+    }
+}
+
+fn copy_bytes<R, W>(src: &mut R, dst: &mut W, len: u64) -> Result<()>
+    where R: io::Read, W: io::Write
+{
+    if io::copy(&mut src.take(len), dst)? != len {
+        return Err(Error::IncompleteNbtValue);
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! Decoding from a shared buffer, for spreading the work over threads.
+//!
+//! A `SharedTag` is a view of the payload of one tag in a reference-counted
+//! buffer. Views are cheap to clone and can be sent to other threads, and the
+//! views of a compound's entries or a list's elements are found by skipping
+//! over the bytes without decoding them. A large document can therefore be
+//! split into pieces which are decoded in parallel, all without copying the
+//! buffer.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use error::{Error, Result};
+use raw;
+use value::Value;
+
+/// A cheaply cloneable view of part of a shared buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SharedBytes {
+    buf: Arc<[u8]>,
+    range: Range<usize>,
+}
+
+impl SharedBytes {
+    /// A view of the whole of a buffer.
+    pub fn new(buf: Arc<[u8]>) -> SharedBytes {
+        let range = 0..buf.len();
+        SharedBytes { buf, range }
+    }
+
+    /// A view of part of this view, such as one chunk's payload in a region
+    /// file. Panics if the range is out of bounds, as slicing does.
+    pub fn slice(&self, range: Range<usize>) -> SharedBytes {
+        assert!(range.start <= range.end && range.end <= self.len(), "range out of bounds");
+        let start = self.range.start;
+        SharedBytes { buf: self.buf.clone(), range: start + range.start..start + range.end }
+    }
+
+    /// The bytes in view.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[self.range.clone()]
+    }
+
+    /// The number of bytes in view.
+    pub fn len(&self) -> usize {
+        self.range.len()
+    }
+
+    /// Whether there are no bytes in view.
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+}
+
+impl From<Vec<u8>> for SharedBytes {
+    fn from(buf: Vec<u8>) -> SharedBytes {
+        SharedBytes::new(buf.into())
+    }
+}
+
+/// The undecoded payload of a tag in a shared buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SharedTag {
+    id: u8,
+    bytes: SharedBytes,
+}
+
+impl SharedTag {
+    /// Finds the root tag of a document, returning its name and payload.
+    pub fn root(bytes: SharedBytes) -> Result<(String, SharedTag)> {
+        let mut src = bytes.as_slice();
+        let (id, title) = raw::emit_next_header(&mut src)?;
+        let start = bytes.len() - src.len();
+        raw::skip_payload(id, &mut src)?;
+        let end = bytes.len() - src.len();
+        Ok((title, SharedTag { id, bytes: bytes.slice(start..end) }))
+    }
+
+    /// The type ID of the tag.
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// The encoded payload of the tag.
+    pub fn bytes(&self) -> &SharedBytes {
+        &self.bytes
+    }
+
+    /// Decodes the tag.
+    pub fn decode(&self) -> Result<Value> {
+        Value::from_reader(self.id, &mut self.bytes.as_slice())
+    }
+
+    /// The entries of a compound, in the order they are stored.
+    pub fn entries(&self) -> Result<Vec<(String, SharedTag)>> {
+        if self.id != 0x0a {
+            return Err(Error::TagMismatch(self.id, 0x0a));
+        }
+        let total = self.bytes.len();
+        let mut src = self.bytes.as_slice();
+        let mut entries = Vec::new();
+        loop {
+            let (id, name) = raw::emit_next_header(&mut src)?;
+            if id == 0x00 {
+                return Ok(entries);
+            }
+            let start = total - src.len();
+            raw::skip_payload(id, &mut src)?;
+            let bytes = self.bytes.slice(start..total - src.len());
+            entries.push((name, SharedTag { id, bytes }));
+        }
+    }
+
+    /// The elements of a list.
+    pub fn elements(&self) -> Result<Vec<SharedTag>> {
+        if self.id != 0x09 {
+            return Err(Error::TagMismatch(self.id, 0x09));
+        }
+        let total = self.bytes.len();
+        let mut src = self.bytes.as_slice();
+        let id = raw::read_bare_byte(&mut src)? as u8;
+        let len = raw::read_bare_int(&mut src)?;
+        let mut elements = Vec::with_capacity(len.clamp(0, 1024) as usize);
+        for _ in 0..len.max(0) {
+            let start = total - src.len();
+            raw::skip_payload(id, &mut src)?;
+            elements.push(SharedTag { id, bytes: self.bytes.slice(start..total - src.len()) });
+        }
+        Ok(elements)
+    }
+}
@@ -0,0 +1,251 @@
+//! Writing `Value` trees as stringified NBT (SNBT), the text form used in
+//! Minecraft commands.
+//!
+//! Output is compact, with no spaces, and compound keys are written in sorted
+//! order so that it is stable. Numbers carry the suffixes the game expects
+//! (`b`, `s`, `L`, `f` and `d`), typed arrays are written as `[B;...]`,
+//! `[I;...]` and `[L;...]`, and strings are quoted the way the game quotes them: with double
+//! quotes, unless the string contains a double quote and no single quote.
+//! Only the quote and backslash are escaped, since versions before 1.21.5
+//! reject any other escape. Non-finite floats have no SNBT form and are
+//! written as Java writes them, which the game reads back as a string.
+//!
+//! `to_string_pretty` spreads compounds and lists over several lines for
+//! reading, keeping lists of numbers or strings and typed arrays on one line.
+//!
+//! `GiveCommand` wraps an `ItemStack` in a `/give` command that can be pasted
+//! into the game or a datapack function.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use mc::item::{ItemData, ItemStack};
+use value::Value;
+
+/// Renders a `Value` as compact SNBT.
+pub fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value);
+    out
+}
+
+/// Renders a `Value` as SNBT spread over several lines, indenting each level
+/// by `indent` spaces.
+pub fn to_string_pretty(value: &Value, indent: usize) -> String {
+    let mut out = String::new();
+    write_pretty(&mut out, value, indent, 0);
+    out
+}
+
+fn is_nested(value: &Value) -> bool {
+    match *value {
+        Value::List(ref v) => !v.is_empty(),
+        Value::Compound(ref v) => !v.is_empty(),
+        _ => false,
+    }
+}
+
+fn write_pretty(out: &mut String, value: &Value, indent: usize, depth: usize) {
+    let pad = |out: &mut String, depth: usize| out.extend((0..indent * depth).map(|_| ' '));
+    match *value {
+        Value::List(ref v) if v.iter().any(is_nested) => {
+            out.push_str("[\n");
+            for (i, element) in v.iter().enumerate() {
+                pad(out, depth + 1);
+                write_pretty(out, element, indent, depth + 1);
+                out.push_str(if i + 1 < v.len() { ",\n" } else { "\n" });
+            }
+            pad(out, depth);
+            out.push(']');
+        },
+        Value::List(ref v) => {
+            out.push('[');
+            for (i, element) in v.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_value(out, element);
+            }
+            out.push(']');
+        },
+        Value::Compound(ref v) if !v.is_empty() => {
+            out.push_str("{\n");
+            let keys = sorted_keys(v);
+            for (i, key) in keys.iter().enumerate() {
+                pad(out, depth + 1);
+                write_key(out, key);
+                out.push_str(": ");
+                write_pretty(out, &v[*key], indent, depth + 1);
+                out.push_str(if i + 1 < keys.len() { ",\n" } else { "\n" });
+            }
+            pad(out, depth);
+            out.push('}');
+        },
+        _ => write_value(out, value),
+    }
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match *value {
+        Value::Byte(v)   => { let _ = write!(out, "{}b", v); },
+        Value::Short(v)  => { let _ = write!(out, "{}s", v); },
+        Value::Int(v)    => { let _ = write!(out, "{}", v); },
+        Value::Long(v)   => { let _ = write!(out, "{}L", v); },
+        Value::Float(v)  => write_float(out, v as f64, format!("{:?}", v), 'f'),
+        Value::Double(v) => write_float(out, v, format!("{:?}", v), 'd'),
+        Value::ByteArray(ref v) => write_array(out, "B;", v.iter().map(|b| format!("{}b", b))),
+        Value::IntArray(ref v) => write_array(out, "I;", v.iter().map(|i| i.to_string())),
+        Value::LongArray(ref v) => write_array(out, "L;", v.iter().map(|l| format!("{}L", l))),
+        Value::String(ref v) => write_string(out, v),
+        Value::List(ref v) => {
+            out.push('[');
+            for (i, element) in v.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, element);
+            }
+            out.push(']');
+        },
+        Value::Compound(ref v) => write_compound(out, v),
+    }
+}
+
+fn sorted_keys(map: &HashMap<String, Value>) -> Vec<&String> {
+    let mut keys: Vec<_> = map.keys().collect();
+    keys.sort();
+    keys
+}
+
+fn write_compound(out: &mut String, map: &HashMap<String, Value>) {
+    out.push('{');
+    for (i, key) in sorted_keys(map).into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_key(out, key);
+        out.push(':');
+        write_value(out, &map[key]);
+    }
+    out.push('}');
+}
+
+#[doc(hidden)]
+pub fn write_float(out: &mut String, v: f64, finite: String, suffix: char) {
+    if v.is_nan() {
+        out.push_str("NaN");
+    } else if v.is_infinite() {
+        out.push_str(if v > 0.0 { "Infinity" } else { "-Infinity" });
+    } else {
+        out.push_str(&finite);
+    }
+    out.push(suffix);
+}
+
+fn write_array<I: Iterator<Item = String>>(out: &mut String, prefix: &str, elements: I) {
+    out.push('[');
+    out.push_str(prefix);
+    for (i, element) in elements.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(&element);
+    }
+    out.push(']');
+}
+
+/// Whether a string can be written without quotes, as a key.
+fn is_plain(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "_-.+".contains(c))
+}
+
+#[doc(hidden)]
+pub fn write_key(out: &mut String, key: &str) {
+    if is_plain(key) {
+        out.push_str(key);
+    } else {
+        write_string(out, key);
+    }
+}
+
+/// Quotes a string as the game does: the first quote character found picks
+/// the other one, and double quotes are used if there is none.
+#[doc(hidden)]
+pub fn write_string(out: &mut String, s: &str) {
+    let quote = match s.chars().find(|&c| c == '"' || c == '\'') {
+        Some('"') => '\'',
+        _ => '"',
+    };
+    out.push(quote);
+    for c in s.chars() {
+        if c == quote || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push(quote);
+}
+
+/// Builds `/give` commands for item stacks.
+///
+/// Stacks in the 1.20.5+ component format are written as
+/// `id[component=value,...] count`, and stacks in the legacy format as
+/// `id{tag} count`. The count is left out when it is 1, and the `Slot` of the
+/// stack is ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GiveCommand {
+    target: String,
+    slash: bool,
+}
+
+impl GiveCommand {
+    /// A command giving items to the players matched by `target`, such as
+    /// `@p` or a player name.
+    pub fn new<S: Into<String>>(target: S) -> GiveCommand {
+        GiveCommand { target: target.into(), slash: true }
+    }
+
+    /// Whether to start the command with a `/`, which is needed in chat but
+    /// not in datapack functions or command blocks. On by default.
+    pub fn with_slash(mut self, slash: bool) -> GiveCommand {
+        self.slash = slash;
+        self
+    }
+
+    /// The full command for a stack.
+    pub fn command(&self, item: &ItemStack) -> String {
+        format!("{}give {} {}", if self.slash { "/" } else { "" }, self.target, item_argument(item))
+    }
+}
+
+/// The item and count arguments of a `/give` command for a stack.
+pub fn item_argument(item: &ItemStack) -> String {
+    let mut out = item.id.clone();
+    match item.data {
+        ItemData::Tag(ref tag) => {
+            if !tag.is_empty() {
+                write_compound(&mut out, tag);
+            }
+        },
+        ItemData::Components(ref components) => {
+            let components = components.to_map();
+            if !components.is_empty() {
+                out.push('[');
+                for (i, key) in sorted_keys(&components).into_iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    // Component ids are resource locations, not SNBT keys.
+                    out.push_str(key);
+                    out.push('=');
+                    write_value(&mut out, &components[key]);
+                }
+                out.push(']');
+            }
+        },
+    }
+    if item.count != 1 {
+        let _ = write!(out, " {}", item.count);
+    }
+    out
+}
@@ -0,0 +1,162 @@
+//! Working out how an unknown file is encoded.
+//!
+//! NBT data turns up in several variants: Java Edition files are big-endian
+//! with a named root tag and usually gzipped; the Java network format, since
+//! 1.20.2, leaves out the root tag's name; Bedrock Edition files are
+//! little-endian; and Bedrock's `level.dat` adds an eight byte header giving
+//! its storage version and length. Region files are a different thing again,
+//! a table of chunks that each hold a document.
+//!
+//! `detect` tries each variant in turn, checking the region table, the
+//! Bedrock header and the compression's magic bytes, and then decoding the
+//! data in each byte order and root style until one accounts for every byte.
+//! Lengths in the data are checked against the bytes left before anything is
+//! allocated, so guessing wrong is cheap, and nesting is limited as by the
+//! default `DecoderOptions`.
+
+use std::io;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use compression::Registry;
+use error::{Error, Result};
+use value::{DecoderOptions, Limited, Value};
+
+const SECTOR: usize = 4096;
+
+/// How the tags of a document are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Big-endian, with a named root tag, as in Java Edition files.
+    Java,
+    /// Big-endian, with a nameless root tag, as in the Java network protocol
+    /// since 1.20.2.
+    Network,
+    /// Little-endian, with a named root tag, as in Bedrock Edition files.
+    Bedrock,
+}
+
+/// The variant of a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A standalone document, compressed with the named scheme.
+    Document {
+        /// The name of the compression scheme, such as `minecraft:gzip`.
+        compression: String,
+        /// How the tags are encoded.
+        encoding: Encoding,
+    },
+    /// A Bedrock `level.dat`, which is uncompressed and little-endian after
+    /// its header.
+    BedrockLevelDat {
+        /// The storage version from the header.
+        storage_version: i32,
+    },
+    /// A region file of chunks, to be read with `region::RegionEditor`.
+    Region,
+}
+
+/// Works out the variant of a file, or `None` if it is not NBT data at all.
+pub fn detect(data: &[u8]) -> Option<Format> {
+    detect_with(&Registry::new(), data)
+}
+
+/// Like `detect`, but recognising the compression schemes of a registry.
+pub fn detect_with(registry: &Registry, data: &[u8]) -> Option<Format> {
+    probe(registry, data, &mut None).map(|(format, _)| format)
+}
+
+/// Decodes a file of any variant except a region file, returning its variant
+/// and the name and value of its root tag. The root tag of the network
+/// variant has an empty name. Lists and compounds nested too deeply for the
+/// default `DecoderOptions` fail with `Error::DepthLimitExceeded` unless
+/// another variant decodes.
+pub fn decode(data: &[u8]) -> Result<(Format, String, Value)> {
+    decode_with(&Registry::new(), data)
+}
+
+/// Like `decode`, but recognising the compression schemes of a registry.
+pub fn decode_with(registry: &Registry, data: &[u8]) -> Result<(Format, String, Value)> {
+    let mut too_deep = None;
+    match probe(registry, data, &mut too_deep) {
+        Some((format, Some((title, value)))) => Ok((format, title, value)),
+        Some((Format::Region, _)) => Err(Error::IoError(io::Error::new(io::ErrorKind::InvalidInput,
+                                                                       "a region file holds many documents"))),
+        _ => Err(too_deep.unwrap_or_else(|| {
+            Error::IoError(io::Error::new(io::ErrorKind::InvalidData, "not a recognised NBT variant"))
+        })),
+    }
+}
+
+/// Reads all of `src` and decodes it as `decode` does.
+pub fn from_reader<R>(src: &mut R) -> Result<(Format, String, Value)>
+    where R: io::Read
+{
+    let mut data = Vec::new();
+    src.read_to_end(&mut data)?;
+    decode(&data)
+}
+
+/// Works out the variant of a file and decodes it, keeping the error of any
+/// attempt that found the data nested too deeply in `too_deep`.
+fn probe(registry: &Registry, data: &[u8], too_deep: &mut Option<Error>)
+         -> Option<(Format, Option<(String, Value)>)> {
+    if is_region(data) {
+        return Some((Format::Region, None));
+    }
+    if data.len() > 8 && data[8] == 0x0a && LittleEndian::read_i32(&data[4..8]) as usize == data.len() - 8 {
+        if let Some(root) = read_document::<LittleEndian>(&data[8..], true, too_deep) {
+            let storage_version = LittleEndian::read_i32(&data[..4]);
+            return Some((Format::BedrockLevelDat { storage_version }, Some(root)));
+        }
+    }
+    let codec = registry.sniff(data)?;
+    let raw = codec.decompress(data).ok()?;
+    let (encoding, root) = if let Some(root) = read_document::<BigEndian>(&raw, true, too_deep) {
+        (Encoding::Java, root)
+    } else if let Some(root) = read_document::<BigEndian>(&raw, false, too_deep) {
+        (Encoding::Network, root)
+    } else {
+        (Encoding::Bedrock, read_document::<LittleEndian>(&raw, true, too_deep)?)
+    };
+    Some((Format::Document { compression: codec.name().to_string(), encoding }, Some(root)))
+}
+
+/// Whether `data` has a region file's chunk table, with every chunk inside
+/// the file and clear of the table.
+fn is_region(data: &[u8]) -> bool {
+    if data.len() < 2 * SECTOR || !data.len().is_multiple_of(SECTOR) {
+        return false;
+    }
+    let sectors = data.len() / SECTOR;
+    data[..SECTOR].chunks(4).all(|entry| {
+        let location = BigEndian::read_u32(entry) as usize;
+        let (offset, count) = (location >> 8, location & 0xff);
+        location == 0 || (offset >= 2 && count > 0 && offset + count <= sectors)
+    })
+}
+
+/// Reads a whole document, returning `None` unless it is a compound that
+/// uses every byte. The reader's byte budget is the size of the data, so a
+/// length claiming more than is left fails before it is allocated for.
+fn read_document<B: ByteOrder>(data: &[u8], named: bool, too_deep: &mut Option<Error>)
+                               -> Option<(String, Value)> {
+    if data.first() != Some(&0x0a) {
+        return None;
+    }
+    let mut src = &data[1..];
+    let options = DecoderOptions { max_bytes: Some(src.len() as u64), ..DecoderOptions::default() };
+    let (title, value) = {
+        let mut reader = Limited::<_, B>::ordered(&mut src, &options);
+        let title = if named { reader.string().ok()? } else { String::new() };
+        match reader.value(0x0a, 0) {
+            Ok(value) => (title, value),
+            Err(e @ Error::DepthLimitExceeded(_)) => {
+                too_deep.get_or_insert(e);
+                return None;
+            },
+            Err(_) => return None,
+        }
+    };
+    if src.is_empty() { Some((title, value)) } else { None }
+}
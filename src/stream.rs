@@ -0,0 +1,448 @@
+//! Reading and writing documents one tag at a time.
+//!
+//! A `Parser` turns a document into a sequence of `Event`s without building a
+//! `Value` tree, so tools can scan large files for a few fields using memory
+//! proportional to the nesting depth rather than the size of the document.
+//! Each event comes with the byte offset in the source where its tag starts:
+//! the type ID of a compound entry or root tag, or the payload of a list
+//! element, which has neither.
+//!
+//! A `Writer` does the reverse, emitting a document as it is described, and
+//! refusing calls that would not make a valid one. `transcode` joins the two
+//! to copy a document while dropping or renaming compound entries.
+//!
+//! ```ignore
+//! let mut parser = Parser::new(file);
+//! while let Some(event) = parser.next() {
+//!     match event? {
+//!         (_, Event::Tag(Some(ref name), Value::Int(v))) if name == "DataVersion" => return Ok(v),
+//!         (_, Event::ListStart { .. }) => parser.skip_rest()?,
+//!         _ => (),
+//!     }
+//! }
+//! ```
+
+use std::io;
+
+use byteorder::WriteBytesExt;
+
+use audit::Counted;
+use error::{Error, Result};
+use raw;
+use value::Value;
+
+/// Something found while parsing a document. Names are `None` for list
+/// elements, and `Some` for compound entries and the root tag.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// The start of a compound. Its entries follow, then an `End`.
+    CompoundStart(Option<String>),
+    /// The start of a list of `len` elements with the given type ID. Its
+    /// elements follow, each with no name, then an `End`.
+    ListStart { name: Option<String>, kind: u8, len: i32 },
+    /// A tag that is not a list or compound.
+    Tag(Option<String>, Value),
+    /// The end of the innermost compound or list that has not ended.
+    End,
+}
+
+/// A compound or list that has been opened but has not ended.
+#[derive(Clone, Copy)]
+pub(crate) enum Frame {
+    Compound,
+    List { kind: u8, remaining: i32 },
+}
+
+/// An iterator over the events of a single document, read from an
+/// `io::Read` source.
+///
+/// Iteration stops after the end of the root tag or the first error.
+pub struct Parser<R> {
+    src: Counted<R>,
+    stack: Vec<Frame>,
+    done: bool,
+}
+
+impl<R: io::Read> Parser<R> {
+    /// Creates a parser for the document at the start of `src`.
+    pub fn new(src: R) -> Parser<R> {
+        Parser { src: Counted { inner: src, pos: 0 }, stack: Vec::new(), done: false }
+    }
+
+    /// The number of bytes read from the source so far.
+    pub fn position(&self) -> u64 {
+        self.src.pos
+    }
+
+    /// How many compounds and lists are open.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Skips the rest of the innermost open compound or list, including its
+    /// `End`. Called right after a `CompoundStart` or `ListStart`, this skips
+    /// the whole tag without decoding it.
+    pub fn skip_rest(&mut self) -> Result<()> {
+        let result = match self.stack.pop() {
+            Some(Frame::Compound) => raw::skip_payload(0x0a, &mut self.src),
+            Some(Frame::List { kind, remaining }) => {
+                (0..remaining).try_for_each(|_| raw::skip_payload(kind, &mut self.src))
+            },
+            None => Ok(()),
+        };
+        if result.is_err() || self.stack.is_empty() {
+            self.done = true;
+        }
+        result
+    }
+
+    /// Reads the tag with the given type ID and name whose payload is next.
+    fn open(&mut self, id: u8, name: Option<String>) -> Result<Event> {
+        match id {
+            0x09 => {
+                let kind = raw::read_bare_byte(&mut self.src)? as u8;
+                let len = raw::read_bare_int(&mut self.src)?;
+                self.stack.push(Frame::List { kind, remaining: len.max(0) });
+                Ok(Event::ListStart { name, kind, len })
+            },
+            0x0a => {
+                self.stack.push(Frame::Compound);
+                Ok(Event::CompoundStart(name))
+            },
+            _ => Ok(Event::Tag(name, Value::from_reader(id, &mut self.src)?)),
+        }
+    }
+
+    fn step(&mut self) -> Result<(u64, Event)> {
+        let offset = self.src.pos;
+        let event = match self.stack.last_mut() {
+            None => {
+                let (id, name) = raw::emit_next_header(&mut self.src)?;
+                if id == 0x00 {
+                    return Err(Error::NoRootCompound);
+                }
+                self.open(id, Some(name))?
+            },
+            Some(&mut Frame::List { remaining: 0, .. }) => {
+                self.stack.pop();
+                Event::End
+            },
+            Some(&mut Frame::List { kind, ref mut remaining }) => {
+                *remaining -= 1;
+                self.open(kind, None)?
+            },
+            Some(&mut Frame::Compound) => {
+                let (id, name) = raw::emit_next_header(&mut self.src)?;
+                if id == 0x00 {
+                    self.stack.pop();
+                    Event::End
+                } else {
+                    self.open(id, Some(name))?
+                }
+            },
+        };
+        if self.stack.is_empty() {
+            self.done = true;
+        }
+        Ok((offset, event))
+    }
+}
+
+impl<R: io::Read> Iterator for Parser<R> {
+    type Item = Result<(u64, Event)>;
+
+    fn next(&mut self) -> Option<Result<(u64, Event)>> {
+        if self.done {
+            return None;
+        }
+        let event = self.step();
+        if event.is_err() {
+            self.done = true;
+        }
+        Some(event)
+    }
+}
+
+/// Writes a single document to an `io::Write` destination one tag at a time.
+///
+/// Names follow the same rule as in `Event`: every compound entry and the
+/// root tag needs one, and list elements have none. Mistakes, such as
+/// writing an element of the wrong type to a list or ending a list before
+/// all of its elements are written, fail with `Error::InvalidWrite` or
+/// `Error::TagMismatch` and write nothing.
+///
+/// ```ignore
+/// let mut writer = Writer::new(file);
+/// writer.begin_compound(Some("Data"))?;
+/// writer.write_int(Some("DataVersion"), 3465)?;
+/// writer.begin_list(Some("Pos"), 0x06, 3)?;
+/// for &v in &[0.5, 64.0, 0.5] {
+///     writer.write_double(None, v)?;
+/// }
+/// writer.end()?;
+/// writer.end()?;
+/// let file = writer.finish()?;
+/// ```
+pub struct Writer<W> {
+    dst: W,
+    stack: Vec<Frame>,
+    done: bool,
+}
+
+impl<W: io::Write> Writer<W> {
+    /// Creates a writer for a document written to `dst`.
+    pub fn new(dst: W) -> Writer<W> {
+        Writer { dst, stack: Vec::new(), done: false }
+    }
+
+    /// How many compounds and lists are open.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Starts a compound. Its entries follow, then a call to `end`.
+    pub fn begin_compound(&mut self, name: Option<&str>) -> Result<()> {
+        self.header(name, 0x0a)?;
+        self.stack.push(Frame::Compound);
+        Ok(())
+    }
+
+    /// Starts a list of exactly `len` elements with the given type ID. Its
+    /// elements follow, then a call to `end`.
+    pub fn begin_list(&mut self, name: Option<&str>, kind: u8, len: i32) -> Result<()> {
+        if len < 0 {
+            return Err(Error::InvalidWrite("a list cannot have a negative length"));
+        }
+        self.header(name, 0x09)?;
+        self.dst.write_u8(kind)?;
+        raw::write_bare_int(&mut self.dst, len)?;
+        self.stack.push(Frame::List { kind, remaining: len });
+        Ok(())
+    }
+
+    /// Ends the innermost open compound or list.
+    pub fn end(&mut self) -> Result<()> {
+        match self.stack.last() {
+            Some(&Frame::Compound) => raw::close_nbt(&mut self.dst)?,
+            Some(&Frame::List { remaining: 0, .. }) => (),
+            Some(&Frame::List { .. }) => {
+                return Err(Error::InvalidWrite("a list has fewer elements than declared"));
+            },
+            None => return Err(Error::InvalidWrite("there is no open compound or list")),
+        }
+        self.stack.pop();
+        self.done = self.stack.is_empty();
+        Ok(())
+    }
+
+    /// Writes any tag, including a whole list or compound.
+    pub fn write_value(&mut self, name: Option<&str>, value: &Value) -> Result<()> {
+        self.header(name, value.id())?;
+        value.write(&mut self.dst)?;
+        self.done = self.stack.is_empty();
+        Ok(())
+    }
+
+    /// Writes a `TAG_Byte`.
+    pub fn write_byte(&mut self, name: Option<&str>, value: i8) -> Result<()> {
+        self.header(name, 0x01)?;
+        raw::write_bare_byte(&mut self.dst, value)?;
+        self.done = self.stack.is_empty();
+        Ok(())
+    }
+
+    /// Writes a `TAG_Short`.
+    pub fn write_short(&mut self, name: Option<&str>, value: i16) -> Result<()> {
+        self.header(name, 0x02)?;
+        raw::write_bare_short(&mut self.dst, value)?;
+        self.done = self.stack.is_empty();
+        Ok(())
+    }
+
+    /// Writes a `TAG_Int`.
+    pub fn write_int(&mut self, name: Option<&str>, value: i32) -> Result<()> {
+        self.header(name, 0x03)?;
+        raw::write_bare_int(&mut self.dst, value)?;
+        self.done = self.stack.is_empty();
+        Ok(())
+    }
+
+    /// Writes a `TAG_Long`.
+    pub fn write_long(&mut self, name: Option<&str>, value: i64) -> Result<()> {
+        self.header(name, 0x04)?;
+        raw::write_bare_long(&mut self.dst, value)?;
+        self.done = self.stack.is_empty();
+        Ok(())
+    }
+
+    /// Writes a `TAG_Float`.
+    pub fn write_float(&mut self, name: Option<&str>, value: f32) -> Result<()> {
+        self.header(name, 0x05)?;
+        raw::write_bare_float(&mut self.dst, value)?;
+        self.done = self.stack.is_empty();
+        Ok(())
+    }
+
+    /// Writes a `TAG_Double`.
+    pub fn write_double(&mut self, name: Option<&str>, value: f64) -> Result<()> {
+        self.header(name, 0x06)?;
+        raw::write_bare_double(&mut self.dst, value)?;
+        self.done = self.stack.is_empty();
+        Ok(())
+    }
+
+    /// Writes a `TAG_String`.
+    pub fn write_string(&mut self, name: Option<&str>, value: &str) -> Result<()> {
+        self.header(name, 0x08)?;
+        raw::write_bare_string(&mut self.dst, value)?;
+        self.done = self.stack.is_empty();
+        Ok(())
+    }
+
+    /// Checks that the document is complete, and returns the destination.
+    pub fn finish(self) -> Result<W> {
+        if !self.done {
+            return Err(Error::InvalidWrite("the document is not finished"));
+        }
+        Ok(self.dst)
+    }
+
+    /// Checks that a tag with the given name and type ID may be written
+    /// next, and writes its header if it needs one.
+    fn header(&mut self, name: Option<&str>, id: u8) -> Result<()> {
+        match self.stack.last_mut() {
+            None if self.done => Err(Error::InvalidWrite("the root tag has already been written")),
+            None | Some(&mut Frame::Compound) => match name {
+                Some(name) => {
+                    self.dst.write_u8(id)?;
+                    raw::write_bare_string(&mut self.dst, name)
+                },
+                None => Err(Error::InvalidWrite("compound entries and the root tag need a name")),
+            },
+            Some(&mut Frame::List { kind, ref mut remaining }) => {
+                if name.is_some() {
+                    return Err(Error::InvalidWrite("list elements cannot have a name"));
+                }
+                if id != kind {
+                    return Err(Error::TagMismatch(id, kind));
+                }
+                if *remaining == 0 {
+                    return Err(Error::InvalidWrite("a list has more elements than declared"));
+                }
+                *remaining -= 1;
+                Ok(())
+            },
+        }
+    }
+}
+
+/// What `transcode` does with a compound entry.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Copy the entry as it is.
+    Keep,
+    /// Leave the entry out, skipping over it without decoding it if it is a
+    /// list or compound.
+    Drop,
+    /// Copy the entry under a new name.
+    Rename(String),
+}
+
+/// A compound or list being copied by `transcode`.
+struct Level {
+    /// The length of the path before this level's own segment was added.
+    restore: usize,
+    /// The index of the next element, if this is a list.
+    index: Option<usize>,
+}
+
+/// Copies a single document from `src` to `dst` without building a `Value`
+/// tree, asking `filter` what to do with every compound entry. Returns the
+/// destination.
+///
+/// The filter is given the path of the entry, such as
+/// `Level.Sections[3].BlockLight`, and its type ID. It is asked before the
+/// entry's payload is read, so dropping a large list or compound costs no
+/// more than reading past it.
+/// The root tag and list elements are always kept, since a list's length is
+/// written before its elements.
+///
+/// ```ignore
+/// let pruned = transcode(chunk, Vec::new(), |path, _| match path {
+///     "Level.Lights" | "Level.Heightmaps" => Action::Drop,
+///     _ => Action::Keep,
+/// })?;
+/// ```
+pub fn transcode<R, W, F>(src: R, dst: W, mut filter: F) -> Result<W>
+    where R: io::Read,
+          W: io::Write,
+          F: FnMut(&str, u8) -> Action,
+{
+    let mut parser = Parser::new(src);
+    let mut writer = Writer::new(dst);
+    let mut path = String::new();
+    let mut levels: Vec<Level> = Vec::new();
+
+    while let Some(event) = parser.next() {
+        let (_, event) = event?;
+        let (name, id) = match event {
+            Event::CompoundStart(ref name) => (name.clone(), 0x0a),
+            Event::ListStart { ref name, .. } => (name.clone(), 0x09),
+            Event::Tag(ref name, ref value) => (name.clone(), value.id()),
+            Event::End => {
+                writer.end()?;
+                if let Some(level) = levels.pop() {
+                    path.truncate(level.restore);
+                }
+                continue;
+            },
+        };
+
+        let restore = path.len();
+        let name = match levels.last_mut() {
+            None => name,
+            Some(&mut Level { index: Some(ref mut index), .. }) => {
+                path.push_str(&format!("[{}]", index));
+                *index += 1;
+                None
+            },
+            Some(_) => {
+                let name = name.unwrap_or_default();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(&name);
+                match filter(&path, id) {
+                    Action::Keep => Some(name),
+                    Action::Rename(name) => Some(name),
+                    Action::Drop => {
+                        if id == 0x09 || id == 0x0a {
+                            parser.skip_rest()?;
+                        }
+                        path.truncate(restore);
+                        continue;
+                    },
+                }
+            },
+        };
+
+        let name = name.as_ref().map(|name| &name[..]);
+        match event {
+            Event::CompoundStart(_) => {
+                writer.begin_compound(name)?;
+                levels.push(Level { restore, index: None });
+            },
+            Event::ListStart { kind, len, .. } => {
+                writer.begin_list(name, kind, len)?;
+                levels.push(Level { restore, index: Some(0) });
+            },
+            Event::Tag(_, ref value) => {
+                writer.write_value(name, value)?;
+                path.truncate(restore);
+            },
+            Event::End => unreachable!(),
+        }
+    }
+
+    writer.finish()
+}
@@ -0,0 +1,343 @@
+//! Support for testing code that produces or consumes NBT.
+//!
+//! `generate` builds random but always valid documents, which is useful for
+//! fuzzing parsers and storage layers with realistic input. The generator is
+//! driven by any source of randomness implementing the small `Rng` trait, and
+//! `XorShift` is provided as a fast, seedable default.
+//!
+//! `assert_roundtrip` and `verify_roundtrip` check that documents survive
+//! being encoded and decoded unchanged, and report the first place where they
+//! do not.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+use error::Result;
+use value::{DecoderOptions, Limited, Value};
+
+/// A source of random numbers for the generator.
+pub trait Rng {
+    /// Returns the next random 64-bit value.
+    fn next_u64(&mut self) -> u64;
+
+    /// Returns a random number in `0..n`, or `0` if `n` is zero.
+    fn below(&mut self, n: u64) -> u64 {
+        if n == 0 { 0 } else { self.next_u64() % n }
+    }
+}
+
+/// A seedable xorshift64* generator. It is not cryptographically secure, but
+/// the same seed always produces the same documents.
+#[derive(Clone, Debug)]
+pub struct XorShift(u64);
+
+impl XorShift {
+    /// Creates a generator from a seed. A zero seed is replaced with a fixed
+    /// non-zero one, since xorshift would otherwise only produce zeros.
+    pub fn new(seed: u64) -> XorShift {
+        XorShift(if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed })
+    }
+}
+
+impl Rng for XorShift {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+/// Options controlling the shape of generated documents.
+#[derive(Clone, Debug)]
+pub struct GenOptions {
+    /// The maximum nesting depth of lists and compounds below the root.
+    pub max_depth: usize,
+    /// The maximum number of entries in a compound, list or array.
+    pub max_len: usize,
+    /// The maximum number of characters in a string or compound key.
+    pub max_string_len: usize,
+    /// The relative weight of each tag type, by type ID. Types that are
+    /// missing or have a weight of zero are never generated.
+    pub weights: Vec<(u8, u32)>,
+    /// The characters that strings and compound keys are made from.
+    pub alphabet: Vec<char>,
+}
+
+impl Default for GenOptions {
+    fn default() -> GenOptions {
+        GenOptions {
+            max_depth: 4,
+            max_len: 8,
+            max_string_len: 16,
+            weights: (0x01..=0x0c).map(|id| (id, 1)).collect(),
+            alphabet: "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_ \u{e9}\u{2603}"
+                .chars().collect(),
+        }
+    }
+}
+
+/// Generates a random compound, suitable as the root of a document.
+///
+/// Panics if the options allow no tag types or have an empty alphabet.
+pub fn generate<R: Rng>(rng: &mut R, options: &GenOptions) -> Value {
+    assert!(options.weights.iter().any(|&(_, w)| w > 0), "no tag types are enabled");
+    assert!(!options.alphabet.is_empty(), "the alphabet is empty");
+    gen_compound(rng, options, 0)
+}
+
+fn gen_len<R: Rng>(rng: &mut R, max: usize) -> usize {
+    rng.below(max as u64 + 1) as usize
+}
+
+fn gen_string<R: Rng>(rng: &mut R, options: &GenOptions, min: usize) -> String {
+    let len = min.max(gen_len(rng, options.max_string_len));
+    (0..len).map(|_| options.alphabet[rng.below(options.alphabet.len() as u64) as usize]).collect()
+}
+
+/// Picks a tag type by weight. Lists and compounds are excluded once the
+/// maximum depth is reached, falling back to a byte if nothing else is left.
+fn gen_id<R: Rng>(rng: &mut R, options: &GenOptions, depth: usize) -> u8 {
+    let nested = depth < options.max_depth;
+    let allowed = |id: u8| nested || (id != 0x09 && id != 0x0a);
+    let total: u64 = options.weights.iter()
+        .filter(|&&(id, _)| allowed(id))
+        .map(|&(_, w)| w as u64)
+        .sum();
+    let mut pick = rng.below(total);
+    for &(id, weight) in options.weights.iter().filter(|&&(id, _)| allowed(id)) {
+        if pick < weight as u64 {
+            return id;
+        }
+        pick -= weight as u64;
+    }
+    0x01
+}
+
+fn gen_compound<R: Rng>(rng: &mut R, options: &GenOptions, depth: usize) -> Value {
+    let mut map = HashMap::new();
+    for _ in 0..gen_len(rng, options.max_len) {
+        let id = gen_id(rng, options, depth);
+        let key = gen_string(rng, options, 1);
+        map.insert(key, gen_value(rng, options, id, depth + 1));
+    }
+    Value::Compound(map)
+}
+
+fn gen_value<R: Rng>(rng: &mut R, options: &GenOptions, id: u8, depth: usize) -> Value {
+    match id {
+        0x01 => Value::Byte(rng.next_u64() as i8),
+        0x02 => Value::Short(rng.next_u64() as i16),
+        0x03 => Value::Int(rng.next_u64() as i32),
+        0x04 => Value::Long(rng.next_u64() as i64),
+        // Keep floats finite so that generated documents compare equal to
+        // themselves after a round trip.
+        0x05 => Value::Float((rng.next_u64() as i32) as f32 / 256.0),
+        0x06 => Value::Double((rng.next_u64() as i64) as f64 / 65536.0),
+        0x07 => Value::ByteArray((0..gen_len(rng, options.max_len)).map(|_| rng.next_u64() as i8).collect()),
+        0x08 => Value::String(gen_string(rng, options, 0)),
+        0x09 => {
+            let elem = gen_id(rng, options, depth);
+            let len = gen_len(rng, options.max_len);
+            Value::List((0..len).map(|_| gen_value(rng, options, elem, depth + 1)).collect())
+        },
+        0x0a => gen_compound(rng, options, depth),
+        0x0b => Value::IntArray((0..gen_len(rng, options.max_len)).map(|_| rng.next_u64() as i32).collect()),
+        0x0c => Value::LongArray((0..gen_len(rng, options.max_len)).map(|_| rng.next_u64() as i64).collect()),
+        _ => Value::Byte(0),
+    }
+}
+
+/// The first difference found between a document and its round-tripped copy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// The path to the differing tag, such as `Level.Sections[2].Y`. The
+    /// root compound has an empty path.
+    pub path: String,
+    /// The byte offset of the differing tag's payload in the original
+    /// encoding, if it appears there.
+    pub offset: Option<u64>,
+    /// A description of the original tag.
+    pub expected: String,
+    /// A description of the round-tripped tag.
+    pub found: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = if self.path.is_empty() { "(root)" } else { &self.path };
+        write!(f, "divergence at {}", path)?;
+        if let Some(offset) = self.offset {
+            write!(f, " (byte {})", offset)?;
+        }
+        write!(f, ": expected {}, found {}", self.expected, self.found)
+    }
+}
+
+/// Encodes `value` as an unnamed document, decodes it again and checks that
+/// nothing changed, then does the same starting from the encoded bytes.
+///
+/// Panics with a description of the first divergence if the round trip is
+/// not exact. Floats are compared by their bits, so NaN payloads and the sign
+/// of zero must be preserved too.
+pub fn assert_roundtrip(value: &Value) {
+    let mut bytes = Vec::new();
+    value.write_header(&mut bytes, "").and_then(|_| value.write(&mut bytes))
+        .unwrap_or_else(|e| panic!("failed to encode value: {}", e));
+    let (decoded, offsets) = decode(&bytes)
+        .unwrap_or_else(|e| panic!("failed to decode encoded value: {}", e));
+    if let Some(divergence) = diff(value, &decoded, String::new(), &offsets) {
+        panic!("{}", divergence);
+    }
+    match verify_roundtrip(&bytes) {
+        Ok(None) => {},
+        Ok(Some(divergence)) => panic!("{}", divergence),
+        Err(e) => panic!("failed to round-trip encoded value: {}", e),
+    }
+}
+
+/// Decodes a document, encodes it again and decodes the result, returning the
+/// first difference between the two decoded trees.
+///
+/// Compound entries are not compared by their order in the encoding, since
+/// it is not preserved. Returns an error if either decoding fails, including
+/// with `Error::DepthLimitExceeded` for lists and compounds nested more
+/// deeply than the default `DecoderOptions` allow.
+pub fn verify_roundtrip(bytes: &[u8]) -> Result<Option<Divergence>> {
+    let (original, offsets) = decode(bytes)?;
+    let mut encoded = Vec::new();
+    original.write_header(&mut encoded, "")?;
+    original.write(&mut encoded)?;
+    let (decoded, _) = decode(&encoded)?;
+    Ok(diff(&original, &decoded, String::new(), &offsets))
+}
+
+/// Returns the first difference between two trees, comparing them the same
+/// way as `assert_roundtrip`. The divergence has no byte offset.
+pub fn compare(expected: &Value, found: &Value) -> Option<Divergence> {
+    diff(expected, found, String::new(), &HashMap::new())
+}
+
+/// Decodes a document, recording the payload offset of every tag by path.
+fn decode(bytes: &[u8]) -> Result<(Value, HashMap<String, u64>)> {
+    let mut cursor = io::Cursor::new(bytes);
+    let options = DecoderOptions::default();
+    let mut src = Limited::new(&mut cursor, &options);
+    let (id, _) = src.entry_header()?;
+    let mut offsets = HashMap::new();
+    let value = read_tracked(id, &mut src, String::new(), 0, &mut offsets)?;
+    Ok((value, offsets))
+}
+
+/// Reads a tag nested `depth` lists and compounds deep.
+fn read_tracked(id: u8, src: &mut Limited<io::Cursor<&[u8]>>, path: String, depth: usize,
+                offsets: &mut HashMap<String, u64>) -> Result<Value> {
+    offsets.insert(path.clone(), src.src.position());
+    match id {
+        0x09 => {
+            src.nest(depth)?;
+            let (elem, len) = src.list_header()?;
+            let mut vals = Vec::with_capacity(::raw::initial_capacity(len));
+            for i in 0..len {
+                vals.push(read_tracked(elem, src, format!("{}[{}]", path, i), depth + 1, offsets)?);
+            }
+            Ok(Value::List(vals))
+        },
+        0x0a => {
+            src.nest(depth)?;
+            let mut map = HashMap::new();
+            loop {
+                let (id, name) = src.entry_header()?;
+                if id == 0x00 {
+                    break;
+                }
+                let child = read_tracked(id, src, child_path(&path, &name), depth + 1, offsets)?;
+                map.insert(name, child);
+            }
+            Ok(Value::Compound(map))
+        },
+        _ => src.value(id, depth),
+    }
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+fn describe(value: &Value) -> String {
+    match *value {
+        Value::List(ref vals) => format!("a list of {} elements", vals.len()),
+        Value::Compound(ref map) => format!("a compound of {} entries", map.len()),
+        Value::ByteArray(ref vals) => format!("a byte array of {} elements", vals.len()),
+        Value::IntArray(ref vals) => format!("an int array of {} elements", vals.len()),
+        Value::LongArray(ref vals) => format!("a long array of {} elements", vals.len()),
+        Value::String(ref s) => format!("{:?}", s),
+        ref other => format!("{} (tag type {})", other, other.id()),
+    }
+}
+
+fn diff(expected: &Value, found: &Value, path: String,
+        offsets: &HashMap<String, u64>) -> Option<Divergence> {
+    let divergence = |path: String, expected: String, found: String| {
+        let offset = offsets.get(&path).cloned();
+        Some(Divergence { path, offset, expected, found })
+    };
+    let same = match (expected, found) {
+        (&Value::Float(a), &Value::Float(b)) => a.to_bits() == b.to_bits(),
+        (&Value::Double(a), &Value::Double(b)) => a.to_bits() == b.to_bits(),
+        (Value::ByteArray(a), Value::ByteArray(b)) => {
+            return diff_arrays(a, b, path, offsets);
+        },
+        (Value::IntArray(a), Value::IntArray(b)) => {
+            return diff_arrays(a, b, path, offsets);
+        },
+        (Value::LongArray(a), Value::LongArray(b)) => {
+            return diff_arrays(a, b, path, offsets);
+        },
+        (Value::List(a), Value::List(b)) if a.len() == b.len() => {
+            return a.iter().zip(b).enumerate()
+                .filter_map(|(i, (a, b))| diff(a, b, format!("{}[{}]", path, i), offsets))
+                .next();
+        },
+        (Value::Compound(a), Value::Compound(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys().filter(|k| !a.contains_key(*k))).collect();
+            keys.sort();
+            for key in keys {
+                let child = child_path(&path, key);
+                let result = match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => diff(a, b, child, offsets),
+                    (Some(a), None) => divergence(child, describe(a), "nothing".to_string()),
+                    (None, Some(b)) => divergence(child, "nothing".to_string(), describe(b)),
+                    (None, None) => None,
+                };
+                if result.is_some() {
+                    return result;
+                }
+            }
+            return None;
+        },
+        _ => expected == found,
+    };
+    if same { None } else { divergence(path, describe(expected), describe(found)) }
+}
+
+fn diff_arrays<T>(expected: &[T], found: &[T], path: String,
+                  offsets: &HashMap<String, u64>) -> Option<Divergence>
+    where T: PartialEq + fmt::Display
+{
+    let offset = offsets.get(&path).cloned();
+    if expected.len() != found.len() {
+        let expected = format!("{} elements", expected.len());
+        let found = format!("{} elements", found.len());
+        return Some(Divergence { path, offset, expected, found });
+    }
+    let size = (::std::mem::size_of::<T>()) as u64;
+    expected.iter().zip(found).position(|(a, b)| a != b).map(|i| Divergence {
+        path: format!("{}[{}]", path, i),
+        // Skip the length prefix to point at the differing element.
+        offset: offset.map(|o| o + 4 + i as u64 * size),
+        expected: expected[i].to_string(),
+        found: found[i].to_string(),
+    })
+}
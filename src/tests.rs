@@ -4,9 +4,39 @@ use std::fs::File;
 
 //use test::Bencher;
 
+use audit;
+use batch::{BatchProcessor, Document};
 use blob::Blob;
+use capture;
+use chunk::{Chunk, Section};
+use compression::{self, Codec, Registry};
+use conformance;
+use diff::{self, Change, DiffEntry};
 use error::Error;
-use value::Value;
+use intern::{Node, Pool};
+use lazy;
+use value::{DecoderOptions, Value};
+use map;
+use mc::entity::{Offer, Offers};
+use normalize;
+use parallel;
+use project::{self, Projection};
+use raw;
+use mc::item::{ItemData, ItemStack};
+use mc::version::{self, DataVersion, Structure};
+use region::RegionEditor;
+use remap::{BlockMapping, Remapper};
+use rewrite::{self, Rules};
+use shared::{SharedBytes, SharedTag};
+use snbt::{self, GiveCommand};
+use path::NbtPath;
+use stream::{Action, Event, Parser, Writer};
+use sniff::{self, Encoding, Format};
+use testing::{self, GenOptions, XorShift};
+use uuid;
+use watch::Watcher;
+use world::World;
+use yaml;
 
 #[test]
 fn nbt_nonempty() {
@@ -234,6 +264,2069 @@ fn nbt_bigtest() {
     assert_eq!(1544, bigtest.len());
 }
 
+#[test]
+fn yaml_roundtrip() {
+    let mut item = HashMap::new();
+    item.insert("id".to_string(), Value::String("minecraft:stone".to_string()));
+    item.insert("Count".to_string(), Value::Byte(64));
+    let mut inner = HashMap::new();
+    inner.insert("empty".to_string(), Value::Compound(HashMap::new()));
+    inner.insert("quoted key".to_string(), Value::String("line\nbreak \"q\"".to_string()));
+
+    let mut map = HashMap::new();
+    map.insert("byte".to_string(), Value::Byte(-3));
+    map.insert("short".to_string(), Value::Short(12345));
+    map.insert("int".to_string(), Value::Int(-7));
+    map.insert("long".to_string(), Value::Long(1 << 40));
+    map.insert("float".to_string(), Value::Float(0.5));
+    map.insert("double".to_string(), Value::Double(3.0));
+    map.insert("infinite".to_string(), Value::Float(f32::INFINITY));
+    map.insert("numeric string".to_string(), Value::String("12b".to_string()));
+    map.insert("bytes".to_string(), Value::ByteArray(vec![1, -2, 3]));
+    map.insert("ints".to_string(), Value::IntArray(vec![]));
+    map.insert("items".to_string(), Value::List(vec![Value::Compound(item)]));
+    map.insert("nested".to_string(), Value::List(vec![
+        Value::List(vec![Value::Int(1), Value::Int(2)]),
+        Value::List(vec![]),
+    ]));
+    map.insert("inner".to_string(), Value::Compound(inner));
+    let value = Value::Compound(map);
+
+    let text = yaml::to_string(&value);
+    assert_eq!(yaml::from_str(&text).unwrap(), value);
+}
+
+#[test]
+fn yaml_hand_written() {
+    let text = "\
+# A hand-edited file.
+name: Herobrine   # plain strings are allowed
+health: 20.5f
+flying: false
+pos: [1.5, 64.0, -3.25]
+inventory:
+- {Slot: 0b, id: 'minecraft:dirt'}
+";
+    let value = yaml::from_str(text).unwrap();
+    let mut item = HashMap::new();
+    item.insert("Slot".to_string(), Value::Byte(0));
+    item.insert("id".to_string(), Value::String("minecraft:dirt".to_string()));
+    let mut map = HashMap::new();
+    map.insert("name".to_string(), Value::String("Herobrine".to_string()));
+    map.insert("health".to_string(), Value::Float(20.5));
+    map.insert("flying".to_string(), Value::Byte(0));
+    map.insert("pos".to_string(), Value::List(vec![
+        Value::Double(1.5), Value::Double(64.0), Value::Double(-3.25)
+    ]));
+    map.insert("inventory".to_string(), Value::List(vec![Value::Compound(item)]));
+    assert_eq!(value, Value::Compound(map));
+
+    assert_eq!(yaml::from_str("list: [1, 2b]"), Err(Error::HeterogeneousList));
+    assert!(yaml::from_str("big: 300b").is_err());
+}
+
+#[test]
+fn yaml_depth_is_limited() {
+    let max = DecoderOptions::default().max_depth;
+    let flow = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+    assert!(yaml::from_str(&flow(max + 1)).is_ok());
+    match yaml::from_str(&flow(max + 2)) {
+        Err(Error::InvalidYaml(_)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match yaml::from_str(&flow(200_000)) {
+        Err(Error::InvalidYaml(_)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // Block collections count towards the same limit.
+    let block = |depth: usize| format!("{}1", "- ".repeat(depth));
+    assert!(yaml::from_str(&block(max + 1)).is_ok());
+    match yaml::from_str(&block(200_000)) {
+        Err(Error::InvalidYaml(_)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match yaml::from_str(&format!("{}{}", "- ".repeat(max), flow(2))) {
+        Err(Error::InvalidYaml(_)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn map_colors() {
+    // Grass at the brightest shade, then water at the darkest.
+    assert_eq!(map::color(6), [0x7f, 0xb2, 0x38, 0xff]);
+    assert_eq!(map::color(12 * 4 + 3), [0x21, 0x21, 0x87, 0xff]);
+    assert_eq!(map::color(0), [0, 0, 0, 0]);
+
+    let colors: Vec<i8> = (0..62 * 4).map(|id| id as u8 as i8).collect();
+    let pixels = map::to_rgba(&colors);
+    assert_eq!(pixels.len(), colors.len() * 4);
+    // Every palette color should quantize to a byte with the same color.
+    assert_eq!(map::to_rgba(&map::from_rgba(&pixels)), pixels);
+    assert_eq!(map::nearest([0xfe, 0x01, 0x00, 0xff]), 4 * 4 + 2);
+    assert_eq!(map::nearest([0xfe, 0x01, 0x00, 0x00]), 0);
+}
+
+fn block_state(name: &str, props: &[(&str, &str)]) -> Value {
+    let mut map = HashMap::new();
+    map.insert("Name".to_string(), Value::String(name.to_string()));
+    if !props.is_empty() {
+        let props = props.iter().map(|&(k, v)| (k.to_string(), Value::String(v.to_string())));
+        map.insert("Properties".to_string(), Value::Compound(props.collect()));
+    }
+    Value::Compound(map)
+}
+
+#[test]
+fn remap_palettes() {
+    let mut block_states = HashMap::new();
+    block_states.insert("palette".to_string(), Value::List(vec![
+        block_state("minecraft:grass_path", &[]),
+        block_state("minecraft:furnace", &[("facing", "north"), ("lit", "false")]),
+        block_state("minecraft:stone", &[]),
+    ]));
+    let mut section = HashMap::new();
+    section.insert("block_states".to_string(), Value::Compound(block_states));
+    let mut chunk = HashMap::new();
+    chunk.insert("sections".to_string(), Value::List(vec![Value::Compound(section)]));
+    // Structure templates keep several palettes in a list of lists.
+    chunk.insert("palettes".to_string(), Value::List(vec![
+        Value::List(vec![block_state("minecraft:stone", &[])]),
+    ]));
+    let mut chunk = Value::Compound(chunk);
+
+    let mut remapper = Remapper::new();
+    remapper.insert("minecraft:grass_path", BlockMapping::new("minecraft:dirt_path"));
+    remapper.insert("minecraft:furnace", BlockMapping::new("minecraft:blast_furnace")
+                    .with_property("facing", "south")
+                    .without_property("lit"));
+    let report = remapper.apply(&mut chunk);
+
+    assert_eq!(report.remapped, 2);
+    assert_eq!(report.unmapped.get("minecraft:stone"), Some(&2));
+
+    let mut expected = HashMap::new();
+    expected.insert("palette".to_string(), Value::List(vec![
+        block_state("minecraft:dirt_path", &[]),
+        block_state("minecraft:blast_furnace", &[("facing", "south")]),
+        block_state("minecraft:stone", &[]),
+    ]));
+    let section = match chunk {
+        Value::Compound(ref map) => match map["sections"] {
+            Value::List(ref sections) => sections[0].clone(),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    };
+    let mut wrapped = HashMap::new();
+    wrapped.insert("block_states".to_string(), Value::Compound(expected));
+    assert_eq!(section, Value::Compound(wrapped));
+}
+
+#[test]
+fn chunk_set_block() {
+    let mut section = Section::new(block_state("minecraft:air", &[]));
+    assert!(section.data().is_empty());
+
+    // Sixteen distinct states fit in four bits; the seventeenth needs five.
+    for i in 0..17 {
+        let state = block_state(&format!("minecraft:block_{}", i), &[]);
+        section.set_block_at(i % 16, i / 16, 3, state);
+    }
+    assert_eq!(section.palette().len(), 18);
+    assert_eq!(section.data().len(), 342);
+    for i in 0..17 {
+        let state = block_state(&format!("minecraft:block_{}", i), &[]);
+        assert_eq!(section.block_at(i % 16, i / 16, 3), &state);
+    }
+    assert_eq!(section.block_at(15, 15, 15), &block_state("minecraft:air", &[]));
+
+    let (palette, data) = section.clone().into_parts();
+    assert_eq!(Section::from_parts(palette.clone(), data).unwrap(), section);
+    assert_eq!(Section::from_parts(palette.clone(), vec![0; 256]), Err(Error::IncompleteNbtValue));
+    // Five bits per index; the first index is 31 but the palette holds 18.
+    let mut bad = vec![0; 342];
+    bad[0] = 31;
+    assert_eq!(Section::from_parts(palette, bad), Err(Error::InvalidPaletteIndex(31, 18)));
+
+    let mut chunk = Chunk::new();
+    let stone = block_state("minecraft:stone", &[]);
+    chunk.set_block_at(1, -60, 2, stone.clone());
+    assert_eq!(chunk.block_at(1, -60, 2), Some(&stone));
+    assert_eq!(chunk.block_at(1, -59, 2), Some(&block_state("minecraft:air", &[])));
+    assert_eq!(chunk.block_at(1, 100, 2), None);
+    assert!(chunk.section(-4).is_some());
+
+    let value = chunk.to_value();
+    assert_eq!(Chunk::from_value(&value).unwrap(), chunk);
+
+    // Writing back keeps the other fields of a section and adds new ones.
+    let mut lit = HashMap::new();
+    lit.insert("Y".to_string(), Value::Byte(-4));
+    lit.insert("SkyLight".to_string(), Value::ByteArray(vec![0; 2048]));
+    let mut light_only = HashMap::new();
+    light_only.insert("Y".to_string(), Value::Byte(5));
+    let mut stored = HashMap::new();
+    stored.insert("sections".to_string(), Value::List(vec![Value::Compound(lit), Value::Compound(light_only)]));
+    let mut stored = Value::Compound(stored);
+    chunk.insert_section(0, section.clone());
+    chunk.write_to(&mut stored).unwrap();
+    let sections = match stored {
+        Value::Compound(ref map) => match map["sections"] {
+            Value::List(ref sections) => sections.clone(),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    };
+    assert_eq!(sections.len(), 3);
+    match sections[0] {
+        Value::Compound(ref map) => assert_eq!(map.len(), 3),
+        _ => unreachable!(),
+    }
+    assert_eq!(Chunk::from_value(&stored).unwrap(), chunk);
+    assert_eq!(Chunk::from_value(&Value::Compound(HashMap::new())),
+               Err(Error::MissingField("sections".to_string())));
+}
+
+#[test]
+fn uuid_replace() {
+    let old = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210u128;
+    let new = 0xdead_beef_0000_0000_0000_0000_0000_0001u128;
+    assert_eq!(uuid::to_string(old), "01234567-89ab-cdef-fedc-ba9876543210");
+    assert_eq!(uuid::parse("1234567-89AB-cdef-fedc-ba9876543210"), Some(old));
+    assert_eq!(uuid::from_int_array(&uuid::to_int_array(old)), Some(old));
+
+    let (most, least) = uuid::to_longs(old);
+    let mut modifier = HashMap::new();
+    modifier.insert("UUIDMost".to_string(), Value::Long(most));
+    modifier.insert("UUIDLeast".to_string(), Value::Long(least));
+    assert_eq!(uuid::get(&modifier, "UUID"), Some(old));
+    assert_eq!(uuid::get(&modifier, "Owner"), None);
+    assert_eq!(uuid::get(&modifier, "UUIDMost"), None);
+    let mut entity = HashMap::new();
+    entity.insert("UUID".to_string(), Value::IntArray(uuid::to_int_array(old).to_vec()));
+    entity.insert("Owner".to_string(), Value::String(uuid::to_string(old)));
+    entity.insert("Modifiers".to_string(), Value::List(vec![Value::Compound(modifier)]));
+    entity.insert("Other".to_string(), Value::IntArray(vec![1, 2, 3, 4]));
+    let mut entity = Value::Compound(entity);
+
+    assert_eq!(uuid::replace(&mut entity, old, new), 3);
+    assert_eq!(uuid::replace(&mut entity, old, new), 0);
+    assert_eq!(uuid::replace(&mut entity, new, old), 3);
+}
+
+#[test]
+fn item_stack_formats() {
+    let mut ench = HashMap::new();
+    ench.insert("id".to_string(), Value::String("minecraft:sharpness".to_string()));
+    ench.insert("lvl".to_string(), Value::Short(5));
+    let mut display = HashMap::new();
+    display.insert("Name".to_string(), Value::String("{\"text\":\"Edge\"}".to_string()));
+    let mut tag = HashMap::new();
+    tag.insert("Damage".to_string(), Value::Int(12));
+    tag.insert("Enchantments".to_string(), Value::List(vec![Value::Compound(ench)]));
+    tag.insert("display".to_string(), Value::Compound(display));
+    tag.insert("PluginData".to_string(), Value::Byte(1));
+    let mut legacy = HashMap::new();
+    legacy.insert("id".to_string(), Value::String("minecraft:diamond_sword".to_string()));
+    legacy.insert("Count".to_string(), Value::Byte(1));
+    legacy.insert("Slot".to_string(), Value::Byte(3));
+    legacy.insert("tag".to_string(), Value::Compound(tag));
+    let legacy = Value::Compound(legacy);
+
+    let stack = ItemStack::from_value(&legacy).unwrap();
+    assert_eq!(stack.slot, Some(3));
+    assert_eq!(stack.to_value(), legacy);
+
+    let modern = stack.to_components();
+    match modern.data {
+        ItemData::Components(ref c) => {
+            assert_eq!(c.damage, Some(12));
+            assert_eq!(c.enchantments.get("minecraft:sharpness"), Some(&5));
+            assert_eq!(c.custom_name, Some("{\"text\":\"Edge\"}".to_string()));
+            assert!(c.other.contains_key("minecraft:custom_data"));
+        },
+        _ => panic!("expected components"),
+    }
+    assert_eq!(ItemStack::from_value(&modern.to_value()).unwrap(), modern);
+    assert_eq!(modern.to_legacy(), stack);
+
+    let mut missing = HashMap::new();
+    missing.insert("count".to_string(), Value::Int(1));
+    assert_eq!(ItemStack::from_value(&Value::Compound(missing)),
+               Err(Error::MissingField("id".to_string())));
+}
+
+#[test]
+fn villager_offers() {
+    let mut offers = Offers::new();
+    offers.push(Offer::new(ItemStack::new("minecraft:emerald", 3),
+                           ItemStack::new("minecraft:bread", 6))
+                .with_buy_b(ItemStack::new("minecraft:wheat", 1))
+                .with_max_uses(16));
+    let mut villager = HashMap::new();
+    villager.insert("id".to_string(), Value::String("minecraft:villager".to_string()));
+    let mut villager = Value::Compound(villager);
+    assert_eq!(Offers::from_entity(&villager).unwrap(), Offers::new());
+
+    offers.write_to_entity(&mut villager).unwrap();
+    let mut read = Offers::from_entity(&villager).unwrap();
+    assert_eq!(read, offers);
+
+    read.recipes[0].uses = 16;
+    assert!(read.recipes[0].is_out_of_stock());
+    read.restock();
+    assert!(!read.recipes[0].is_out_of_stock());
+}
+
+#[test]
+fn generated_documents() {
+    fn depth(value: &Value) -> usize {
+        match *value {
+            Value::List(ref vals) => 1 + vals.iter().map(depth).max().unwrap_or(0),
+            Value::Compound(ref map) => 1 + map.values().map(depth).max().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    let options = GenOptions { max_depth: 3, ..GenOptions::default() };
+    let mut rng = XorShift::new(42);
+    for _ in 0..50 {
+        let value = testing::generate(&mut rng, &options);
+        assert!(depth(&value) <= 4);
+        let mut buf = Vec::new();
+        value.write(&mut buf).unwrap();
+        assert_eq!(Value::from_reader(0x0a, &mut io::Cursor::new(buf)).unwrap(), value);
+    }
+
+    // The same seed always produces the same document.
+    let a = testing::generate(&mut XorShift::new(7), &options);
+    let b = testing::generate(&mut XorShift::new(7), &options);
+    assert_eq!(a, b);
+
+    // Only enabled tag types are generated.
+    let options = GenOptions { weights: vec![(0x08, 1)], ..GenOptions::default() };
+    match testing::generate(&mut rng, &options) {
+        Value::Compound(ref map) => assert!(map.values().all(|v| v.id() == 0x08)),
+        _ => panic!("the root must be a compound"),
+    }
+}
+
+#[test]
+fn roundtrip_harness() {
+    let mut rng = XorShift::new(1234);
+    for _ in 0..20 {
+        testing::assert_roundtrip(&testing::generate(&mut rng, &GenOptions::default()));
+    }
+    let mut bytes = Vec::new();
+    io::Read::read_to_end(&mut File::open("tests/small4.nbt").unwrap(), &mut bytes).unwrap();
+    assert_eq!(testing::verify_roundtrip(&bytes).unwrap(), None);
+    assert!(testing::verify_roundtrip(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+#[should_panic(expected = "failed to encode")]
+fn roundtrip_harness_bad_value() {
+    testing::assert_roundtrip(&Value::List(vec![Value::Byte(1), Value::Int(2)]));
+}
+
+#[test]
+fn conformance_corpus() {
+    assert_eq!(conformance::run(conformance::decode), vec![]);
+
+    // A decoder that loses data is caught, with the path of the loss.
+    let failures = conformance::run(|bytes| {
+        let (title, mut value) = conformance::decode(bytes)?;
+        if let Value::Compound(ref mut map) = value {
+            map.remove("byteTest");
+        }
+        Ok((title, value))
+    });
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].case, "bigtest");
+    assert!(failures[0].reason.contains("byteTest"));
+}
+
+#[test]
+fn compression_registry() {
+    struct Reverse;
+
+    impl Codec for Reverse {
+        fn name(&self) -> &str { "test:reverse" }
+        fn decompress(&self, data: &[u8]) -> ::error::Result<Vec<u8>> {
+            Ok(data.iter().rev().cloned().collect())
+        }
+        fn compress(&self, data: &[u8]) -> ::error::Result<Vec<u8>> {
+            Ok(data.iter().rev().cloned().collect())
+        }
+    }
+
+    let mut registry = Registry::new();
+    let mut bytes = Vec::new();
+    io::Read::read_to_end(&mut File::open("tests/small4.nbt").unwrap(), &mut bytes).unwrap();
+
+    for &(name, id) in &[("minecraft:gzip", 1), ("minecraft:deflate", 2), ("minecraft:none", 3)] {
+        let (region_id, payload) = registry.compress_region(name, &bytes).unwrap();
+        assert_eq!(region_id, id);
+        assert_eq!(registry.sniff(&payload).unwrap().name(), name);
+        assert_eq!(registry.decompress(&payload).unwrap(), bytes);
+        assert_eq!(registry.decompress_region(id, &payload).unwrap(), bytes);
+    }
+
+    assert_eq!(registry.decompress_region(127, b"\x00\x0ctest:reverse\x01\x02"),
+               Err(Error::UnknownCompression("test:reverse".to_string())));
+    registry.register(Reverse);
+    let (id, payload) = registry.compress_region("test:reverse", &bytes).unwrap();
+    assert_eq!(id, compression::CUSTOM_REGION_ID);
+    assert_eq!(registry.decompress_region(id, &payload).unwrap(), bytes);
+    assert_eq!(registry.decompress_region(4, &payload),
+               Err(Error::UnknownCompression("region compression 4".to_string())));
+}
+
+#[test]
+fn captured_bytes() {
+    let mut bytes = Vec::new();
+    io::Read::read_to_end(&mut File::open("tests/small3.nbt").unwrap(), &mut bytes).unwrap();
+    let captured = capture::read_captured(&bytes[..]).unwrap();
+    assert_eq!(captured.title, "aaa");
+    assert_eq!(captured.raw, bytes);
+
+    let first = captured.span.child("bbb").and_then(|s| s.element(0)).unwrap();
+    let name = first.child("name").unwrap();
+    assert_eq!(captured.bytes(name), b"\x00\x06wololo");
+    assert_eq!(captured.bytes(first.child("ccc").unwrap()), &[0x11, 0x22, 0x33, 0x44]);
+
+    // An unchanged document is written back exactly.
+    let mut dst = Vec::new();
+    captured.write_reusing("aaa", &captured.value, &mut dst).unwrap();
+    assert_eq!(dst, bytes);
+
+    // Changes are encoded, and the rest is copied.
+    let mut value = captured.value.clone();
+    if let Value::Compound(ref mut map) = value {
+        if let Some(&mut Value::List(ref mut list)) = map.get_mut("bbb") {
+            list[1] = Value::Compound(HashMap::new());
+        }
+    }
+    let mut dst = Vec::new();
+    captured.write_reusing("aaa", &value, &mut dst).unwrap();
+    let second = captured.span.child("bbb").and_then(|s| s.element(1)).unwrap();
+    assert_eq!(dst[..second.range.start], bytes[..second.range.start]);
+    assert_eq!(capture::read_captured(&dst[..]).unwrap().value, value);
+}
+
+#[test]
+fn streaming_rewrite() {
+    let mut file = File::open("tests/small3.nbt").unwrap();
+    let rules = Rules::new()
+        .set("bbb.*.ccc", 7i32)
+        .rename("bbb.1.name", "label")
+        .delete("bbb.0.name")
+        .set("bbb.0.added", "new")
+        .set("ddd", 1i8);
+    let mut dst = Vec::new();
+    assert_eq!(rewrite::rewrite(&mut file, &mut dst, &rules).unwrap(), 6);
+
+    let blob = Blob::from_reader(&mut &dst[..]).unwrap();
+    let mut first = HashMap::new();
+    first.insert("ccc".to_string(), Value::Int(7));
+    first.insert("added".to_string(), Value::String("new".to_string()));
+    let mut second = HashMap::new();
+    second.insert("ccc".to_string(), Value::Int(7));
+    second.insert("label".to_string(), Value::String("wololo".to_string()));
+    assert_eq!(blob["bbb"], Value::List(vec![Value::Compound(first), Value::Compound(second)]));
+    assert_eq!(blob["ddd"], Value::Byte(1));
+
+    // List elements must keep the type of the list.
+    let mut file = File::open("tests/small3.nbt").unwrap();
+    let rules = Rules::new().set("bbb.0", 1i8);
+    assert_eq!(rewrite::rewrite(&mut file, &mut Vec::new(), &rules), Err(Error::HeterogeneousList));
+}
+
+#[test]
+fn batch_processing() {
+    use std::fs;
+
+    let root = ::std::env::temp_dir().join(format!("nbt-batch-{}", ::std::process::id()));
+    fs::create_dir_all(root.join("playerdata")).unwrap();
+    for name in &["a.dat", "b.dat"] {
+        let mut nbt = Blob::new("".to_string());
+        nbt.insert("Health".to_string(), 20i16).unwrap();
+        nbt.write_gzip(&mut File::create(root.join("playerdata").join(name)).unwrap()).unwrap();
+    }
+    fs::write(root.join("playerdata").join("b.dat_old"), b"not nbt").unwrap();
+    fs::write(root.join("broken.dat"), b"not nbt either").unwrap();
+
+    let report = BatchProcessor::new(&root).include("*.dat").threads(2).run(|path, doc| {
+        if path.ends_with("b.dat") {
+            return Ok(false);
+        }
+        assert_eq!(doc.compression, "minecraft:gzip");
+        if let Value::Compound(ref mut map) = doc.value {
+            map.insert("Health".to_string(), Value::Short(1));
+        }
+        doc.compression = "minecraft:deflate".to_string();
+        Ok(true)
+    }).unwrap();
+
+    assert_eq!(report.files.len(), 3);
+    assert_eq!(report.modified(), vec![root.join("playerdata").join("a.dat").as_path()]);
+    assert_eq!(report.failed().len(), 1);
+    assert_eq!(report.failed()[0].0, root.join("broken.dat").as_path());
+
+    let a = Blob::from_zlib(&mut File::open(root.join("playerdata").join("a.dat")).unwrap()).unwrap();
+    assert_eq!(a["Health"], Value::Short(1));
+    let b = Blob::from_gzip(&mut File::open(root.join("playerdata").join("b.dat")).unwrap()).unwrap();
+    assert_eq!(b["Health"], Value::Short(20));
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn watch_changes() {
+    use std::fs;
+    use std::time::Duration;
+
+    let root = ::std::env::temp_dir().join(format!("nbt-watch-{}", ::std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    let path = root.join("level.dat");
+    let mut nbt = Blob::new("".to_string());
+    nbt.insert("Time".to_string(), 1i64).unwrap();
+    nbt.write_gzip(&mut File::create(&path).unwrap()).unwrap();
+
+    let mut watcher = Watcher::new(Duration::from_secs(0));
+    watcher.watch(&root);
+    assert!(watcher.poll().is_empty());
+
+    // A change is reported once it has been seen unchanged by two polls.
+    nbt.insert("Day".to_string(), 2i64).unwrap();
+    nbt.write_gzip(&mut File::create(&path).unwrap()).unwrap();
+    fs::write(root.join("notes.txt"), b"ignored").unwrap();
+    assert!(watcher.poll().is_empty());
+    let changes = watcher.poll();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path, path);
+    let doc = changes[0].result.as_ref().unwrap();
+    assert_eq!(doc.compression, "minecraft:gzip");
+    match doc.value {
+        Value::Compound(ref map) => assert_eq!(map["Day"], Value::Long(2)),
+        _ => panic!("expected a compound"),
+    }
+    assert!(watcher.poll().is_empty());
+
+    // A truncated file gets one more chance before it is reported.
+    let mut bytes = Vec::new();
+    nbt.write(&mut bytes).unwrap();
+    fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+    assert!(watcher.poll().is_empty());
+    assert!(watcher.poll().is_empty());
+    let changes = watcher.poll();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].result.as_ref().err(), Some(&Error::IncompleteNbtValue));
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn normalized_encoding() {
+    use std::fs;
+
+    let mut map = HashMap::new();
+    for (i, name) in ["zeta", "alpha", "mid", "beta"].iter().enumerate() {
+        map.insert(name.to_string(), Value::Int(i as i32));
+    }
+    map.insert("empty".to_string(), Value::List(Vec::new()));
+    let value = Value::Compound(map);
+
+    let mut a = Vec::new();
+    normalize::write_canonical(&mut a, "", &value).unwrap();
+    let mut b = Vec::new();
+    normalize::write_canonical(&mut b, "", &value.clone()).unwrap();
+    assert_eq!(a, b);
+    // Entries are sorted, and empty lists are tagged as lists of TAG_End.
+    assert_eq!(&a[3..12], &[0x03, 0x00, 0x05, b'a', b'l', b'p', b'h', b'a', 0x00]);
+    assert_eq!(&a[26..38], &[0x09, 0x00, 0x05, b'e', b'm', b'p', b't', b'y', 0x00, 0, 0, 0]);
+    assert_eq!(Value::from_reader(0x0a, &mut &a[3..]).unwrap(), value);
+
+    let root = ::std::env::temp_dir().join(format!("nbt-normalize-{}", ::std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    let path = root.join("level.dat");
+    fs::copy("tests/level.dat", &path).unwrap();
+    let report = normalize::normalize_world(&root).unwrap();
+    assert_eq!(report.modified(), vec![path.as_path()]);
+    let normalized = fs::read(&path).unwrap();
+    assert!(!normalize::normalize_file(&path).unwrap());
+    assert_eq!(fs::read(&path).unwrap(), normalized);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn interned_subtrees() {
+    let item = |count: i8| {
+        let mut map = HashMap::new();
+        map.insert("id".to_string(), Value::String("minecraft:stone".to_string()));
+        map.insert("Count".to_string(), Value::Byte(count));
+        Value::Compound(map)
+    };
+    let mut pool = Pool::new();
+    let a = pool.intern(&Value::List(vec![item(1), item(64), item(1)]));
+    let b = pool.intern(&Value::List(vec![item(64)]));
+
+    let (first, other) = match (&*a, &*b) {
+        (Node::List(a), Node::List(b)) => (a.clone(), b.clone()),
+        _ => panic!("expected lists"),
+    };
+    assert!(std::sync::Arc::ptr_eq(&first[0], &first[2]));
+    assert!(std::sync::Arc::ptr_eq(&first[1], &other[0]));
+    assert!(!std::sync::Arc::ptr_eq(&first[0], &first[1]));
+    assert_eq!(a.to_value(), Value::List(vec![item(1), item(64), item(1)]));
+
+    // The string and both counts, two items, and two lists.
+    assert_eq!(pool.len(), 7);
+    assert!(pool.hits() > 0);
+
+    drop((a, first));
+    pool.shrink();
+    assert_eq!(pool.len(), 4);
+
+    let mut src = io::Cursor::new(vec![0x0a, 0x00, 0x01, b'x', 0x00]);
+    let (title, root) = pool.read(&mut src).unwrap();
+    assert_eq!(title, "x");
+    assert_eq!(*root, Node::Compound(Default::default()));
+}
+
+#[test]
+fn projected_columns() {
+    let chunk = |time: i64, ys: &[i8]| {
+        let sections = ys.iter().map(|&y| {
+            let mut section = HashMap::new();
+            section.insert("Y".to_string(), Value::Byte(y));
+            section.insert("Blocks".to_string(), Value::ByteArray(vec![0; 64]));
+            Value::Compound(section)
+        }).collect();
+        let mut level = HashMap::new();
+        level.insert("InhabitedTime".to_string(), Value::Long(time));
+        level.insert("Sections".to_string(), Value::List(sections));
+        let mut root = HashMap::new();
+        root.insert("Level".to_string(), Value::Compound(level));
+        let root = Value::Compound(root);
+        let mut bytes = Vec::new();
+        root.write_header(&mut bytes, "").unwrap();
+        root.write(&mut bytes).unwrap();
+        bytes
+    };
+    let documents = [chunk(10, &[0, 1]), chunk(20, &[]), chunk(30, &[4])];
+
+    let projection = Projection::new(&["Level.InhabitedTime", "Level.Sections.0.Y", "Level.Missing"]);
+    let columns = projection.collect(documents.iter().map(|d| &d[..])).unwrap();
+    assert_eq!(columns.len(), 3);
+    assert_eq!(columns.column("Level.InhabitedTime").unwrap(),
+               &[Some(Value::Long(10)), Some(Value::Long(20)), Some(Value::Long(30))][..]);
+    assert_eq!(columns.column("Level.Sections.0.Y").unwrap(),
+               &[Some(Value::Byte(0)), None, Some(Value::Byte(4))][..]);
+    assert_eq!(columns.column("Level.Missing").unwrap(), &[None, None, None][..]);
+    assert!(columns.column("Level").is_none());
+
+    assert!(Projection::new(&["Level.InhabitedTime"]).read(&mut &documents[0][..10]).is_err());
+}
+
+#[test]
+fn csv_export() {
+    let player = |name: &str, level: i32| {
+        let mut root = HashMap::new();
+        root.insert("Name".to_string(), Value::String(name.to_string()));
+        root.insert("XpLevel".to_string(), Value::Int(level));
+        root.insert("Pos".to_string(), Value::List(vec![Value::Double(0.5)]));
+        let root = Value::Compound(root);
+        let mut bytes = Vec::new();
+        root.write_header(&mut bytes, "").unwrap();
+        root.write(&mut bytes).unwrap();
+        bytes
+    };
+    let documents = [player("Steve", 30), player("Alex, \"the\" builder", 2)];
+
+    let mut csv = Vec::new();
+    let rows = project::to_csv(&mut csv, &["Name", "XpLevel", "Pos.0", "Pos"],
+                               documents.iter().map(|d| &d[..])).unwrap();
+    assert_eq!(rows, 2);
+    assert_eq!(String::from_utf8(csv).unwrap(),
+               "Name,XpLevel,Pos.0,Pos\r\n\
+                Steve,30,0.5,\r\n\
+                \"Alex, \"\"the\"\" builder\",2,0.5,\r\n");
+}
+
+#[test]
+fn audit_collects_problems() {
+    let bytes = vec![
+        0x0a, 0x00, 0x00,
+            0x08, 0x00, 0x04, b'n', b'a', b'm', b'e', 0x00, 0x02, 0xff, 0xfe,
+            0x09, 0x00, 0x01, b'l', 0x0d, 0x00, 0x00, 0x00, 0x00,
+            0x01, 0x00, 0x01, b'b', 0x05,
+            0x0f, 0x00, 0x01, b'x', 0x01, 0x02,
+    ];
+    let report = audit::audit(&bytes[..]);
+    assert!(!report.is_clean());
+    let problems: Vec<_> = report.problems.iter()
+        .map(|p| (&p.path[..], p.offset, &p.error))
+        .collect();
+    assert_eq!(problems, vec![
+        ("name", 10, &Error::InvalidUtf8),
+        ("l", 18, &Error::InvalidTypeId(0x0d)),
+        ("x", 28, &Error::InvalidTypeId(0x0f)),
+    ]);
+    assert_eq!(report.problems[0].to_string(), "name (at byte 10): a string is not valid UTF-8");
+
+    let mut map = HashMap::new();
+    map.insert("name".to_string(), Value::String("\u{fffd}\u{fffd}".to_string()));
+    map.insert("l".to_string(), Value::List(vec![]));
+    map.insert("b".to_string(), Value::Byte(5));
+    assert_eq!(report.value, Some(Value::Compound(map)));
+
+    // A truncated document keeps what was decoded before the end.
+    let report = audit::audit(&bytes[..26]);
+    assert_eq!(report.problems.len(), 3);
+    assert_eq!(report.problems[2].error, Error::IncompleteNbtValue);
+    assert_eq!(report.problems[2].path, "");
+
+    let clean = audit::audit(&[0x0a, 0x00, 0x00, 0x00][..]);
+    assert!(clean.is_clean());
+    assert_eq!(clean.value, Some(Value::Compound(HashMap::new())));
+}
+
+#[test]
+fn audit_warnings() {
+    let bytes = vec![
+        0x0a, 0x00, 0x00,
+            0x09, 0x00, 0x01, b'l', 0x03, 0x00, 0x00, 0x00, 0x00,
+            0x04, 0x00, 0x08, b'U', b'U', b'I', b'D', b'M', b'o', b's', b't', 0, 0, 0, 0, 0, 0, 0, 1,
+            0x04, 0x00, 0x09, b'U', b'U', b'I', b'D', b'L', b'e', b'a', b's', b't', 0, 0, 0, 0, 0, 0, 0, 2,
+            0x0b, 0x00, 0x01, b'a', 0x00, 0x00, 0x00, 0x02, 0, 0, 0, 1, 0, 0, 0, 2,
+            0x01, 0x00, 0x01, b'b', 0x01,
+            0x01, 0x00, 0x01, b'b', 0x02,
+        0x00,
+    ];
+    let options = audit::Options { max_array_len: 1, ..Default::default() };
+    let report = audit::audit_with(&bytes[..], &options);
+    assert!(report.is_clean());
+    let warnings: Vec<_> = report.warnings.iter()
+        .map(|w| (&w.path[..], w.offset, w.kind.clone()))
+        .collect();
+    assert_eq!(warnings, vec![
+        ("l", 7, audit::WarningKind::TypedEmptyList(0x03)),
+        ("a", 55, audit::WarningKind::LargeArray(2)),
+        ("b", 72, audit::WarningKind::DuplicateKey),
+        ("UUIDMost", 12, audit::WarningKind::LegacyUuid),
+    ]);
+    assert_eq!(report.warnings[0].to_string(), "l (at byte 7): empty list has element type 3");
+
+    let mut count = 0;
+    let (_, value) = audit::read_with_warnings(&bytes[..], &Default::default(), |_| count += 1).unwrap();
+    assert_eq!(count, 3);
+    assert_eq!(value, report.value.unwrap());
+
+    let bad = [0x0a, 0x00, 0x00, 0x08, 0x00, 0x01, b's', 0x00, 0x01, 0xff, 0x00];
+    let result = audit::read_with_warnings(&bad[..], &Default::default(), |_| ());
+    assert_eq!(result.err(), Some(Error::InvalidUtf8));
+}
+
+#[test]
+fn data_version_stamping() {
+    let world = DataVersion::new(3700).with_oldest(1500);
+
+    let mut chunk = Value::Compound(HashMap::new());
+    assert_eq!(version::read(Structure::Chunk, &chunk), Ok(0));
+    assert_eq!(world.check(Structure::Chunk, &chunk), Err(Error::DataVersionTooOld(0, 1500)));
+    world.stamp(Structure::Chunk, &mut chunk).unwrap();
+    assert_eq!(world.check(Structure::Chunk, &chunk), Ok(3700));
+    assert_eq!(DataVersion::new(3600).check(Structure::Chunk, &chunk),
+               Err(Error::DataVersionTooNew(3700, 3600)));
+
+    let mut level = HashMap::new();
+    level.insert("Data".to_string(), Value::Compound(HashMap::new()));
+    let mut level = Value::Compound(level);
+    world.stamp(Structure::Level, &mut level).unwrap();
+    assert_eq!(version::read(Structure::Level, &level), Ok(3700));
+    assert_eq!(version::read(Structure::Chunk, &level), Ok(0));
+    assert_eq!(world.stamp(Structure::Level, &mut Value::Compound(HashMap::new())),
+               Err(Error::MissingField("Data".to_string())));
+    assert_eq!(Error::DataVersionTooNew(3700, 3600).to_string(),
+               "data version 3700 is newer than the newest supported, 3600");
+}
+
+#[test]
+fn region_minimal_rewrite() {
+    use std::fs;
+
+    let chunk = |fill: usize| {
+        let mut map = HashMap::new();
+        map.insert("Blocks".to_string(), Value::ByteArray(vec![7; fill]));
+        Document {
+            title: String::new(),
+            value: Value::Compound(map),
+            compression: "minecraft:none".to_string(),
+        }
+    };
+    let path = ::std::env::temp_dir().join(format!("nbt-region-{}.mca", ::std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let mut region = RegionEditor::open(&path).unwrap();
+    region.write_chunk(0, 0, &chunk(10)).unwrap();
+    region.write_chunk(33, 0, &chunk(20)).unwrap();
+    assert_eq!(region.dirty_chunks(), vec![(0, 0), (1, 0)]);
+    assert_eq!(region.save().unwrap(), 2);
+    assert!(region.dirty_chunks().is_empty());
+    let before = fs::read(&path).unwrap();
+    assert_eq!(before.len(), 4 * 4096);
+
+    // A chunk that outgrows its sector moves to the end of the file.
+    let mut region = RegionEditor::open(&path).unwrap();
+    assert_eq!(region.read_chunk(1, 0).unwrap(), Some(chunk(20)));
+    region.write_chunk(0, 0, &chunk(5000)).unwrap();
+    assert_eq!(region.read_chunk(0, 0).unwrap(), Some(chunk(5000)));
+    region.save().unwrap();
+    let after = fs::read(&path).unwrap();
+    assert_eq!(after.len(), 6 * 4096);
+    assert_eq!(&after[4..8], &before[4..8]);
+    assert_eq!(&after[3 * 4096..4 * 4096], &before[3 * 4096..4 * 4096]);
+    assert_eq!(&after[0..4], &[0, 0, 4, 2]);
+
+    // A chunk that shrinks reuses its sectors, and removal frees them.
+    region.write_chunk(0, 0, &chunk(1)).unwrap();
+    region.remove_chunk(1, 0);
+    region.save().unwrap();
+    let mut region = RegionEditor::open(&path).unwrap();
+    assert_eq!(region.read_chunk(0, 0).unwrap(), Some(chunk(1)));
+    assert_eq!(region.read_chunk(1, 0).unwrap(), None);
+    assert_eq!(region.timestamp(1, 0), 0);
+    assert!(region.timestamp(0, 0) > 0);
+    assert_eq!(&fs::read(&path).unwrap()[0..8], &[0, 0, 4, 1, 0, 0, 0, 0]);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn region_chunk_iteration() {
+    use std::fs;
+    use std::io::Read;
+
+    let chunk = |x: i32| {
+        let mut map = HashMap::new();
+        map.insert("xPos".to_string(), Value::Int(x));
+        Document { title: String::new(), value: Value::Compound(map), compression: "minecraft:deflate".to_string() }
+    };
+    let path = ::std::env::temp_dir().join(format!("nbt-region-iter-{}.mca", ::std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let mut region = RegionEditor::open(&path).unwrap();
+    region.write_chunk(3, 1, &chunk(3)).unwrap();
+    region.write_chunk(2, 0, &chunk(2)).unwrap();
+    region.save().unwrap();
+    let info = region.chunk_info(3, 1).unwrap().unwrap();
+    assert_eq!((info.sectors, &info.compression[..], info.external), (1, "minecraft:deflate", false));
+    assert!(info.length > 1 && info.timestamp > 0);
+    assert_eq!(region.chunk_info(4, 4).unwrap(), None);
+    assert_eq!(region.free_sectors().unwrap(), 0);
+    region.write_chunk(5, 5, &chunk(5)).unwrap();
+
+    let read: Vec<_> = region.iter_chunks().map(|(pos, chunk)| (pos, chunk.unwrap())).collect();
+    assert_eq!(read, vec![((2, 0), chunk(2)), ((3, 1), chunk(3)), ((5, 5), chunk(5))]);
+    let par: Vec<_> = region.par_iter_chunks().into_iter().map(|(pos, chunk)| (pos, chunk.unwrap())).collect();
+    assert_eq!(par, read);
+
+    let mut data = Vec::new();
+    region.chunk(3, 1).unwrap().unwrap().read_to_end(&mut data).unwrap();
+    let mut expected = Vec::new();
+    chunk(3).value.write_header(&mut expected, "").unwrap();
+    chunk(3).value.write(&mut expected).unwrap();
+    assert_eq!(data, expected);
+    assert!(region.chunk(0, 0).unwrap().is_none());
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn region_compaction() {
+    use std::fs;
+
+    let chunk = |fill: usize| {
+        let mut map = HashMap::new();
+        map.insert("Blocks".to_string(), Value::ByteArray(vec![1; fill]));
+        Value::Compound(map)
+    };
+    let data = |fill: usize| {
+        let mut data = Vec::new();
+        chunk(fill).write_header(&mut data, "").unwrap();
+        chunk(fill).write(&mut data).unwrap();
+        data
+    };
+    let path = ::std::env::temp_dir().join(format!("nbt-region-compact-{}.mca", ::std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let mut region = RegionEditor::open(&path).unwrap();
+    region.write_chunk_data(0, 0, "minecraft:none", &data(5000)).unwrap();
+    region.write_chunk_data(1, 0, "minecraft:none", &data(10)).unwrap();
+    region.write_chunk_data(2, 0, "minecraft:none", &data(10)).unwrap();
+    region.save().unwrap();
+    assert_eq!(fs::read(&path).unwrap().len(), 6 * 4096);
+
+    // Removing the first chunk leaves a two sector gap.
+    region.remove_chunk(0, 0);
+    region.save().unwrap();
+    assert_eq!(region.free_sectors().unwrap(), 2);
+    assert_eq!(region.compact().unwrap(), 2);
+    assert_eq!(region.free_sectors().unwrap(), 0);
+    let file = fs::read(&path).unwrap();
+    assert_eq!(file.len(), 4 * 4096);
+    assert_eq!(&file[4..12], &[0, 0, 2, 1, 0, 0, 3, 1]);
+
+    let mut region = RegionEditor::open(&path).unwrap();
+    assert_eq!(region.read_chunk(2, 0).unwrap().unwrap().value, chunk(10));
+    assert_eq!(region.compact().unwrap(), 0);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn region_external_chunks() {
+    use std::fs;
+
+    let chunk = |fill: usize| {
+        let mut map = HashMap::new();
+        map.insert("Blocks".to_string(), Value::ByteArray(vec![3; fill]));
+        Document { title: String::new(), value: Value::Compound(map), compression: "minecraft:none".to_string() }
+    };
+    let dir = ::std::env::temp_dir().join(format!("nbt-region-mcc-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("r.-1.2.mca");
+    let mcc = dir.join("c.-31.64.mcc");
+
+    let mut region = RegionEditor::open(&path).unwrap();
+    region.write_chunk(1, 0, &chunk(2 << 20)).unwrap();
+    region.save().unwrap();
+    let file = fs::read(&path).unwrap();
+    assert_eq!(file.len(), 3 * 4096);
+    let info = region.chunk_info(1, 0).unwrap().unwrap();
+    assert_eq!((info.length, info.sectors, info.external), (1, 1, true));
+    assert_eq!(&file[4..8], &[0, 0, 2, 1]);
+    assert_eq!(&file[2 * 4096..2 * 4096 + 5], &[0, 0, 0, 1, 0x83]);
+    assert!(fs::metadata(&mcc).unwrap().len() > 2 << 20);
+
+    let mut region = RegionEditor::open(&path).unwrap();
+    assert_eq!(region.read_chunk(1, 0).unwrap(), Some(chunk(2 << 20)));
+
+    // A chunk that shrinks back into the region file no longer needs its
+    // .mcc file.
+    region.write_chunk(1, 0, &chunk(10)).unwrap();
+    region.save().unwrap();
+    assert!(!mcc.exists());
+    assert_eq!(RegionEditor::open(&path).unwrap().read_chunk(1, 0).unwrap(), Some(chunk(10)));
+
+    // Without the usual file name, there is nowhere to put a large chunk.
+    let mut unnamed = RegionEditor::open(dir.join("chunks.mca")).unwrap();
+    assert!(unnamed.write_chunk(0, 0, &chunk(2 << 20)).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn shared_buffer_views() {
+    let entities: Vec<Value> = (0..8).map(|i| {
+        let mut map = HashMap::new();
+        map.insert("id".to_string(), Value::String(format!("minecraft:mob{}", i)));
+        map.insert("Pos".to_string(), Value::List(vec![Value::Double(i as f64); 3]));
+        Value::Compound(map)
+    }).collect();
+    let mut root = HashMap::new();
+    root.insert("Entities".to_string(), Value::List(entities.clone()));
+    root.insert("Version".to_string(), Value::Int(3));
+    let root = Value::Compound(root);
+    let mut bytes = Vec::new();
+    root.write_header(&mut bytes, "chunk").unwrap();
+    root.write(&mut bytes).unwrap();
+
+    let (title, tag) = SharedTag::root(SharedBytes::from(bytes)).unwrap();
+    assert_eq!(title, "chunk");
+    assert_eq!(tag.decode().unwrap(), root);
+    let entries = tag.entries().unwrap();
+    assert_eq!(entries.len(), 2);
+    let list = &entries.iter().find(|e| e.0 == "Entities").unwrap().1;
+    assert!(list.entries().is_err());
+
+    let elements = list.elements().unwrap();
+    let decoded: Vec<Value> = ::std::thread::scope(|scope| {
+        let workers: Vec<_> = elements.iter().cloned()
+            .map(|element| scope.spawn(move || element.decode().unwrap()))
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+    assert_eq!(decoded, entities);
+
+    let view = SharedBytes::from(vec![1, 2, 3, 4]).slice(1..4).slice(1..3);
+    assert_eq!(view.as_slice(), &[3, 4]);
+}
+
+#[test]
+fn parallel_tree_operations() {
+    let mut level = HashMap::new();
+    let entities = (0..64).map(|i| {
+        let mut entity = HashMap::new();
+        entity.insert("id".to_string(), Value::String(if i % 8 == 0 { "creeper" } else { "cow" }.to_string()));
+        entity.insert("Health".to_string(), Value::Float(i as f32));
+        Value::Compound(entity)
+    }).collect();
+    level.insert("Entities".to_string(), Value::List(entities));
+    level.insert("Time".to_string(), Value::Long(24000));
+    let mut root = Value::Compound(level);
+
+    let count = ::std::sync::atomic::AtomicUsize::new(0);
+    parallel::par_walk(&root, |_, _| { count.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed); });
+    // The root, the list, 64 compounds of two entries each, and Time.
+    assert_eq!(count.into_inner(), 1 + 1 + 64 * 3 + 1);
+
+    let creepers = parallel::par_find(&root, |_, v| *v == Value::String("creeper".to_string()));
+    assert_eq!(creepers.len(), 8);
+    assert!(creepers.contains(&"Entities[0].id".to_string()));
+    assert!(creepers.contains(&"Entities[56].id".to_string()));
+
+    parallel::par_transform(&mut root, |path, v| {
+        if path.ends_with(".Health") {
+            *v = Value::Float(20.0);
+        }
+    });
+    let healthy = parallel::par_find(&root, |path, v| path.ends_with(".Health") && *v == Value::Float(20.0));
+    assert_eq!(healthy.len(), 64);
+    assert_eq!(parallel::par_find(&root, |path, _| path == "Time"), vec!["Time".to_string()]);
+}
+
+#[test]
+fn snbt_give_commands() {
+    let mut map = HashMap::new();
+    map.insert("b".to_string(), Value::Byte(1));
+    map.insert("s".to_string(), Value::Short(-2));
+    map.insert("L".to_string(), Value::Long(3));
+    map.insert("f".to_string(), Value::Float(1.5));
+    map.insert("d".to_string(), Value::Double(2.0));
+    map.insert("bytes".to_string(), Value::ByteArray(vec![1, -1]));
+    map.insert("ints".to_string(), Value::IntArray(vec![]));
+    map.insert("list".to_string(), Value::List(vec![Value::Int(1), Value::Int(2)]));
+    map.insert("odd key".to_string(), Value::String("say \"hi\"".to_string()));
+    map.insert("quote".to_string(), Value::String("it's \\".to_string()));
+    assert_eq!(snbt::to_string(&Value::Compound(map)),
+               "{L:3L,b:1b,bytes:[B;1b,-1b],d:2.0d,f:1.5f,ints:[I;],list:[1,2],\
+                \"odd key\":'say \"hi\"',quote:\"it's \\\\\",s:-2s}");
+
+    let mut sword = ItemStack::new("minecraft:diamond_sword", 1);
+    if let ItemData::Components(ref mut components) = sword.data {
+        components.custom_name = Some("{\"text\":\"Edge\"}".to_string());
+        components.damage = Some(5);
+    }
+    assert_eq!(GiveCommand::new("@p").command(&sword),
+               "/give @p minecraft:diamond_sword[minecraft:custom_name='{\"text\":\"Edge\"}',minecraft:damage=5]");
+
+    let mut tag = HashMap::new();
+    tag.insert("Unbreakable".to_string(), Value::Byte(1));
+    let legacy = ItemStack { data: ItemData::Tag(tag), ..ItemStack::new("minecraft:stone", 64) };
+    assert_eq!(GiveCommand::new("Steve").with_slash(false).command(&legacy),
+               "give Steve minecraft:stone{Unbreakable:1b} 64");
+    assert_eq!(snbt::item_argument(&ItemStack::new("minecraft:dirt", 2)), "minecraft:dirt 2");
+}
+
+#[test]
+fn snbt_pretty() {
+    let mut pos = HashMap::new();
+    pos.insert("y".to_string(), Value::Int(64));
+    pos.insert("x".to_string(), Value::Int(-3));
+    let mut map = HashMap::new();
+    map.insert("id".to_string(), Value::String("minecraft:chest".to_string()));
+    map.insert("tags".to_string(), Value::List(vec![Value::Byte(1), Value::Byte(2)]));
+    map.insert("pos".to_string(), Value::List(vec![Value::Compound(pos), Value::Compound(HashMap::new())]));
+    map.insert("heights".to_string(), Value::LongArray(vec![1, 2]));
+    let value = Value::Compound(map);
+
+    assert_eq!(value.to_snbt_pretty(2),
+               "{\n  heights: [L;1L,2L],\n  id: \"minecraft:chest\",\n  pos: [\n    {\n      x: -3,\n      y: 64\n    },\n    {}\n  ],\n  tags: [1b, 2b]\n}");
+    assert_eq!(value.to_snbt(), snbt::to_string(&value));
+    assert_eq!(Value::Int(5).to_snbt_pretty(4), "5");
+}
+
+#[test]
+fn world_search() {
+    use std::fs;
+
+    let chunk = |id: &str| {
+        let mut item = HashMap::new();
+        item.insert("id".to_string(), Value::String(id.to_string()));
+        let mut chest = HashMap::new();
+        chest.insert("Items".to_string(), Value::List(vec![Value::Compound(item)]));
+        let mut map = HashMap::new();
+        map.insert("block_entities".to_string(), Value::List(vec![Value::Compound(chest)]));
+        Document { title: String::new(), value: Value::Compound(map), compression: "minecraft:deflate".to_string() }
+    };
+    let root = ::std::env::temp_dir().join(format!("nbt-world-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for dir in &["region", "DIM-1/region", "dimensions/mymod/deep/sky/region"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    let mut region = RegionEditor::open(root.join("region/r.0.0.mca")).unwrap();
+    region.write_chunk(0, 0, &chunk("minecraft:diamond")).unwrap();
+    region.write_chunk(3, 1, &chunk("minecraft:stick")).unwrap();
+    region.save().unwrap();
+    let mut region = RegionEditor::open(root.join("DIM-1/region/r.-1.0.mca")).unwrap();
+    region.write_chunk(31, 0, &chunk("minecraft:diamond")).unwrap();
+    region.save().unwrap();
+    fs::write(root.join("dimensions/mymod/deep/sky/region/r.0.0.mca"), b"").unwrap();
+    fs::write(root.join("region/r.1.0.mca"), b"corrupt").unwrap();
+
+    let world = World::new(&root).threads(2);
+    let dims: Vec<String> = world.dimensions().unwrap().into_iter().map(|(id, _)| id).collect();
+    assert_eq!(dims, vec!["minecraft:overworld", "minecraft:the_nether", "mymod:deep/sky"]);
+
+    let results = world.search(|_, v| *v == Value::String("minecraft:diamond".to_string())).unwrap();
+    let found: Vec<_> = results.matches.iter().map(|m| (&m.dimension[..], m.chunk, &m.path[..])).collect();
+    assert_eq!(found, vec![("minecraft:the_nether", (31, 0), "block_entities[0].Items[0].id"),
+                           ("minecraft:overworld", (0, 0), "block_entities[0].Items[0].id")]);
+    assert_eq!(results.failures.len(), 1);
+    assert_eq!(results.failures[0].region, root.join("region/r.1.0.mca"));
+
+    let ids = world.search_path("block_entities[*].Items[*].id").unwrap();
+    assert_eq!(ids.matches.len(), 3);
+
+    let chunks = ::std::sync::Mutex::new(Vec::new());
+    let failures = world.par_chunks(|dim, region, pos, chunk| {
+        let region = region.file_name().unwrap().to_str().unwrap().to_string();
+        chunks.lock().unwrap().push((dim.to_string(), region, pos, chunk.unwrap().value));
+    }).unwrap();
+    let mut chunks = chunks.into_inner().unwrap();
+    chunks.sort_by(|a, b| (&a.0, &a.1, a.2).cmp(&(&b.0, &b.1, b.2)));
+    let expected = vec![
+        ("minecraft:overworld", "r.0.0.mca", (0, 0), chunk("minecraft:diamond").value),
+        ("minecraft:overworld", "r.0.0.mca", (3, 1), chunk("minecraft:stick").value),
+        ("minecraft:the_nether", "r.-1.0.mca", (31, 0), chunk("minecraft:diamond").value),
+    ];
+    assert_eq!(chunks.len(), expected.len());
+    for (got, want) in chunks.iter().zip(&expected) {
+        assert_eq!((&got.0[..], &got.1[..], got.2, &got.3), (want.0, want.1, want.2, &want.3));
+    }
+    assert_eq!(failures.len(), 1);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn world_files() {
+    use std::fs;
+
+    let doc = |key: &str, v: i32| {
+        let mut map = HashMap::new();
+        map.insert(key.to_string(), Value::Int(v));
+        Document { title: String::new(), value: Value::Compound(map), compression: "minecraft:gzip".to_string() }
+    };
+    let root = ::std::env::temp_dir().join(format!("nbt-world-files-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("region")).unwrap();
+    fs::create_dir_all(root.join("playerdata")).unwrap();
+    assert!(World::open(&root).is_err());
+
+    let registry = Registry::new();
+    doc("DataVersion", 3465).write(&registry, &root.join("level.dat")).unwrap();
+    let (alex, steve) = (0x0123_4567_89ab_cdef_0123_4567_89ab_cdef, 0xffff_0000_ffff_0000_ffff_0000_ffff_0000);
+    for &(uuid, xp) in &[(steve, 2), (alex, 1)] {
+        let path = root.join("playerdata").join(format!("{}.dat", uuid::to_string(uuid)));
+        doc("XpLevel", xp).write(&registry, &path).unwrap();
+    }
+    fs::write(root.join("playerdata").join(format!("{}.dat_old", uuid::to_string(alex))), b"old").unwrap();
+    fs::write(root.join("playerdata/notes.dat"), b"junk").unwrap();
+    let mut region = RegionEditor::open(root.join("region/r.-1.2.mca")).unwrap();
+    region.write_chunk(4, 5, &doc("xPos", -28)).unwrap();
+    region.save().unwrap();
+
+    let world = World::open(&root).unwrap();
+    assert_eq!(world.level_dat().unwrap(), doc("DataVersion", 3465));
+    assert_eq!(world.player(steve).unwrap(), Some(doc("XpLevel", 2)));
+    assert_eq!(world.player(1).unwrap(), None);
+    let players: Vec<_> = world.iter_players().unwrap().map(|(uuid, p)| (uuid, p.unwrap())).collect();
+    assert_eq!(players, vec![(alex, doc("XpLevel", 1)), (steve, doc("XpLevel", 2))]);
+
+    let mut region = world.region("minecraft:overworld", -1, 2).unwrap().unwrap();
+    assert_eq!(region.read_chunk(4, 5).unwrap().unwrap().value, doc("xPos", -28).value);
+    assert!(world.region("minecraft:overworld", 0, 0).unwrap().is_none());
+    assert!(world.region("minecraft:the_end", -1, 2).unwrap().is_none());
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn world_reindex_uuid() {
+    use std::fs;
+
+    let (old, new) = (0x0123_4567_89ab_cdef_0123_4567_89ab_cdef, 0xdead_beef_0000_0000_0000_0000_0000_0001);
+    let doc = |key: &str, uuid: u128| {
+        let mut map = HashMap::new();
+        map.insert(key.to_string(), Value::IntArray(uuid::to_int_array(uuid).to_vec()));
+        Document { title: String::new(), value: Value::Compound(map), compression: "minecraft:gzip".to_string() }
+    };
+    let root = ::std::env::temp_dir().join(format!("nbt-world-reindex-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for dir in &["region", "entities", "playerdata"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    let registry = Registry::new();
+    let mut level = HashMap::new();
+    level.insert("Player".to_string(), doc("UUID", old).value);
+    Document { title: String::new(), value: Value::Compound(level), compression: "minecraft:gzip".to_string() }
+        .write(&registry, &root.join("level.dat")).unwrap();
+    doc("UUID", old).write(&registry, &root.join("playerdata").join(format!("{}.dat", uuid::to_string(old)))).unwrap();
+    doc("UUID", 7).write(&registry, &root.join("playerdata").join(format!("{}.dat", uuid::to_string(7)))).unwrap();
+    let mut region = RegionEditor::open(root.join("region/r.0.0.mca")).unwrap();
+    region.write_chunk(0, 0, &doc("Owner", old)).unwrap();
+    region.write_chunk(1, 0, &doc("Owner", 7)).unwrap();
+    region.save().unwrap();
+    let mut entities = RegionEditor::open(root.join("entities/r.0.0.mca")).unwrap();
+    entities.write_chunk(2, 3, &doc("UUID", old)).unwrap();
+    entities.save().unwrap();
+    fs::write(root.join("region/r.1.0.mca"), b"corrupt").unwrap();
+
+    let world = World::open(&root).unwrap();
+    let reindexed = world.reindex_uuid(old, new).unwrap();
+    assert_eq!(reindexed.replaced, 4);
+    assert_eq!(reindexed.failures.len(), 1);
+    assert_eq!(reindexed.failures[0].region, root.join("region/r.1.0.mca"));
+
+    let mut region = RegionEditor::open(root.join("region/r.0.0.mca")).unwrap();
+    assert_eq!(region.read_chunk(0, 0).unwrap().unwrap().value, doc("Owner", new).value);
+    assert_eq!(region.read_chunk(1, 0).unwrap().unwrap().value, doc("Owner", 7).value);
+    let mut entities = RegionEditor::open(root.join("entities/r.0.0.mca")).unwrap();
+    assert_eq!(entities.read_chunk(2, 3).unwrap().unwrap().value, doc("UUID", new).value);
+    assert_eq!(world.player(old).unwrap(), None);
+    assert_eq!(world.player(new).unwrap(), Some(doc("UUID", new)));
+    assert_eq!(world.player(7).unwrap(), Some(doc("UUID", 7)));
+    match world.level_dat().unwrap().value {
+        Value::Compound(ref map) => assert_eq!(map["Player"], doc("UUID", new).value),
+        ref other => panic!("unexpected level.dat {:?}", other),
+    }
+    assert_eq!(world.reindex_uuid(old, new).unwrap().replaced, 0);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn sniff_variants() {
+    let mut map = HashMap::new();
+    map.insert("a".to_string(), Value::Int(1));
+    let value = Value::Compound(map);
+
+    let mut java = Vec::new();
+    value.write_header(&mut java, "root").unwrap();
+    value.write(&mut java).unwrap();
+    let gzipped = compression::Gzip.compress(&java).unwrap();
+    assert_eq!(sniff::decode(&gzipped).unwrap(),
+               (Format::Document { compression: "minecraft:gzip".to_string(), encoding: Encoding::Java },
+                "root".to_string(), value.clone()));
+
+    let mut network = vec![0x0a];
+    value.write(&mut network).unwrap();
+    assert_eq!(sniff::detect(&network),
+               Some(Format::Document { compression: "minecraft:none".to_string(), encoding: Encoding::Network }));
+
+    let bedrock = vec![0x0a, 4, 0, b'r', b'o', b'o', b't', 0x03, 1, 0, b'a', 1, 0, 0, 0, 0x00];
+    let (format, title, decoded) = sniff::decode(&bedrock).unwrap();
+    assert_eq!(format, Format::Document { compression: "minecraft:none".to_string(), encoding: Encoding::Bedrock });
+    assert_eq!((&title[..], decoded), ("root", value.clone()));
+
+    let mut level_dat = vec![10, 0, 0, 0, bedrock.len() as u8, 0, 0, 0];
+    level_dat.extend_from_slice(&bedrock);
+    assert_eq!(sniff::detect(&level_dat), Some(Format::BedrockLevelDat { storage_version: 10 }));
+    assert_eq!(sniff::decode(&level_dat).unwrap().2, value);
+
+    let mut region = vec![0; 3 * 4096];
+    region[2] = 2;
+    region[3] = 1;
+    assert_eq!(sniff::detect(&region), Some(Format::Region));
+    assert!(sniff::decode(&region).is_err());
+
+    // Trailing bytes, or a list claiming more elements than there are bytes.
+    java.push(0);
+    assert_eq!(sniff::detect(&java), None);
+    assert_eq!(sniff::detect(&[0x0a, 0x09, 0, 0, 0x01, 0x7f, 0xff, 0xff, 0xff, 0]), None);
+}
+
+#[test]
+fn long_array_round_trip() {
+    let mut map = HashMap::new();
+    map.insert("Heightmap".to_string(), Value::LongArray(vec![i64::MIN, -1, 0, i64::MAX]));
+    map.insert("Empty".to_string(), Value::LongArray(vec![]));
+    map.insert("Nested".to_string(), Value::List(vec![Value::LongArray(vec![7])]));
+    let value = Value::Compound(map);
+
+    let mut bytes = Vec::new();
+    value.write_header(&mut bytes, "").unwrap();
+    value.write(&mut bytes).unwrap();
+    assert_eq!(bytes.len(), 3 + value.len());
+    let mut src = &bytes[..];
+    let (id, _) = Value::read_header(&mut src).unwrap();
+    assert_eq!(Value::from_reader(id, &mut src).unwrap(), value);
+
+    assert_eq!(yaml::from_str(&yaml::to_string(&value)).unwrap(), value);
+    assert_eq!(snbt::to_string(&Value::LongArray(vec![1, -2])), "[L;1L,-2L]");
+    assert_eq!(sniff::decode(&bytes).unwrap().2, value);
+    let audit = audit::audit(&mut &bytes[..]);
+    assert!(audit.is_clean());
+    assert_eq!(audit.value, Some(value));
+}
+
+#[test]
+fn reader_auto_detects_compression() {
+    let mut blob = Blob::new("Data".to_string());
+    blob.insert("Score".to_string(), Value::Int(42)).unwrap();
+
+    let mut raw = Vec::new();
+    blob.write(&mut raw).unwrap();
+    let mut gzip = Vec::new();
+    blob.write_gzip(&mut gzip).unwrap();
+    let mut zlib = Vec::new();
+    blob.write_zlib(&mut zlib).unwrap();
+
+    for data in &[raw, gzip, zlib] {
+        assert_eq!(::from_reader_auto(&data[..]).unwrap(), blob);
+        let (title, value) = Value::from_reader_auto(&data[..]).unwrap();
+        assert_eq!(title, "Data");
+        assert_eq!(value, Value::Compound(vec![("Score".to_string(), Value::Int(42))].into_iter().collect()));
+    }
+
+    match Value::from_reader_auto(&[0x08, 0x00, 0x00][..]) {
+        Err(Error::UnknownCompression(_)) => (),
+        other => panic!("expected an unknown compression error, got {:?}", other),
+    }
+}
+
+#[test]
+fn decoder_limits() {
+    // A list of lists of lists, each holding one element.
+    let nested = vec![
+        0x09, 0x00, 0x00, 0x00, 0x01,
+            0x09, 0x00, 0x00, 0x00, 0x01,
+                0x01, 0x00, 0x00, 0x00, 0x01,
+                    0x7f,
+    ];
+    let value = Value::from_reader(0x09, &mut &nested[..]).unwrap();
+    let shallow = DecoderOptions { max_depth: 1, ..DecoderOptions::default() };
+    assert_eq!(Value::from_reader_with(0x09, &mut &nested[..], &shallow),
+               Err(Error::DepthLimitExceeded(1)));
+    let options = DecoderOptions { max_depth: 2, ..DecoderOptions::default() };
+    assert_eq!(Value::from_reader_with(0x09, &mut &nested[..], &options), Ok(value));
+
+    // The declared length is checked before anything is allocated.
+    let huge = vec![0x7f, 0xff, 0xff, 0xff];
+    let options = DecoderOptions { max_len: 1 << 16, ..DecoderOptions::default() };
+    assert_eq!(Value::from_reader_with(0x0c, &mut &huge[..], &options),
+               Err(Error::LengthLimitExceeded(1 << 16)));
+    let options = DecoderOptions { max_bytes: Some(1024), ..DecoderOptions::default() };
+    assert_eq!(Value::from_reader_with(0x0b, &mut &huge[..], &options),
+               Err(Error::SizeLimitExceeded(1024)));
+
+    let string = vec![0x00, 0x03, 0x61, 0x62, 0x63];
+    let options = DecoderOptions { max_string_len: 2, ..DecoderOptions::default() };
+    assert_eq!(Value::from_reader_with(0x08, &mut &string[..], &options),
+               Err(Error::LengthLimitExceeded(2)));
+}
+
+/// A compound holding a list named "a" of lists nested 200,000 deep, deep
+/// enough to overflow the stack if skipping it were unbounded.
+fn deeply_nested() -> Vec<u8> {
+    let mut data = vec![0x0a, 0x00, 0x00, 0x09, 0x00, 0x01, b'a'];
+    for _ in 0..200_000 {
+        data.extend_from_slice(&[0x09, 0x00, 0x00, 0x00, 0x01]);
+    }
+    data.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    data
+}
+
+#[test]
+fn skipping_is_depth_limited() {
+    let max = DecoderOptions::default().max_depth;
+    let data = deeply_nested();
+    let doc = lazy::Document::open(io::Cursor::new(&data[..])).unwrap();
+    assert_eq!(doc.get("a").err(), Some(Error::DepthLimitExceeded(max)));
+
+    assert_eq!(raw::skip_payload(0x0a, &mut &data[3..]), Err(Error::DepthLimitExceeded(max)));
+    assert_eq!(Projection::new(&["x"]).read(&mut &data[..]), Err(Error::DepthLimitExceeded(max)));
+    assert_eq!(rewrite::rewrite(&mut &data[..], &mut Vec::new(), &Rules::new().delete("a")),
+               Err(Error::DepthLimitExceeded(max)));
+    assert_eq!(rewrite::rewrite(&mut &data[..], &mut Vec::new(), &Rules::new()),
+               Err(Error::DepthLimitExceeded(max)));
+}
+
+#[test]
+fn decoding_is_depth_limited() {
+    let max = DecoderOptions::default().max_depth;
+    let data = deeply_nested();
+
+    let report = audit::audit(&data[..]);
+    assert_eq!(report.problems.len(), 1);
+    assert_eq!(report.problems[0].error, Error::DepthLimitExceeded(max));
+    assert_eq!(report.problems[0].offset, 7 + 5 * max as u64);
+    assert!(report.value.is_some());
+    assert_eq!(audit::read_with_warnings(&data[..], &Default::default(), |_| ()).err(),
+               Some(Error::DepthLimitExceeded(max)));
+
+    assert_eq!(capture::read_captured(&data[..]).err(), Some(Error::DepthLimitExceeded(max)));
+    let options = DecoderOptions { max_depth: 1, ..DecoderOptions::default() };
+    assert_eq!(capture::read_captured_with(&data[..], &options).err(),
+               Some(Error::DepthLimitExceeded(1)));
+
+    assert_eq!(sniff::decode(&data).err(), Some(Error::DepthLimitExceeded(max)));
+    assert_eq!(sniff::detect(&data), None);
+
+    assert_eq!(testing::verify_roundtrip(&data).err(), Some(Error::DepthLimitExceeded(max)));
+
+    // A list in a list in the root, so nested two deep.
+    let shallow = [0x0a, 0x00, 0x00, 0x09, 0x00, 0x01, b'a', 0x09, 0x00, 0x00, 0x00, 0x01,
+                   0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+    let options = DecoderOptions { max_depth: 1, ..DecoderOptions::default() };
+    let doc = lazy::Document::open_with(io::Cursor::new(&shallow[..]), options).unwrap();
+    assert_eq!(doc.root().read().err(), Some(Error::DepthLimitExceeded(1)));
+    let options = DecoderOptions { max_depth: 2, ..DecoderOptions::default() };
+    let doc = lazy::Document::open_with(io::Cursor::new(&shallow[..]), options).unwrap();
+    assert_eq!(doc.get("a").unwrap().read().unwrap(), Value::List(vec![Value::List(Vec::new())]));
+}
+
+#[test]
+fn truncated_lengths_fail_without_allocating() {
+    // Trusting these lengths would allocate gigabytes before reaching the
+    // end of the data.
+    let huge = vec![0x7f, 0xff, 0xff, 0xff];
+    for &id in &[0x07, 0x0b, 0x0c] {
+        assert_eq!(Value::from_reader(id, &mut &huge[..]), Err(Error::IncompleteNbtValue));
+    }
+    let list = vec![0x04, 0x7f, 0xff, 0xff, 0xff];
+    assert_eq!(Value::from_reader(0x09, &mut &list[..]), Err(Error::IncompleteNbtValue));
+    assert_eq!(raw::read_bare_long_array(&mut &huge[..]), Err(Error::IncompleteNbtValue));
+    assert_eq!(raw::read_bare_string(&mut &[0xff, 0xff, 0x61][..]), Err(Error::IncompleteNbtValue));
+}
+
+#[test]
+fn stream_parser_events() {
+    let mut level = HashMap::new();
+    level.insert("xPos".to_string(), Value::Int(3));
+    let mut root = Blob::new("Chunk".to_string());
+    root.insert("Level".to_string(), Value::Compound(level)).unwrap();
+    root.insert("Sections".to_string(),
+                Value::List(vec![Value::Byte(1), Value::Byte(2)])).unwrap();
+    let mut bytes = Vec::new();
+    root.write(&mut bytes).unwrap();
+
+    let mut events = Vec::new();
+    for event in Parser::new(&bytes[..]) {
+        let (offset, event) = event.unwrap();
+        events.push(event.clone());
+        // Every named tag starts with its type ID.
+        match event {
+            Event::CompoundStart(Some(_)) => assert_eq!(bytes[offset as usize], 0x0a),
+            Event::ListStart { name: Some(_), .. } => assert_eq!(bytes[offset as usize], 0x09),
+            Event::Tag(Some(_), ref value) => assert_eq!(bytes[offset as usize], value.id()),
+            _ => (),
+        }
+    }
+
+    // Compound entries come out in the order they were written.
+    let level = events.iter().position(|e| *e == Event::CompoundStart(Some("Level".to_string())));
+    let level = level.unwrap();
+    assert_eq!(events[0], Event::CompoundStart(Some("Chunk".to_string())));
+    assert_eq!(events[level + 1], Event::Tag(Some("xPos".to_string()), Value::Int(3)));
+    assert_eq!(events[level + 2], Event::End);
+    let list = events.iter().position(|e| match *e {
+        Event::ListStart { .. } => true,
+        _ => false,
+    }).unwrap();
+    assert_eq!(&events[list..list + 4], &[
+        Event::ListStart { name: Some("Sections".to_string()), kind: 0x01, len: 2 },
+        Event::Tag(None, Value::Byte(1)),
+        Event::Tag(None, Value::Byte(2)),
+        Event::End,
+    ]);
+    assert_eq!(events.last(), Some(&Event::End));
+    assert_eq!(events.len(), 9);
+
+    // Skipping a container leaves the parser after its end.
+    let mut parser = Parser::new(&bytes[..]);
+    parser.next().unwrap().unwrap();
+    let mut names = Vec::new();
+    while let Some(event) = parser.next() {
+        match event.unwrap().1 {
+            Event::CompoundStart(name) | Event::ListStart { name, .. } => {
+                names.push(name.unwrap());
+                parser.skip_rest().unwrap();
+            },
+            Event::End => assert_eq!(parser.depth(), 0),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+    names.sort();
+    assert_eq!(names, vec!["Level", "Sections"]);
+    assert_eq!(parser.position(), bytes.len() as u64);
+
+    let truncated = &bytes[..bytes.len() - 3];
+    assert!(Parser::new(truncated).any(|event| event.is_err()));
+}
+
+#[test]
+fn stream_writer_round_trip() {
+    let mut writer = Writer::new(Vec::new());
+    writer.begin_compound(Some("Data")).unwrap();
+    writer.write_int(Some("DataVersion"), 3465).unwrap();
+    writer.begin_list(Some("Pos"), 0x06, 2).unwrap();
+    writer.write_double(None, 0.5).unwrap();
+    assert_eq!(writer.write_int(None, 1), Err(Error::TagMismatch(0x03, 0x06)));
+    assert_eq!(writer.write_double(Some("y"), 1.0),
+               Err(Error::InvalidWrite("list elements cannot have a name")));
+    assert_eq!(writer.end(), Err(Error::InvalidWrite("a list has fewer elements than declared")));
+    writer.write_double(None, 64.0).unwrap();
+    assert_eq!(writer.write_double(None, 1.0),
+               Err(Error::InvalidWrite("a list has more elements than declared")));
+    writer.end().unwrap();
+    writer.write_value(Some("Tags"), &Value::IntArray(vec![1, 2])).unwrap();
+    assert_eq!(writer.depth(), 1);
+    writer.end().unwrap();
+    assert_eq!(writer.write_byte(Some("extra"), 1),
+               Err(Error::InvalidWrite("the root tag has already been written")));
+    let bytes = writer.finish().unwrap();
+
+    let mut expected = Blob::new("Data".to_string());
+    expected.insert("DataVersion".to_string(), Value::Int(3465)).unwrap();
+    expected.insert("Pos".to_string(), Value::List(vec![Value::Double(0.5), Value::Double(64.0)])).unwrap();
+    expected.insert("Tags".to_string(), Value::IntArray(vec![1, 2])).unwrap();
+    assert_eq!(Blob::from_reader(&mut &bytes[..]).unwrap(), expected);
+
+    let mut writer = Writer::new(Vec::new());
+    assert_eq!(writer.begin_compound(None),
+               Err(Error::InvalidWrite("compound entries and the root tag need a name")));
+    writer.begin_compound(Some("")).unwrap();
+    assert!(writer.finish().is_err());
+}
+
+#[test]
+fn transcode_filters_entries() {
+    let mut section = HashMap::new();
+    section.insert("Y".to_string(), Value::Byte(0));
+    section.insert("BlockLight".to_string(), Value::ByteArray(vec![0; 16]));
+    let mut level = HashMap::new();
+    level.insert("xPos".to_string(), Value::Int(1));
+    level.insert("Heightmaps".to_string(), Value::Compound(HashMap::new()));
+    level.insert("Sections".to_string(), Value::List(vec![Value::Compound(section)]));
+    let mut chunk = Blob::new("".to_string());
+    chunk.insert("Level".to_string(), Value::Compound(level)).unwrap();
+    let mut bytes = Vec::new();
+    chunk.write(&mut bytes).unwrap();
+
+    let mut seen = Vec::new();
+    let pruned = ::transcode(&bytes[..], Vec::new(), |path, _| {
+        seen.push(path.to_string());
+        match path {
+            "Level.Heightmaps" | "Level.Sections[0].BlockLight" => Action::Drop,
+            "Level.xPos" => Action::Rename("X".to_string()),
+            _ => Action::Keep,
+        }
+    }).unwrap();
+
+    seen.sort();
+    assert_eq!(seen, vec!["Level", "Level.Heightmaps", "Level.Sections",
+                          "Level.Sections[0].BlockLight", "Level.Sections[0].Y", "Level.xPos"]);
+
+    let mut section = HashMap::new();
+    section.insert("Y".to_string(), Value::Byte(0));
+    let mut level = HashMap::new();
+    level.insert("X".to_string(), Value::Int(1));
+    level.insert("Sections".to_string(), Value::List(vec![Value::Compound(section)]));
+    let mut expected = Blob::new("".to_string());
+    expected.insert("Level".to_string(), Value::Compound(level)).unwrap();
+    assert_eq!(Blob::from_reader(&mut &pruned[..]).unwrap(), expected);
+}
+
+#[test]
+fn nbt_path_queries() {
+    let item = |slot: i8, id: &str, enchantment: Option<&str>| {
+        let mut item = HashMap::new();
+        item.insert("Slot".to_string(), Value::Byte(slot));
+        item.insert("id".to_string(), Value::String(id.to_string()));
+        if let Some(e) = enchantment {
+            let mut ench = HashMap::new();
+            ench.insert("id".to_string(), Value::String(e.to_string()));
+            let mut tag = HashMap::new();
+            tag.insert("Enchantments".to_string(), Value::List(vec![Value::Compound(ench)]));
+            item.insert("tag".to_string(), Value::Compound(tag));
+        }
+        Value::Compound(item)
+    };
+    let mut player = HashMap::new();
+    player.insert("Inventory".to_string(), Value::List(vec![
+        item(0, "minecraft:diamond_sword", Some("minecraft:sharpness")),
+        item(1, "minecraft:bread", None),
+        item(2, "minecraft:bow", Some("minecraft:power")),
+    ]));
+    let mut player = Value::Compound(player);
+
+    let path: NbtPath = "Inventory[{Slot:0b}].tag.Enchantments[0].id".parse().unwrap();
+    assert_eq!(path.get(&player), vec![&Value::String("minecraft:sharpness".to_string())]);
+    assert_eq!(path.to_string(), "Inventory[{Slot:0b}].tag.Enchantments[0].id");
+
+    let ids = NbtPath::parse("Inventory[].tag.Enchantments[].id").unwrap();
+    assert_eq!(ids.get(&player).len(), 2);
+    assert_eq!(NbtPath::parse("Inventory[-1].Slot").unwrap().get(&player), vec![&Value::Byte(2)]);
+    assert!(NbtPath::parse("Inventory[{Slot:0}]").unwrap().get(&player).is_empty());
+    assert_eq!(NbtPath::parse("{Inventory:[{id:\"minecraft:bread\"}]}").unwrap().get(&player).len(), 1);
+
+    for id in ids.get_mut(&mut player) {
+        *id = Value::String("minecraft:unbreaking".to_string());
+    }
+    assert_eq!(path.get(&player), vec![&Value::String("minecraft:unbreaking".to_string())]);
+
+    // Setting creates missing compounds, and counts only tags that changed.
+    let name = NbtPath::parse("Inventory[{id:'minecraft:bread'}].tag.display.Name").unwrap();
+    assert_eq!(name.set(&mut player, "Loaf"), 1);
+    assert_eq!(name.set(&mut player, "Loaf"), 0);
+    assert_eq!(name.get(&player), vec![&Value::String("Loaf".to_string())]);
+    assert_eq!(NbtPath::parse("Inventory[].Slot").unwrap().set(&mut player, 5i8), 3);
+    assert_eq!(NbtPath::parse("Inventory[0]").unwrap().set(&mut player, 5i8), 0);
+
+    assert_eq!(NbtPath::parse("Inventory[{tag:{}}]").unwrap().remove(&mut player), 3);
+    assert_eq!(NbtPath::parse("Inventory").unwrap().get(&player), vec![&Value::List(vec![])]);
+
+    for bad in &["", "a..b", "a.", "Inventory[x]", "Inventory[{Slot:0b]", "\"open"] {
+        match NbtPath::parse(bad) {
+            Err(Error::InvalidPath(_)) => {},
+            other => panic!("{:?} parsed as {:?}", bad, other),
+        }
+    }
+}
+
+#[test]
+fn nbt_macro() {
+    let slot = 3i8;
+    let key = "Custom".to_string();
+    let value = nbt!({
+        "id": "minecraft:chest",
+        "Items": [
+            {"Slot": 0i8, "Count": 1i8, "id": "minecraft:stone"},
+            {"Slot": slot, "Count": -1i8},
+        ],
+        "Pos": [1.5, 64.0, -3.25],
+        "Lock": [B; 1, -1],
+        "Heights": [L; 1 << 40, -2],
+        "Tags": [],
+        "Data": {},
+        (key): [I; 1, 2, 3],
+        "Time": 20i64 * 60,
+        "Speed": 0.5f32,
+        "Damage": 2i16,
+    });
+
+    let mut first = HashMap::new();
+    first.insert("Slot".to_string(), Value::Byte(0));
+    first.insert("Count".to_string(), Value::Byte(1));
+    first.insert("id".to_string(), Value::String("minecraft:stone".to_string()));
+    let mut second = HashMap::new();
+    second.insert("Slot".to_string(), Value::Byte(3));
+    second.insert("Count".to_string(), Value::Byte(-1));
+    let mut expected = HashMap::new();
+    expected.insert("id".to_string(), Value::String("minecraft:chest".to_string()));
+    expected.insert("Items".to_string(),
+                    Value::List(vec![Value::Compound(first), Value::Compound(second)]));
+    expected.insert("Pos".to_string(),
+                    Value::List(vec![Value::Double(1.5), Value::Double(64.0), Value::Double(-3.25)]));
+    expected.insert("Lock".to_string(), Value::ByteArray(vec![1, -1]));
+    expected.insert("Heights".to_string(), Value::LongArray(vec![1 << 40, -2]));
+    expected.insert("Tags".to_string(), Value::List(vec![]));
+    expected.insert("Data".to_string(), Value::Compound(HashMap::new()));
+    expected.insert("Custom".to_string(), Value::IntArray(vec![1, 2, 3]));
+    expected.insert("Time".to_string(), Value::Long(1200));
+    expected.insert("Speed".to_string(), Value::Float(0.5));
+    expected.insert("Damage".to_string(), Value::Short(2));
+    assert_eq!(value, Value::Compound(expected));
+
+    assert_eq!(nbt!(5), Value::Int(5));
+    assert_eq!(nbt!([1, 2]), Value::List(vec![Value::Int(1), Value::Int(2)]));
+}
+
+#[test]
+fn value_accessors() {
+    let mut chest = nbt!({
+        "id": "minecraft:chest",
+        "Items": [{"Slot": 0i8, "Count": 12i8}],
+        "Pos": [I; 1, 64, -3],
+        "Speed": 0.5f32,
+    });
+
+    assert_eq!(chest["id"].as_str(), Some("minecraft:chest"));
+    assert_eq!(chest["Items"][0]["Count"].as_i64(), Some(12));
+    assert_eq!(chest.get("Items").and_then(|items| items.get_index(0))
+                    .and_then(|item| item.get("Slot")).and_then(Value::as_i64), Some(0));
+    assert_eq!(chest.get("Items").and_then(|items| items.get_index(1)), None);
+    assert_eq!(chest.get("Missing"), None);
+    assert_eq!(chest["Pos"].as_int_array(), Some(&[1, 64, -3][..]));
+    assert_eq!(chest["Speed"].as_f64(), Some(0.5));
+    assert_eq!(chest["Speed"].as_i64(), None);
+    assert_eq!(chest["id"].get("id"), None);
+    assert_eq!(chest.as_compound().map(|map| map.len()), Some(4));
+
+    chest["Items"][0]["Count"] = Value::Byte(64);
+    chest.get_mut("Items").and_then(Value::as_list_mut).unwrap().push(nbt!({"Slot": 1i8}));
+    assert_eq!(chest["Items"][0]["Count"], Value::Byte(64));
+    assert_eq!(chest["Items"].as_list().map(|items| items.len()), Some(2));
+
+    assert!(::std::panic::catch_unwind(|| chest["Missing"].clone()).is_err());
+}
+
+#[test]
+fn compound_mutation() {
+    let mut chest = nbt!({
+        "id": "minecraft:chest",
+        "CustomName": "Loot",
+        "display": {"Name": "Old", "Lore": ["a"]},
+    });
+
+    assert_eq!(chest.insert("Lock", "key").unwrap(), None);
+    assert_eq!(chest.insert("id", "minecraft:barrel").unwrap(), Some(Value::from("minecraft:chest")));
+    assert_eq!(chest.insert("Bad", nbt!([1, "two"])), Err(Error::HeterogeneousList));
+    assert_eq!(Value::Int(1).insert("a", 1), Err(Error::TagMismatch(0x03, 0x0a)));
+    assert_eq!(chest.remove("CustomName"), Some(Value::from("Loot")));
+    assert_eq!(chest.remove("CustomName"), None);
+
+    *chest.entry("Count").unwrap().or_insert(Value::Int(0)) = Value::Int(3);
+    chest.entry("Count").unwrap().or_insert(Value::Int(10));
+    assert_eq!(chest["Count"], Value::Int(3));
+    assert!(Value::Int(1).entry("a").is_err());
+
+    chest.retain(|key, _| key != "Lock");
+    assert_eq!(chest.get("Lock"), None);
+
+    chest.merge(&nbt!({
+        "display": {"Name": "New", "Color": 5},
+        "Count": 7i8,
+    }));
+    assert_eq!(chest, nbt!({
+        "id": "minecraft:barrel",
+        "display": {"Name": "New", "Lore": ["a"], "Color": 5},
+        "Count": 7i8,
+    }));
+
+    let mut scalar = Value::Int(1);
+    scalar.merge(&chest);
+    assert_eq!(scalar, chest);
+}
+
+#[test]
+fn structural_diff() {
+    let old = nbt!({
+        "Health": 20.0f32,
+        "Pos": [1.0, 64.0, 2.0],
+        "Inventory": [{"Slot": 0i8, "Count": 1i8}, {"Slot": 1i8, "Count": 5i8}],
+        "Tags": ["a", "b"],
+        "odd key": 1,
+        "Gone": 2i8,
+    });
+    let new = nbt!({
+        "Health": 19.999999f32,
+        "Pos": [1.0, 64.0, 2.0000001],
+        "Inventory": [{"Slot": 0i8, "Count": 2i8}],
+        "Tags": ["b", "a"],
+        "odd key": 1i64,
+        "New": "x",
+    });
+
+    let entries = diff::diff(&old, &new);
+    let paths: Vec<&str> = entries.iter().map(|e| &e.path[..]).collect();
+    assert_eq!(paths, vec!["Gone", "Health", "Inventory[0].Count", "Inventory[1]", "New",
+                           "Pos[2]", "Tags[0]", "Tags[1]", "\"odd key\""]);
+    assert_eq!(entries[0].change, Change::Removed(Value::Byte(2)));
+    assert_eq!(entries[2], DiffEntry {
+        path: "Inventory[0].Count".to_string(),
+        change: Change::Changed(Value::Byte(1), Value::Byte(2)),
+    });
+    assert_eq!(entries[4].to_string(), "+ New: \"x\"");
+    assert!(NbtPath::parse(&entries[8].path).unwrap().get(&old) == vec![&Value::Int(1)]);
+
+    let options = diff::Options { epsilon: 1e-5, ignore_list_order: true };
+    let entries = diff::diff_with(&old, &new, &options);
+    let paths: Vec<&str> = entries.iter().map(|e| &e.path[..]).collect();
+    assert_eq!(paths, vec!["Gone", "Inventory[0]", "Inventory[1]", "Inventory[0]", "New",
+                           "\"odd key\""]);
+
+    assert!(diff::diff(&old, &old.clone()).is_empty());
+}
+
+#[test]
+fn canonical_hashing() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+    use std::io::Write;
+
+    fn hash(value: &Value) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.canonical_hash(&mut hasher).unwrap();
+        hasher.finish()
+    }
+
+    let value = nbt!({"b": [1, 2], "a": {"y": 1i8, "x": "text"}, "c": []});
+    let mut rebuilt = Value::Compound(HashMap::new());
+    for &key in &["c", "a", "b"] {
+        rebuilt.insert(key, value[key].clone()).unwrap();
+    }
+    assert_eq!(hash(&value), hash(&rebuilt));
+    assert!(hash(&value) != hash(&nbt!({"b": [1, 2], "a": {"y": 1i8, "x": "text"}})));
+    assert!(hash(&Value::Int(1)) != hash(&Value::Long(1)));
+
+    // The same document hashes the same whatever its compression.
+    let mut blob = Blob::new("".to_string());
+    blob.insert("data".to_string(), value.clone()).unwrap();
+    let (mut gzip, mut zlib) = (Vec::new(), Vec::new());
+    blob.write_gzip(&mut gzip).unwrap();
+    blob.write_zlib(&mut zlib).unwrap();
+    let from_gzip = Blob::from_gzip(&mut &gzip[..]).unwrap();
+    let from_zlib = Blob::from_zlib(&mut &zlib[..]).unwrap();
+    assert_eq!(hash(&from_gzip["data"]), hash(&value));
+    assert_eq!(hash(&from_zlib["data"]), hash(&value));
+
+    let mut bad = Value::Compound(HashMap::new());
+    bad.as_compound_mut().unwrap().insert("l".to_string(), Value::List(vec![Value::Int(1), Value::Byte(1)]));
+    assert_eq!(bad.canonical_hash(&mut DefaultHasher::new()), Err(Error::HeterogeneousList));
+
+    // Hashing what an encoder writes is the same as hashing the bytes.
+    let mut bytes = Vec::new();
+    normalize::write_canonical(&mut bytes, "", &value).unwrap();
+    let mut dst = normalize::HashingWriter::new(DefaultHasher::new());
+    normalize::write_canonical(&mut dst, "", &value).unwrap();
+    dst.flush().unwrap();
+    let mut expected = DefaultHasher::new();
+    expected.write(&bytes);
+    assert_eq!(dst.written(), bytes.len() as u64);
+    assert_eq!(dst.finish(), expected.finish());
+}
+
+#[test]
+fn tree_display() {
+    let value = nbt!({
+        "Pos": [1.5, 64.0],
+        "id": "minecraft:pig",
+        "Brain": {"memories": {}},
+        "Heights": [L; 1, 2, 3],
+    });
+    let expected = "4 entries
+{
+  TAG_Compound(\"Brain\"): 1 entry
+  {
+    TAG_Compound(\"memories\"): 0 entries
+    {
+    }
+  }
+  TAG_LongArray(\"Heights\"): [3 longs]
+  TAG_List(\"Pos\"): 2 entries
+  {
+    TAG_Double(None): 1.5
+    TAG_Double(None): 64
+  }
+  TAG_String(\"id\"): minecraft:pig
+}";
+    assert_eq!(value.to_string(), expected);
+
+    let mut blob = Blob::new("Entity".to_string());
+    blob.insert("id".to_string(), "minecraft:pig").unwrap();
+    assert_eq!(blob.to_string(), "TAG_Compound(\"Entity\"): 1 entry\n{\n  TAG_String(\"id\"): minecraft:pig\n}");
+
+    if cfg!(feature = "color") {
+        assert!(format!("{:#}", value).contains("\x1b[36mTAG_String\x1b[0m"));
+    } else {
+        assert_eq!(format!("{:#}", value), expected);
+    }
+}
+
+#[test]
+fn push_parser() {
+    use push::{self, Status};
+
+    let bytes = ::std::fs::read("tests/small4.nbt").unwrap();
+    let expected: Vec<(u64, Event)> = Parser::new(&bytes[..]).map(|e| e.unwrap()).collect();
+
+    // Fed a byte at a time, the push parser sees the same events.
+    let mut parser = push::Parser::new();
+    let mut events = Vec::new();
+    let mut fed = 0;
+    loop {
+        match parser.next_event().unwrap() {
+            Status::Event(offset, event) => events.push((offset, event)),
+            Status::NeedMoreData => {
+                parser.feed(&bytes[fed..fed + 1]);
+                fed += 1;
+            },
+            Status::Done => break,
+        }
+    }
+    assert_eq!(events, expected);
+    assert!(parser.finish().is_ok());
+    assert_eq!(parser.position(), bytes.len() as u64);
+
+    // Bytes after the document are left alone.
+    let mut parser = push::Parser::new();
+    parser.feed(&bytes);
+    parser.feed(&[1, 2, 3]);
+    while parser.next_event().unwrap() != Status::Done {}
+    assert_eq!(parser.remaining(), &[1, 2, 3]);
+
+    // A truncated document is incomplete.
+    let mut parser = push::Parser::new();
+    parser.feed(&bytes[..bytes.len() - 1]);
+    while parser.next_event().unwrap() != Status::NeedMoreData {}
+    assert_eq!(parser.depth(), 1);
+    assert_eq!(parser.finish(), Err(Error::IncompleteNbtValue));
+
+    // Limits apply to the whole document, however it is fed.
+    let limited = |options: DecoderOptions, data: &[u8]| {
+        let mut parser = push::Parser::with_options(options);
+        parser.feed(data);
+        loop {
+            match parser.next_event() {
+                Ok(Status::Event(..)) => (),
+                other => return other,
+            }
+        }
+    };
+    let max = DecoderOptions::default().max_depth;
+    assert_eq!(limited(DecoderOptions::default(), &deeply_nested()), Err(Error::DepthLimitExceeded(max)));
+    let options = DecoderOptions { max_bytes: Some(50), ..DecoderOptions::default() };
+    assert_eq!(limited(options.clone(), &bytes), Err(Error::SizeLimitExceeded(50)));
+    assert_eq!(limited(options, &bytes[..60]), Err(Error::SizeLimitExceeded(50)));
+    let options = DecoderOptions { max_string_len: 2, ..DecoderOptions::default() };
+    assert_eq!(limited(options, &bytes), Err(Error::LengthLimitExceeded(2)));
+}
+
+#[test]
+fn concatenated_documents() {
+    let mut bytes = Vec::new();
+    Value::Int(1).write_header(&mut bytes, "one").unwrap();
+    Value::Int(1).write(&mut bytes).unwrap();
+    let second = Value::Compound(HashMap::new());
+    second.write_header(&mut bytes, "two").unwrap();
+    second.write(&mut bytes).unwrap();
+
+    let documents: Vec<(String, Value)> = Value::read_all(&bytes[..]).map(|d| d.unwrap()).collect();
+    assert_eq!(documents, vec![("one".to_string(), Value::Int(1)), ("two".to_string(), second)]);
+    assert_eq!(Value::read_all(&[][..]).count(), 0);
+
+    // A truncated document is an error, after which the iterator ends.
+    let mut read = Value::read_all(&bytes[..bytes.len() - 1]);
+    assert!(read.next().unwrap().is_ok());
+    assert_eq!(read.next().unwrap(), Err(Error::IncompleteNbtValue));
+    assert!(read.next().is_none());
+}
+
+#[test]
+fn long_strings_and_lists() {
+    let long = "a".repeat(70_000);
+    let mut dst = Vec::new();
+    assert_eq!(Value::String(long.clone()).write(&mut dst), Err(Error::StringTooLong(70_000)));
+
+    let mut blob = Blob::new("".to_string());
+    blob.insert(long, Value::Byte(1)).unwrap();
+    assert_eq!(blob.write(&mut Vec::new()), Err(Error::StringTooLong(70_000)));
+
+    // The longest string still fits.
+    let longest = "a".repeat(65_535);
+    Value::String(longest).write(&mut Vec::new()).unwrap();
+
+    assert_eq!(raw::list_len(i32::MAX as usize), Ok(i32::MAX));
+    assert_eq!(raw::list_len(i32::MAX as usize + 1), Err(Error::ListTooLong(i32::MAX as usize + 1)));
+}
+
+#[test]
+fn raw_arrays_in_bulk() {
+    let ints = vec![1, -2, 0x0102_0304, i32::MIN];
+    let mut bytes = Vec::new();
+    raw::write_bare_int_array(&mut bytes, &ints).unwrap();
+    assert_eq!(&bytes[..8], &[0, 0, 0, 4, 0, 0, 0, 1]);
+    assert_eq!(&bytes[12..16], &[1, 2, 3, 4]);
+    assert_eq!(raw::read_bare_int_array(&mut &bytes[..]).unwrap(), ints);
+    assert_eq!(raw::read_bare_int_array(&mut &bytes[..17]), Err(Error::IncompleteNbtValue));
+
+    let longs: Vec<i64> = (0..1000).map(|i| i * -0x0102_0304_0506).collect();
+    let mut bytes = Vec::new();
+    raw::write_bare_long_array(&mut bytes, &longs).unwrap();
+    assert_eq!(bytes.len(), 4 + 8 * longs.len());
+    assert_eq!(raw::read_bare_long_array(&mut &bytes[..]).unwrap(), longs);
+
+    let signed = vec![-128, -1, 0, 127];
+    let mut bytes = Vec::new();
+    raw::write_bare_byte_array(&mut bytes, &signed).unwrap();
+    assert_eq!(bytes, vec![0, 0, 0, 4, 0x80, 0xff, 0x00, 0x7f]);
+    assert_eq!(raw::read_bare_byte_array(&mut &bytes[..]).unwrap(), signed);
+}
+
+#[test]
+fn lazy_document() {
+    let section = |y: i8| {
+        let mut map = HashMap::new();
+        map.insert("Y".to_string(), Value::Byte(y));
+        map.insert("BlockStates".to_string(), Value::LongArray(vec![y as i64; 256]));
+        Value::Compound(map)
+    };
+    let mut level = HashMap::new();
+    level.insert("Sections".to_string(), Value::List((0..4).map(section).collect()));
+    level.insert("Heights".to_string(), Value::List(vec![Value::Short(7), Value::Short(9)]));
+    level.insert("Biomes".to_string(), Value::IntArray(vec![1; 1024]));
+    let mut root = HashMap::new();
+    root.insert("Level".to_string(), Value::Compound(level));
+    root.insert("DataVersion".to_string(), Value::Int(3465));
+    let value = Value::Compound(root);
+
+    let mut bytes = Vec::new();
+    value.write_header(&mut bytes, "chunk").unwrap();
+    value.write(&mut bytes).unwrap();
+
+    let doc = lazy::Document::open(io::Cursor::new(&bytes[..])).unwrap();
+    assert_eq!(doc.name(), "chunk");
+    assert_eq!(doc.get("DataVersion").unwrap().read().unwrap(), Value::Int(3465));
+    let sections = doc.get("Level").unwrap().get("Sections").unwrap();
+    assert_eq!(sections.index(2).unwrap().get("Y").unwrap().read().unwrap(), Value::Byte(2));
+    assert_eq!(sections.index(3).unwrap().read().unwrap(), section(3));
+    let heights = doc.get("Level").unwrap().get("Heights").unwrap();
+    assert_eq!(heights.index(1).unwrap().read().unwrap(), Value::Short(9));
+    assert_eq!(doc.root().read().unwrap(), value);
+
+    let mut keys = doc.get("Level").unwrap().keys().unwrap();
+    keys.sort();
+    assert_eq!(keys, vec!["Biomes", "Heights", "Sections"]);
+    assert_eq!(doc.get("Entities").err(), Some(Error::MissingField("Entities".to_string())));
+    assert_eq!(sections.index(4).err(), Some(Error::MissingField("[4]".to_string())));
+    assert_eq!(sections.get("Y").err(), Some(Error::TagMismatch(0x09, 0x0a)));
+
+    // Nothing is checked beyond what is asked for, but a truncated compound
+    // fails once it is scanned.
+    let doc = lazy::Document::open(io::Cursor::new(&bytes[..bytes.len() - 4])).unwrap();
+    assert!(doc.get("Level").is_err());
+}
+
+#[cfg(all(feature = "mmap", unix))]
+#[test]
+fn mmap_document() {
+    use std::fs;
+
+    use mmap::{open_mmap, Mmap};
+
+    let mut map = HashMap::new();
+    map.insert("size".to_string(), Value::List(vec![Value::Int(48); 3]));
+    map.insert("blocks".to_string(), Value::IntArray(vec![5; 4096]));
+    let value = Value::Compound(map);
+    let mut bytes = Vec::new();
+    value.write_header(&mut bytes, "").unwrap();
+    value.write(&mut bytes).unwrap();
+
+    let path = ::std::env::temp_dir().join(format!("nbt-mmap-{}.nbt", ::std::process::id()));
+    fs::write(&path, &bytes).unwrap();
+    let doc = open_mmap(&path).unwrap();
+    assert_eq!(doc.get("size").unwrap().index(2).unwrap().read().unwrap(), Value::Int(48));
+    assert_eq!(doc.root().read().unwrap(), value);
+    assert_eq!(&Mmap::open(&path).unwrap()[..], &bytes[..]);
+
+    fs::write(&path, b"").unwrap();
+    assert!(Mmap::open(&path).unwrap().is_empty());
+    fs::remove_file(&path).unwrap();
+}
+
 //#[bench]
 //fn nbt_bench_bigwrite(b: &mut Bencher) {
 //    let mut file = File::open("tests/big1.nbt").unwrap();
@@ -0,0 +1,122 @@
+//! Helpers for the various ways UUIDs are stored in NBT data.
+//!
+//! Minecraft has used three representations over time:
+//!
+//! * a `TAG_Int_Array` of four ints, most significant first (1.16+);
+//! * a pair of `TAG_Long`s named `<prefix>Most` and `<prefix>Least`, such as
+//!   `UUIDMost`/`UUIDLeast` or `OwnerUUIDMost`/`OwnerUUIDLeast`;
+//! * a hyphenated hexadecimal `TAG_String`, such as the `Owner` of older
+//!   tamed animals.
+//!
+//! UUIDs are represented as `u128` values here. `replace` rewrites one UUID
+//! within a single value; `world::World::reindex_uuid` does so across a whole
+//! world save.
+
+use std::collections::HashMap;
+
+use value::Value;
+
+/// Splits a UUID into the four ints of its `TAG_Int_Array` form.
+pub fn to_int_array(uuid: u128) -> [i32; 4] {
+    [(uuid >> 96) as i32, (uuid >> 64) as i32, (uuid >> 32) as i32, uuid as i32]
+}
+
+/// Joins the four ints of a `TAG_Int_Array` back into a UUID.
+pub fn from_int_array(ints: &[i32]) -> Option<u128> {
+    if ints.len() != 4 {
+        return None;
+    }
+    Some(ints.iter().fold(0, |acc, &i| (acc << 32) | (i as u32 as u128)))
+}
+
+/// Splits a UUID into its most and least significant longs.
+pub fn to_longs(uuid: u128) -> (i64, i64) {
+    ((uuid >> 64) as i64, uuid as i64)
+}
+
+/// Joins the most and least significant longs of a UUID.
+pub fn from_longs(most: i64, least: i64) -> u128 {
+    ((most as u64 as u128) << 64) | (least as u64 as u128)
+}
+
+/// Formats a UUID in its hyphenated hexadecimal form.
+pub fn to_string(uuid: u128) -> String {
+    let hex = format!("{:032x}", uuid);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// Parses a UUID in its hyphenated hexadecimal form. Case is ignored, and
+/// each group may omit leading zeros, as Java's `UUID.fromString` allows.
+pub fn parse(s: &str) -> Option<u128> {
+    let widths = [8, 4, 4, 4, 12];
+    let groups: Vec<&str> = s.split('-').collect();
+    if groups.len() != widths.len() {
+        return None;
+    }
+    let mut uuid = 0u128;
+    for (group, &width) in groups.iter().zip(widths.iter()) {
+        if group.is_empty() || group.len() > width {
+            return None;
+        }
+        let value = u64::from_str_radix(group, 16).ok()?;
+        uuid = (uuid << (width * 4)) | value as u128;
+    }
+    Some(uuid)
+}
+
+/// Reads the UUID called `name` from a compound's entries, in any of the
+/// three stored forms. The legacy form is looked for as `<name>Most` and
+/// `<name>Least`.
+pub fn get(map: &HashMap<String, Value>, name: &str) -> Option<u128> {
+    match map.get(name) {
+        Some(Value::IntArray(ints)) => return from_int_array(ints),
+        Some(Value::String(s)) => return parse(s),
+        Some(_) => return None,
+        None => (),
+    }
+    match (map.get(&format!("{}Most", name)), map.get(&format!("{}Least", name))) {
+        (Some(&Value::Long(most)), Some(&Value::Long(least))) => Some(from_longs(most, least)),
+        _ => None,
+    }
+}
+
+/// Replaces every occurrence of the UUID `old` in a `Value` tree with `new`,
+/// in any of the three stored forms, and returns how many were replaced.
+///
+/// Int arrays are only considered if they have exactly four elements, and
+/// long pairs only if both halves are present in the same compound.
+pub fn replace(value: &mut Value, old: u128, new: u128) -> usize {
+    match *value {
+        Value::IntArray(ref mut ints) if from_int_array(ints) == Some(old) => {
+            ints.copy_from_slice(&to_int_array(new));
+            1
+        },
+        Value::String(ref mut s) if parse(s) == Some(old) => {
+            *s = to_string(new);
+            1
+        },
+        Value::List(ref mut vals) => vals.iter_mut().map(|v| replace(v, old, new)).sum(),
+        Value::Compound(ref mut map) => {
+            let mut count = 0;
+            let prefixes: Vec<String> = map.keys()
+                .filter(|k| k.ends_with("Most"))
+                .map(|k| k[..k.len() - 4].to_string())
+                .collect();
+            for prefix in prefixes {
+                let (most_key, least_key) = (format!("{}Most", prefix), format!("{}Least", prefix));
+                let found = match (map.get(&most_key), map.get(&least_key)) {
+                    (Some(&Value::Long(most)), Some(&Value::Long(least))) => from_longs(most, least) == old,
+                    _ => false,
+                };
+                if found {
+                    let (most, least) = to_longs(new);
+                    map.insert(most_key, Value::Long(most));
+                    map.insert(least_key, Value::Long(least));
+                    count += 1;
+                }
+            }
+            count + map.values_mut().map(|v| replace(v, old, new)).sum::<usize>()
+        },
+        _ => 0,
+    }
+}
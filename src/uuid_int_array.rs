@@ -0,0 +1,57 @@
+//! `serde` helpers for UUID fields, enabled by the `serde` feature.
+//!
+//! Use with `#[serde(with = "nbt::uuid_int_array")]` on a `u128` field. UUIDs
+//! are written in the modern `TAG_Int_Array` form, and read from that or a
+//! hyphenated `TAG_String`. The legacy `UUIDMost`/`UUIDLeast` pair is two
+//! fields of the enclosing compound, so `uuid::get` reads it from a `Value`.
+
+use std::fmt;
+
+use serde::{de, ser};
+
+use markers::INT_ARRAY;
+use uuid;
+
+/// Writes a UUID as a `TAG_Int_Array` of four ints.
+pub fn serialize<S>(uuid: &u128, serializer: S) -> Result<S::Ok, S::Error>
+    where S: ser::Serializer
+{
+    serializer.serialize_newtype_struct(INT_ARRAY, &uuid::to_int_array(*uuid)[..])
+}
+
+/// Reads a UUID from four ints or a hyphenated string.
+pub fn deserialize<D>(deserializer: D) -> Result<u128, D::Error>
+    where D: de::Deserializer
+{
+    deserializer.deserialize(UuidVisitor)
+}
+
+struct UuidVisitor;
+
+impl de::Visitor for UuidVisitor {
+    type Value = u128;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a UUID as four ints or a hyphenated string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<u128, E> {
+        uuid::parse(v).ok_or_else(|| de::Error::invalid_value(de::Unexpected::Str(v), &self))
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<u128, D::Error>
+        where D: de::Deserializer
+    {
+        deserializer.deserialize(self)
+    }
+
+    fn visit_seq<V>(self, mut visitor: V) -> Result<u128, V::Error>
+        where V: de::SeqVisitor
+    {
+        let mut ints = Vec::with_capacity(4);
+        while let Some(i) = visitor.visit::<i32>()? {
+            ints.push(i);
+        }
+        uuid::from_int_array(&ints).ok_or_else(|| de::Error::invalid_length(ints.len(), &self))
+    }
+}
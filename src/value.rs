@@ -1,11 +1,18 @@
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fmt;
+use std::hash::Hasher;
 use std::io;
+use std::marker::PhantomData;
+use std::ops::{Index, IndexMut};
 
-use byteorder::{BigEndian, WriteBytesExt, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt, ReadBytesExt};
+use flate2::read::{GzDecoder, ZlibDecoder};
 
 use error::{Error, Result};
+use normalize::{self, HashingWriter};
 use raw;
+use snbt;
 
 /// Values which can be represented in the Named Binary Tag format.
 #[derive(Clone, Debug, PartialEq)]
@@ -19,13 +26,16 @@ pub enum Value {
     ByteArray(Vec<i8>),
     String(String),
     List(Vec<Value>),
+    /// A compound's entries. The order they were read in is not kept, so
+    /// writing a compound back may reorder it.
     Compound(HashMap<String, Value>),
     IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
 }
 
 impl Value {
     /// The type ID of this `Value`, which is a single byte in the range
-    /// `0x01` to `0x0b`.
+    /// `0x01` to `0x0c`.
     pub fn id(&self) -> u8 {
         match *self {
             Value::Byte(_)      => 0x01,
@@ -38,7 +48,8 @@ impl Value {
             Value::String(_)    => 0x08,
             Value::List(_)      => 0x09,
             Value::Compound(_)  => 0x0a,
-            Value::IntArray(_)  => 0x0b
+            Value::IntArray(_)  => 0x0b,
+            Value::LongArray(_) => 0x0c,
         }
     }
 
@@ -55,7 +66,8 @@ impl Value {
             Value::String(_)    => "TAG_String",
             Value::List(_)      => "TAG_List",
             Value::Compound(_)  => "TAG_Compound",
-            Value::IntArray(_)  => "TAG_IntArray"
+            Value::IntArray(_)  => "TAG_IntArray",
+            Value::LongArray(_) => "TAG_LongArray",
         }
     }
 
@@ -81,9 +93,194 @@ impl Value {
                 }).fold(0, |acc, item| acc + item) + 1 // + u8 for the Tag_End
             },
             Value::IntArray(ref val)  => 4 + 4 * val.len(),
+            Value::LongArray(ref val) => 4 + 8 * val.len(),
         }
     }
 
+    /// The entry called `key`, if this is a compound that has one.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_compound().and_then(|map| map.get(key))
+    }
+
+    /// The entry called `key`, mutably, if this is a compound that has one.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        self.as_compound_mut().and_then(|map| map.get_mut(key))
+    }
+
+    /// The element at `index`, if this is a list that long.
+    pub fn get_index(&self, index: usize) -> Option<&Value> {
+        self.as_list().and_then(|list| list.get(index))
+    }
+
+    /// The value of a byte, short, int or long, widened to an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Byte(v)  => Some(v as i64),
+            Value::Short(v) => Some(v as i64),
+            Value::Int(v)   => Some(v as i64),
+            Value::Long(v)  => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The value of a float or double, widened to an `f64`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Float(v)  => Some(v as f64),
+            Value::Double(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The contents of a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The elements of a byte array.
+    pub fn as_byte_array(&self) -> Option<&[i8]> {
+        match *self {
+            Value::ByteArray(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The elements of an int array.
+    pub fn as_int_array(&self) -> Option<&[i32]> {
+        match *self {
+            Value::IntArray(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The elements of a long array.
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match *self {
+            Value::LongArray(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The elements of a list.
+    pub fn as_list(&self) -> Option<&Vec<Value>> {
+        match *self {
+            Value::List(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The elements of a list, mutably.
+    pub fn as_list_mut(&mut self) -> Option<&mut Vec<Value>> {
+        match *self {
+            Value::List(ref mut v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The entries of a compound.
+    pub fn as_compound(&self) -> Option<&HashMap<String, Value>> {
+        match *self {
+            Value::Compound(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The entries of a compound, mutably.
+    pub fn as_compound_mut(&mut self) -> Option<&mut HashMap<String, Value>> {
+        match *self {
+            Value::Compound(ref mut v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Inserts an entry into a compound, returning the value it replaced.
+    ///
+    /// Like `Blob::insert`, this fails if `value` is a list with heterogeneous
+    /// elements. It also fails if this is not a compound.
+    pub fn insert<K, V>(&mut self, key: K, value: V) -> Result<Option<Value>>
+        where K: Into<String>, V: Into<Value>
+    {
+        let value = value.into();
+        if let Value::List(ref vals) = value {
+            if vals.iter().any(|v| v.id() != vals[0].id()) {
+                return Err(Error::HeterogeneousList);
+            }
+        }
+        match *self {
+            Value::Compound(ref mut map) => Ok(map.insert(key.into(), value)),
+            ref other => Err(Error::TagMismatch(other.id(), 0x0a)),
+        }
+    }
+
+    /// Removes an entry from a compound, returning it if it was there.
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.as_compound_mut().and_then(|map| map.remove(key))
+    }
+
+    /// The entry called `key` in a compound, for in-place manipulation. Fails
+    /// if this is not a compound.
+    pub fn entry<K: Into<String>>(&mut self, key: K) -> Result<Entry<'_, String, Value>> {
+        match *self {
+            Value::Compound(ref mut map) => Ok(map.entry(key.into())),
+            ref other => Err(Error::TagMismatch(other.id(), 0x0a)),
+        }
+    }
+
+    /// Keeps only the compound entries for which `f` returns `true`. Does
+    /// nothing if this is not a compound.
+    pub fn retain<F>(&mut self, mut f: F)
+        where F: FnMut(&str, &mut Value) -> bool
+    {
+        if let Value::Compound(ref mut map) = *self {
+            map.retain(|key, value| f(key, value));
+        }
+    }
+
+    /// Merges `other` into this value, as the `/data merge` command does.
+    ///
+    /// Where both are compounds, each entry of `other` is merged into the
+    /// entry of the same name, so nested compounds keep the entries `other`
+    /// does not mention. Anything else is replaced by a copy of `other`.
+    pub fn merge(&mut self, other: &Value) {
+        match (self, other) {
+            (Value::Compound(map), Value::Compound(entries)) => {
+                for (key, value) in entries {
+                    match map.entry(key.clone()) {
+                        Entry::Occupied(mut entry) => entry.get_mut().merge(value),
+                        Entry::Vacant(entry) => { entry.insert(value.clone()); },
+                    }
+                }
+            },
+            (this, _) => *this = other.clone(),
+        }
+    }
+
+    /// Feeds this `Value` into `state` in the canonical encoding written by
+    /// `normalize::write_canonical`, without a name. The hash does not depend
+    /// on the order of compound entries, or on how the data was compressed,
+    /// and is equal for two values exactly when those encodings are. Fails
+    /// if a list has heterogeneous elements.
+    pub fn canonical_hash<H: Hasher>(&self, state: &mut H) -> Result<()> {
+        let mut dst = HashingWriter::new(state);
+        dst.write_u8(self.id())?;
+        normalize::write_payload(&mut dst, self)
+    }
+
+    /// Renders this `Value` as compact SNBT, the text form used in Minecraft
+    /// commands. See the `snbt` module for the details.
+    pub fn to_snbt(&self) -> String {
+        snbt::to_string(self)
+    }
+
+    /// Renders this `Value` as SNBT spread over several lines, indenting each
+    /// level by `indent` spaces.
+    pub fn to_snbt_pretty(&self, indent: usize) -> String {
+        snbt::to_string_pretty(self, indent)
+    }
+
     /// Writes the header (that is, the value's type ID and optionally a title)
     /// of this `Value` to an `io::Write` destination.
     pub fn write_header(&self, mut dst: &mut io::Write, title: &str) -> Result<()> {
@@ -112,7 +309,7 @@ impl Value {
                     // Otherwise, use the first element of the list.
                     let first_id = vals[0].id();
                     try!(dst.write_u8(first_id));
-                    try!(dst.write_i32::<BigEndian>(vals.len() as i32));
+                    try!(dst.write_i32::<BigEndian>(try!(raw::list_len(vals.len()))));
                     for nbt in vals {
                         // Ensure that all of the tags are the same type.
                         if nbt.id() != first_id {
@@ -133,6 +330,7 @@ impl Value {
                 raw::close_nbt(&mut dst)
             },
             Value::IntArray(ref vals) => raw::write_bare_int_array(&mut dst, &vals[..]),
+            Value::LongArray(ref vals) => raw::write_bare_long_array(&mut dst, &vals[..]),
         }
     }
 
@@ -147,74 +345,413 @@ impl Value {
     }
 
     /// Reads the payload of an `Value` with a given type ID from an
-    /// `io::Read` source.
-    pub fn from_reader(id: u8, mut src: &mut io::Read) -> Result<Value> {
+    /// `io::Read` source, within the default `DecoderOptions`.
+    pub fn from_reader(id: u8, src: &mut io::Read) -> Result<Value> {
+        Value::from_reader_with(id, src, &DecoderOptions::default())
+    }
+
+    /// Reads the payload of an `Value` with a given type ID from an
+    /// `io::Read` source, failing as soon as the data goes beyond one of the
+    /// limits in `options`.
+    pub fn from_reader_with(id: u8, src: &mut io::Read, options: &DecoderOptions)
+                            -> Result<Value> {
+        Limited::new(src, options).value(id, 0)
+    }
+
+    /// Reads a whole document from an `io::Read` source, working out from its
+    /// first two bytes whether it is gzipped, zlib-compressed or uncompressed,
+    /// and returns the name and value of its root tag.
+    ///
+    /// World saves use all three: `level.dat` and player files are gzipped,
+    /// region chunks are usually zlib-compressed, and some files written by
+    /// tools are not compressed at all.
+    pub fn from_reader_auto<R: io::Read>(mut src: R) -> Result<(String, Value)> {
+        let mut magic = [0u8; 2];
+        src.read_exact(&mut magic)?;
+        let src = io::Read::chain(&magic[..], src);
+        match magic {
+            [0x1f, 0x8b] => read_root(GzDecoder::new(src)?),
+            // A zlib header names the deflate method and is a multiple of 31.
+            [cmf, flg] if cmf & 0x0f == 8 && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0 => {
+                read_root(ZlibDecoder::new(src))
+            },
+            [0x0a, _] => read_root(src),
+            _ => Err(Error::UnknownCompression(format!("unrecognised magic bytes {:02x} {:02x}",
+                                                       magic[0], magic[1]))),
+        }
+    }
+
+    /// Reads uncompressed documents stored back to back, as some tools and
+    /// Bedrock Edition's LevelDB values pack them, yielding the name and
+    /// value of each root tag until the source ends between two documents.
+    pub fn read_all<R: io::Read>(src: R) -> ReadAll<R> {
+        ReadAll { src, done: false }
+    }
+}
+
+/// An iterator over the documents in a source, returned by
+/// `Value::read_all`. It ends after the first error.
+pub struct ReadAll<R> {
+    src: R,
+    done: bool,
+}
+
+impl<R: io::Read> Iterator for ReadAll<R> {
+    type Item = Result<(String, Value)>;
+
+    fn next(&mut self) -> Option<Result<(String, Value)>> {
+        if self.done {
+            return None;
+        }
+        // Only the end of the source before a document's first byte is the
+        // end of the stream, rather than a truncated document.
+        let mut id = [0u8];
+        let read = loop {
+            match self.src.read(&mut id) {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                read => break read,
+            }
+        };
+        let result = match read {
+            Ok(0) => {
+                self.done = true;
+                return None;
+            },
+            Ok(_) => read_root(io::Read::chain(&id[..], &mut self.src)),
+            Err(e) => Err(e.into()),
+        };
+        self.done = result.is_err();
+        Some(result)
+    }
+}
+
+/// Limits on what decoding a `Value` may use, for reading data from
+/// untrusted sources. Lengths are checked before anything is allocated for
+/// them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecoderOptions {
+    /// How deeply lists and compounds may nest below the value being read.
+    /// Deeper values fail with `Error::DepthLimitExceeded`. Defaults to 512,
+    /// as in the game.
+    pub max_depth: usize,
+    /// The longest string or compound key, in bytes. Longer ones fail with
+    /// `Error::LengthLimitExceeded`. Defaults to 65535, the longest a string
+    /// can be.
+    pub max_string_len: usize,
+    /// The most elements a list or array may have. Longer ones, and negative
+    /// lengths, fail with `Error::LengthLimitExceeded`. Defaults to 2^31 - 1,
+    /// the longest a list or array can be.
+    pub max_len: usize,
+    /// The most bytes that may be read in total, or `None` for no limit.
+    /// Reading more fails with `Error::SizeLimitExceeded`. Defaults to `None`.
+    pub max_bytes: Option<u64>,
+}
+
+impl Default for DecoderOptions {
+    fn default() -> DecoderOptions {
+        DecoderOptions {
+            max_depth: 512,
+            max_string_len: u16::MAX as usize,
+            max_len: i32::MAX as usize,
+            max_bytes: None,
+        }
+    }
+}
+
+/// Reads values in the byte order `B` while keeping track of the limits
+/// they count against.
+///
+/// Readers that record more than the `Value` as they go, such as
+/// `capture::read_captured`, walk lists and compounds themselves with
+/// `nest`, `list_header` and `entry_header` and read everything else with
+/// `value`, so that they are bounded just as `Value::from_reader_with` is.
+pub(crate) struct Limited<'a, R: ?Sized + 'a, B = BigEndian> {
+    pub(crate) src: &'a mut R,
+    options: &'a DecoderOptions,
+    read: u64,
+    order: PhantomData<B>,
+}
+
+impl<'a, R: ?Sized + io::Read> Limited<'a, R> {
+    pub(crate) fn new(src: &'a mut R, options: &'a DecoderOptions) -> Limited<'a, R> {
+        Limited::ordered(src, options)
+    }
+}
+
+impl<'a, R: ?Sized + io::Read, B: ByteOrder> Limited<'a, R, B> {
+    /// Reads in the byte order `B`, such as `LittleEndian` for Bedrock
+    /// Edition files.
+    pub(crate) fn ordered(src: &'a mut R, options: &'a DecoderOptions) -> Limited<'a, R, B> {
+        Limited { src, options, read: 0, order: PhantomData }
+    }
+
+    /// Counts `n` bytes that are about to be read against the budget.
+    fn consume(&mut self, n: u64) -> Result<()> {
+        self.read = self.read.saturating_add(n);
+        match self.options.max_bytes {
+            Some(max) if self.read > max => Err(Error::SizeLimitExceeded(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the length of a list or array whose elements are `size` bytes
+    /// or more.
+    fn len(&mut self, size: u64) -> Result<usize> {
+        self.consume(4)?;
+        let len = self.src.read_i32::<B>()? as u32 as usize;
+        self.check_len(len)?;
+        self.consume(len as u64 * size)?;
+        Ok(len)
+    }
+
+    /// Fails unless a list or array may have `len` elements.
+    pub(crate) fn check_len(&self, len: usize) -> Result<()> {
+        if len > self.options.max_len {
+            return Err(Error::LengthLimitExceeded(self.options.max_len));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String> {
+        self.consume(2)?;
+        let len = self.src.read_u16::<B>()? as usize;
+        if len > self.options.max_string_len {
+            return Err(Error::LengthLimitExceeded(self.options.max_string_len));
+        }
+        self.consume(len as u64)?;
+        let bytes = raw::read_bytes(self.src, len)?;
+        String::from_utf8(bytes).map_err(From::from)
+    }
+
+    /// Fails unless a list or compound may be nested `depth` deep.
+    pub(crate) fn nest(&self, depth: usize) -> Result<()> {
+        if depth > self.options.max_depth {
+            return Err(Error::DepthLimitExceeded(self.options.max_depth));
+        }
+        Ok(())
+    }
+
+    /// Reads the element type and length of a list.
+    pub(crate) fn list_header(&mut self) -> Result<(u8, usize)> {
+        self.consume(1)?;
+        let id = self.src.read_u8()?;
+        // Elements are counted as they are read, since their size is not
+        // known yet.
+        let len = self.len(0)?;
+        Ok((id, len))
+    }
+
+    /// Reads the type ID and name of the next compound entry, or a type ID
+    /// of 0 at the end of the compound.
+    pub(crate) fn entry_header(&mut self) -> Result<(u8, String)> {
+        self.consume(1)?;
+        match self.src.read_u8()? {
+            0x00 => Ok((0x00, String::new())),
+            id => Ok((id, self.string()?)),
+        }
+    }
+
+    /// Reads a value nested `depth` lists and compounds deep.
+    pub(crate) fn value(&mut self, id: u8, depth: usize) -> Result<Value> {
         match id {
-            0x01 => Ok(Value::Byte(raw::read_bare_byte(&mut src)?)),
-            0x02 => Ok(Value::Short(raw::read_bare_short(&mut src)?)),
-            0x03 => Ok(Value::Int(raw::read_bare_int(&mut src)?)),
-            0x04 => Ok(Value::Long(raw::read_bare_long(&mut src)?)),
-            0x05 => Ok(Value::Float(raw::read_bare_float(&mut src)?)),
-            0x06 => Ok(Value::Double(raw::read_bare_double(&mut src)?)),
-            0x07 => Ok(Value::ByteArray(raw::read_bare_byte_array(&mut src)?)),
-            0x08 => Ok(Value::String(raw::read_bare_string(&mut src)?)),
-            0x09 => { // List
-                let id = try!(src.read_u8());
-                let len = try!(src.read_i32::<BigEndian>()) as usize;
-                let mut buf = Vec::with_capacity(len);
+            0x09 => {
+                self.nest(depth)?;
+                let (id, len) = self.list_header()?;
+                let mut buf = Vec::with_capacity(raw::initial_capacity(len));
                 for _ in 0..len {
-                    buf.push(try!(Value::from_reader(id, src)));
+                    buf.push(self.value(id, depth + 1)?);
                 }
                 Ok(Value::List(buf))
             },
-            0x0a => { // Compound
+            0x0a => {
+                self.nest(depth)?;
                 let mut buf = HashMap::new();
                 loop {
-                    let (id, name) = try!(Value::read_header(src));
+                    let (id, name) = self.entry_header()?;
                     if id == 0x00 { break; }
-                    let tag = try!(Value::from_reader(id, src));
+                    let tag = self.value(id, depth + 1)?;
                     buf.insert(name, tag);
                 }
                 Ok(Value::Compound(buf))
             },
-            0x0b => Ok(Value::IntArray(raw::read_bare_int_array(&mut src)?)),
+            _ => self.leaf(id),
+        }
+    }
+
+    /// Reads a value that holds no other values. Kept apart from `value`,
+    /// which recurses once for every level of nesting, so that its stack
+    /// frame stays small.
+    #[inline(never)]
+    fn leaf(&mut self, id: u8) -> Result<Value> {
+        match id {
+            0x01 => { self.consume(1)?; Ok(Value::Byte(self.src.read_i8()?)) },
+            0x02 => { self.consume(2)?; Ok(Value::Short(self.src.read_i16::<B>()?)) },
+            0x03 => { self.consume(4)?; Ok(Value::Int(self.src.read_i32::<B>()?)) },
+            0x04 => { self.consume(8)?; Ok(Value::Long(self.src.read_i64::<B>()?)) },
+            0x05 => { self.consume(4)?; Ok(Value::Float(self.src.read_f32::<B>()?)) },
+            0x06 => { self.consume(8)?; Ok(Value::Double(self.src.read_f64::<B>()?)) },
+            0x07 => {
+                let len = self.len(1)?;
+                Ok(Value::ByteArray(raw::read_bytes_i8(self.src, len)?))
+            },
+            0x08 => Ok(Value::String(self.string()?)),
+            0x0b => {
+                let len = self.len(4)?;
+                Ok(Value::IntArray(raw::read_bytes_i32::<B, _>(self.src, len)?))
+            },
+            0x0c => {
+                let len = self.len(8)?;
+                Ok(Value::LongArray(raw::read_bytes_i64::<B, _>(self.src, len)?))
+            },
             e => Err(Error::InvalidTypeId(e))
         }
     }
 }
 
+fn read_root<R: io::Read>(mut src: R) -> Result<(String, Value)> {
+    let (id, title) = Value::read_header(&mut src)?;
+    let value = Value::from_reader(id, &mut src)?;
+    Ok((title, value))
+}
+
+/// Renders a `Value` as an indented tree, in the style of the NBT
+/// specification and NBTExplorer:
+///
+/// ```text
+/// 2 entries
+/// {
+///   TAG_Int("DataVersion"): 3465
+///   TAG_List("Pos"): 3 entries
+///   {
+///   ...
+/// ```
+///
+/// Compound entries are sorted by name, and arrays are summarized by their
+/// length. With the `color` feature, the alternate form (`{:#}`) highlights
+/// tag types, names and values with ANSI escape codes.
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Value::Byte(v)   => write!(f, "{}", v),
-            Value::Short(v)  => write!(f, "{}", v),
-            Value::Int(v)    => write!(f, "{}", v),
-            Value::Long(v)   => write!(f, "{}", v),
-            Value::Float(v)  => write!(f, "{}", v),
-            Value::Double(v) => write!(f, "{}", v),
-            Value::ByteArray(ref v) => write!(f, "{:?}", v),
-            Value::String(ref v) => write!(f, "{}", v),
-            Value::List(ref v) => {
-                if v.len() == 0 {
-                    write!(f, "zero entries")
-                } else {
-                    try!(write!(f, "{} entries of type {}\n{{\n", v.len(), v[0].tag_name()));
-                    for tag in v {
-                        try!(write!(f, "{}(None): {}\n", tag.tag_name(), tag));
-                    }
-                    try!(write!(f, "}}"));
-                    Ok(())
-                }
-            }
+        Tree::new(f).payload(f, self, 0)
+    }
+}
+
+/// Renders a named tag as a tree, as `Blob` does for its root.
+pub(crate) fn write_tree(f: &mut fmt::Formatter, name: &str, value: &Value) -> fmt::Result {
+    Tree::new(f).tag(f, Some(name), value, 0)
+}
+
+struct Tree {
+    color: bool,
+}
+
+impl Tree {
+    fn new(f: &fmt::Formatter) -> Tree {
+        Tree { color: cfg!(feature = "color") && f.alternate() }
+    }
+
+    fn paint<T: fmt::Display>(&self, f: &mut fmt::Formatter, color: u8, text: T) -> fmt::Result {
+        if self.color {
+            write!(f, "\x1b[{}m{}\x1b[0m", color, text)
+        } else {
+            write!(f, "{}", text)
+        }
+    }
+
+    fn tag(&self, f: &mut fmt::Formatter, name: Option<&str>, value: &Value, depth: usize)
+           -> fmt::Result {
+        write!(f, "{:1$}", "", depth * 2)?;
+        self.paint(f, 36, value.tag_name())?;
+        match name {
+            Some(name) => {
+                f.write_str("(")?;
+                self.paint(f, 32, format_args!("\"{}\"", name))?;
+                f.write_str("): ")?;
+            },
+            None => f.write_str("(None): ")?,
+        }
+        self.payload(f, value, depth)
+    }
+
+    fn payload(&self, f: &mut fmt::Formatter, value: &Value, depth: usize) -> fmt::Result {
+        match *value {
+            Value::Byte(v)   => self.paint(f, 33, v),
+            Value::Short(v)  => self.paint(f, 33, v),
+            Value::Int(v)    => self.paint(f, 33, v),
+            Value::Long(v)   => self.paint(f, 33, v),
+            Value::Float(v)  => self.paint(f, 33, v),
+            Value::Double(v) => self.paint(f, 33, v),
+            Value::String(ref v) => self.paint(f, 33, v),
+            Value::ByteArray(ref v) => self.paint(f, 33, format_args!("[{} bytes]", v.len())),
+            Value::IntArray(ref v)  => self.paint(f, 33, format_args!("[{} ints]", v.len())),
+            Value::LongArray(ref v) => self.paint(f, 33, format_args!("[{} longs]", v.len())),
+            Value::List(ref v) => self.children(f, v.iter().map(|v| (None, v)).collect(), depth),
             Value::Compound(ref v) => {
-                try!(write!(f, "{} entry(ies)\n{{\n", v.len()));
-                for (name, tag) in v {
-                    try!(write!(f, "{}(\"{}\"): {}\n", tag.tag_name(), name, tag));
-                }
-                try!(write!(f, "}}"));
-                Ok(())
-            }
-            Value::IntArray(ref v) => write!(f, "{:?}", v)
+                let mut entries: Vec<_> = v.iter().map(|(k, v)| (Some(&k[..]), v)).collect();
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                self.children(f, entries, depth)
+            },
+        }
+    }
+
+    fn children(&self, f: &mut fmt::Formatter, children: Vec<(Option<&str>, &Value)>, depth: usize)
+                -> fmt::Result {
+        let noun = if children.len() == 1 { "entry" } else { "entries" };
+        writeln!(f, "{} {}", children.len(), noun)?;
+        writeln!(f, "{:1$}{{", "", depth * 2)?;
+        for (name, value) in children {
+            self.tag(f, name, value, depth + 1)?;
+            f.write_str("\n")?;
+        }
+        write!(f, "{:1$}}}", "", depth * 2)
+    }
+}
+
+/// Looks up a compound entry, panicking if this is not a compound or the
+/// entry is missing. Use `Value::get` to avoid the panic.
+impl Index<&str> for Value {
+    type Output = Value;
+
+    fn index(&self, key: &str) -> &Value {
+        match *self {
+            Value::Compound(ref map) => match map.get(key) {
+                Some(value) => value,
+                None => panic!("no entry called {:?} in compound", key),
+            },
+            ref other => panic!("cannot look up {:?} in a {}", key, other.tag_name()),
+        }
+    }
+}
+
+impl IndexMut<&str> for Value {
+    fn index_mut(&mut self, key: &str) -> &mut Value {
+        match *self {
+            Value::Compound(ref mut map) => match map.get_mut(key) {
+                Some(value) => value,
+                None => panic!("no entry called {:?} in compound", key),
+            },
+            ref other => panic!("cannot look up {:?} in a {}", key, other.tag_name()),
+        }
+    }
+}
+
+/// Looks up a list element, panicking if this is not a list or the index is
+/// out of bounds. Use `Value::get_index` to avoid the panic.
+impl Index<usize> for Value {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Value {
+        match *self {
+            Value::List(ref list) => &list[index],
+            ref other => panic!("cannot index into a {}", other.tag_name()),
+        }
+    }
+}
+
+impl IndexMut<usize> for Value {
+    fn index_mut(&mut self, index: usize) -> &mut Value {
+        match *self {
+            Value::List(ref mut list) => &mut list[index],
+            ref other => panic!("cannot index into a {}", other.tag_name()),
         }
     }
 }
@@ -266,3 +803,11 @@ impl From<Vec<i32>> for Value {
 impl<'a> From<&'a [i32]> for Value {
     fn from(t: &'a [i32]) -> Value { Value::IntArray(t.into()) }
 }
+
+impl From<Vec<i64>> for Value {
+    fn from(t: Vec<i64>) -> Value { Value::LongArray(t) }
+}
+
+impl<'a> From<&'a [i64]> for Value {
+    fn from(t: &'a [i64]) -> Value { Value::LongArray(t.into()) }
+}
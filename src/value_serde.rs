@@ -0,0 +1,168 @@
+//! `serde` support for `Value`, enabled by the `serde` feature.
+//!
+//! A `Value` serializes as the tag it holds, so a struct can keep data it
+//! does not model in a `Value` field. Typed arrays and lists are wrapped in
+//! the newtype structs `nbt_serde::arrays` uses to choose between the two,
+//! so the `nbt_serde` encoder writes them back as the tags they were read as.
+//! Compound entries come in the order of their `HashMap`; the `nbt_serde`
+//! encoder's `with_sorted_keys` writes them sorted by name, as
+//! `normalize::write_canonical` does, so the output is the same every time.
+//!
+//! `Value::Compound` does not keep the order its entries were read in: it is
+//! still a `HashMap`, and no order-preserving (`IndexMap`) compound exists.
+//! A file decoded and encoded again therefore comes back byte-identical only
+//! if its compounds were already sorted by name. Sorted output makes re-saved
+//! files stable to diff; it is not a byte-identical round trip.
+//!
+//! Deserializing asks for a newtype struct with a name the `nbt_serde`
+//! decoder recognizes, which then describes each tag as an enum variant
+//! named by its type ID, so that arrays can be told apart from lists. Other
+//! formats see a newtype struct and give whatever they hold; their sequences
+//! become lists.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{de, ser};
+
+use markers::{BYTE_ARRAY, INT_ARRAY, LIST, LONG_ARRAY, VALUE};
+use value::Value;
+
+impl ser::Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: ser::Serializer
+    {
+        match *self {
+            Value::Byte(v) => serializer.serialize_i8(v),
+            Value::Short(v) => serializer.serialize_i16(v),
+            Value::Int(v) => serializer.serialize_i32(v),
+            Value::Long(v) => serializer.serialize_i64(v),
+            Value::Float(v) => serializer.serialize_f32(v),
+            Value::Double(v) => serializer.serialize_f64(v),
+            Value::ByteArray(ref v) => serializer.serialize_newtype_struct(BYTE_ARRAY, v),
+            Value::String(ref v) => serializer.serialize_str(v),
+            Value::List(ref v) => serializer.serialize_newtype_struct(LIST, v),
+            Value::Compound(ref v) => v.serialize(serializer),
+            Value::IntArray(ref v) => serializer.serialize_newtype_struct(INT_ARRAY, v),
+            Value::LongArray(ref v) => serializer.serialize_newtype_struct(LONG_ARRAY, v),
+        }
+    }
+}
+
+impl de::Deserialize for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Value, D::Error>
+        where D: de::Deserializer
+    {
+        deserializer.deserialize_newtype_struct(VALUE, ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl de::Visitor for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an NBT tag")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Byte(v as i8))
+    }
+
+    fn visit_i8<E: de::Error>(self, v: i8) -> Result<Value, E> {
+        Ok(Value::Byte(v))
+    }
+
+    fn visit_i16<E: de::Error>(self, v: i16) -> Result<Value, E> {
+        Ok(Value::Short(v))
+    }
+
+    fn visit_i32<E: de::Error>(self, v: i32) -> Result<Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Long(v))
+    }
+
+    fn visit_f32<E: de::Error>(self, v: f32) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Double(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Value, D::Error>
+        where D: de::Deserializer
+    {
+        deserializer.deserialize(self)
+    }
+
+    fn visit_seq<V>(self, mut visitor: V) -> Result<Value, V::Error>
+        where V: de::SeqVisitor
+    {
+        let mut list = Vec::new();
+        while let Some(value) = visitor.visit()? {
+            list.push(value);
+        }
+        Ok(Value::List(list))
+    }
+
+    fn visit_map<V>(self, mut visitor: V) -> Result<Value, V::Error>
+        where V: de::MapVisitor
+    {
+        let mut map = HashMap::new();
+        while let Some((key, value)) = visitor.visit()? {
+            map.insert(key, value);
+        }
+        Ok(Value::Compound(map))
+    }
+
+    /// Reads a tag described by the `nbt_serde` decoder, as a variant named
+    /// by its type ID holding the payload.
+    fn visit_enum<V>(self, visitor: V) -> Result<Value, V::Error>
+        where V: de::EnumVisitor
+    {
+        use serde::de::VariantVisitor;
+
+        let (id, payload): (u8, _) = visitor.visit_variant()?;
+        Ok(match id {
+            0x09 => Value::List(payload.visit_newtype()?),
+            0x0a => Value::Compound(payload.visit_newtype()?),
+            _ => visit_leaf(id, payload)?,
+        })
+    }
+}
+
+/// Reads the payload of a tag that holds no other tags. Kept apart from
+/// `visit_enum`, which recurses once for every level of nesting, so that
+/// its stack frame stays small.
+#[inline(never)]
+fn visit_leaf<V>(id: u8, payload: V) -> Result<Value, V::Error>
+    where V: de::VariantVisitor
+{
+    Ok(match id {
+        0x01 => Value::Byte(payload.visit_newtype()?),
+        0x02 => Value::Short(payload.visit_newtype()?),
+        0x03 => Value::Int(payload.visit_newtype()?),
+        0x04 => Value::Long(payload.visit_newtype()?),
+        0x05 => Value::Float(payload.visit_newtype()?),
+        0x06 => Value::Double(payload.visit_newtype()?),
+        0x07 => Value::ByteArray(payload.visit_newtype()?),
+        0x08 => Value::String(payload.visit_newtype()?),
+        0x0b => Value::IntArray(payload.visit_newtype()?),
+        0x0c => Value::LongArray(payload.visit_newtype()?),
+        _ => return Err(de::Error::invalid_value(de::Unexpected::Unsigned(u64::from(id)),
+                                                 &"an NBT tag type ID")),
+    })
+}
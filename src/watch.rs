@@ -0,0 +1,162 @@
+//! Watching NBT files for changes, for dashboards and tools that follow a
+//! running server.
+//!
+//! A `Watcher` polls a set of files and directories and reports each file that
+//! changed, decoded afresh. Changes are debounced: a file is only read once its
+//! size and modification time have stayed the same for the debounce period, so
+//! a file that is still being written is not reported until it settles. A file
+//! that decodes as incomplete is given one more debounce period before the
+//! error is reported, since the server may not have finished writing it.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use batch::Document;
+use compression::Registry;
+use error::{Error, Result};
+
+/// A file that changed, with its freshly decoded contents.
+#[derive(Debug)]
+pub struct Change {
+    /// The path to the file.
+    pub path: PathBuf,
+    /// The decoded document, or the error encountered reading it.
+    pub result: Result<Document>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Pending {
+    stamp: Stamp,
+    since: Instant,
+    retried: bool,
+}
+
+/// Polls files and directories for changed NBT files.
+#[derive(Debug)]
+pub struct Watcher {
+    paths: Vec<PathBuf>,
+    extensions: Vec<String>,
+    debounce: Duration,
+    registry: Registry,
+    known: HashMap<PathBuf, Stamp>,
+    pending: HashMap<PathBuf, Pending>,
+}
+
+impl Watcher {
+    /// Creates a watcher that waits for files to stay unchanged for
+    /// `debounce` before reading them. It watches `.dat` files by default.
+    pub fn new(debounce: Duration) -> Watcher {
+        Watcher {
+            paths: Vec::new(),
+            extensions: vec!["dat".to_string()],
+            debounce,
+            registry: Registry::new(),
+            known: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Sets the file extensions to watch, replacing the default.
+    pub fn extensions(mut self, extensions: &[&str]) -> Watcher {
+        self.extensions = extensions.iter().map(|e| e.to_string()).collect();
+        self
+    }
+
+    /// Starts watching a file, or every matching file below a directory.
+    /// Files that already exist are only reported once they change.
+    pub fn watch<P: Into<PathBuf>>(&mut self, path: P) {
+        let path = path.into();
+        let mut files = Vec::new();
+        self.scan(&path, &mut files);
+        for file in files {
+            if let Some(stamp) = stamp(&file) {
+                self.known.insert(file, stamp);
+            }
+        }
+        self.paths.push(path);
+    }
+
+    fn scan(&self, path: &Path, files: &mut Vec<PathBuf>) {
+        if path.is_dir() {
+            if let Ok(entries) = fs::read_dir(path) {
+                for entry in entries.flatten() {
+                    self.scan(&entry.path(), files);
+                }
+            }
+        } else if path.extension().is_some_and(|e| self.extensions.iter().any(|x| **x == *e)) {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    /// Checks every watched file once, returning the changes that have
+    /// settled since the last poll.
+    pub fn poll(&mut self) -> Vec<Change> {
+        let now = Instant::now();
+        let mut files = Vec::new();
+        for path in &self.paths {
+            self.scan(path, &mut files);
+        }
+        files.sort();
+        files.dedup();
+
+        let mut changes = Vec::new();
+        for path in files {
+            let stamp = match stamp(&path) {
+                Some(stamp) => stamp,
+                None => continue,
+            };
+            if self.known.get(&path) == Some(&stamp) {
+                self.pending.remove(&path);
+                continue;
+            }
+            let pending = match self.pending.get(&path) {
+                Some(p) if p.stamp == stamp => *p,
+                _ => {
+                    self.pending.insert(path, Pending { stamp, since: now, retried: false });
+                    continue;
+                },
+            };
+            if now.duration_since(pending.since) < self.debounce {
+                continue;
+            }
+            let result = Document::read(&self.registry, &path);
+            if result.as_ref().err() == Some(&Error::IncompleteNbtValue) && !pending.retried {
+                self.pending.insert(path, Pending { stamp, since: now, retried: true });
+                continue;
+            }
+            self.pending.remove(&path);
+            self.known.insert(path.clone(), stamp);
+            changes.push(Change { path, result });
+        }
+        changes
+    }
+
+    /// Polls every `interval`, passing each change to `callback` until it
+    /// returns `false`.
+    pub fn run<F>(&mut self, interval: Duration, mut callback: F)
+        where F: FnMut(Change) -> bool
+    {
+        loop {
+            for change in self.poll() {
+                if !callback(change) {
+                    return;
+                }
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let meta = fs::metadata(path).ok()?;
+    Some(Stamp { modified: meta.modified().ok(), len: meta.len() })
+}
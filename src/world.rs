@@ -0,0 +1,443 @@
+//! Reading world saves, and searching every chunk of them.
+//!
+//! `World::open` gives the parts of a save most tools want: `level.dat`, the
+//! region files of each dimension, and the player files in `playerdata`,
+//! named by the player's UUID.
+//!
+//! A world stores each dimension's chunks in region files: the overworld in
+//! `region`, the Nether in `DIM-1/region`, the End in `DIM1/region`, and
+//! datapack dimensions in `dimensions/<namespace>/<path>/region`. Since 1.17
+//! entities are kept apart from the chunks, in `entities` next to `region`;
+//! both are searched.
+//!
+//! `World::search` spreads the region files over a pool of worker threads, as
+//! `batch::BatchProcessor` does, and hands matches back to the calling thread
+//! as they are found. Paths within a chunk are given as in
+//! `parallel::par_walk`, such as `block_entities[3].Items[0].id`.
+//! `World::par_chunks` reads every chunk the same way, and hands each to a
+//! callback on the worker thread that read it.
+//!
+//! `World::reindex_uuid` rewrites one UUID to another throughout the save:
+//! in every chunk of every region and entities file, in the player files,
+//! and in `level.dat`. The player file of the old UUID is renamed to match.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+use batch::{self, Document};
+use compression::Registry;
+use error::{Error, Result};
+use parallel;
+use region::RegionEditor;
+use uuid;
+use value::Value;
+
+/// The folders of a dimension which hold region files.
+const FOLDERS: &[&str] = &["region", "entities"];
+
+/// A tag that matched a search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    /// The dimension id, such as `minecraft:overworld`.
+    pub dimension: String,
+    /// The region file the chunk is stored in.
+    pub region: PathBuf,
+    /// The coordinates of the chunk within its region.
+    pub chunk: (i32, i32),
+    /// The path of the tag within the chunk.
+    pub path: String,
+}
+
+/// A region file or chunk that could not be read.
+#[derive(Debug)]
+pub struct Failure {
+    /// The region file.
+    pub region: PathBuf,
+    /// The chunk within the region, or `None` if the region file itself could
+    /// not be read.
+    pub chunk: Option<(i32, i32)>,
+    /// The error encountered.
+    pub error: Error,
+}
+
+/// The outcome of a search.
+#[derive(Debug, Default)]
+pub struct Results {
+    /// The matches, sorted by region file, chunk and path.
+    pub matches: Vec<Match>,
+    /// The region files and chunks which were skipped.
+    pub failures: Vec<Failure>,
+}
+
+/// The outcome of `World::reindex_uuid`.
+#[derive(Debug, Default)]
+pub struct Reindexed {
+    /// The number of UUIDs replaced.
+    pub replaced: usize,
+    /// The files and chunks which could not be read or written. A player file
+    /// or `level.dat` is given as `region`, with no chunk.
+    pub failures: Vec<Failure>,
+}
+
+/// A world save directory.
+#[derive(Clone, Debug)]
+pub struct World {
+    root: PathBuf,
+    threads: usize,
+}
+
+impl World {
+    /// A world stored in `root`, the directory containing `level.dat`,
+    /// searched using one worker thread per available CPU.
+    pub fn new<P: Into<PathBuf>>(root: P) -> World {
+        World { root: root.into(), threads: thread::available_parallelism().map_or(1, |n| n.get()) }
+    }
+
+    /// Sets the number of worker threads.
+    pub fn threads(mut self, threads: usize) -> World {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Opens the world stored in `root`, failing unless it holds a
+    /// `level.dat`.
+    pub fn open<P: Into<PathBuf>>(root: P) -> Result<World> {
+        let world = World::new(root);
+        if !world.root.join("level.dat").is_file() {
+            return Err(Error::IoError(io::Error::new(io::ErrorKind::NotFound,
+                format!("{} has no level.dat", world.root.display()))));
+        }
+        Ok(world)
+    }
+
+    /// Reads `level.dat`.
+    pub fn level_dat(&self) -> Result<Document> {
+        Document::read(&Registry::new(), &self.root.join("level.dat"))
+    }
+
+    /// Opens the region file of the given dimension at the given region
+    /// coordinates for editing, or gives `None` if the dimension or the
+    /// region file does not exist.
+    pub fn region(&self, dimension: &str, rx: i32, rz: i32) -> Result<Option<RegionEditor>> {
+        let dir = match self.dimensions()?.into_iter().find(|d| d.0 == dimension) {
+            Some((_, dir)) => dir,
+            None => return Ok(None),
+        };
+        let path = dir.join("region").join(format!("r.{}.{}.mca", rx, rz));
+        if !path.is_file() {
+            return Ok(None);
+        }
+        RegionEditor::open(path).map(Some)
+    }
+
+    /// Reads the player file of the player with the given UUID, if there is
+    /// one.
+    pub fn player(&self, uuid: u128) -> Result<Option<Document>> {
+        let path = self.root.join("playerdata").join(format!("{}.dat", uuid::to_string(uuid)));
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Document::read(&Registry::new(), &path).map(Some)
+    }
+
+    /// Reads every player file, in order of UUID. Files not named by a UUID,
+    /// such as the game's `.dat_old` backups, are skipped, and a file that
+    /// cannot be read does not stop the others.
+    pub fn iter_players(&self) -> Result<impl Iterator<Item = (u128, Result<Document>)>> {
+        let dir = self.root.join("playerdata");
+        let mut players = Vec::new();
+        if dir.is_dir() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.extension().is_none_or(|e| e != "dat") {
+                    continue;
+                }
+                if let Some(uuid) = path.file_stem().and_then(|s| s.to_str()).and_then(uuid::parse) {
+                    players.push((uuid, path));
+                }
+            }
+        }
+        players.sort();
+        let registry = Registry::new();
+        Ok(players.into_iter().map(move |(uuid, path)| (uuid, Document::read(&registry, &path))))
+    }
+
+    /// The dimensions present in the world, with the directories they are
+    /// stored in, in sorted order.
+    pub fn dimensions(&self) -> Result<Vec<(String, PathBuf)>> {
+        let mut dims = Vec::new();
+        for &(id, dir) in &[("minecraft:overworld", ""), ("minecraft:the_nether", "DIM-1"), ("minecraft:the_end", "DIM1")] {
+            let dir = self.root.join(dir);
+            if is_dimension(&dir) {
+                dims.push((id.to_string(), dir));
+            }
+        }
+        let custom = self.root.join("dimensions");
+        if custom.is_dir() {
+            for namespace in sorted_dirs(&custom)? {
+                let prefix = format!("{}:", file_name(&namespace));
+                find_custom(&namespace, &prefix, &mut dims)?;
+            }
+        }
+        dims.sort();
+        Ok(dims)
+    }
+
+    /// The region files of every dimension, with their dimension ids.
+    pub fn region_files(&self) -> Result<Vec<(String, PathBuf)>> {
+        let mut files = Vec::new();
+        for (id, dir) in self.dimensions()? {
+            for folder in FOLDERS {
+                let folder = dir.join(folder);
+                if !folder.is_dir() {
+                    continue;
+                }
+                let mut entries = fs::read_dir(&folder)?
+                    .map(|e| e.map(|e| e.path()))
+                    .collect::<io::Result<Vec<_>>>()?;
+                entries.sort();
+                files.extend(entries.into_iter()
+                    .filter(|p| p.extension().is_some_and(|e| e == "mca"))
+                    .map(|p| (id.clone(), p)));
+            }
+        }
+        Ok(files)
+    }
+
+    /// Finds every tag in the world for which `predicate` returns true.
+    pub fn search<P>(&self, predicate: P) -> Result<Results>
+        where P: Fn(&str, &Value) -> bool + Sync
+    {
+        let mut matches = Vec::new();
+        let failures = self.search_with(predicate, |m| matches.push(m))?;
+        matches.sort_by(|a, b| (&a.region, a.chunk, &a.path).cmp(&(&b.region, b.chunk, &b.path)));
+        Ok(Results { matches, failures })
+    }
+
+    /// Finds every tag whose path matches `pattern`, in which `*` matches any
+    /// run of characters and `?` any one character. For example
+    /// `Entities[*].Tags[*]` finds every scoreboard tag of every entity.
+    pub fn search_path(&self, pattern: &str) -> Result<Results> {
+        let pattern: Vec<char> = pattern.chars().collect();
+        self.search(|path, _| batch::glob(&pattern, &path.chars().collect::<Vec<_>>()))
+    }
+
+    /// Like `search`, but calls `on_match` on the calling thread for each
+    /// match as soon as it is found, in no particular order. Returns the
+    /// region files and chunks which could not be read.
+    pub fn search_with<P, F>(&self, predicate: P, mut on_match: F) -> Result<Vec<Failure>>
+        where P: Fn(&str, &Value) -> bool + Sync, F: FnMut(Match)
+    {
+        let files = self.region_files()?;
+        let next = Mutex::new(files.iter());
+        let failures = Mutex::new(Vec::new());
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(files.len()) {
+                let sender = sender.clone();
+                let (next, failures, predicate) = (&next, &failures, &predicate);
+                scope.spawn(move || loop {
+                    let (dimension, region) = match next.lock().unwrap().next() {
+                        Some(file) => file,
+                        None => break,
+                    };
+                    let failed = search_region(dimension, region, predicate, &sender);
+                    failures.lock().unwrap().extend(failed);
+                });
+            }
+            // Stop waiting for matches once every worker has finished.
+            drop(sender);
+            for m in receiver {
+                on_match(m);
+            }
+        });
+        Ok(failures.into_inner().unwrap())
+    }
+
+    /// Reads every chunk of every dimension, calling `f` on a worker thread
+    /// with the dimension id, the region file, the coordinates of the chunk
+    /// within it, and the chunk or the error reading it. Region files are
+    /// spread over the workers as for `search`. Returns the region files
+    /// which could not be opened.
+    pub fn par_chunks<F>(&self, f: F) -> Result<Vec<Failure>>
+        where F: Fn(&str, &Path, (i32, i32), Result<Document>) + Sync
+    {
+        let files = self.region_files()?;
+        let next = Mutex::new(files.iter());
+        let failures = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(files.len()) {
+                let (next, failures, f) = (&next, &failures, &f);
+                scope.spawn(move || loop {
+                    let (dimension, region) = match next.lock().unwrap().next() {
+                        Some(file) => file,
+                        None => break,
+                    };
+                    failures.lock().unwrap().extend(read_region(dimension, region, f));
+                });
+            }
+        });
+        Ok(failures.into_inner().unwrap())
+    }
+
+    /// Replaces the UUID `old` with `new` wherever it appears in the world,
+    /// in any of the forms `uuid::replace` understands, and writes the
+    /// changed chunks and files back. The player file of `old` is renamed to
+    /// that of `new`, unless `new` already has one. A file or chunk that
+    /// cannot be read or written is recorded and does not stop the others.
+    pub fn reindex_uuid(&self, old: u128, new: u128) -> Result<Reindexed> {
+        let mut reindexed = Reindexed::default();
+        for (_, region) in self.region_files()? {
+            reindex_region(&region, old, new, &mut reindexed);
+        }
+
+        let registry = Registry::new();
+        let players = self.root.join("playerdata");
+        for (uuid, player) in self.iter_players()? {
+            let path = players.join(format!("{}.dat", uuid::to_string(uuid)));
+            let moved = players.join(format!("{}.dat", uuid::to_string(new)));
+            let rename = uuid == old && !moved.exists();
+            let result = player.and_then(|mut player| {
+                let replaced = uuid::replace(&mut player.value, old, new);
+                if rename {
+                    player.write(&registry, &moved)?;
+                    fs::remove_file(&path)?;
+                } else if replaced > 0 {
+                    player.write(&registry, &path)?;
+                }
+                Ok(replaced)
+            });
+            match result {
+                Ok(replaced) => reindexed.replaced += replaced,
+                Err(error) => reindexed.failures.push(Failure { region: path, chunk: None, error }),
+            }
+        }
+
+        let level = self.root.join("level.dat");
+        if level.is_file() {
+            let result = Document::read(&registry, &level).and_then(|mut doc| {
+                let replaced = uuid::replace(&mut doc.value, old, new);
+                if replaced > 0 {
+                    doc.write(&registry, &level)?;
+                }
+                Ok(replaced)
+            });
+            match result {
+                Ok(replaced) => reindexed.replaced += replaced,
+                Err(error) => reindexed.failures.push(Failure { region: level, chunk: None, error }),
+            }
+        }
+        Ok(reindexed)
+    }
+}
+
+fn reindex_region(region: &Path, old: u128, new: u128, reindexed: &mut Reindexed) {
+    let mut editor = match RegionEditor::open(region) {
+        Ok(editor) => editor,
+        Err(error) => {
+            reindexed.failures.push(Failure { region: region.to_path_buf(), chunk: None, error });
+            return;
+        },
+    };
+    for (x, z) in editor.chunks() {
+        let result = editor.read_chunk(x, z).and_then(|chunk| match chunk {
+            Some(mut chunk) => {
+                let replaced = uuid::replace(&mut chunk.value, old, new);
+                if replaced > 0 {
+                    editor.write_chunk(x, z, &chunk)?;
+                }
+                Ok(replaced)
+            },
+            None => Ok(0),
+        });
+        match result {
+            Ok(replaced) => reindexed.replaced += replaced,
+            Err(error) => reindexed.failures.push(Failure { region: region.to_path_buf(), chunk: Some((x, z)), error }),
+        }
+    }
+    if let Err(error) = editor.save() {
+        reindexed.failures.push(Failure { region: region.to_path_buf(), chunk: None, error });
+    }
+}
+
+fn read_region<F>(dimension: &str, region: &Path, f: &F) -> Option<Failure>
+    where F: Fn(&str, &Path, (i32, i32), Result<Document>)
+{
+    let mut editor = match RegionEditor::open_read_only(region) {
+        Ok(editor) => editor,
+        Err(error) => return Some(Failure { region: region.to_path_buf(), chunk: None, error }),
+    };
+    for (chunk, result) in editor.iter_chunks() {
+        f(dimension, region, chunk, result);
+    }
+    None
+}
+
+fn search_region<P>(dimension: &str, region: &Path, predicate: &P, sender: &mpsc::Sender<Match>) -> Vec<Failure>
+    where P: Fn(&str, &Value) -> bool
+{
+    let mut editor = match RegionEditor::open_read_only(region) {
+        Ok(editor) => editor,
+        Err(error) => return vec![Failure { region: region.to_path_buf(), chunk: None, error }],
+    };
+    let mut failures = Vec::new();
+    for (x, z) in editor.chunks() {
+        let chunk = match editor.read_chunk(x, z) {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => continue,
+            Err(error) => {
+                failures.push(Failure { region: region.to_path_buf(), chunk: Some((x, z)), error });
+                continue;
+            },
+        };
+        parallel::walk("", &chunk.value, &|path: &str, value: &Value| {
+            if predicate(path, value) {
+                let _ = sender.send(Match {
+                    dimension: dimension.to_string(),
+                    region: region.to_path_buf(),
+                    chunk: (x, z),
+                    path: path.to_string(),
+                });
+            }
+        });
+    }
+    failures
+}
+
+fn is_dimension(dir: &Path) -> bool {
+    FOLDERS.iter().any(|folder| dir.join(folder).is_dir())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned())
+}
+
+fn sorted_dirs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    dirs.retain(|p| p.is_dir());
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Finds the dimensions below a namespace directory, whose ids may contain
+/// slashes.
+fn find_custom(dir: &Path, id: &str, dims: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    for sub in sorted_dirs(dir)? {
+        let name = file_name(&sub);
+        if FOLDERS.contains(&&name[..]) {
+            continue;
+        }
+        let sub_id = if id.ends_with(':') { format!("{}{}", id, name) } else { format!("{}/{}", id, name) };
+        if is_dimension(&sub) {
+            dims.push((sub_id.clone(), sub.clone()));
+        }
+        find_custom(&sub, &sub_id, dims)?;
+    }
+    Ok(())
+}
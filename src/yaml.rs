@@ -0,0 +1,750 @@
+//! Conversion between `Value` trees and YAML text.
+//!
+//! YAML has no notion of integer widths or typed arrays, so the following
+//! conventions are used to make round-trips lossless:
+//!
+//! | NBT tag          | YAML representation                         |
+//! |------------------|---------------------------------------------|
+//! | `TAG_Byte`       | integer with a `b` suffix, e.g. `12b`       |
+//! | `TAG_Short`      | integer with an `s` suffix, e.g. `12s`      |
+//! | `TAG_Int`        | plain integer, e.g. `12`                    |
+//! | `TAG_Long`       | integer with an `L` suffix, e.g. `12L`      |
+//! | `TAG_Float`      | number with an `f` suffix, e.g. `1.5f`      |
+//! | `TAG_Double`     | plain number with a fraction, e.g. `1.5`    |
+//! | `TAG_String`     | double-quoted string                        |
+//! | `TAG_Byte_Array` | `!byte_array [1, 2, 3]`                     |
+//! | `TAG_Int_Array`  | `!int_array [1, 2, 3]`                      |
+//! | `TAG_Long_Array` | `!long_array [1, 2, 3]`                     |
+//! | `TAG_List`       | block sequence, or `[]` when empty          |
+//! | `TAG_Compound`   | block mapping, or `{}` when empty           |
+//!
+//! Suffixes are case-insensitive when reading, and a `d` suffix is accepted
+//! for doubles. Non-finite floats are written as `.inf`, `-.inf` and `.nan`,
+//! tagged with `!float` for `TAG_Float`. The explicit tags `!byte`, `!short`,
+//! `!int`, `!long`, `!float` and `!double` may also be used on any number.
+//!
+//! To make hand-editing pleasant, the reader also accepts plain (unquoted)
+//! strings, `true`/`false` (as bytes), single-quoted strings, flow mappings and
+//! comments. Compound keys are written in sorted order so that output is
+//! stable. Lists and compounds may nest as deeply as the default
+//! `DecoderOptions` allow, block and flow collections alike.
+
+use std::collections::HashMap;
+use std::io;
+
+use error::{Error, Result};
+use value::{DecoderOptions, Value};
+
+/// Renders a `Value` as a YAML document.
+pub fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+    match *value {
+        Value::List(ref v) if !v.is_empty() => write_block(&mut out, value, 0, false),
+        Value::Compound(ref v) if !v.is_empty() => write_block(&mut out, value, 0, false),
+        _ => {
+            write_inline(&mut out, value);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Writes a `Value` as a YAML document to an `io::Write` destination.
+pub fn to_writer<W>(dst: &mut W, value: &Value) -> Result<()>
+    where W: io::Write
+{
+    dst.write_all(to_string(value).as_bytes()).map_err(From::from)
+}
+
+/// Parses a YAML document into a `Value`.
+pub fn from_str(src: &str) -> Result<Value> {
+    let lines = split_lines(src)?;
+    if lines.is_empty() {
+        return Err(syntax(0, "empty document"));
+    }
+    let mut parser = Parser { lines, pos: 0 };
+    let indent = parser.lines[0].indent;
+    let value = parser.parse_block(indent, 0)?;
+    if let Some(line) = parser.lines.get(parser.pos) {
+        return Err(syntax(line.number, "unexpected content after document"));
+    }
+    Ok(value)
+}
+
+/// Reads a YAML document from an `io::Read` source and parses it into a
+/// `Value`.
+pub fn from_reader<R>(src: &mut R) -> Result<Value>
+    where R: io::Read
+{
+    let mut text = String::new();
+    src.read_to_string(&mut text)?;
+    from_str(&text)
+}
+
+fn syntax(line: usize, msg: &str) -> Error {
+    Error::InvalidYaml(format!("line {}: {}", line, msg))
+}
+
+/// Fails unless a list or compound may be nested `depth` deep.
+fn check_depth(depth: usize, line: usize) -> Result<()> {
+    let max = DecoderOptions::default().max_depth;
+    if depth > max {
+        return Err(syntax(line, &format!("lists and compounds are nested more than {} deep", max)));
+    }
+    Ok(())
+}
+
+/* Writing */
+
+fn is_block(value: &Value) -> bool {
+    match *value {
+        Value::List(ref v) => !v.is_empty(),
+        Value::Compound(ref v) => !v.is_empty(),
+        _ => false,
+    }
+}
+
+fn indent_to(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push(' ');
+    }
+}
+
+/// Writes a non-empty list or compound as a block, one entry per line. When
+/// `continued` is set, the indentation of the first line has already been
+/// written (e.g. after a `- ` sequence marker).
+fn write_block(out: &mut String, value: &Value, indent: usize, continued: bool) {
+    let mut first = true;
+    match *value {
+        Value::List(ref vals) => {
+            for item in vals {
+                if !(first && continued) {
+                    indent_to(out, indent);
+                }
+                first = false;
+                out.push_str("- ");
+                if is_block(item) {
+                    write_block(out, item, indent + 2, true);
+                } else {
+                    write_inline(out, item);
+                    out.push('\n');
+                }
+            }
+        },
+        Value::Compound(ref vals) => {
+            let mut keys: Vec<&String> = vals.keys().collect();
+            keys.sort();
+            for key in keys {
+                let item = &vals[key];
+                if !(first && continued) {
+                    indent_to(out, indent);
+                }
+                first = false;
+                write_string(out, key, true);
+                out.push(':');
+                if is_block(item) {
+                    out.push('\n');
+                    write_block(out, item, indent + 2, false);
+                } else {
+                    out.push(' ');
+                    write_inline(out, item);
+                    out.push('\n');
+                }
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn write_inline(out: &mut String, value: &Value) {
+    match *value {
+        Value::Byte(v)   => out.push_str(&format!("{}b", v)),
+        Value::Short(v)  => out.push_str(&format!("{}s", v)),
+        Value::Int(v)    => out.push_str(&format!("{}", v)),
+        Value::Long(v)   => out.push_str(&format!("{}L", v)),
+        Value::Float(v)  => {
+            if v.is_finite() {
+                out.push_str(&format!("{:?}f", v));
+            } else {
+                out.push_str("!float ");
+                write_non_finite(out, v as f64);
+            }
+        },
+        Value::Double(v) => {
+            if v.is_finite() {
+                out.push_str(&format!("{:?}", v));
+            } else {
+                write_non_finite(out, v);
+            }
+        },
+        Value::ByteArray(ref v) => {
+            out.push_str("!byte_array ");
+            write_flow(out, v);
+        },
+        Value::IntArray(ref v) => {
+            out.push_str("!int_array ");
+            write_flow(out, v);
+        },
+        Value::LongArray(ref v) => {
+            out.push_str("!long_array ");
+            write_flow(out, v);
+        },
+        Value::String(ref v) => write_string(out, v, false),
+        // Only empty containers are written inline.
+        Value::List(_) => out.push_str("[]"),
+        Value::Compound(_) => out.push_str("{}"),
+    }
+}
+
+fn write_non_finite(out: &mut String, v: f64) {
+    if v.is_nan() {
+        out.push_str(".nan");
+    } else if v > 0.0 {
+        out.push_str(".inf");
+    } else {
+        out.push_str("-.inf");
+    }
+}
+
+fn write_flow<T: ToString>(out: &mut String, vals: &[T]) {
+    out.push('[');
+    for (i, v) in vals.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        out.push_str(&v.to_string());
+    }
+    out.push(']');
+}
+
+/// Writes a string, double-quoted unless it is a key that can safely be
+/// written plain.
+fn write_string(out: &mut String, s: &str, key: bool) {
+    let plain = key && s.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+        && s.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.');
+    if plain {
+        out.push_str(s);
+        return;
+    }
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"'  => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                out.push_str(&format!("\\u{:04x}", c as u32));
+            },
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/* Reading */
+
+struct Line {
+    number: usize,
+    indent: usize,
+    /// Whether this line is a `-` sequence marker. The rest of a marker line,
+    /// if any, is split into a separate line at the column it starts on.
+    dash: bool,
+    content: String,
+}
+
+/// Splits the document into meaningful lines, stripping comments and blank
+/// lines and separating sequence markers from their content.
+fn split_lines(src: &str) -> Result<Vec<Line>> {
+    let mut lines = Vec::new();
+    for (i, raw) in src.lines().enumerate() {
+        let number = i + 1;
+        if raw[..raw.len() - raw.trim_start().len()].contains('\t') {
+            return Err(syntax(number, "tabs are not allowed in indentation"));
+        }
+        let text = strip_comment(raw);
+        let trimmed = text.trim_end();
+        let content = trimmed.trim_start();
+        if content.is_empty() || content == "---" {
+            continue;
+        }
+        let mut indent = trimmed.len() - content.len();
+        let mut rest = content;
+        while rest == "-" || rest.starts_with("- ") {
+            lines.push(Line { number, indent, dash: true, content: String::new() });
+            let after = rest[1..].trim_start();
+            indent += rest.len() - after.len();
+            rest = after;
+        }
+        if !rest.is_empty() {
+            lines.push(Line { number, indent, dash: false, content: rest.to_string() });
+        }
+    }
+    Ok(lines)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut prev_space = true;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' && q == '"' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+            },
+            None => {
+                if c == '#' && prev_space {
+                    return &line[..i];
+                }
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+            },
+        }
+        prev_space = c == ' ';
+    }
+    line
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    pos: usize,
+}
+
+impl Parser {
+    /// Parses the block starting at the current line, which must be indented
+    /// by exactly `indent` columns, nested `depth` lists and compounds deep.
+    fn parse_block(&mut self, indent: usize, depth: usize) -> Result<Value> {
+        let (number, dash) = {
+            let line = &self.lines[self.pos];
+            if line.indent != indent {
+                return Err(syntax(line.number, "inconsistent indentation"));
+            }
+            (line.number, line.dash)
+        };
+        if dash {
+            return self.parse_sequence(indent, depth);
+        }
+        if split_key(&self.lines[self.pos].content, number)?.is_some() {
+            return self.parse_mapping(indent, depth);
+        }
+        let value = parse_inline(&self.lines[self.pos].content, number, depth)?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    /// Parses the value that follows a `key:` or `-` with nothing else on the
+    /// line: a nested block that is indented further than `parent`.
+    fn parse_nested(&mut self, parent: usize, number: usize, depth: usize) -> Result<Value> {
+        match self.lines.get(self.pos) {
+            Some(line) if line.indent > parent => {},
+            _ => return Err(syntax(number, "missing value")),
+        }
+        let indent = self.lines[self.pos].indent;
+        self.parse_block(indent, depth)
+    }
+
+    /// Checks whether the next line continues the block at `indent`, failing
+    /// if it is indented further than it should be.
+    fn continues(&self, indent: usize, dash: bool) -> Result<bool> {
+        match self.lines.get(self.pos) {
+            Some(line) if line.indent > indent => {
+                Err(syntax(line.number, "unexpected indentation"))
+            },
+            Some(line) => Ok(line.indent == indent && line.dash == dash),
+            None => Ok(false),
+        }
+    }
+
+    fn parse_sequence(&mut self, indent: usize, depth: usize) -> Result<Value> {
+        check_depth(depth, self.lines[self.pos].number)?;
+        let mut items = Vec::new();
+        while self.continues(indent, true)? {
+            let number = self.lines[self.pos].number;
+            self.pos += 1;
+            let item = self.parse_nested(indent, number, depth + 1)?;
+            if let Some(first) = items.first().map(Value::id) {
+                if item.id() != first {
+                    return Err(Error::HeterogeneousList);
+                }
+            }
+            items.push(item);
+        }
+        Ok(Value::List(items))
+    }
+
+    fn parse_mapping(&mut self, indent: usize, depth: usize) -> Result<Value> {
+        check_depth(depth, self.lines[self.pos].number)?;
+        let mut map = HashMap::new();
+        while self.continues(indent, false)? {
+            let (number, key, rest) = {
+                let line = &self.lines[self.pos];
+                match split_key(&line.content, line.number)? {
+                    Some((key, rest)) => (line.number, key, rest.to_string()),
+                    None => return Err(syntax(line.number, "expected a key")),
+                }
+            };
+            self.pos += 1;
+            let value = if !rest.is_empty() {
+                parse_inline(&rest, number, depth + 1)?
+            } else if self.lines.get(self.pos).is_some_and(|l| l.indent == indent && l.dash) {
+                // Sequences may be written at the same indentation as their
+                // key.
+                self.parse_sequence(indent, depth + 1)?
+            } else {
+                self.parse_nested(indent, number, depth + 1)?
+            };
+            if map.insert(key, value).is_some() {
+                return Err(syntax(number, "duplicate key"));
+            }
+        }
+        Ok(Value::Compound(map))
+    }
+}
+
+/// Splits a `key: value` line, returning `None` if the line is not a mapping
+/// entry.
+fn split_key(content: &str, number: usize) -> Result<Option<(String, &str)>> {
+    let (key, rest) = if content.starts_with('"') || content.starts_with('\'') {
+        let (key, len) = parse_quoted(content, number)?;
+        (key, &content[len..])
+    } else if content.starts_with('[') || content.starts_with('{') || content.starts_with('!') {
+        return Ok(None);
+    } else {
+        let end = content.find(": ").or_else(|| {
+            if content.ends_with(':') { Some(content.len() - 1) } else { None }
+        });
+        match end {
+            Some(end) => (content[..end].trim_end().to_string(), &content[end..]),
+            None => return Ok(None),
+        }
+    };
+    let rest = rest.trim_start();
+    Ok(rest.strip_prefix(':').map(|rest| (key, rest.trim())))
+}
+
+/// Parses a quoted string at the start of `s`, returning it along with the
+/// number of bytes consumed.
+fn parse_quoted(s: &str, number: usize) -> Result<(String, usize)> {
+    let quote = s.chars().next().unwrap();
+    let mut out = String::new();
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        if c == quote {
+            // Single-quoted strings escape quotes by doubling them.
+            if quote == '\'' && s[i + 1..].starts_with('\'') {
+                chars.next();
+                out.push('\'');
+                continue;
+            }
+            return Ok((out, i + 1));
+        }
+        if c == '\\' && quote == '"' {
+            let esc = match chars.next() {
+                Some((_, esc)) => esc,
+                None => break,
+            };
+            match esc {
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                '0' => out.push('\0'),
+                '"' | '\\' | '/' => out.push(esc),
+                'u' => {
+                    let mut code = 0;
+                    for _ in 0..4 {
+                        let digit = chars.next().and_then(|(_, d)| d.to_digit(16));
+                        match digit {
+                            Some(d) => code = code * 16 + d,
+                            None => return Err(syntax(number, "invalid unicode escape")),
+                        }
+                    }
+                    match ::std::char::from_u32(code) {
+                        Some(c) => out.push(c),
+                        None => return Err(syntax(number, "invalid unicode escape")),
+                    }
+                },
+                _ => return Err(syntax(number, "invalid escape sequence")),
+            }
+            continue;
+        }
+        out.push(c);
+    }
+    Err(syntax(number, "unterminated string"))
+}
+
+/// Parses a value that fits on a single line, nested `depth` lists and
+/// compounds deep in the block around it.
+fn parse_inline(s: &str, number: usize, depth: usize) -> Result<Value> {
+    let mut cursor = Cursor { s, pos: 0, number, base: depth, depth: 0 };
+    let value = cursor.value()?;
+    cursor.skip_space();
+    if cursor.pos != s.len() {
+        return Err(syntax(number, "unexpected trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Cursor<'a> {
+    s: &'a str,
+    pos: usize,
+    number: usize,
+    /// How many block collections the line is nested in.
+    base: usize,
+    /// How many flow collections the cursor is nested in.
+    depth: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn rest(&self) -> &'a str {
+        &self.s[self.pos..]
+    }
+
+    fn skip_space(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_space();
+        if self.rest().starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_space();
+        let rest = self.rest();
+        if rest.starts_with('"') || rest.starts_with('\'') {
+            let (s, len) = parse_quoted(rest, self.number)?;
+            self.pos += len;
+            return Ok(Value::String(s));
+        }
+        if rest.starts_with('!') {
+            let end = rest.find(' ').unwrap_or(rest.len());
+            let tag = &rest[1..end];
+            self.pos += end;
+            return self.tagged(tag);
+        }
+        if self.eat('[') {
+            check_depth(self.base + self.depth, self.number)?;
+            let items = self.flow_items(']')?;
+            for item in &items[..] {
+                if item.id() != items[0].id() {
+                    return Err(Error::HeterogeneousList);
+                }
+            }
+            return Ok(Value::List(items));
+        }
+        if self.eat('{') {
+            check_depth(self.base + self.depth, self.number)?;
+            return self.flow_mapping();
+        }
+        let token = self.scalar_token();
+        parse_scalar(token.trim(), self.number)
+    }
+
+    /// Reads a plain scalar, stopping at flow indicators when inside a flow
+    /// collection.
+    fn scalar_token(&mut self) -> &'a str {
+        let rest = self.rest();
+        let end = if self.depth > 0 {
+            rest.find([',', ']', '}']).unwrap_or(rest.len())
+        } else {
+            rest.len()
+        };
+        self.pos += end;
+        &rest[..end]
+    }
+
+    fn flow_items(&mut self, close: char) -> Result<Vec<Value>> {
+        let mut items = Vec::new();
+        if self.eat(close) {
+            return Ok(items);
+        }
+        self.depth += 1;
+        loop {
+            items.push(self.value()?);
+            if self.eat(close) {
+                self.depth -= 1;
+                return Ok(items);
+            }
+            if !self.eat(',') {
+                return Err(syntax(self.number, "expected ',' in flow sequence"));
+            }
+        }
+    }
+
+    fn flow_mapping(&mut self) -> Result<Value> {
+        let mut map = HashMap::new();
+        if self.eat('}') {
+            return Ok(Value::Compound(map));
+        }
+        self.depth += 1;
+        loop {
+            self.skip_space();
+            let rest = self.rest();
+            let key = if rest.starts_with('"') || rest.starts_with('\'') {
+                let (key, len) = parse_quoted(rest, self.number)?;
+                self.pos += len;
+                key
+            } else {
+                let end = match rest.find(':') {
+                    Some(end) => end,
+                    None => return Err(syntax(self.number, "expected ':' in flow mapping")),
+                };
+                self.pos += end;
+                rest[..end].trim().to_string()
+            };
+            if !self.eat(':') {
+                return Err(syntax(self.number, "expected ':' in flow mapping"));
+            }
+            let value = self.value()?;
+            map.insert(key, value);
+            if self.eat('}') {
+                self.depth -= 1;
+                return Ok(Value::Compound(map));
+            }
+            if !self.eat(',') {
+                return Err(syntax(self.number, "expected ',' in flow mapping"));
+            }
+        }
+    }
+
+    fn tagged(&mut self, tag: &str) -> Result<Value> {
+        match tag {
+            "byte_array" | "int_array" => {
+                if !self.eat('[') {
+                    return Err(syntax(self.number, "expected '[' after array tag"));
+                }
+                let items = self.flow_items(']')?;
+                let mut ints = Vec::with_capacity(items.len());
+                for item in items {
+                    match item {
+                        Value::Int(v) => ints.push(v),
+                        _ => return Err(syntax(self.number, "array elements must be plain integers")),
+                    }
+                }
+                if tag == "int_array" {
+                    return Ok(Value::IntArray(ints));
+                }
+                let mut bytes = Vec::with_capacity(ints.len());
+                for v in ints {
+                    if v < i8::MIN as i32 || v > i8::MAX as i32 {
+                        return Err(syntax(self.number, "byte array element out of range"));
+                    }
+                    bytes.push(v as i8);
+                }
+                Ok(Value::ByteArray(bytes))
+            },
+            "long_array" => {
+                if !self.eat('[') {
+                    return Err(syntax(self.number, "expected '[' after array tag"));
+                }
+                // Elements are read as tokens, since plain integers outside
+                // the range of an int are otherwise rejected.
+                let mut longs = Vec::new();
+                self.depth += 1;
+                while !self.eat(']') {
+                    if !longs.is_empty() && !self.eat(',') {
+                        return Err(syntax(self.number, "expected ',' in flow sequence"));
+                    }
+                    self.skip_space();
+                    match self.scalar_token().trim().trim_end_matches(['l', 'L']).parse() {
+                        Ok(v) => longs.push(v),
+                        Err(_) => return Err(syntax(self.number, "array elements must be plain integers")),
+                    }
+                }
+                self.depth -= 1;
+                Ok(Value::LongArray(longs))
+            },
+            "byte" | "short" | "int" | "long" | "float" | "double" => {
+                self.skip_space();
+                let token = self.scalar_token().trim();
+                let value = match tag {
+                    "float" | "double" => parse_float(token).or_else(|| {
+                        parse_float(token.trim_end_matches(['f', 'F', 'd', 'D']))
+                    }).map(|v| {
+                        if tag == "float" { Value::Float(v as f32) } else { Value::Double(v) }
+                    }),
+                    _ => {
+                        let digits = token.trim_end_matches(['b', 'B', 's', 'S', 'l', 'L']);
+                        digits.parse::<i64>().ok().and_then(|v| narrow(v, tag))
+                    },
+                };
+                value.ok_or_else(|| syntax(self.number, "invalid number"))
+            },
+            _ => Err(syntax(self.number, "unknown tag")),
+        }
+    }
+}
+
+fn narrow(v: i64, tag: &str) -> Option<Value> {
+    match tag {
+        "byte" if v >= i8::MIN as i64 && v <= i8::MAX as i64 => Some(Value::Byte(v as i8)),
+        "short" if v >= i16::MIN as i64 && v <= i16::MAX as i64 => Some(Value::Short(v as i16)),
+        "int" if v >= i32::MIN as i64 && v <= i32::MAX as i64 => Some(Value::Int(v as i32)),
+        "long" => Some(Value::Long(v)),
+        _ => None,
+    }
+}
+
+fn parse_float(s: &str) -> Option<f64> {
+    match s {
+        ".inf" | "+.inf" | ".Inf" | ".INF" => Some(f64::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF" => Some(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => Some(f64::NAN),
+        _ => s.parse().ok(),
+    }
+}
+
+/// Interprets a plain scalar, falling back to a string if it is not a number
+/// or boolean.
+fn parse_scalar(token: &str, number: usize) -> Result<Value> {
+    match token {
+        "true" => return Ok(Value::Byte(1)),
+        "false" => return Ok(Value::Byte(0)),
+        _ => {},
+    }
+    let numeric = token.starts_with(|c: char| {
+        c.is_ascii_digit() || c == '-' || c == '+' || c == '.'
+    });
+    if !numeric {
+        return Ok(Value::String(token.to_string()));
+    }
+    let last = token.chars().last().unwrap();
+    let body = &token[..token.len() - last.len_utf8()];
+    let tag = match last {
+        'b' | 'B' => Some("byte"),
+        's' | 'S' => Some("short"),
+        'l' | 'L' => Some("long"),
+        _ => None,
+    };
+    if let (Some(tag), Ok(v)) = (tag, body.parse::<i64>()) {
+        return narrow(v, tag).ok_or_else(|| syntax(number, "integer out of range"));
+    }
+    match last {
+        'f' | 'F' => if let Some(v) = parse_float(body) { return Ok(Value::Float(v as f32)); },
+        'd' | 'D' => if let Some(v) = parse_float(body) { return Ok(Value::Double(v)); },
+        _ => {},
+    }
+    if let Ok(v) = token.parse::<i64>() {
+        return narrow(v, "int").ok_or_else(|| {
+            syntax(number, "integer out of range, use an `L` suffix for longs")
+        });
+    }
+    Ok(match parse_float(token) {
+        Some(v) => Value::Double(v),
+        None => Value::String(token.to_string()),
+    })
+}